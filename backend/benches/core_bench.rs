@@ -1,32 +1,6 @@
-use backend::utils::{burrow_valid, email};
+use backend::utils::email;
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
-fn valid_burrow_list(c: &mut Criterion) {
-    let mut group = c.benchmark_group("get_burrow_list");
-    for burrow in ["", "1", "1,2", "1,2,3", "1,2,3,4,5", "1,2,3,4,5,6,7,8,9,10"].iter() {
-        group.bench_with_input(
-            BenchmarkId::from_parameter(burrow),
-            &burrow,
-            |b, &burrow| {
-                b.iter(|| burrow_valid::get_burrow_list(burrow));
-            },
-        );
-    }
-    group.finish();
-
-    let mut group = c.benchmark_group("is_valid_burrow");
-    for burrow in ["", "1", "1,2", "1,2,3", "1,2,3,4,5", "1,2,3,4,5,6,7,8,9,10"].iter() {
-        group.bench_with_input(
-            BenchmarkId::from_parameter(burrow),
-            &burrow,
-            |b, &burrow| {
-                b.iter(|| burrow_valid::is_valid_burrow(burrow, &5));
-            },
-        );
-    }
-    group.finish();
-}
-
 fn check_email_syntax(c: &mut Criterion) {
     let mut group = c.benchmark_group("check_email_syntax");
     for email in [
@@ -122,7 +96,6 @@ criterion_group!(
     assemble_headers,
     gen_payload,
     signature,
-    valid_burrow_list,
     check_email_syntax,
     id_generator
 );
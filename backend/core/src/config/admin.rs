@@ -0,0 +1,5 @@
+pub static REPORT_PER_PAGE: usize = 20;
+pub static AUDIT_PER_PAGE: usize = 20;
+pub static DEAD_LETTER_PER_PAGE: usize = 20;
+/// Longest time-limited ban in seconds, about 10 years
+pub static MAX_BAN_DURATION: i64 = 10 * 366 * 24 * 3600;
@@ -1,28 +1,9 @@
-use lazy_static::lazy_static;
-
-use super::BACKEND_TEST_MODE;
-
 pub static POST_PER_PAGE: usize = 20;
 pub static REPLY_PER_PAGE: usize = 20;
 pub static MAX_SECTION: usize = 3;
 pub static MAX_TAG: usize = 10;
-
-lazy_static! {
-    pub static ref POST_DELETE_DURATION: i64 = {
-        if *BACKEND_TEST_MODE {
-            2
-        } else {
-            60 * 2
-        }
-    };
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_post_delete_duration_config() {
-        assert_eq!(2, *POST_DELETE_DURATION);
-    }
-}
+pub static QUOTE_PREVIEW_LEN: usize = 50;
+pub static MAX_REPORT_DESCRIPTION_LEN: usize = 500;
+pub static FILTER_RELOAD_INTERVAL: u64 = 60;
+pub static MAX_POLL_OPTION: usize = 10;
+pub static MAX_POLL_OPTION_LEN: usize = 100;
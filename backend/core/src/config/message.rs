@@ -0,0 +1,5 @@
+pub static MESSAGE_PER_PAGE: usize = 20;
+pub static CONVERSATION_PER_PAGE: usize = 20;
+pub static MAX_MESSAGE_LEN: usize = 1000;
+pub static MESSAGE_RATE_LIMIT: usize = 30;
+pub static MESSAGE_RATE_WINDOW: usize = 60;
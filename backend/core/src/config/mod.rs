@@ -1,9 +1,11 @@
 //! Module of configuration
 
+pub mod admin;
 pub mod burrow;
 pub mod content;
-pub mod email;
-pub mod mq;
+pub mod message;
+pub mod search;
+pub mod settings;
 pub mod storage;
 pub mod user;
 
@@ -0,0 +1,6 @@
+pub static REINDEX_BATCH: u64 = 500;
+pub static DIFF_REPORT_LIMIT: usize = 20;
+/// Seconds before the start of a reindex from which the search events of the
+/// outbox are replayed after the alias is swapped, to cover transactions which
+/// commit late
+pub static REINDEX_CATCH_UP_MARGIN: i64 = 60;
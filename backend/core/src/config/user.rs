@@ -1,4 +1,20 @@
 pub static TOKEN_TO_ID_EX: i32 = 14400;
 pub static REF_TOKEN_TO_ID_EX: i32 = 15 * 24 * 3600;
-pub static ID_TO_TOKEN_EX: i32 = 16 * 24 * 3600;
+pub static REFRESH_REUSE_GRACE: i64 = 10;
+pub static SESSION_EX: i32 = 16 * 24 * 3600;
+pub static SESSION_TOUCH_INTERVAL: i64 = 60;
+pub static MAX_SESSION_NUM: usize = 10;
+pub static MAX_DEVICE_LEN: usize = 256;
 pub static SEND_EMAIL_LIMIT: usize = 3;
+pub static EMAIL_TOKEN_EX: i32 = 14400;
+pub static NOTIFICATION_PER_PAGE: usize = 20;
+pub static TOTP_ISSUER: &str = "THUBurrow";
+pub static TOTP_STEP: i64 = 30;
+pub static TOTP_DIGITS: u32 = 6;
+pub static TOTP_SKEW: i64 = 1;
+pub static TOTP_RECOVERY_CODE_NUM: usize = 8;
+pub static TOTP_LOGIN_EX: i64 = 300;
+pub static TOTP_LOGIN_ATTEMPT: i64 = 5;
+pub static LOGIN_FAIL_WINDOW: i64 = 3600;
+pub static LOGIN_LOCK_BASE: i64 = 30;
+pub static LOGIN_LOCK_MAX: i64 = 3600;
@@ -25,11 +25,29 @@ pub struct Model {
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
-pub enum Relation {}
+pub enum Relation {
+    ContentPost,
+    UserFollow,
+}
 
 impl RelationTrait for Relation {
     fn def(&self) -> RelationDef {
-        panic!("No RelationDef")
+        match self {
+            Self::ContentPost => Entity::has_many(super::content_post::Entity).into(),
+            Self::UserFollow => Entity::has_many(super::user_follow::Entity).into(),
+        }
+    }
+}
+
+impl Related<super::content_post::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ContentPost.def()
+    }
+}
+
+impl Related<super::user_follow::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserFollow.def()
     }
 }
 
@@ -25,11 +25,34 @@ pub struct Model {
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
-pub enum Relation {}
+pub enum Relation {
+    Burrow,
+    ContentReply,
+}
 
 impl RelationTrait for Relation {
     fn def(&self) -> RelationDef {
-        panic!("No RelationDef")
+        match self {
+            Self::Burrow => Entity::belongs_to(super::burrow::Entity)
+                .from(Column::BurrowId)
+                .to(super::burrow::Column::BurrowId)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::Restrict)
+                .into(),
+            Self::ContentReply => Entity::has_many(super::content_reply::Entity).into(),
+        }
+    }
+}
+
+impl Related<super::burrow::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Burrow.def()
+    }
+}
+
+impl Related<super::content_reply::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ContentReply.def()
     }
 }
 
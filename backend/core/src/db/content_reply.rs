@@ -14,16 +14,47 @@ pub struct Model {
     pub update_time: DateTimeWithTimeZone,
     #[sea_orm(column_type = "Text")]
     pub content: String,
+    #[sea_orm(column_type = "Text")]
+    pub content_html: String,
     pub reply_state: i32,
     pub permission: i32,
+    pub parent_reply_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
-pub enum Relation {}
+pub enum Relation {
+    ContentPost,
+    Burrow,
+}
 
 impl RelationTrait for Relation {
     fn def(&self) -> RelationDef {
-        panic!("No RelationDef")
+        match self {
+            Self::ContentPost => Entity::belongs_to(super::content_post::Entity)
+                .from(Column::PostId)
+                .to(super::content_post::Column::PostId)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::Cascade)
+                .into(),
+            Self::Burrow => Entity::belongs_to(super::burrow::Entity)
+                .from(Column::BurrowId)
+                .to(super::burrow::Column::BurrowId)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::Restrict)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::content_post::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ContentPost.def()
+    }
+}
+
+impl Related<super::burrow::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Burrow.def()
     }
 }
 
@@ -16,11 +16,26 @@ pub struct Model {
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
-pub enum Relation {}
+pub enum Relation {
+    User,
+}
 
 impl RelationTrait for Relation {
     fn def(&self) -> RelationDef {
-        panic!("No RelationDef")
+        match self {
+            Self::User => Entity::belongs_to(super::user::Entity)
+                .from(Column::Uid)
+                .to(super::user::Column::Uid)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::Restrict)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
     }
 }
 
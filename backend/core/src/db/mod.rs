@@ -3,13 +3,27 @@
 pub mod prelude;
 
 pub mod admin;
+pub mod admin_audit;
+pub mod ban_record;
 pub mod burrow;
+pub mod burrow_block;
 pub mod content_post;
 pub mod content_reply;
+pub mod dead_letter;
 pub mod image;
+pub mod message;
+pub mod notification;
+pub mod outbox;
+pub mod poll;
+pub mod poll_vote;
+pub mod poll_voter;
+pub mod report;
+pub mod schema_version;
+pub mod sensitive_word;
 pub mod user;
 pub mod user_collection;
 pub mod user_follow;
 pub mod user_like;
 pub mod user_status;
 pub mod user_storage;
+pub mod user_totp;
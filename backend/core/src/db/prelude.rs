@@ -1,12 +1,27 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.4.0
 
 pub use super::admin::Entity as Admin;
+pub use super::admin_audit::Entity as AdminAudit;
+pub use super::ban_record::Entity as BanRecord;
 pub use super::burrow::Entity as Burrow;
+pub use super::burrow_block::Entity as BurrowBlock;
 pub use super::content_post::Entity as ContentPost;
 pub use super::content_reply::Entity as ContentReply;
+pub use super::dead_letter::Entity as DeadLetter;
 pub use super::image::Entity as Image;
+pub use super::message::Entity as Message;
+pub use super::notification::Entity as Notification;
+pub use super::outbox::Entity as Outbox;
+pub use super::poll::Entity as Poll;
+pub use super::poll_vote::Entity as PollVote;
+pub use super::poll_voter::Entity as PollVoter;
+pub use super::report::Entity as Report;
+pub use super::schema_version::Entity as SchemaVersion;
+pub use super::sensitive_word::Entity as SensitiveWord;
 pub use super::user::Entity as User;
 pub use super::user_collection::Entity as UserCollection;
 pub use super::user_follow::Entity as UserFollow;
 pub use super::user_like::Entity as UserLike;
 pub use super::user_status::Entity as UserStatus;
+pub use super::user_storage::Entity as UserStorage;
+pub use super::user_totp::Entity as UserTotp;
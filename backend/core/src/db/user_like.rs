@@ -12,11 +12,26 @@ pub struct Model {
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
-pub enum Relation {}
+pub enum Relation {
+    ContentPost,
+}
 
 impl RelationTrait for Relation {
     fn def(&self) -> RelationDef {
-        panic!("No RelationDef")
+        match self {
+            Self::ContentPost => Entity::belongs_to(super::content_post::Entity)
+                .from(Column::PostId)
+                .to(super::content_post::Column::PostId)
+                .on_update(ForeignKeyAction::NoAction)
+                .on_delete(ForeignKeyAction::Cascade)
+                .into(),
+        }
+    }
+}
+
+impl Related<super::content_post::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ContentPost.def()
     }
 }
 
@@ -9,10 +9,6 @@ pub struct Model {
     pub uid: i64,
     pub update_time: DateTimeWithTimeZone,
     pub user_state: i32,
-    #[sea_orm(column_type = "Text")]
-    pub valid_burrow: String,
-    #[sea_orm(column_type = "Text")]
-    pub banned_burrow: String,
     pub permission: i32,
     pub file_capacity: i64,
     pub file_num: i32,
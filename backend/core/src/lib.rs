@@ -7,6 +7,7 @@ extern crate rocket;
 
 pub mod config;
 pub mod db;
+pub mod migration;
 pub mod models;
 pub mod pool;
 pub mod routes;
@@ -38,18 +39,32 @@ pub fn rocket_init() -> Rocket<Build> {
     log_init();
     setup::id_generator::init(1);
     let cors_handler = setup::cors::init();
-    rocket::build()
+    rocket::custom(config::settings::rocket_figment())
+        .attach(AdHoc::try_on_ignite("check_config", |rocket| async move {
+            match config::settings::check_config() {
+                Ok(_) => Ok(rocket),
+                Err(e) => {
+                    log::error!("[CONFIG] Invalid config: {}", e);
+                    Err(rocket)
+                }
+            }
+        }))
         .mount("/", rocket_cors::catch_all_options_routes())
         .attach(cors_handler.clone())
         .manage(cors_handler)
         .attach(pool::PgDb::init())
         .attach(pool::RedisDb::init())
-        .attach(pool::PulsarMq::init())
+        .attach(pool::Mq::init())
         .attach(pool::MinioImageStorage::init())
         .attach(pool::TypesenseSearch::init())
         .attach(AdHoc::on_ignite("mount_routes", routes::routes_init))
+        .register("/", catchers![utils::rate_limit::too_many_requests])
         .attach(AdHoc::try_on_ignite(
             "setup_postgresql_tables",
             setup::postgres::postgres_table_setup,
         ))
+        .attach(AdHoc::on_liftoff(
+            "reload_content_filter",
+            utils::content_filter::filter_reload_init,
+        ))
 }
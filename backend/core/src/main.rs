@@ -1,7 +1,11 @@
-#[macro_use]
-extern crate rocket;
-
-#[launch]
-fn rocket() -> _ {
-    backend::rocket_init()
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|s| s.as_str()) == Some("migrate") {
+        std::process::exit(backend::migration::run_command(&args[2..]).await);
+    }
+    if let Err(e) = backend::rocket_init().launch().await {
+        log::error!("[BACKEND] Failed to launch: {:?}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,611 @@
+//! Module of versioned schema migrations
+//!
+//! Migrations are numbered from 1 without gaps, each with the statements to migrate
+//! `up` to it and `down` from it. Applied versions are recorded in the table
+//! `schema_version`. Pending migrations are applied when the server launches, and
+//! migrations can be applied or rolled back by hand with the subcommand `migrate`:
+//!
+//! ```text
+//! backend migrate status
+//! backend migrate up [<version>]
+//! backend migrate down [--drop-all] [<version>]
+//! ```
+//!
+//! `up` migrates to the latest version by default, and `down` rolls back the last
+//! applied migration by default. Rolling back the baseline drops every table, so
+//! `down` refuses to do it unless `--drop-all` is given. Add a new migration to
+//! the end of `MIGRATIONS`, never change a migration once it has been released.
+
+use chrono::{FixedOffset, Utc};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::{
+    entity::*, query::*, DatabaseConnection, DatabaseTransaction, DbBackend, DbErr, Statement,
+    TransactionTrait,
+};
+use std::fmt;
+
+use crate::config::settings::{check_config, CONFIG};
+use crate::db::{prelude::*, schema_version};
+use crate::setup::postgres;
+
+/// Key of the advisory lock held while migrating, so that replicas launching at
+/// the same time do not apply a migration twice
+const MIGRATION_LOCK: i64 = 0x6275_7272_6f77;
+
+/// A migration of the schema
+///
+/// ## Fields
+///
+/// - `version`: i64, version of the schema after migrating up
+/// - `name`: &str, short description of the migration
+/// - `up`: statements to migrate from `version - 1` to `version`
+/// - `down`: statements to migrate from `version` back to `version - 1`
+/// - `tolerant`: bool, run each statement of `up` in a savepoint and ignore
+///   failures, only for the baseline, which may have been set up before
+///   migrations existed
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: fn() -> Vec<String>,
+    pub down: fn() -> Vec<String>,
+    pub tolerant: bool,
+}
+
+/// All the migrations, in the order of versions
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        up: postgres::baseline_up,
+        down: postgres::baseline_down,
+        tolerant: true,
+    },
+    Migration {
+        version: 2,
+        name: "burrow_owner",
+        up: postgres::burrow_owner_up,
+        down: postgres::burrow_owner_down,
+        tolerant: false,
+    },
+    Migration {
+        version: 3,
+        name: "foreign_keys",
+        up: postgres::foreign_keys_up,
+        down: postgres::foreign_keys_down,
+        tolerant: false,
+    },
+    Migration {
+        version: 4,
+        name: "outbox",
+        up: postgres::outbox_up,
+        down: postgres::outbox_down,
+        tolerant: false,
+    },
+    Migration {
+        version: 5,
+        name: "dead_letter",
+        up: postgres::dead_letter_up,
+        down: postgres::dead_letter_down,
+        tolerant: false,
+    },
+    Migration {
+        version: 6,
+        name: "admin_audit_target",
+        up: postgres::admin_audit_target_up,
+        down: postgres::admin_audit_target_down,
+        tolerant: false,
+    },
+    Migration {
+        version: 7,
+        name: "poll_voter",
+        up: postgres::poll_voter_up,
+        down: postgres::poll_voter_down,
+        tolerant: false,
+    },
+];
+
+/// State of a migration
+///
+/// ## Fields
+///
+/// - `version`: i64, version of the migration
+/// - `name`: String, name of the migration
+/// - `applied_time`: Option<DateTimeWithTimeZone>, `None` if pending
+/// - `known`: bool, `false` if it is applied by a newer version of backend
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub applied_time: Option<DateTimeWithTimeZone>,
+    pub known: bool,
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Db(DbErr),
+    /// The schema has been migrated to a version unknown to this build
+    UnknownVersion(i64),
+    /// The target version is out of range
+    InvalidTarget(i64),
+    /// Rolling back would drop every table and `--drop-all` is not given
+    DropAllRefused,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Db(e) => write!(f, "DbErr: {:?}", e),
+            MigrationError::UnknownVersion(v) => write!(
+                f,
+                "schema version {} is newer than the latest known version {}, refuse to run",
+                v,
+                latest_version()
+            ),
+            MigrationError::InvalidTarget(v) => write!(f, "invalid target version {}", v),
+            MigrationError::DropAllRefused => write!(
+                f,
+                "rolling back the baseline drops every table, add --drop-all to confirm"
+            ),
+        }
+    }
+}
+
+impl From<DbErr> for MigrationError {
+    fn from(e: DbErr) -> Self {
+        MigrationError::Db(e)
+    }
+}
+
+/// Latest version known to this build, 0 if there is no migration
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Check the version applied to the database against the known migrations
+///
+/// ## Returns
+///
+/// The current version, 0 if nothing is applied.
+///
+/// ## Errors
+///
+/// `MigrationError::UnknownVersion` if the database has been migrated by a newer
+/// version of backend.
+pub fn check_version(applied: &[i64]) -> Result<i64, MigrationError> {
+    let current = applied.iter().copied().max().unwrap_or(0);
+    if current > latest_version() {
+        Err(MigrationError::UnknownVersion(current))
+    } else {
+        Ok(current)
+    }
+}
+
+/// Migrations to apply to go from `current` up to `target`, in order
+fn pending(current: i64, target: i64) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+        .collect()
+}
+
+/// Migrations to roll back to go from `current` down to `target`, in order
+fn rollback(current: i64, target: i64) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .rev()
+        .filter(|m| m.version > target && m.version <= current)
+        .collect()
+}
+
+fn statement(sql: String) -> Statement {
+    Statement::from_string(DbBackend::Postgres, sql)
+}
+
+/// Get the applied migrations, creating the table `schema_version` if needed
+pub async fn get_applied(
+    db: &DatabaseConnection,
+) -> Result<Vec<schema_version::Model>, MigrationError> {
+    db.execute(statement(postgres::create_schema_version_table()))
+        .await?;
+    Ok(SchemaVersion::find()
+        .order_by_asc(schema_version::Column::Version)
+        .all(db)
+        .await?)
+}
+
+/// Get the current version of the schema
+///
+/// ## Errors
+///
+/// `MigrationError::UnknownVersion` if the database has been migrated by a newer
+/// version of backend.
+pub async fn current_version(db: &DatabaseConnection) -> Result<i64, MigrationError> {
+    let applied: Vec<i64> = get_applied(db).await?.iter().map(|a| a.version).collect();
+    check_version(&applied)
+}
+
+/// Connect to the database in the config and check the version of the schema,
+/// for processes which do not migrate but must not run against a newer schema
+pub async fn check_schema() -> Result<i64, MigrationError> {
+    let db = sea_orm::Database::connect(&CONFIG.databases.pgdb.url).await?;
+    current_version(&db).await
+}
+
+/// Get the state of all the known and applied migrations
+pub async fn get_status(db: &DatabaseConnection) -> Result<Vec<MigrationStatus>, MigrationError> {
+    let applied = get_applied(db).await?;
+    let mut status: Vec<MigrationStatus> = MIGRATIONS
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name.to_string(),
+            applied_time: applied
+                .iter()
+                .find(|a| a.version == m.version)
+                .map(|a| a.applied_time),
+            known: true,
+        })
+        .collect();
+    for a in applied.into_iter().filter(|a| a.version > latest_version()) {
+        status.push(MigrationStatus {
+            version: a.version,
+            name: a.name,
+            applied_time: Some(a.applied_time),
+            known: false,
+        });
+    }
+    Ok(status)
+}
+
+/// Lock the migrations in a transaction and check whether a version is applied
+async fn lock_and_check(txn: &DatabaseTransaction, version: i64) -> Result<bool, DbErr> {
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT pg_advisory_xact_lock($1)",
+        vec![MIGRATION_LOCK.into()],
+    ))
+    .await?;
+    Ok(SchemaVersion::find_by_id(version).one(txn).await?.is_some())
+}
+
+async fn apply(db: &DatabaseConnection, migration: &Migration) -> Result<bool, DbErr> {
+    let txn = db.begin().await?;
+    if lock_and_check(&txn, migration.version).await? {
+        return Ok(false);
+    }
+    for sql in (migration.up)() {
+        if !migration.tolerant {
+            txn.execute(statement(sql)).await?;
+            continue;
+        }
+        // a failed statement aborts the whole transaction unless it is rolled
+        // back to a savepoint
+        txn.execute(statement("SAVEPOINT tolerant".to_string()))
+            .await?;
+        match txn.execute(statement(sql)).await {
+            Ok(_) => {
+                txn.execute(statement("RELEASE SAVEPOINT tolerant".to_string()))
+                    .await?;
+            }
+            Err(e) => {
+                info!(
+                    "[MIGRATION] skip statement of version {}: {:?}",
+                    migration.version, e
+                );
+                txn.execute(statement("ROLLBACK TO SAVEPOINT tolerant".to_string()))
+                    .await?;
+            }
+        }
+    }
+    schema_version::ActiveModel {
+        version: Set(migration.version),
+        name: Set(migration.name.to_string()),
+        applied_time: Set(Utc::now().with_timezone(&FixedOffset::east(8 * 3600))),
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+async fn revert(db: &DatabaseConnection, migration: &Migration) -> Result<bool, DbErr> {
+    let txn = db.begin().await?;
+    if !lock_and_check(&txn, migration.version).await? {
+        return Ok(false);
+    }
+    for sql in (migration.down)() {
+        txn.execute(statement(sql)).await?;
+    }
+    SchemaVersion::delete_by_id(migration.version)
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+/// Apply the pending migrations up to `target`, the latest version by default
+///
+/// ## Returns
+///
+/// Versions applied by this call, in order.
+///
+/// ## Errors
+///
+/// - `MigrationError::UnknownVersion` if the database is newer than this build
+/// - `MigrationError::InvalidTarget` if `target` is not a known version
+/// - `MigrationError::Db` if a migration fails, the migrations applied before
+///   it are kept
+pub async fn migrate_up(
+    db: &DatabaseConnection,
+    target: Option<i64>,
+) -> Result<Vec<i64>, MigrationError> {
+    let current = current_version(db).await?;
+    let target = target.unwrap_or_else(latest_version);
+    if target < 0 || target > latest_version() {
+        return Err(MigrationError::InvalidTarget(target));
+    }
+    let mut versions = Vec::new();
+    for migration in pending(current, target) {
+        if apply(db, migration).await? {
+            info!(
+                "[MIGRATION] applied version {}: {}",
+                migration.version, migration.name
+            );
+            versions.push(migration.version);
+        }
+    }
+    Ok(versions)
+}
+
+/// Roll back the applied migrations down to `target`, the version before the
+/// current one by default
+///
+/// ## Parameters
+///
+/// - `target`: Option<i64>, version to roll back to
+/// - `drop_all`: bool, whether rolling back the baseline, which drops every
+///   table, is allowed
+///
+/// ## Returns
+///
+/// Versions rolled back by this call, in order.
+///
+/// ## Errors
+///
+/// - `MigrationError::UnknownVersion` if the database is newer than this build
+/// - `MigrationError::InvalidTarget` if `target` is negative or above the current
+///   version
+/// - `MigrationError::DropAllRefused` if the baseline would be rolled back
+///   without `drop_all`
+/// - `MigrationError::Db` if a rollback fails, the rollbacks before it are kept
+pub async fn migrate_down(
+    db: &DatabaseConnection,
+    target: Option<i64>,
+    drop_all: bool,
+) -> Result<Vec<i64>, MigrationError> {
+    let current = current_version(db).await?;
+    let target = target.unwrap_or_else(|| (current - 1).max(0));
+    if target < 0 || target > current {
+        return Err(MigrationError::InvalidTarget(target));
+    }
+    if !drop_all && rollback(current, target).iter().any(|m| m.tolerant) {
+        return Err(MigrationError::DropAllRefused);
+    }
+    let mut versions = Vec::new();
+    for migration in rollback(current, target) {
+        if revert(db, migration).await? {
+            log::warn!(
+                "[MIGRATION] rolled back version {}: {}",
+                migration.version,
+                migration.name
+            );
+            versions.push(migration.version);
+        }
+    }
+    Ok(versions)
+}
+
+/// Subcommand `migrate`
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Status,
+    Up(Option<i64>),
+    /// Roll back to the version, allowing to drop every table if `true`
+    Down(Option<i64>, bool),
+}
+
+const USAGE: &str =
+    "usage: backend migrate <status | up [<version>] | down [--drop-all] [<version>]>";
+
+/// Parse the arguments after `migrate`
+pub fn parse_command(args: &[String]) -> Result<Command, String> {
+    let (drop_all, args) = match args.get(1).map(|s| s.as_str()) {
+        Some("--drop-all") if args[0] == "down" => (true, [&args[..1], &args[2..]].concat()),
+        _ => (false, args.to_vec()),
+    };
+    let version = match args.get(1) {
+        Some(v) => match v.parse::<i64>() {
+            Ok(v) => Some(v),
+            Err(_) => return Err(format!("invalid version {}\n{}", v, USAGE)),
+        },
+        None => None,
+    };
+    if args.len() > 2 {
+        return Err(USAGE.to_string());
+    }
+    match (args.get(0).map(|s| s.as_str()), version) {
+        (Some("status"), None) => Ok(Command::Status),
+        (Some("up"), v) => Ok(Command::Up(v)),
+        (Some("down"), v) => Ok(Command::Down(v, drop_all)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Run the subcommand `migrate` with the arguments after it
+///
+/// ## Returns
+///
+/// The exit code of the process.
+pub async fn run_command(args: &[String]) -> i32 {
+    let command = match parse_command(args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    if let Err(e) = check_config() {
+        eprintln!("invalid config: {}", e);
+        return 1;
+    }
+    let db = match sea_orm::Database::connect(&CONFIG.databases.pgdb.url).await {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("failed to connect to postgres: {:?}", e);
+            return 1;
+        }
+    };
+    let result = match command {
+        Command::Status => get_status(&db).await.map(|status| {
+            for s in status {
+                let state = match (s.applied_time, s.known) {
+                    (Some(t), true) => format!("applied at {}", t),
+                    (Some(t), false) => format!("applied at {} (unknown)", t),
+                    (None, _) => "pending".to_string(),
+                };
+                println!("{:>4}  {:<24}  {}", s.version, s.name, state);
+            }
+        }),
+        Command::Up(target) => migrate_up(&db, target).await.map(|versions| {
+            for v in versions.iter() {
+                println!("applied version {}", v);
+            }
+            if versions.is_empty() {
+                println!("nothing to migrate");
+            }
+        }),
+        Command::Down(target, drop_all) => {
+            migrate_down(&db, target, drop_all).await.map(|versions| {
+                for v in versions.iter() {
+                    println!("rolled back version {}", v);
+                }
+                if versions.is_empty() {
+                    println!("nothing to roll back");
+                }
+            })
+        }
+    };
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_migrations() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(i as i64 + 1, m.version);
+            assert!(!(m.up)().is_empty());
+            assert!(!(m.down)().is_empty());
+            assert!(!m.tolerant || m.version == 1);
+        }
+        assert!(postgres::baseline_up()
+            .iter()
+            .any(|s| s.starts_with(r#"CREATE TABLE IF NOT EXISTS "user""#)));
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let up = postgres::foreign_keys_up();
+        let down = postgres::foreign_keys_down();
+        for name in [
+            "fk-content-post-burrow",
+            "fk-content-reply-post",
+            "fk-content-reply-burrow",
+            "fk-user-like-post",
+            "fk-user-collection-post",
+            "fk-user-follow-burrow",
+            "fk-image-user",
+        ] {
+            assert!(up
+                .iter()
+                .any(|s| s.starts_with("DO $$") && s.contains(&format!("cannot add {}:", name))));
+            assert!(up
+                .iter()
+                .any(|s| s.contains("FOREIGN KEY") && s.contains(name)));
+            assert!(down.iter().any(|s| s.contains(name)));
+        }
+        assert!(!up.iter().any(|s| s.starts_with("DELETE")));
+        assert_eq!(
+            down.len(),
+            up.iter().filter(|s| s.contains("FOREIGN KEY")).count()
+        );
+    }
+
+    #[test]
+    fn test_admin_audit_target() {
+        let up = postgres::admin_audit_target_up();
+        assert!(up[0].contains(r#"ADD COLUMN "target_type""#));
+        assert!(up[1].starts_with(r#"UPDATE "admin_audit""#));
+        let down = postgres::admin_audit_target_down();
+        assert!(down
+            .iter()
+            .any(|s| s.contains(r#"DROP COLUMN "target_type""#)));
+    }
+
+    #[test]
+    fn test_check_version() {
+        let latest = latest_version();
+        assert_eq!(0, check_version(&[]).unwrap());
+        assert_eq!(latest, check_version(&[latest]).unwrap());
+        assert!(matches!(
+            check_version(&[1, latest + 1]),
+            Err(MigrationError::UnknownVersion(v)) if v == latest + 1
+        ));
+        assert_eq!(latest as usize, pending(0, latest).len());
+        assert!(pending(latest, latest).is_empty());
+        assert_eq!(latest as usize, rollback(latest, 0).len());
+        assert!(rollback(0, 0).is_empty());
+        assert!(rollback(1, 0).iter().any(|m| m.tolerant));
+        assert!(!rollback(latest, 1).iter().any(|m| m.tolerant));
+        let versions: Vec<i64> = rollback(latest, 0).iter().map(|m| m.version).collect();
+        assert_eq!(Some(&latest), versions.first());
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Ok(Command::Status), parse_command(&args("status")));
+        assert_eq!(Ok(Command::Up(None)), parse_command(&args("up")));
+        assert_eq!(Ok(Command::Up(Some(3))), parse_command(&args("up 3")));
+        assert_eq!(Ok(Command::Down(None, false)), parse_command(&args("down")));
+        assert_eq!(
+            Ok(Command::Down(Some(0), false)),
+            parse_command(&args("down 0"))
+        );
+        assert_eq!(
+            Ok(Command::Down(Some(0), true)),
+            parse_command(&args("down --drop-all 0"))
+        );
+        assert_eq!(
+            Ok(Command::Down(None, true)),
+            parse_command(&args("down --drop-all"))
+        );
+        assert!(parse_command(&args("up --drop-all")).is_err());
+        assert!(parse_command(&args("down 0 --drop-all")).is_err());
+        assert!(parse_command(&args("")).is_err());
+        assert!(parse_command(&args("status 1")).is_err());
+        assert!(parse_command(&args("up x")).is_err());
+        assert!(parse_command(&args("up 1 2")).is_err());
+        assert!(parse_command(&args("sideways")).is_err());
+    }
+}
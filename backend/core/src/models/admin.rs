@@ -1,19 +1,492 @@
 //! Models of admin
 
 use rocket::serde::{Deserialize, Serialize};
+use rocket::FromFormField;
+use sea_orm::prelude::DateTimeWithTimeZone;
 
+use crate::db::{admin_audit, dead_letter};
+use crate::models::filter::FilterAction;
+
+/// Operations of admin
+///
+/// `BanUserFor` and `BanBurrowFor` ban the target for `duration` seconds, after
+/// which the ban is lifted by task-executor. `AddFilterRule` and `DeleteFilterRule`
+/// manage the rules of the sensitive word filter.
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum AdminOperation {
-    BanUser { uid: i64 },
-    ReopenUser { uid: i64 },
-    BanBurrow { burrow_id: i64 },
-    ReopenBurrow { burrow_id: i64 },
-    BanPost { post_id: i64 },
-    ReopenPost { post_id: i64 },
-    BanReply { post_id: i64, reply_id: i32 },
-    ReopenReply { post_id: i64, reply_id: i32 },
-    CreateAdmin { uid: i64 },
-    DeleteAdmin { uid: i64 },
-    SetAdminRole { uid: i64, role: i32 },
-    GetUserId { burrow_id: i64 },
+    BanUser {
+        uid: i64,
+    },
+    ReopenUser {
+        uid: i64,
+    },
+    BanUserFor {
+        uid: i64,
+        duration: i64,
+        reason: String,
+    },
+    BanBurrow {
+        burrow_id: i64,
+    },
+    ReopenBurrow {
+        burrow_id: i64,
+    },
+    BanBurrowFor {
+        burrow_id: i64,
+        duration: i64,
+        reason: String,
+    },
+    BanPost {
+        post_id: i64,
+    },
+    ReopenPost {
+        post_id: i64,
+    },
+    BanReply {
+        post_id: i64,
+        reply_id: i32,
+    },
+    ReopenReply {
+        post_id: i64,
+        reply_id: i32,
+    },
+    CreateAdmin {
+        uid: i64,
+    },
+    DeleteAdmin {
+        uid: i64,
+    },
+    SetAdminRole {
+        uid: i64,
+        role: i32,
+    },
+    GetUserId {
+        burrow_id: i64,
+    },
+    AddFilterRule {
+        pattern: String,
+        #[serde(default)]
+        is_regex: bool,
+        action: FilterAction,
+    },
+    DeleteFilterRule {
+        word_id: i64,
+    },
+}
+
+/// Target of a time-limited ban
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BanTarget {
+    User,
+    Burrow,
+}
+
+/// State of a time-limited ban
+///
+/// ## Fields
+///
+/// - `BanState::Active`: The ban is waiting for its expiry
+/// - `BanState::Expired`: The ban has expired and been lifted by task-executor
+/// - `BanState::Revoked`: The ban has been replaced by a later ban or reopen of the target
+///
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BanState {
+    Active,
+    Expired,
+    Revoked,
+}
+
+/// Type of the target of an entry of the admin audit log
+///
+/// ## Fields
+///
+/// - `AuditTarget::User`: `target_id` is a uid, also for the operations on admins
+/// - `AuditTarget::Burrow`: `target_id` is a burrow id
+/// - `AuditTarget::Post`: `target_id` is a post id
+/// - `AuditTarget::Reply`: `target_id` is a post id and `target_sub_id` a reply id
+/// - `AuditTarget::FilterRule`: `target_id` is a word id, 0 for a new rule
+/// - `AuditTarget::Lockout`: no id, the username or ip is in the operation
+/// - `AuditTarget::DeadLetter`: `target_id` is a dead letter id
+///
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, FromFormField)]
+pub enum AuditTarget {
+    User,
+    Burrow,
+    Post,
+    Reply,
+    FilterRule,
+    Lockout,
+    DeadLetter,
+}
+
+/// Target of a login lockout
+///
+/// ## Fields
+///
+/// - `LockoutTarget::User`: Failed logins of a username
+/// - `LockoutTarget::Ip`: Failed logins from a client ip
+///
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum LockoutTarget {
+    User,
+    Ip,
+}
+
+/// Response struct of `get_lockouts`
+///
+/// ## Fields
+///
+/// - `target`: LockoutTarget, whether a username or an ip is locked
+/// - `value`: String, the username or ip
+/// - `failures`: i64, failed logins in the current window
+/// - `retry_after`: i64, seconds until the lockout is lifted
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct LockoutDisplay {
+    pub target: LockoutTarget,
+    pub value: String,
+    pub failures: i64,
+    pub retry_after: i64,
+}
+
+/// Entry of the admin audit log
+///
+/// ## Fields
+///
+/// - `audit_id`: i64, id of the entry
+/// - `uid`: i64, uid of the admin who performed the operation
+/// - `role`: i32, role of the admin at that time
+/// - `operation`: Option<AdminOperation>, the operation, `None` for the actions
+///   of other routes, e.g. `ClearLockout`, or if it can no longer be parsed
+/// - `kind`: String, name of the operation, e.g. `BanUser` or `ReplayDeadLetter`
+/// - `target_type`: Option<AuditTarget>, what `target_id` refers to, `None` if it
+///   is unknown to this version of backend
+/// - `target_id`: i64, uid, burrow id or post id the operation acts on
+/// - `target_sub_id`: i32, reply id for reply operations, otherwise 0
+/// - `status`: i32, HTTP status code returned for the operation
+/// - `result`: String, "Success" or the returned ErrorCode
+/// - `create_time`: DateTimeWithTimeZone, time of the operation
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AuditDisplay {
+    pub audit_id: i64,
+    pub uid: i64,
+    pub role: i32,
+    pub operation: Option<AdminOperation>,
+    pub kind: String,
+    pub target_type: Option<AuditTarget>,
+    pub target_id: i64,
+    pub target_sub_id: i32,
+    pub status: i32,
+    pub result: String,
+    pub create_time: DateTimeWithTimeZone,
+}
+
+/// Dead letter of a consumer of task-executor
+///
+/// ## Fields
+///
+/// - `id`: i64, id of the dead letter
+/// - `topic`: String, topic the message was sent to, e.g. `search`
+/// - `payload`: String, the message as json
+/// - `error`: String, the last error of handling the message
+/// - `attempts`: i32, times the message was tried
+/// - `create_time`: DateTimeWithTimeZone, time the message was dead-lettered
+/// - `replay_time`: Option<DateTimeWithTimeZone>, time of the last replay, `None`
+///   if it has never been replayed
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DeadLetterDisplay {
+    pub id: i64,
+    pub topic: String,
+    pub payload: String,
+    pub error: String,
+    pub attempts: i32,
+    pub create_time: DateTimeWithTimeZone,
+    pub replay_time: Option<DateTimeWithTimeZone>,
+}
+
+impl AdminOperation {
+    /// Name of the operation, used to filter the audit log
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AdminOperation::BanUser { .. } => "BanUser",
+            AdminOperation::ReopenUser { .. } => "ReopenUser",
+            AdminOperation::BanUserFor { .. } => "BanUserFor",
+            AdminOperation::BanBurrow { .. } => "BanBurrow",
+            AdminOperation::ReopenBurrow { .. } => "ReopenBurrow",
+            AdminOperation::BanBurrowFor { .. } => "BanBurrowFor",
+            AdminOperation::BanPost { .. } => "BanPost",
+            AdminOperation::ReopenPost { .. } => "ReopenPost",
+            AdminOperation::BanReply { .. } => "BanReply",
+            AdminOperation::ReopenReply { .. } => "ReopenReply",
+            AdminOperation::CreateAdmin { .. } => "CreateAdmin",
+            AdminOperation::DeleteAdmin { .. } => "DeleteAdmin",
+            AdminOperation::SetAdminRole { .. } => "SetAdminRole",
+            AdminOperation::GetUserId { .. } => "GetUserId",
+            AdminOperation::AddFilterRule { .. } => "AddFilterRule",
+            AdminOperation::DeleteFilterRule { .. } => "DeleteFilterRule",
+        }
+    }
+
+    /// Ids the operation acts on, in the order of `(target_id, target_sub_id)`
+    pub fn target(&self) -> (i64, i32) {
+        match *self {
+            AdminOperation::BanUser { uid }
+            | AdminOperation::ReopenUser { uid }
+            | AdminOperation::BanUserFor { uid, .. }
+            | AdminOperation::CreateAdmin { uid }
+            | AdminOperation::DeleteAdmin { uid }
+            | AdminOperation::SetAdminRole { uid, .. } => (uid, 0),
+            AdminOperation::BanBurrow { burrow_id }
+            | AdminOperation::ReopenBurrow { burrow_id }
+            | AdminOperation::BanBurrowFor { burrow_id, .. }
+            | AdminOperation::GetUserId { burrow_id } => (burrow_id, 0),
+            AdminOperation::BanPost { post_id } | AdminOperation::ReopenPost { post_id } => {
+                (post_id, 0)
+            }
+            AdminOperation::BanReply { post_id, reply_id }
+            | AdminOperation::ReopenReply { post_id, reply_id } => (post_id, reply_id),
+            AdminOperation::AddFilterRule { .. } => (0, 0),
+            AdminOperation::DeleteFilterRule { word_id } => (word_id, 0),
+        }
+    }
+
+    /// Type of the target returned by `target`
+    pub fn target_type(&self) -> AuditTarget {
+        match self {
+            AdminOperation::BanUser { .. }
+            | AdminOperation::ReopenUser { .. }
+            | AdminOperation::BanUserFor { .. }
+            | AdminOperation::CreateAdmin { .. }
+            | AdminOperation::DeleteAdmin { .. }
+            | AdminOperation::SetAdminRole { .. } => AuditTarget::User,
+            AdminOperation::BanBurrow { .. }
+            | AdminOperation::ReopenBurrow { .. }
+            | AdminOperation::BanBurrowFor { .. }
+            | AdminOperation::GetUserId { .. } => AuditTarget::Burrow,
+            AdminOperation::BanPost { .. } | AdminOperation::ReopenPost { .. } => AuditTarget::Post,
+            AdminOperation::BanReply { .. } | AdminOperation::ReopenReply { .. } => {
+                AuditTarget::Reply
+            }
+            AdminOperation::AddFilterRule { .. } | AdminOperation::DeleteFilterRule { .. } => {
+                AuditTarget::FilterRule
+            }
+        }
+    }
+}
+
+impl AuditTarget {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            AuditTarget::User => 0,
+            AuditTarget::Burrow => 1,
+            AuditTarget::Post => 2,
+            AuditTarget::Reply => 3,
+            AuditTarget::FilterRule => 4,
+            AuditTarget::Lockout => 5,
+            AuditTarget::DeadLetter => 6,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<AuditTarget> {
+        match code {
+            0 => Some(AuditTarget::User),
+            1 => Some(AuditTarget::Burrow),
+            2 => Some(AuditTarget::Post),
+            3 => Some(AuditTarget::Reply),
+            4 => Some(AuditTarget::FilterRule),
+            5 => Some(AuditTarget::Lockout),
+            6 => Some(AuditTarget::DeadLetter),
+            _ => None,
+        }
+    }
+}
+
+impl BanTarget {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            BanTarget::User => 0,
+            BanTarget::Burrow => 1,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<BanTarget> {
+        match code {
+            0 => Some(BanTarget::User),
+            1 => Some(BanTarget::Burrow),
+            _ => None,
+        }
+    }
+}
+
+impl BanState {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            BanState::Active => 0,
+            BanState::Expired => 1,
+            BanState::Revoked => 2,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<BanState> {
+        match code {
+            0 => Some(BanState::Active),
+            1 => Some(BanState::Expired),
+            2 => Some(BanState::Revoked),
+            _ => None,
+        }
+    }
+}
+
+impl LockoutTarget {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            LockoutTarget::User => "user",
+            LockoutTarget::Ip => "ip",
+        }
+    }
+
+    pub fn parse(target: &str) -> Option<LockoutTarget> {
+        match target {
+            "user" => Some(LockoutTarget::User),
+            "ip" => Some(LockoutTarget::Ip),
+            _ => None,
+        }
+    }
+}
+
+impl From<&admin_audit::Model> for AuditDisplay {
+    fn from(a: &admin_audit::Model) -> AuditDisplay {
+        AuditDisplay {
+            audit_id: a.audit_id,
+            uid: a.uid,
+            role: a.role,
+            operation: serde_json::from_str(&a.operation).ok(),
+            kind: a.operation_kind.to_owned(),
+            target_type: AuditTarget::from_i32(a.target_type),
+            target_id: a.target_id,
+            target_sub_id: a.target_sub_id,
+            status: a.status,
+            result: a.result.to_owned(),
+            create_time: a.create_time,
+        }
+    }
+}
+
+impl From<dead_letter::Model> for DeadLetterDisplay {
+    fn from(d: dead_letter::Model) -> DeadLetterDisplay {
+        DeadLetterDisplay {
+            id: d.id,
+            topic: d.topic,
+            payload: d.payload,
+            error: d.error,
+            attempts: d.attempts,
+            create_time: d.create_time,
+            replay_time: d.replay_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_admin_operation_target() {
+        let operation = AdminOperation::BanReply {
+            post_id: 1,
+            reply_id: 2,
+        };
+        assert_eq!("BanReply", operation.kind());
+        assert_eq!((1, 2), operation.target());
+        assert_eq!(AuditTarget::Reply, operation.target_type());
+        let operation = AdminOperation::SetAdminRole { uid: 3, role: 1 };
+        assert_eq!("SetAdminRole", operation.kind());
+        assert_eq!((3, 0), operation.target());
+        assert_eq!(AuditTarget::User, operation.target_type());
+        let operation = AdminOperation::GetUserId { burrow_id: 4 };
+        assert_eq!("GetUserId", operation.kind());
+        assert_eq!((4, 0), operation.target());
+        assert_eq!(AuditTarget::Burrow, operation.target_type());
+        let operation: AdminOperation = serde_json::from_str(
+            r#"{"BanBurrowFor":{"burrow_id":5,"duration":3600,"reason":"spam"}}"#,
+        )
+        .unwrap();
+        assert_eq!("BanBurrowFor", operation.kind());
+        assert_eq!((5, 0), operation.target());
+        assert_eq!(AuditTarget::Burrow, operation.target_type());
+    }
+
+    #[test]
+    fn test_ban_target_and_state() {
+        for target in [BanTarget::User, BanTarget::Burrow] {
+            assert_eq!(Some(target), BanTarget::from_i32(target.to_i32()));
+        }
+        assert_eq!(None, BanTarget::from_i32(2));
+        for state in [BanState::Active, BanState::Expired, BanState::Revoked] {
+            assert_eq!(Some(state), BanState::from_i32(state.to_i32()));
+        }
+        assert_eq!(None, BanState::from_i32(-1));
+        for target in [LockoutTarget::User, LockoutTarget::Ip] {
+            assert_eq!(Some(target), LockoutTarget::parse(target.to_str()));
+        }
+        assert_eq!(None, LockoutTarget::parse("burrow"));
+        for target in [
+            AuditTarget::User,
+            AuditTarget::Burrow,
+            AuditTarget::Post,
+            AuditTarget::Reply,
+            AuditTarget::FilterRule,
+            AuditTarget::Lockout,
+            AuditTarget::DeadLetter,
+        ] {
+            assert_eq!(Some(target), AuditTarget::from_i32(target.to_i32()));
+        }
+        assert_eq!(None, AuditTarget::from_i32(7));
+    }
+
+    #[test]
+    fn test_audit_display() {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let operation = AdminOperation::BanPost { post_id: 5 };
+        let model = admin_audit::Model {
+            audit_id: 1,
+            uid: 2,
+            role: 1,
+            operation: serde_json::to_string(&operation).unwrap(),
+            operation_kind: operation.kind().to_string(),
+            target_type: AuditTarget::Post.to_i32(),
+            target_id: 5,
+            target_sub_id: 0,
+            status: 200,
+            result: "Success".to_string(),
+            create_time: now,
+        };
+        let target = AuditDisplay {
+            audit_id: 1,
+            uid: 2,
+            role: 1,
+            operation: Some(AdminOperation::BanPost { post_id: 5 }),
+            kind: "BanPost".to_string(),
+            target_type: Some(AuditTarget::Post),
+            target_id: 5,
+            target_sub_id: 0,
+            status: 200,
+            result: "Success".to_string(),
+            create_time: now,
+        };
+        assert_eq!(target, (&model).into());
+        let model = admin_audit::Model {
+            operation: "{}".to_string(),
+            ..model
+        };
+        assert_eq!(None, AuditDisplay::from(&model).operation);
+        let model = admin_audit::Model {
+            target_type: -1,
+            ..model
+        };
+        assert_eq!(None, AuditDisplay::from(&model).target_type);
+    }
 }
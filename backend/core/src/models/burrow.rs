@@ -53,11 +53,14 @@ pub struct BurrowCreateResponse {
 /// - `title`: String, title of burrow
 /// - `description`: String, description of burrow
 /// - `posts`: Vec<Post>, information of posts in burrow
+/// - `next_cursor`: Option<String>, cursor to read the next page with, `None` if it is the last page
 #[derive(Serialize, Deserialize)]
 pub struct BurrowShowResponse {
     pub title: String,
     pub description: String,
     pub posts: Vec<Post>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 /// Burrow Metadata
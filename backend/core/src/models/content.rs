@@ -1,8 +1,12 @@
 //! Models for content
 
-use crate::db::{content_post, content_reply};
+use crate::config::content::QUOTE_PREVIEW_LEN;
+use crate::db::{content_post, content_reply, poll};
+use crate::utils::markdown::render_markdown;
 use rocket::serde::{Deserialize, Serialize};
-use sea_orm::{prelude::DateTimeWithTimeZone, FromQueryResult};
+use rocket::FromFormField;
+use sea_orm::{prelude::DateTimeWithTimeZone, DbErr, FromQueryResult, QueryResult};
+use std::collections::HashMap;
 
 /// Section of post
 ///
@@ -21,6 +25,34 @@ pub enum PostSection {
     NSFW,
 }
 
+/// Type of post, stored in `content_post.post_type`
+///
+/// ## Fields
+///
+/// - `PostType::Normal`: Post with text only
+/// - `PostType::Poll`: Post carrying a poll
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PostType {
+    Normal,
+    Poll,
+}
+
+/// View mode of replies when reading a post
+///
+/// ## Fields
+///
+/// - `ReplyView::Flat`: Replies in a single list ordered by reply id
+/// - `ReplyView::Nested`: Replies nested under the reply they answer
+/// - `ReplyView::Quoted`: Replies in a single list, each quoting the reply it answers
+///
+#[derive(Debug, PartialEq, Eq, Clone, Copy, FromFormField)]
+pub enum ReplyView {
+    Flat,
+    Nested,
+    Quoted,
+}
+
 /// Last Post Sequence Number
 ///
 /// ## Fields
@@ -76,6 +108,9 @@ pub struct ReplyCreateResponse {
 /// - `PostPage::page`: Page number of the post
 /// - `PostPage::like`: Flag indicating whether the user liked the post
 /// - `PostPage::collection`: Flag indicating whether the user collected the post
+/// - `PostPage::reply_thread`: Thread view of `reply_page`, only filled in nested or quoted view
+/// - `PostPage::poll`: Poll of the post with its tallies, if the post carries one
+/// - `PostPage::next_cursor`: Cursor to read the next page of replies with, `None` if it is the last page
 ///
 #[derive(Serialize, Deserialize)]
 pub struct PostPage {
@@ -84,6 +119,12 @@ pub struct PostPage {
     pub page: usize,
     pub like: bool,
     pub collection: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reply_thread: Vec<ReplyNode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<PollDisplay>,
+    #[serde(default)]
+    pub next_cursor: Option<String>,
 }
 
 /// Post general information for one page
@@ -92,11 +133,27 @@ pub struct PostPage {
 ///
 /// - `ListPage::post_page`: Post general information vector of the posts, with the length up to ten
 /// - `ListPage::page`: Page number of the list
+/// - `ListPage::next_cursor`: Cursor to read the next page with, `None` if it is the last page
 ///
 #[derive(Serialize, Deserialize)]
 pub struct ListPage {
     pub post_page: Vec<PostDisplay>,
     pub page: usize,
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+/// Posts of the home feed
+///
+/// ## Fields
+///
+/// - `FeedPage::post_page`: Post general information vector of the posts, newest first
+/// - `FeedPage::next_cursor`: Cursor to read the next page with, `None` if there are no more posts
+///
+#[derive(Serialize, Deserialize)]
+pub struct FeedPage {
+    pub post_page: Vec<PostDisplay>,
+    pub next_cursor: Option<String>,
 }
 
 /// Post create information of request
@@ -107,7 +164,8 @@ pub struct ListPage {
 /// - `PostInfo::burrow_id`: Burrow id of the post
 /// - `PostInfo::section`: Section of the post
 /// - `PostInfo::tag`: Tag of the post
-/// - `PostInfo::content`: Content of the post
+/// - `PostInfo::content`: Content of the post in Markdown
+/// - `PostInfo::poll`: Poll carried by the post, if any
 ///
 #[derive(Serialize, Deserialize)]
 pub struct PostInfo {
@@ -116,6 +174,36 @@ pub struct PostInfo {
     pub section: Vec<PostSection>,
     pub tag: Vec<String>,
     pub content: String,
+    #[serde(default)]
+    pub poll: Option<PollInfo>,
+}
+
+/// Poll create information of request
+///
+/// ## Fields
+///
+/// - `PollInfo::options`: Options of the poll
+/// - `PollInfo::multiple`: Flag indicating whether a voter can choose more than one option
+/// - `PollInfo::close_time`: Time after which no votes are accepted, open forever if not set
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PollInfo {
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub multiple: bool,
+    #[serde(default)]
+    pub close_time: Option<DateTimeWithTimeZone>,
+}
+
+/// Vote information of request
+///
+/// ## Fields
+///
+/// - `VoteInfo::choices`: Option ids chosen by the user, starting from 0
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct VoteInfo {
+    pub choices: Vec<i32>,
 }
 
 /// Post updated information of request
@@ -139,13 +227,16 @@ pub struct PostUpdateInfo {
 ///
 /// - `ReplyInfo::post_id`: Post id of the reply
 /// - `ReplyInfo::burrow_id`: Burrow id of the reply
-/// - `ReplyInfo::content`: Content of the reply
+/// - `ReplyInfo::content`: Content of the reply in Markdown
+/// - `ReplyInfo::parent_reply_id`: Reply id of the reply this one answers, if any
 ///
 #[derive(Deserialize)]
 pub struct ReplyInfo {
     pub post_id: i64,
     pub burrow_id: i64,
     pub content: String,
+    #[serde(default)]
+    pub parent_reply_id: Option<i32>,
 }
 
 /// Reply update information of request
@@ -154,7 +245,7 @@ pub struct ReplyInfo {
 ///
 /// - `ReplyUpdateInfo::post_id`: Post id of updated reply
 /// - `ReplyUpdateInfo::reply_id`: Reply id of updated reply
-/// - `ReplyUpdateInfo::content`: New content of updated reply
+/// - `ReplyUpdateInfo::content`: New content of updated reply in Markdown
 ///
 #[derive(Deserialize)]
 pub struct ReplyUpdateInfo {
@@ -180,6 +271,22 @@ pub struct PostDisplay {
     pub is_update: bool,
 }
 
+/// Read from a query of posts joined with the likes and collections of a user,
+/// see `routes::content::select_post_display`
+impl FromQueryResult for PostDisplay {
+    fn from_query_result(res: &QueryResult, pre: &str) -> Result<Self, DbErr> {
+        let post = content_post::Model::from_query_result(res, pre)?;
+        Ok(PostDisplay {
+            post: post.into(),
+            like: res.try_get(pre, "like")?,
+            collection: res.try_get(pre, "collection")?,
+            is_update: res
+                .try_get::<Option<bool>>(pre, "is_update")?
+                .unwrap_or(false),
+        })
+    }
+}
+
 /// Post information of database
 ///
 /// ## Fields
@@ -222,10 +329,12 @@ pub struct Post {
 /// - `Reply::burrow_id`: Burrow id of the reply
 /// - `Reply::create_time`: Created time of the reply
 /// - `Reply::update_time`: Updated time of the reply
-/// - `Reply::content`: Content of the reply
+/// - `Reply::content`: Content of the reply in Markdown
+/// - `Reply::content_html`: Sanitized HTML rendered from the content
 /// - `Reply::reply_state`: State of the post
+/// - `Reply::parent_reply_id`: Reply id of the reply this one answers, if any
 ///
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Reply {
     pub post_id: i64,
     pub reply_id: i32,
@@ -233,7 +342,90 @@ pub struct Reply {
     pub create_time: DateTimeWithTimeZone,
     pub update_time: DateTimeWithTimeZone,
     pub content: String,
+    #[serde(default)]
+    pub content_html: String,
     pub reply_state: i32,
+    #[serde(default)]
+    pub parent_reply_id: Option<i32>,
+}
+
+/// Quoted preview of the reply being answered
+///
+/// ## Fields
+///
+/// - `ReplyQuote::reply_id`: Reply id of the quoted reply
+/// - `ReplyQuote::burrow_id`: Burrow id of the quoted reply
+/// - `ReplyQuote::content`: Content of the quoted reply, truncated to `QUOTE_PREVIEW_LEN` chars
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ReplyQuote {
+    pub reply_id: i32,
+    pub burrow_id: i64,
+    pub content: String,
+}
+
+/// Reply in the thread view of a post
+///
+/// ## Fields
+///
+/// - `ReplyNode::reply`: Reply information
+/// - `ReplyNode::quote`: Preview of the answered reply, only filled in quoted view
+/// - `ReplyNode::children`: Replies answering this reply, only filled in nested view
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ReplyNode {
+    pub reply: Reply,
+    pub quote: Option<ReplyQuote>,
+    pub children: Vec<ReplyNode>,
+}
+
+/// Poll of a post
+///
+/// Only the number of votes of each option is shown, voters are never revealed.
+///
+/// ## Fields
+///
+/// - `PollDisplay::options`: Options of the poll with their tallies
+/// - `PollDisplay::multiple`: Flag indicating whether a voter can choose more than one option
+/// - `PollDisplay::close_time`: Time after which no votes are accepted
+/// - `PollDisplay::closed`: Flag indicating whether the poll has been closed
+/// - `PollDisplay::voted`: Options chosen by the user, empty if the user has not voted
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct PollDisplay {
+    pub options: Vec<PollOption>,
+    pub multiple: bool,
+    pub close_time: Option<DateTimeWithTimeZone>,
+    pub closed: bool,
+    pub voted: Vec<i32>,
+}
+
+/// Option of a poll
+///
+/// ## Fields
+///
+/// - `PollOption::option_id`: Id of the option, which is its index in the poll
+/// - `PollOption::content`: Content of the option
+/// - `PollOption::vote_num`: Number of votes of the option
+///
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct PollOption {
+    pub option_id: i32,
+    pub content: String,
+    pub vote_num: i64,
+}
+
+/// Number of votes of an option, aggregated from `poll_vote`
+///
+/// ## Fields
+///
+/// - `PollTally::option_id`: Id of the option
+/// - `PollTally::vote_num`: Number of votes of the option
+///
+#[derive(Debug, FromQueryResult)]
+pub struct PollTally {
+    pub option_id: i32,
+    pub vote_num: i64,
 }
 
 // pub struct GetPostList {}
@@ -272,6 +464,57 @@ pub struct Reply {
 //     }
 // }
 
+impl PostType {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            PostType::Normal => 0,
+            PostType::Poll => 1,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<PostType> {
+        match code {
+            0 => Some(PostType::Normal),
+            1 => Some(PostType::Poll),
+            _ => None,
+        }
+    }
+}
+
+impl PollDisplay {
+    /// Build the poll shown to a user from its tallies and the choices of the user
+    pub fn new(
+        poll: &poll::Model,
+        tallies: &[PollTally],
+        voted: Vec<i32>,
+        now: DateTimeWithTimeZone,
+    ) -> PollDisplay {
+        let contents: Vec<String> = serde_json::from_str(&poll.options).unwrap_or_default();
+        let options = contents
+            .into_iter()
+            .enumerate()
+            .map(|(i, content)| {
+                let option_id = i as i32;
+                PollOption {
+                    option_id,
+                    content,
+                    vote_num: tallies
+                        .iter()
+                        .find(|t| t.option_id == option_id)
+                        .map_or(0, |t| t.vote_num),
+                }
+            })
+            .collect();
+        PollDisplay {
+            options,
+            multiple: poll.multiple,
+            close_time: poll.close_time,
+            closed: poll.close_time.map_or(false, |t| t <= now),
+            voted,
+        }
+    }
+}
+
 impl std::fmt::Display for PostSection {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         std::fmt::Debug::fmt(self, f)
@@ -327,6 +570,16 @@ impl From<&content_post::Model> for Post {
     }
 }
 
+/// The stored HTML of a reply, rendered from its source for the replies written
+/// before HTML was stored, whose column was filled with an empty string
+fn stored_html(content_html: String, content: &str) -> String {
+    if content_html.is_empty() {
+        render_markdown(content)
+    } else {
+        content_html
+    }
+}
+
 impl From<content_reply::Model> for Reply {
     fn from(reply_info: content_reply::Model) -> Reply {
         Reply {
@@ -341,7 +594,14 @@ impl From<content_reply::Model> for Reply {
                     _ => "Admin has banned this reply".to_string(),
                 }
             },
+            content_html: {
+                match reply_info.reply_state {
+                    0 => stored_html(reply_info.content_html, &reply_info.content),
+                    _ => "<p>Admin has banned this reply</p>".to_string(),
+                }
+            },
             reply_state: reply_info.reply_state,
+            parent_reply_id: reply_info.parent_reply_id,
         }
     }
 }
@@ -360,11 +620,75 @@ impl From<&content_reply::Model> for Reply {
                     _ => "Admin has banned this reply".to_string(),
                 }
             },
+            content_html: {
+                match reply_info.reply_state {
+                    0 => stored_html(reply_info.content_html.to_owned(), &reply_info.content),
+                    _ => "<p>Admin has banned this reply</p>".to_string(),
+                }
+            },
             reply_state: reply_info.reply_state,
+            parent_reply_id: reply_info.parent_reply_id,
+        }
+    }
+}
+
+impl From<&Reply> for ReplyQuote {
+    fn from(reply: &Reply) -> ReplyQuote {
+        ReplyQuote {
+            reply_id: reply.reply_id,
+            burrow_id: reply.burrow_id,
+            content: reply.content.chars().take(QUOTE_PREVIEW_LEN).collect(),
         }
     }
 }
 
+/// Build the nested thread view of a page of replies
+///
+/// Replies whose parent is not in the page are placed at the top level,
+/// so every reply of the page appears exactly once.
+pub fn build_reply_thread(replies: Vec<Reply>) -> Vec<ReplyNode> {
+    let in_page: Vec<i32> = replies.iter().map(|r| r.reply_id).collect();
+    let mut roots: Vec<Reply> = Vec::new();
+    let mut children: HashMap<i32, Vec<Reply>> = HashMap::new();
+    for reply in replies {
+        match reply.parent_reply_id {
+            Some(parent) if in_page.contains(&parent) && parent != reply.reply_id => {
+                children.entry(parent).or_default().push(reply)
+            }
+            _ => roots.push(reply),
+        }
+    }
+    fn attach(reply: Reply, children: &mut HashMap<i32, Vec<Reply>>) -> ReplyNode {
+        let sub = children.remove(&reply.reply_id).unwrap_or_default();
+        ReplyNode {
+            reply,
+            quote: None,
+            children: sub.into_iter().map(|r| attach(r, children)).collect(),
+        }
+    }
+    roots
+        .into_iter()
+        .map(|r| attach(r, &mut children))
+        .collect()
+}
+
+/// Build the quoted thread view of a page of replies
+///
+/// `parents` holds the answered replies, which may lie outside the page.
+pub fn build_reply_quotes(replies: Vec<Reply>, parents: &HashMap<i32, Reply>) -> Vec<ReplyNode> {
+    replies
+        .into_iter()
+        .map(|reply| ReplyNode {
+            quote: reply
+                .parent_reply_id
+                .and_then(|parent| parents.get(&parent))
+                .map(|parent| parent.into()),
+            reply,
+            children: Vec::new(),
+        })
+        .collect()
+}
+
 impl From<LastPostSeq> for PostTotalCount {
     fn from(seq: LastPostSeq) -> PostTotalCount {
         PostTotalCount {
@@ -475,6 +799,7 @@ mod tests {
         let reply_id: i32 = 2;
         let burrow_id: i64 = 666;
         let content = "content".to_string();
+        let content_html = "<p>content</p>\n".to_string();
         let reply_state: i32 = 0;
         let reply_banned_state: i32 = 1;
         let reply_data = Reply {
@@ -484,7 +809,9 @@ mod tests {
             create_time: now,
             update_time: now,
             content: content.clone(),
+            content_html: content_html.clone(),
             reply_state,
+            parent_reply_id: Some(1),
         };
         let reply_banned_data = Reply {
             post_id,
@@ -493,7 +820,9 @@ mod tests {
             create_time: now,
             update_time: now,
             content: "Admin has banned this reply".to_string(),
+            content_html: "<p>Admin has banned this reply</p>".to_string(),
             reply_state: reply_banned_state,
+            parent_reply_id: None,
         };
         let reply_info = content_reply::Model {
             post_id,
@@ -502,8 +831,10 @@ mod tests {
             create_time: now,
             update_time: now,
             content: content.clone(),
+            content_html: content_html.clone(),
             reply_state,
             permission: 0,
+            parent_reply_id: Some(1),
         };
         let reply_banned_info = content_reply::Model {
             post_id,
@@ -512,8 +843,10 @@ mod tests {
             create_time: now,
             update_time: now,
             content,
+            content_html,
             reply_state: reply_banned_state,
             permission: 0,
+            parent_reply_id: None,
         };
         let reply_info_ref = &reply_info;
         let reply_banned_info_ref = &reply_banned_info;
@@ -521,6 +854,131 @@ mod tests {
         assert_eq!(reply_data, reply_info_ref.into());
         assert_eq!(reply_banned_data, reply_banned_info_ref.into());
         assert_eq!(reply_banned_data, reply_banned_info.into());
+        // replies written before HTML was stored are rendered on read
+        let legacy_info = content_reply::Model {
+            content_html: String::new(),
+            ..reply_info
+        };
+        assert_eq!(reply_data, (&legacy_info).into());
+        assert_eq!(reply_data, legacy_info.into());
+    }
+
+    #[test]
+    fn test_poll_display() {
+        for post_type in [PostType::Normal, PostType::Poll] {
+            assert_eq!(Some(post_type), PostType::from_i32(post_type.to_i32()));
+        }
+        assert_eq!(None, PostType::from_i32(2));
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let poll_info = poll::Model {
+            post_id: 1,
+            options: serde_json::to_string(&["a", "b", "c"]).unwrap(),
+            multiple: true,
+            close_time: Some(now),
+        };
+        let tallies = vec![
+            PollTally {
+                option_id: 0,
+                vote_num: 3,
+            },
+            PollTally {
+                option_id: 2,
+                vote_num: 1,
+            },
+        ];
+        let target = PollDisplay {
+            options: vec![
+                PollOption {
+                    option_id: 0,
+                    content: "a".to_string(),
+                    vote_num: 3,
+                },
+                PollOption {
+                    option_id: 1,
+                    content: "b".to_string(),
+                    vote_num: 0,
+                },
+                PollOption {
+                    option_id: 2,
+                    content: "c".to_string(),
+                    vote_num: 1,
+                },
+            ],
+            multiple: true,
+            close_time: Some(now),
+            closed: true,
+            voted: vec![0, 2],
+        };
+        assert_eq!(
+            target,
+            PollDisplay::new(&poll_info, &tallies, vec![0, 2], now)
+        );
+        let poll_info = poll::Model {
+            close_time: None,
+            ..poll_info
+        };
+        assert!(!PollDisplay::new(&poll_info, &tallies, Vec::new(), now).closed);
+    }
+
+    fn build_reply(reply_id: i32, parent_reply_id: Option<i32>) -> Reply {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        Reply {
+            post_id: 1,
+            reply_id,
+            burrow_id: 666,
+            create_time: now,
+            update_time: now,
+            content: format!("reply {}", reply_id),
+            content_html: format!("<p>reply {}</p>\n", reply_id),
+            reply_state: 0,
+            parent_reply_id,
+        }
+    }
+
+    #[test]
+    fn test_reply_thread() {
+        let replies = vec![
+            build_reply(0, None),
+            build_reply(1, None),
+            build_reply(2, Some(1)),
+            build_reply(3, Some(2)),
+            build_reply(4, Some(1)),
+            build_reply(5, Some(99)),
+        ];
+        let thread = build_reply_thread(replies);
+        let ids: Vec<i32> = thread.iter().map(|n| n.reply.reply_id).collect();
+        assert_eq!(vec![0, 1, 5], ids);
+        let ids: Vec<i32> = thread[1]
+            .children
+            .iter()
+            .map(|n| n.reply.reply_id)
+            .collect();
+        assert_eq!(vec![2, 4], ids);
+        assert_eq!(3, thread[1].children[0].children[0].reply.reply_id);
+        assert!(thread[0].children.is_empty());
+        assert!(thread.iter().all(|n| n.quote.is_none()));
+    }
+
+    #[test]
+    fn test_reply_quotes() {
+        let mut parent = build_reply(1, None);
+        parent.content = "a".repeat(QUOTE_PREVIEW_LEN + 10);
+        let parents: HashMap<i32, Reply> = vec![(1, parent)].into_iter().collect();
+        let quoted = build_reply_quotes(
+            vec![
+                build_reply(2, Some(1)),
+                build_reply(3, None),
+                build_reply(4, Some(7)),
+            ],
+            &parents,
+        );
+        assert_eq!(3, quoted.len());
+        let quote = quoted[0].quote.as_ref().unwrap();
+        assert_eq!(1, quote.reply_id);
+        assert_eq!(QUOTE_PREVIEW_LEN, quote.content.chars().count());
+        assert_eq!(None, quoted[1].quote);
+        assert_eq!(None, quoted[2].quote);
+        assert!(quoted.iter().all(|n| n.children.is_empty()));
     }
 
     #[test]
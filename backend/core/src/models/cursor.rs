@@ -0,0 +1,71 @@
+//! Models of cursor for keyset pagination
+
+use rocket::serde::{Deserialize, Serialize};
+
+/// Position in a list after which the next page starts
+///
+/// Frontend receives it as an opaque token and passes it back as the `cursor`
+/// query parameter. Lists still accept `page` when no cursor is given.
+///
+/// ## Fields
+///
+/// - `Cursor::Post`: Position in a list ordered by post id
+/// - `Cursor::Reply`: Position in the replies of a post, ordered by reply id
+/// - `Cursor::Burrow`: Position in a list ordered by burrow id
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cursor {
+    Post { post_id: i64 },
+    Reply { post_id: i64, reply_id: i32 },
+    Burrow { burrow_id: i64 },
+}
+
+impl Cursor {
+    /// Encode the cursor into an opaque token
+    pub fn encode(&self) -> String {
+        hex::encode(serde_json::to_string(self).unwrap())
+    }
+
+    /// Decode a token, `None` if it is not produced by `Cursor::encode`
+    pub fn decode(token: &str) -> Option<Cursor> {
+        hex::decode(token)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    }
+
+    /// Token of the next page, `None` if the page is not full, which means it is the last one
+    pub fn next_token(len: usize, per_page: usize, last: Option<Cursor>) -> Option<String> {
+        match last {
+            Some(cursor) if len >= per_page => Some(cursor.encode()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        for cursor in [
+            Cursor::Post { post_id: 10 },
+            Cursor::Reply {
+                post_id: 10,
+                reply_id: 3,
+            },
+            Cursor::Burrow { burrow_id: 5 },
+        ] {
+            assert_eq!(Some(cursor), Cursor::decode(&cursor.encode()));
+        }
+        assert_eq!(None, Cursor::decode("not a cursor"));
+        assert_eq!(None, Cursor::decode(&hex::encode("{}")));
+        let cursor = Cursor::Post { post_id: 1 };
+        assert_eq!(
+            Some(cursor.encode()),
+            Cursor::next_token(2, 2, Some(cursor))
+        );
+        assert_eq!(None, Cursor::next_token(1, 2, Some(cursor)));
+        assert_eq!(None, Cursor::next_token(0, 0, None));
+    }
+}
@@ -1,5 +1,9 @@
 //! Error Response of all the public api interfaces
 
+use rocket::http::Status;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::Request;
 use serde::{Deserialize, Serialize};
 
 /// ErrorCode for all the public interfaces
@@ -43,6 +47,38 @@ pub enum ErrorCode {
     UnsupportedMediaType,
     /// 404 NotFound
     FileNotExist,
+    /// 409 Conflict
+    ReportDuplicate,
+    /// 404 NotFound
+    ReportNotExist,
+    /// 400 BadRequest
+    ContentSensitive,
+    /// 400 BadRequest
+    FilterRuleInvalid,
+    /// 400 BadRequest
+    ImageInvalid,
+    /// 400 BadRequest
+    PollInvalid,
+    /// 403 Forbidden
+    PollClosed,
+    /// 400 BadRequest
+    VoteDuplicate,
+    /// 400 BadRequest
+    CursorInvalid,
+    /// 404 NotFound
+    SessionNotExist,
+    /// 400 BadRequest
+    TotpInvalid,
+    /// 400 BadRequest
+    TotpEnabled,
+    /// 403 Forbidden
+    TotpRequired,
+    /// 404 NotFound
+    DeadLetterNotExist,
+    /// 400 BadRequest
+    BanDurationInvalid,
+    /// 400 BadRequest
+    ContentTooLong,
     /// 500 InternalServerError
     Unknown,
     None,
@@ -96,6 +132,34 @@ impl Default for ErrorResponse {
     }
 }
 
+/// Response of a rate-limited request
+///
+/// Responds with status 429, `ErrorCode::RateLimit` and a `Retry-After` header
+/// telling the client how many seconds to wait.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitResponse {
+    pub retry_after: i64,
+    pub error: ErrorResponse,
+}
+
+impl RateLimitResponse {
+    pub fn build<T: Into<String>>(retry_after: i64, message: T) -> Self {
+        RateLimitResponse {
+            retry_after: retry_after.max(1),
+            error: ErrorResponse::build(ErrorCode::RateLimit, message),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for RateLimitResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(Json(self.error).respond_to(request)?)
+            .status(Status::TooManyRequests)
+            .raw_header("Retry-After", self.retry_after.to_string())
+            .ok()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,6 +370,166 @@ mod tests {
                 },
             }
         );
+        let error = ErrorResponse::build(ErrorCode::ReportDuplicate, "ReportDuplicate");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::ReportDuplicate,
+                    message: String::from("ReportDuplicate"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::ReportNotExist, "ReportNotExist");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::ReportNotExist,
+                    message: String::from("ReportNotExist"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::ContentSensitive, "ContentSensitive");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::ContentSensitive,
+                    message: String::from("ContentSensitive"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::FilterRuleInvalid, "FilterRuleInvalid");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::FilterRuleInvalid,
+                    message: String::from("FilterRuleInvalid"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::ImageInvalid, "ImageInvalid");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::ImageInvalid,
+                    message: String::from("ImageInvalid"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::PollInvalid, "PollInvalid");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::PollInvalid,
+                    message: String::from("PollInvalid"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::PollClosed, "PollClosed");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::PollClosed,
+                    message: String::from("PollClosed"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::VoteDuplicate, "VoteDuplicate");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::VoteDuplicate,
+                    message: String::from("VoteDuplicate"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::CursorInvalid, "CursorInvalid");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::CursorInvalid,
+                    message: String::from("CursorInvalid"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::SessionNotExist, "SessionNotExist");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::SessionNotExist,
+                    message: String::from("SessionNotExist"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::TotpInvalid, "TotpInvalid");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::TotpInvalid,
+                    message: String::from("TotpInvalid"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::TotpEnabled, "TotpEnabled");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::TotpEnabled,
+                    message: String::from("TotpEnabled"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::TotpRequired, "TotpRequired");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::TotpRequired,
+                    message: String::from("TotpRequired"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::DeadLetterNotExist, "DeadLetterNotExist");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::DeadLetterNotExist,
+                    message: String::from("DeadLetterNotExist"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::BanDurationInvalid, "BanDurationInvalid");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::BanDurationInvalid,
+                    message: String::from("BanDurationInvalid"),
+                },
+            }
+        );
+        let error = ErrorResponse::build(ErrorCode::ContentTooLong, "ContentTooLong");
+        assert_eq!(
+            error,
+            ErrorResponse {
+                error: ErrorMessage {
+                    code: ErrorCode::ContentTooLong,
+                    message: String::from("ContentTooLong"),
+                },
+            }
+        );
         let error = ErrorResponse::build(ErrorCode::Unknown, "Unknown");
         assert_eq!(
             error,
@@ -327,4 +551,12 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_rate_limit_response() {
+        let response = RateLimitResponse::build(30, "Too many requests.");
+        assert_eq!(30, response.retry_after);
+        assert_eq!(ErrorCode::RateLimit, response.error.error.code);
+        assert_eq!(1, RateLimitResponse::build(0, "").retry_after);
+    }
 }
@@ -0,0 +1,141 @@
+//! Models for sensitive word filter
+
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+use crate::db::sensitive_word;
+
+/// Action taken when a rule of the filter matches
+///
+/// ## Fields
+///
+/// - `FilterAction::Reject`: Reject the content with `ErrorCode::ContentSensitive`
+/// - `FilterAction::Mask`: Replace every char of the matched text with `*`
+/// - `FilterAction::Flag`: Keep the content and report it for review by admins
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FilterAction {
+    Reject,
+    Mask,
+    Flag,
+}
+
+/// Rule of the sensitive word filter
+///
+/// ## Fields
+///
+/// - `pattern`: String, the keyword, or the regular expression if `is_regex` is set
+/// - `is_regex`: bool, whether `pattern` is a regular expression
+/// - `action`: FilterAction, action taken when the rule matches
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct FilterRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub is_regex: bool,
+    pub action: FilterAction,
+}
+
+/// Rule of the sensitive word filter stored in database
+///
+/// ## Fields
+///
+/// - `word_id`: i64, id of the rule
+/// - `rule`: FilterRule, the rule
+/// - `uid`: i64, uid of the admin who added the rule
+/// - `create_time`: DateTimeWithTimeZone, created time of the rule
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct FilterRuleDisplay {
+    pub word_id: i64,
+    pub rule: FilterRule,
+    pub uid: i64,
+    pub create_time: DateTimeWithTimeZone,
+}
+
+impl FilterAction {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            FilterAction::Reject => 0,
+            FilterAction::Mask => 1,
+            FilterAction::Flag => 2,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<FilterAction> {
+        match code {
+            0 => Some(FilterAction::Reject),
+            1 => Some(FilterAction::Mask),
+            2 => Some(FilterAction::Flag),
+            _ => None,
+        }
+    }
+}
+
+impl From<&sensitive_word::Model> for FilterRule {
+    fn from(w: &sensitive_word::Model) -> FilterRule {
+        FilterRule {
+            pattern: w.pattern.to_owned(),
+            is_regex: w.is_regex,
+            // unknown actions are written by newer versions only, be strict with them
+            action: FilterAction::from_i32(w.action).unwrap_or(FilterAction::Reject),
+        }
+    }
+}
+
+impl From<&sensitive_word::Model> for FilterRuleDisplay {
+    fn from(w: &sensitive_word::Model) -> FilterRuleDisplay {
+        FilterRuleDisplay {
+            word_id: w.word_id,
+            rule: w.into(),
+            uid: w.uid,
+            create_time: w.create_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_filter_action() {
+        for action in [FilterAction::Reject, FilterAction::Mask, FilterAction::Flag] {
+            assert_eq!(Some(action), FilterAction::from_i32(action.to_i32()));
+        }
+        assert_eq!(None, FilterAction::from_i32(3));
+    }
+
+    #[test]
+    fn test_filter_rule() {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let model = sensitive_word::Model {
+            word_id: 1,
+            pattern: "spam".to_string(),
+            is_regex: false,
+            action: 1,
+            uid: 2,
+            create_time: now,
+        };
+        let target = FilterRuleDisplay {
+            word_id: 1,
+            rule: FilterRule {
+                pattern: "spam".to_string(),
+                is_regex: false,
+                action: FilterAction::Mask,
+            },
+            uid: 2,
+            create_time: now,
+        };
+        assert_eq!(target, (&model).into());
+        assert_eq!(
+            FilterRule {
+                pattern: "a+".to_string(),
+                is_regex: false,
+                action: FilterAction::Flag,
+            },
+            serde_json::from_str::<FilterRule>(r#"{"pattern":"a+","action":"Flag"}"#).unwrap()
+        );
+    }
+}
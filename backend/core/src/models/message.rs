@@ -0,0 +1,130 @@
+//! Models for private message between burrows
+
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::{prelude::DateTimeWithTimeZone, FromQueryResult};
+
+use crate::db::message;
+
+/// Message create information of request
+///
+/// ## Fields
+///
+/// - `MessageInfo::sender_burrow`: Burrow id of the sender, must be owned by the user
+/// - `MessageInfo::receiver_burrow`: Burrow id of the receiver
+/// - `MessageInfo::content`: Content of the message
+///
+#[derive(Serialize, Deserialize)]
+pub struct MessageInfo {
+    pub sender_burrow: i64,
+    pub receiver_burrow: i64,
+    pub content: String,
+}
+
+/// Message Create Response
+///
+/// ## Fields
+///
+/// - `MessageCreateResponse::message_id`: Message id of created message
+///
+#[derive(Serialize, Deserialize)]
+pub struct MessageCreateResponse {
+    pub message_id: i64,
+}
+
+/// Message information
+///
+/// ## Fields
+///
+/// - `MessageDisplay::message_id`: Message id of the message
+/// - `MessageDisplay::sender_burrow`: Burrow id of the sender
+/// - `MessageDisplay::receiver_burrow`: Burrow id of the receiver
+/// - `MessageDisplay::content`: Content of the message
+/// - `MessageDisplay::is_read`: Flag indicating whether the receiver has read the message
+/// - `MessageDisplay::create_time`: Created time of the message
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MessageDisplay {
+    pub message_id: i64,
+    pub sender_burrow: i64,
+    pub receiver_burrow: i64,
+    pub content: String,
+    pub is_read: bool,
+    pub create_time: DateTimeWithTimeZone,
+}
+
+/// Conversation between a burrow and one of its peers
+///
+/// ## Fields
+///
+/// - `ConversationDisplay::peer_burrow`: Burrow id of the other side of the conversation
+/// - `ConversationDisplay::message_id`: Message id of the latest message
+/// - `ConversationDisplay::sender_burrow`: Sender of the latest message
+/// - `ConversationDisplay::receiver_burrow`: Receiver of the latest message
+/// - `ConversationDisplay::content`: Content of the latest message
+/// - `ConversationDisplay::create_time`: Created time of the latest message
+/// - `ConversationDisplay::unread`: Number of unread messages sent by the peer
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, FromQueryResult)]
+pub struct ConversationDisplay {
+    pub peer_burrow: i64,
+    pub message_id: i64,
+    pub sender_burrow: i64,
+    pub receiver_burrow: i64,
+    pub content: String,
+    pub create_time: DateTimeWithTimeZone,
+    pub unread: i64,
+}
+
+/// Block information of request
+///
+/// ## Fields
+///
+/// - `BlockInfo::burrow_id`: Burrow id owned by the user
+/// - `BlockInfo::blocked_burrow_id`: Burrow id which can no longer send messages to `burrow_id`
+///
+#[derive(Serialize, Deserialize)]
+pub struct BlockInfo {
+    pub burrow_id: i64,
+    pub blocked_burrow_id: i64,
+}
+
+impl From<&message::Model> for MessageDisplay {
+    fn from(m: &message::Model) -> MessageDisplay {
+        MessageDisplay {
+            message_id: m.message_id,
+            sender_burrow: m.sender_burrow,
+            receiver_burrow: m.receiver_burrow,
+            content: m.content.to_owned(),
+            is_read: m.is_read,
+            create_time: m.create_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_message_display() {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let model = message::Model {
+            message_id: 1,
+            sender_burrow: 2,
+            receiver_burrow: 3,
+            content: "hello".to_string(),
+            is_read: false,
+            create_time: now,
+        };
+        let target = MessageDisplay {
+            message_id: 1,
+            sender_burrow: 2,
+            receiver_burrow: 3,
+            content: "hello".to_string(),
+            is_read: false,
+            create_time: now,
+        };
+        assert_eq!(target, (&model).into());
+    }
+}
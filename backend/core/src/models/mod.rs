@@ -3,8 +3,14 @@
 pub mod admin;
 pub mod burrow;
 pub mod content;
+pub mod cursor;
 pub mod error;
+pub mod filter;
+pub mod message;
+pub mod notification;
 pub mod pulsar;
+pub mod report;
 pub mod search;
+pub mod session;
 pub mod storage;
 pub mod user;
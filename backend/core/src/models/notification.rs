@@ -0,0 +1,165 @@
+//! Models for notification
+
+use rocket::serde::{Deserialize, Serialize};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+use crate::db::notification;
+
+/// Kind of notification
+///
+/// ## Fields
+///
+/// - `NotificationKind::Reply`: Someone replied to a post of the user
+/// - `NotificationKind::ReplyToReply`: Someone replied to a reply of the user
+/// - `NotificationKind::FollowedPost`: A burrow followed by the user has a new post
+/// - `NotificationKind::Like`: Someone liked a post of the user
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NotificationKind {
+    Reply,
+    ReplyToReply,
+    FollowedPost,
+    Like,
+}
+
+/// Notification information
+///
+/// ## Fields
+///
+/// - `NotificationDisplay::notification_id`: Id of the notification
+/// - `NotificationDisplay::kind`: Kind of the notification
+/// - `NotificationDisplay::burrow_id`: Burrow id of the reply or post which triggered the notification
+/// - `NotificationDisplay::post_id`: Post id related to the notification
+/// - `NotificationDisplay::reply_id`: Reply id related to the notification, 0 if not a reply
+/// - `NotificationDisplay::is_read`: Flag indicating whether the notification has been read
+/// - `NotificationDisplay::create_time`: Created time of the notification
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct NotificationDisplay {
+    pub notification_id: i64,
+    pub kind: NotificationKind,
+    pub burrow_id: i64,
+    pub post_id: i64,
+    pub reply_id: i32,
+    pub is_read: bool,
+    pub create_time: DateTimeWithTimeZone,
+}
+
+/// Number of unread notifications
+///
+/// ## Fields
+///
+/// - `NotificationUnread::unread`: Number of unread notifications
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct NotificationUnread {
+    pub unread: usize,
+}
+
+/// Notifications to be marked as read
+///
+/// Example of request of frontend: `"All"` or `{"Ids": [1, 2]}`
+///
+/// ## Fields
+///
+/// - `NotificationReadInfo::All`: Mark all notifications of the user as read
+/// - `NotificationReadInfo::Ids`: Mark notifications with these ids as read
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum NotificationReadInfo {
+    All,
+    Ids(Vec<i64>),
+}
+
+impl NotificationKind {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            NotificationKind::Reply => 0,
+            NotificationKind::ReplyToReply => 1,
+            NotificationKind::FollowedPost => 2,
+            NotificationKind::Like => 3,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<NotificationKind> {
+        match code {
+            0 => Some(NotificationKind::Reply),
+            1 => Some(NotificationKind::ReplyToReply),
+            2 => Some(NotificationKind::FollowedPost),
+            3 => Some(NotificationKind::Like),
+            _ => None,
+        }
+    }
+}
+
+impl From<&notification::Model> for NotificationDisplay {
+    fn from(n: &notification::Model) -> NotificationDisplay {
+        NotificationDisplay {
+            notification_id: n.notification_id,
+            // unknown kinds are written by newer versions only, treat them as replies
+            kind: NotificationKind::from_i32(n.notification_type)
+                .unwrap_or(NotificationKind::Reply),
+            burrow_id: n.burrow_id,
+            post_id: n.post_id,
+            reply_id: n.reply_id,
+            is_read: n.is_read,
+            create_time: n.create_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_notification_kind() {
+        for kind in [
+            NotificationKind::Reply,
+            NotificationKind::ReplyToReply,
+            NotificationKind::FollowedPost,
+            NotificationKind::Like,
+        ] {
+            assert_eq!(Some(kind), NotificationKind::from_i32(kind.to_i32()));
+        }
+        assert_eq!(None, NotificationKind::from_i32(-1));
+    }
+
+    #[test]
+    fn test_notification() {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let model = notification::Model {
+            notification_id: 1,
+            uid: 2,
+            notification_type: NotificationKind::Like.to_i32(),
+            burrow_id: 3,
+            post_id: 4,
+            reply_id: 0,
+            is_read: false,
+            create_time: now,
+        };
+        let target = NotificationDisplay {
+            notification_id: 1,
+            kind: NotificationKind::Like,
+            burrow_id: 3,
+            post_id: 4,
+            reply_id: 0,
+            is_read: false,
+            create_time: now,
+        };
+        assert_eq!(target, (&model).into());
+    }
+
+    #[test]
+    fn test_notification_read_info() {
+        assert_eq!(
+            NotificationReadInfo::All,
+            serde_json::from_str::<NotificationReadInfo>(r#""All""#).unwrap()
+        );
+        assert_eq!(
+            NotificationReadInfo::Ids(vec![1, 2]),
+            serde_json::from_str::<NotificationReadInfo>(r#"{"Ids":[1,2]}"#).unwrap()
+        );
+    }
+}
@@ -114,6 +114,9 @@ pub enum RelationData {
 }
 
 /// Wrap RelationData with uid
+///
+/// `PulsarRelationData::Vote` carries uid, post id and the chosen options of a poll,
+/// which are checked before sent.
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum PulsarRelationData {
     ActivateLike(i64, i64),
@@ -122,6 +125,32 @@ pub enum PulsarRelationData {
     DeactivateCollection(i64, i64),
     ActivateFollow(i64, i64),
     DeactivateFollow(i64, i64),
+    Vote(i64, i64, Vec<i32>),
+}
+
+/// Event sent to task executor to notify users
+///
+/// ## Fields
+///
+/// - `PulsarNotificationData::NewReply`: A reply is created, notify the post author and the author of the answered reply
+/// - `PulsarNotificationData::NewPost`: A post is created, notify the followers of the burrow
+/// - `PulsarNotificationData::NewLike`: A post is liked by `uid`, notify the post author
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PulsarNotificationData {
+    NewReply {
+        post_id: i64,
+        reply_id: i32,
+        burrow_id: i64,
+        parent_reply_id: Option<i32>,
+    },
+    NewPost {
+        post_id: i64,
+        burrow_id: i64,
+    },
+    NewLike {
+        uid: i64,
+        post_id: i64,
+    },
 }
 
 /// Email operation sent to task executor
@@ -213,6 +242,24 @@ impl DeserializeMessage for PulsarSendEmail {
     }
 }
 
+impl SerializeMessage for PulsarNotificationData {
+    fn serialize_message(input: Self) -> Result<producer::Message, PulsarError> {
+        let payload = serde_json::to_vec(&input).map_err(|e| PulsarError::Custom(e.to_string()))?;
+        Ok(producer::Message {
+            payload,
+            ..Default::default()
+        })
+    }
+}
+
+impl DeserializeMessage for PulsarNotificationData {
+    type Output = Result<PulsarNotificationData, serde_json::Error>;
+
+    fn deserialize_message(payload: &Payload) -> Self::Output {
+        serde_json::from_slice(&payload.data)
+    }
+}
+
 impl From<PulsarSearchBurrowData> for TypesenseBurrowData {
     fn from(burrow: PulsarSearchBurrowData) -> TypesenseBurrowData {
         TypesenseBurrowData {
@@ -0,0 +1,320 @@
+//! Models for report
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::FromFormField;
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+use crate::db::report;
+use crate::models::admin::AdminOperation;
+
+/// Target of a report
+///
+/// Example of request of frontend: `{"Reply": {"post_id": 1, "reply_id": 2}}`
+///
+/// ## Fields
+///
+/// - `ReportTarget::Post`: Report a post
+/// - `ReportTarget::Reply`: Report a reply of a post
+/// - `ReportTarget::Burrow`: Report a burrow
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReportTarget {
+    Post { post_id: i64 },
+    Reply { post_id: i64, reply_id: i32 },
+    Burrow { burrow_id: i64 },
+}
+
+/// Reason of a report
+///
+/// `ReportReason::AutoFlagged` is only used by reports created by the sensitive word filter.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReportReason {
+    Spam,
+    Harassment,
+    Pornography,
+    Illegal,
+    Other,
+    AutoFlagged,
+}
+
+/// State of a report
+///
+/// ## Fields
+///
+/// - `ReportState::Pending`: Waiting for an admin to handle it
+/// - `ReportState::Banned`: The target has been banned
+/// - `ReportState::Dismissed`: The report has been dismissed
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, FromFormField)]
+pub enum ReportState {
+    Pending,
+    Banned,
+    Dismissed,
+}
+
+/// Resolution of a report chosen by admin
+///
+/// Example of request of frontend: `"Ban"` or `"Dismiss"`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReportResolution {
+    Ban,
+    Dismiss,
+}
+
+/// Report information submitted by user
+///
+/// ## Fields
+///
+/// - `target`: ReportTarget, what is being reported
+/// - `reason`: ReportReason, why it is being reported
+/// - `description`: String, optional detail of the report
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReportInfo {
+    pub target: ReportTarget,
+    pub reason: ReportReason,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Report shown in the moderation queue
+///
+/// ## Fields
+///
+/// - `report_id`: i64, id of the report
+/// - `uid`: i64, uid of the reporter
+/// - `target`: ReportTarget, what is being reported
+/// - `reason`: ReportReason, why it is being reported
+/// - `description`: String, detail of the report
+/// - `state`: ReportState, state of the report
+/// - `operation`: AdminOperation, the ban operation which resolves the report
+/// - `operator`: i64, uid of the admin who resolved the report, 0 if pending
+/// - `create_time`: DateTimeWithTimeZone, created time of the report
+/// - `resolve_time`: Option<DateTimeWithTimeZone>, resolved time of the report
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ReportDisplay {
+    pub report_id: i64,
+    pub uid: i64,
+    pub target: ReportTarget,
+    pub reason: ReportReason,
+    pub description: String,
+    pub state: ReportState,
+    pub operation: AdminOperation,
+    pub operator: i64,
+    pub create_time: DateTimeWithTimeZone,
+    pub resolve_time: Option<DateTimeWithTimeZone>,
+}
+
+impl ReportTarget {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            ReportTarget::Post { .. } => 0,
+            ReportTarget::Reply { .. } => 1,
+            ReportTarget::Burrow { .. } => 2,
+        }
+    }
+
+    /// Ids stored in the report table, in the order of `(post_id, reply_id, burrow_id)`
+    pub fn to_ids(self) -> (i64, i32, i64) {
+        match self {
+            ReportTarget::Post { post_id } => (post_id, 0, 0),
+            ReportTarget::Reply { post_id, reply_id } => (post_id, reply_id, 0),
+            ReportTarget::Burrow { burrow_id } => (0, 0, burrow_id),
+        }
+    }
+
+    pub fn from_ids(
+        target_type: i32,
+        post_id: i64,
+        reply_id: i32,
+        burrow_id: i64,
+    ) -> Option<ReportTarget> {
+        match target_type {
+            0 => Some(ReportTarget::Post { post_id }),
+            1 => Some(ReportTarget::Reply { post_id, reply_id }),
+            2 => Some(ReportTarget::Burrow { burrow_id }),
+            _ => None,
+        }
+    }
+
+    /// The admin operation that bans the reported target
+    pub fn to_admin_operation(self) -> AdminOperation {
+        match self {
+            ReportTarget::Post { post_id } => AdminOperation::BanPost { post_id },
+            ReportTarget::Reply { post_id, reply_id } => {
+                AdminOperation::BanReply { post_id, reply_id }
+            }
+            ReportTarget::Burrow { burrow_id } => AdminOperation::BanBurrow { burrow_id },
+        }
+    }
+}
+
+impl ReportReason {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            ReportReason::Spam => 0,
+            ReportReason::Harassment => 1,
+            ReportReason::Pornography => 2,
+            ReportReason::Illegal => 3,
+            ReportReason::Other => 4,
+            ReportReason::AutoFlagged => 5,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<ReportReason> {
+        match code {
+            0 => Some(ReportReason::Spam),
+            1 => Some(ReportReason::Harassment),
+            2 => Some(ReportReason::Pornography),
+            3 => Some(ReportReason::Illegal),
+            4 => Some(ReportReason::Other),
+            5 => Some(ReportReason::AutoFlagged),
+            _ => None,
+        }
+    }
+}
+
+impl ReportState {
+    pub fn to_i32(self) -> i32 {
+        match self {
+            ReportState::Pending => 0,
+            ReportState::Banned => 1,
+            ReportState::Dismissed => 2,
+        }
+    }
+
+    pub fn from_i32(code: i32) -> Option<ReportState> {
+        match code {
+            0 => Some(ReportState::Pending),
+            1 => Some(ReportState::Banned),
+            2 => Some(ReportState::Dismissed),
+            _ => None,
+        }
+    }
+}
+
+impl From<&report::Model> for ReportDisplay {
+    fn from(r: &report::Model) -> ReportDisplay {
+        // rows are only written through ReportTarget, fall back to post for safety
+        let target = ReportTarget::from_ids(r.target_type, r.post_id, r.reply_id, r.burrow_id)
+            .unwrap_or(ReportTarget::Post { post_id: r.post_id });
+        ReportDisplay {
+            report_id: r.report_id,
+            uid: r.uid,
+            target,
+            reason: ReportReason::from_i32(r.reason).unwrap_or(ReportReason::Other),
+            description: r.description.to_owned(),
+            state: ReportState::from_i32(r.report_state).unwrap_or(ReportState::Pending),
+            operation: target.to_admin_operation(),
+            operator: r.operator,
+            create_time: r.create_time,
+            resolve_time: r.resolve_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_report_target() {
+        for target in [
+            ReportTarget::Post { post_id: 1 },
+            ReportTarget::Reply {
+                post_id: 1,
+                reply_id: 2,
+            },
+            ReportTarget::Burrow { burrow_id: 3 },
+        ] {
+            let (post_id, reply_id, burrow_id) = target.to_ids();
+            assert_eq!(
+                Some(target),
+                ReportTarget::from_ids(target.to_i32(), post_id, reply_id, burrow_id)
+            );
+        }
+        assert_eq!(None, ReportTarget::from_ids(3, 0, 0, 0));
+        assert_eq!(
+            AdminOperation::BanReply {
+                post_id: 1,
+                reply_id: 2
+            },
+            ReportTarget::Reply {
+                post_id: 1,
+                reply_id: 2
+            }
+            .to_admin_operation()
+        );
+        assert_eq!(
+            ReportTarget::Burrow { burrow_id: 3 },
+            serde_json::from_str::<ReportTarget>(r#"{"Burrow":{"burrow_id":3}}"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_report_reason_and_state() {
+        for reason in [
+            ReportReason::Spam,
+            ReportReason::Harassment,
+            ReportReason::Pornography,
+            ReportReason::Illegal,
+            ReportReason::Other,
+            ReportReason::AutoFlagged,
+        ] {
+            assert_eq!(Some(reason), ReportReason::from_i32(reason.to_i32()));
+        }
+        assert_eq!(None, ReportReason::from_i32(6));
+        for state in [
+            ReportState::Pending,
+            ReportState::Banned,
+            ReportState::Dismissed,
+        ] {
+            assert_eq!(Some(state), ReportState::from_i32(state.to_i32()));
+        }
+        assert_eq!(None, ReportState::from_i32(-1));
+    }
+
+    #[test]
+    fn test_report_display() {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let model = report::Model {
+            report_id: 1,
+            uid: 2,
+            target_type: 0,
+            post_id: 3,
+            reply_id: 0,
+            burrow_id: 0,
+            reason: 0,
+            description: "spam".to_string(),
+            report_state: 0,
+            operator: 0,
+            create_time: now,
+            resolve_time: None,
+        };
+        let target = ReportDisplay {
+            report_id: 1,
+            uid: 2,
+            target: ReportTarget::Post { post_id: 3 },
+            reason: ReportReason::Spam,
+            description: "spam".to_string(),
+            state: ReportState::Pending,
+            operation: AdminOperation::BanPost { post_id: 3 },
+            operator: 0,
+            create_time: now,
+            resolve_time: None,
+        };
+        assert_eq!(target, (&model).into());
+        let info: ReportInfo =
+            serde_json::from_str(r#"{"target":{"Post":{"post_id":3}},"reason":"Spam"}"#).unwrap();
+        assert_eq!(
+            ReportInfo {
+                target: ReportTarget::Post { post_id: 3 },
+                reason: ReportReason::Spam,
+                description: "".to_string(),
+            },
+            info
+        );
+    }
+}
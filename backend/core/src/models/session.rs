@@ -0,0 +1,128 @@
+//! Models of login session
+
+use chrono::{DateTime, FixedOffset};
+use rocket::serde::{Deserialize, Serialize};
+
+/// Session stored in redis under `session:<uid>`, keyed by `session_id`
+///
+/// ## Fields
+///
+/// - `session_id`: String, id of the session, unchanged when its token is refreshed
+/// - `token`: String, current token of the session
+/// - `refresh_token`: String, hash of the current refresh token of the session
+/// - `prev_refresh_token`: String, hash of the refresh token replaced by the last rotation
+/// - `rotate_time`: Option<DateTime<FixedOffset>>, time of the last rotation
+/// - `device`: String, user agent of the client
+/// - `ip`: String, ip address of the client when logging in
+/// - `create_time`: DateTime<FixedOffset>, time of logging in
+/// - `last_seen`: DateTime<FixedOffset>, time of the last authenticated request
+/// - `mfa`: bool, if the second factor was checked for the session, i.e. it was
+///   created by a TOTP login or TOTP was confirmed in it
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub token: String,
+    #[serde(default)]
+    pub refresh_token: String,
+    #[serde(default)]
+    pub prev_refresh_token: String,
+    #[serde(default)]
+    pub rotate_time: Option<DateTime<FixedOffset>>,
+    pub device: String,
+    pub ip: String,
+    pub create_time: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+    #[serde(default)]
+    pub mfa: bool,
+}
+
+/// Response struct of `get_sessions`
+///
+/// ## Fields
+///
+/// - `session_id`: String, id of the session
+/// - `device`: String, user agent of the client
+/// - `ip`: String, ip address of the client when logging in
+/// - `create_time`: DateTime<FixedOffset>, time of logging in
+/// - `last_seen`: DateTime<FixedOffset>, time of the last authenticated request
+/// - `current`: bool, if it is the session making the request
+///
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SessionDisplay {
+    pub session_id: String,
+    pub device: String,
+    pub ip: String,
+    pub create_time: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+    pub current: bool,
+}
+
+impl SessionInfo {
+    pub fn display(&self, current_session: &str) -> SessionDisplay {
+        SessionDisplay {
+            session_id: self.session_id.to_owned(),
+            device: self.device.to_owned(),
+            ip: self.ip.to_owned(),
+            create_time: self.create_time,
+            last_seen: self.last_seen,
+            current: self.session_id == current_session,
+        }
+    }
+}
+
+/// Value stored under a token or refresh token, in the form of `<uid>:<session_id>`
+pub fn session_value(uid: i64, session_id: &str) -> String {
+    format!("{}:{}", uid, session_id)
+}
+
+/// Parse the value stored under a token or refresh token into `(uid, session_id)`
+pub fn parse_session_value(value: &str) -> Option<(i64, String)> {
+    let (uid, session_id) = value.split_once(':')?;
+    if session_id.is_empty() {
+        return None;
+    }
+    Some((uid.parse().ok()?, session_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_session_value() {
+        let value = session_value(1, "abc");
+        assert_eq!("1:abc", value);
+        assert_eq!(Some((1, "abc".to_string())), parse_session_value(&value));
+        assert_eq!(None, parse_session_value("1"));
+        assert_eq!(None, parse_session_value("1:"));
+        assert_eq!(None, parse_session_value("a:abc"));
+    }
+
+    #[test]
+    fn test_session_display() {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let session = SessionInfo {
+            session_id: "abc".to_string(),
+            token: "token".to_string(),
+            refresh_token: "refresh".to_string(),
+            prev_refresh_token: "".to_string(),
+            rotate_time: None,
+            device: "Mozilla/5.0".to_string(),
+            ip: "127.0.0.1".to_string(),
+            create_time: now,
+            last_seen: now,
+            mfa: false,
+        };
+        let display = session.display("abc");
+        assert!(display.current);
+        assert_eq!("abc", display.session_id);
+        assert!(!session.display("def").current);
+        let json = serde_json::to_string(&session).unwrap();
+        assert_eq!(session, serde_json::from_str(&json).unwrap());
+        let json = serde_json::to_string(&display).unwrap();
+        assert!(!json.contains("token"));
+        assert!(!json.contains("refresh"));
+    }
+}
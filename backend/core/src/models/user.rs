@@ -81,6 +81,39 @@ pub struct UserLoginInfo<'r> {
     pub password: &'r str,
 }
 
+/// Input struct of TOTP operations and the second step of login
+///
+/// ## Fields
+///
+/// - `code`: &str, code of the authenticator app, or a recovery code
+#[derive(Deserialize)]
+pub struct TotpCode<'r> {
+    pub code: &'r str,
+}
+
+/// Response struct of `totp_enroll`
+///
+/// ## Fields
+///
+/// - `secret`: String, base32 secret, for manual entry in authenticator apps
+/// - `uri`: String, `otpauth://` URI of the secret, usually shown as QR code
+#[derive(Serialize, Deserialize)]
+pub struct TotpEnrollResponse {
+    pub secret: String,
+    pub uri: String,
+}
+
+/// Response struct of `totp_confirm`
+///
+/// ## Fields
+///
+/// - `recovery_codes`: Vec<String>, one-time codes to log in without the authenticator app,
+///   only shown once
+#[derive(Serialize, Deserialize)]
+pub struct TotpRecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
 /// Response struct of `user_sign_up`
 ///
 /// ## Fields
@@ -97,10 +130,13 @@ pub struct UserResponse {
 ///
 /// - `post`: struct Post, information of post
 /// - `is_update`: bool, if post is updated since last view
+/// - `cursor`: String, cursor to read the posts after this one with
 #[derive(Serialize, Deserialize)]
 pub struct UserGetCollectionResponse {
     pub post: Post,
     pub is_update: bool,
+    #[serde(default)]
+    pub cursor: String,
 }
 
 /// Response struct of `get_follow`
@@ -109,10 +145,13 @@ pub struct UserGetCollectionResponse {
 ///
 /// - `burrow`: struct BurrowMetadata, information of burrow
 /// - `is_update`: bool, if burrow is updated since last view
+/// - `cursor`: String, cursor to read the burrows after this one with
 #[derive(Serialize, Deserialize)]
 pub struct UserGetFollowResponse {
     pub burrow: BurrowMetadata,
     pub is_update: bool,
+    #[serde(default)]
+    pub cursor: String,
 }
 
 // pub struct UserGetFavResponse {
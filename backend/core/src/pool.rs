@@ -5,16 +5,16 @@
 
 use deadpool::managed::{self, Manager, Object, PoolConfig, PoolError};
 use deadpool::Runtime;
-use pulsar::{
-    message::proto, producer, Error as PulsarError, MultiTopicProducer, Pulsar, TokioExecutor,
-};
 use reqwest;
 use rocket::State;
 use rocket_db_pools::{rocket::figment::Figment, Config, Database, Error, Pool};
 use s3::{bucket::Bucket, creds::Credentials, region::Region, BucketConfiguration};
 use sea_orm::{DatabaseConnection, DbErr};
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::utils::queue::{self, MessageQueue, QueueError};
+
 /// Manager used for managing redis connection pool.
 pub trait DeadManager: Manager + Sized + Send + Sync + 'static {
     fn new(config: &Config) -> Result<Self, Self::Error>;
@@ -101,38 +101,31 @@ impl Pool for SeaOrmPool {
     }
 }
 
-/// Pulsar Connection Pool
+/// Message Queue Connection Pool
+///
+/// The scheme of the url selects Pulsar, Redis Streams or an in-process queue, see
+/// `utils::queue`.
 #[derive(Database)]
 #[database("pulsar-mq")]
-pub struct PulsarMq(PulsarProducerPool);
+pub struct Mq(MqPool);
 
-pub struct PulsarProducerPool {
-    pub pulsar: Pulsar<TokioExecutor>,
+pub struct MqPool {
+    pub queue: Arc<dyn MessageQueue>,
 }
 
 #[rocket::async_trait]
-impl Pool for PulsarProducerPool {
-    type Connection = MultiTopicProducer<TokioExecutor>;
-    type Error = PulsarError;
+impl Pool for MqPool {
+    type Connection = Arc<dyn MessageQueue>;
+    type Error = QueueError;
 
     async fn init(figment: &Figment) -> Result<Self, Self::Error> {
         let config: Config = figment.extract().unwrap();
-        let pulsar = Pulsar::builder(&config.url, TokioExecutor).build().await?;
-        Ok(PulsarProducerPool { pulsar })
+        let queue = queue::connect(&config.url).await?;
+        Ok(MqPool { queue })
     }
 
     async fn get(&self) -> Result<Self::Connection, Self::Error> {
-        Ok(self
-            .pulsar
-            .producer()
-            .with_options(producer::ProducerOptions {
-                schema: Some(proto::Schema {
-                    r#type: proto::schema::Type::String as i32,
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .build_multi_topic())
+        Ok(self.queue.clone())
     }
 }
 
@@ -262,14 +255,11 @@ impl Pool for TypesenseSearchPool {
         let info: Vec<&str> = config.url.split('@').collect();
         let addr: String;
         let api_key: String;
-        if info.len() == 1 {
-            addr = info[0].to_owned();
-            api_key = "8Dz4jRrsBjYgdCD/VGP1bleph7oBThJr5IcF43l0U24=".to_owned();
-        } else if info.len() == 2 {
+        if info.len() == 2 && !info[1].is_empty() {
             addr = info[0].to_owned();
             api_key = info[1].to_owned();
         } else {
-            panic!("Invalid typesense url.");
+            panic!("Invalid typesense url, expected <address>@<api key>.");
         }
         Ok(TypesenseSearchPool {
             search_client: SearchClient {
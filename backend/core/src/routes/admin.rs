@@ -1,35 +1,73 @@
 //! Routes for admin
 
-use chrono::{FixedOffset, Utc};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use regex::Regex;
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::{Build, Rocket};
 use rocket_db_pools::Connection;
-use sea_orm::{entity::*, DbErr, TransactionTrait};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    entity::*, Condition, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr,
+    PaginatorTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
 
+use crate::config::admin::{
+    AUDIT_PER_PAGE, DEAD_LETTER_PER_PAGE, MAX_BAN_DURATION, REPORT_PER_PAGE,
+};
+use crate::config::settings::CONFIG;
 #[cfg(debug_assertions)]
 use crate::config::BACKEND_TEST_MODE;
 use crate::db::{self, prelude::*};
 use crate::models::pulsar::{
     PulsarSearchBurrowData, PulsarSearchData, PulsarSearchPostData, PulsarSearchReplyData,
 };
-use crate::models::{admin::*, error::*};
-use crate::pool::{PgDb, PulsarMq};
-use crate::utils::auth::Auth;
-use crate::utils::burrow_valid::get_burrow_list;
-use crate::utils::dedup::remove_duplicate;
+use crate::models::{admin::*, error::*, filter::*, report::*};
+use crate::pool::{PgDb, RedisDb};
+use crate::utils::auth::{is_mfa_session, Auth};
+use crate::utils::content_filter::reload_filter;
+use crate::utils::dead_letter;
+use crate::utils::login_guard;
+use crate::utils::outbox;
+use crate::utils::totp::get_enabled_totp;
 
 pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
     #[cfg(debug_assertions)]
     {
-        let mut rocket = rocket.mount("/admin", routes![admin_operation]);
+        let mut rocket = rocket.mount(
+            "/admin",
+            routes![
+                admin_operation,
+                get_reports,
+                resolve_report,
+                get_audit_log,
+                get_filter_rules,
+                get_lockouts,
+                clear_lockout,
+                get_dead_letters,
+                replay_dead_letter
+            ],
+        );
         if *BACKEND_TEST_MODE {
             rocket = rocket.mount("/admin", routes![admin_test]);
         }
         rocket
     }
     #[cfg(not(debug_assertions))]
-    rocket.mount("/admin", routes![admin_operation])
+    rocket.mount(
+        "/admin",
+        routes![
+            admin_operation,
+            get_reports,
+            resolve_report,
+            get_audit_log,
+            get_filter_rules,
+            get_lockouts,
+            clear_lockout,
+            get_dead_letters,
+            replay_dead_letter
+        ],
+    )
 }
 
 /// Process admin operations
@@ -38,8 +76,8 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
 ///
 /// - `Auth`: Authenticated user
 /// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
 /// - `Json<AdminOperation>`: Admin operation
-/// - `Connection<PulsarMq>`: Pulsar search producer connection
 ///
 /// ## Returns
 ///
@@ -55,517 +93,545 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
 ///   - `ErrorCode::BurrowNotExist`
 ///   - `ErrorCode::PostNotExist`
 ///   - `ErrorCode::ReplyNotExist`
+///   - `ErrorCode::FilterRuleInvalid`
+///   - `ErrorCode::BanDurationInvalid`
+///   - `ErrorCode::TotpRequired`
 #[post("/", data = "<operation>", format = "json")]
 pub async fn admin_operation(
     auth: Auth,
     db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
     operation: Json<AdminOperation>,
-    mut producer: Connection<PulsarMq>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
     let operation = operation.into_inner();
-    match Admin::find_by_id(auth.id).one(&pg_con).await {
-        Ok(admin) => match admin {
-            Some(admin) => match operation {
-                AdminOperation::BanUser { uid } => {
-                    match UserStatus::find_by_id(uid).one(&pg_con).await {
-                        Ok(user) => match user {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
-                            ),
-                            Some(user) => {
-                                if admin.role < user.permission {
-                                    (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
-                                    )
-                                } else {
-                                    let mut ust: db::user_status::ActiveModel = user.into();
-                                    ust.user_state = Set(1);
-                                    ust.permission = Set(admin.role);
-                                    match ust.update(&pg_con).await {
-                                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
+    let admin = match get_admin(&pg_con, auth.id).await {
+        Ok(admin) => admin,
+        Err((status, e)) => return (status, Err(e)),
+    };
+    if let Err((status, e)) = check_admin_totp(&pg_con, kv_conn.as_mut(), &auth).await {
+        return (status, Err(e));
+    }
+    let reload = matches!(
+        operation,
+        AdminOperation::AddFilterRule { .. } | AdminOperation::DeleteFilterRule { .. }
+    );
+    let txn = match pg_con.begin().await {
+        Ok(txn) => txn,
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    let (status, res) = match execute_admin_operation(&admin, operation, &txn).await {
+        Ok(response) => response,
+        Err((status, e)) => return (status, Err(e)),
+    };
+    // commit failed operations too, to keep their entries in the audit log
+    if let Err(e) = txn.commit().await {
+        log::error!("[ADMIN] Database Error: {:?}", e);
+        return (
+            Status::InternalServerError,
+            Err(Json(ErrorResponse::default())),
+        );
+    }
+    // the filter is loaded from the committed rules
+    if reload && res.is_ok() {
+        if let Err(e) = reload_filter(&pg_con).await {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+        }
+    }
+    (status, res)
+}
+
+/// Get the admin of a user
+///
+/// ## Errors
+///
+/// - `ErrorCode::UserForbidden` if the user is not an admin
+/// - `ErrorCode::DatabaseErr`
+async fn get_admin(
+    pg_con: &DatabaseConnection,
+    uid: i64,
+) -> Result<db::admin::Model, (Status, Json<ErrorResponse>)> {
+    match Admin::find_by_id(uid).one(pg_con).await {
+        Ok(Some(admin)) => Ok(admin),
+        Ok(None) => Err((
+            Status::Forbidden,
+            Json(ErrorResponse::build(
+                ErrorCode::UserForbidden,
+                "Permission denied.",
+            )),
+        )),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            Err((Status::InternalServerError, Json(ErrorResponse::default())))
+        }
+    }
+}
+
+/// Check that an admin has enabled TOTP and that the current session was checked
+/// with it, which is required to perform admin operations unless
+/// `admin.totp_required` is off in the settings
+///
+/// Sessions logged in with the password alone, e.g. those created before TOTP
+/// was enabled, have to log in again with TOTP.
+///
+/// ## Errors
+///
+/// - `ErrorCode::TotpRequired`
+/// - `ErrorCode::DatabaseErr`
+async fn check_admin_totp(
+    pg_con: &DatabaseConnection,
+    kv_conn: &mut redis::aio::Connection,
+    auth: &Auth,
+) -> Result<(), (Status, Json<ErrorResponse>)> {
+    if !CONFIG.admin.totp_required {
+        return Ok(());
+    }
+    match get_enabled_totp(pg_con, auth.id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err((
+                Status::Forbidden,
+                Json(ErrorResponse::build(
+                    ErrorCode::TotpRequired,
+                    "TOTP must be enabled to perform admin operations.",
+                )),
+            ))
+        }
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return Err((Status::InternalServerError, Json(ErrorResponse::default())));
+        }
+    }
+    match is_mfa_session(auth.id, &auth.session_id, kv_conn).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            Status::Forbidden,
+            Json(ErrorResponse::build(
+                ErrorCode::TotpRequired,
+                "Log in with TOTP to perform admin operations.",
+            )),
+        )),
+        Err(e) => Err((Status::InternalServerError, Json(e))),
+    }
+}
+
+/// Execute an admin operation on behalf of an admin
+///
+/// Shared by the admin operation route and the report queue, so that resolving
+/// a report goes through exactly the same permission checks. Every operation is
+/// appended to the audit log in `txn` together with its result. The operation
+/// itself runs in a savepoint of `txn`, which is rolled back if the operation
+/// fails, so the caller commits `txn` either way to keep the audit entry.
+///
+/// ## Parameters
+///
+/// - `&db::admin::Model`: Admin who performs the operation
+/// - `AdminOperation`: Admin operation
+/// - `&DatabaseTransaction`: Transaction to run the operation and write the audit in
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status of the operation
+/// - `Result<String, Json<ErrorResponse>>`: Response of the operation, String
+///   "Success" or one of
+///   - `ErrorCode::DatabaseErr`
+///   - `ErrorCode::UserNotExist`
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::BurrowNotExist`
+///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::ReplyNotExist`
+///   - `ErrorCode::BanDurationInvalid`
+///
+/// ## Errors
+///
+/// - `ErrorCode::DatabaseErr` if the audit entry cannot be written, then `txn`
+///   must be rolled back
+pub async fn execute_admin_operation(
+    admin: &db::admin::Model,
+    operation: AdminOperation,
+    txn: &DatabaseTransaction,
+) -> Result<(Status, Result<String, Json<ErrorResponse>>), (Status, Json<ErrorResponse>)> {
+    let operation_str = serde_json::to_string(&operation).unwrap_or_default();
+    let kind = operation.kind();
+    let target_type = operation.target_type();
+    let target = operation.target();
+    let savepoint = match txn.begin().await {
+        Ok(savepoint) => savepoint,
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return Err((Status::InternalServerError, Json(ErrorResponse::default())));
+        }
+    };
+    let (status, res) = run_admin_operation(admin, operation, &savepoint).await;
+    // the savepoint is rolled back when dropped
+    if res.is_ok() {
+        if let Err(e) = savepoint.commit().await {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return Err((Status::InternalServerError, Json(ErrorResponse::default())));
+        }
+    }
+    let audit = audit_entry(
+        admin,
+        kind,
+        operation_str,
+        target_type,
+        target,
+        status,
+        &res,
+    );
+    match audit.insert(txn).await {
+        Ok(_) => Ok((status, res)),
+        Err(e) => {
+            log::error!("[ADMIN] Failed to write audit log: {:?}", e);
+            Err((Status::InternalServerError, Json(ErrorResponse::default())))
+        }
+    }
+}
+
+/// Entry of the audit log of an action of an admin
+///
+/// ## Parameters
+///
+/// - `admin`: Admin who performs the action
+/// - `kind`: Name of the action, e.g. `BanUser` or `ClearLockout`
+/// - `operation`: The action as json
+/// - `target_type`: What the ids of `target` refer to
+/// - `target`: Ids the action acts on, as `(target_id, target_sub_id)`
+/// - `status`, `res`: Response of the action
+fn audit_entry<T>(
+    admin: &db::admin::Model,
+    kind: &str,
+    operation: String,
+    target_type: AuditTarget,
+    target: (i64, i32),
+    status: Status,
+    res: &Result<T, Json<ErrorResponse>>,
+) -> db::admin_audit::ActiveModel {
+    let result = match res {
+        Ok(_) => "Success".to_string(),
+        Err(e) => format!("{:?}", e.error.code),
+    };
+    db::admin_audit::ActiveModel {
+        uid: Set(admin.uid),
+        role: Set(admin.role),
+        operation: Set(operation),
+        operation_kind: Set(kind.to_string()),
+        target_type: Set(target_type.to_i32()),
+        target_id: Set(target.0),
+        target_sub_id: Set(target.1),
+        status: Set(status.code as i32),
+        result: Set(result),
+        create_time: Set(Utc::now().with_timezone(&FixedOffset::east(8 * 3600))),
+        ..Default::default()
+    }
+}
+
+/// Append an entry to the audit log of an action which does not change Postgres,
+/// or which failed and was rolled back
+///
+/// The action has already taken effect or failed, so a failed write is only logged.
+async fn write_audit(pg_con: &DatabaseConnection, audit: db::admin_audit::ActiveModel) {
+    if let Err(e) = audit.insert(pg_con).await {
+        log::error!("[ADMIN] Failed to write audit log: {:?}", e);
+    }
+}
+
+async fn run_admin_operation<C: ConnectionTrait>(
+    admin: &db::admin::Model,
+    operation: AdminOperation,
+    db: &C,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    match operation {
+        AdminOperation::BanUser { uid } => {
+            set_ban_state(admin, BanTarget::User, uid, true, None, db).await
+        }
+        AdminOperation::ReopenUser { uid } => {
+            set_ban_state(admin, BanTarget::User, uid, false, None, db).await
+        }
+        AdminOperation::BanUserFor {
+            uid,
+            duration,
+            reason,
+        } => {
+            let ban = match new_ban_record(admin, BanTarget::User, uid, duration, reason) {
+                Ok(ban) => ban,
+                Err(e) => return (Status::BadRequest, Err(e)),
+            };
+            set_ban_state(admin, BanTarget::User, uid, true, Some(ban), db).await
+        }
+        AdminOperation::BanBurrow { burrow_id } => {
+            set_ban_state(admin, BanTarget::Burrow, burrow_id, true, None, db).await
+        }
+        AdminOperation::ReopenBurrow { burrow_id } => {
+            set_ban_state(admin, BanTarget::Burrow, burrow_id, false, None, db).await
+        }
+        AdminOperation::BanBurrowFor {
+            burrow_id,
+            duration,
+            reason,
+        } => {
+            let ban = match new_ban_record(admin, BanTarget::Burrow, burrow_id, duration, reason) {
+                Ok(ban) => ban,
+                Err(e) => return (Status::BadRequest, Err(e)),
+            };
+            set_ban_state(admin, BanTarget::Burrow, burrow_id, true, Some(ban), db).await
+        }
+        AdminOperation::BanPost { post_id } => {
+            match ContentPost::find_by_id(post_id).one(db).await {
+                Ok(post) => match post {
+                    None => (
+                        Status::BadRequest,
+                        Err(Json(ErrorResponse::build(ErrorCode::PostNotExist, ""))),
+                    ),
+                    Some(post) => {
+                        if admin.role < post.permission {
                             (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
+                                Status::Forbidden,
+                                Err(Json(ErrorResponse::build(
+                                    ErrorCode::UserForbidden,
+                                    "Permission Denied.",
+                                ))),
                             )
-                        }
-                    }
-                }
-                AdminOperation::ReopenUser { uid } => {
-                    match UserStatus::find_by_id(uid).one(&pg_con).await {
-                        Ok(user) => match user {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
-                            ),
-                            Some(user) => {
-                                if admin.role < user.permission {
+                        } else {
+                            let mut pst: db::content_post::ActiveModel = post.into();
+                            pst.post_state = Set(1);
+                            pst.permission = Set(admin.role);
+                            let result = match pst.update(db).await {
+                                Ok(_) => {
+                                    let msg = PulsarSearchData::DeletePost(post_id);
+                                    outbox::enqueue_search(db, msg).await
+                                }
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                                Err(e) => {
+                                    log::error!("[ADMIN] Database Error: {:?}", e);
                                     (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
+                                        Status::InternalServerError,
+                                        Err(Json(ErrorResponse::default())),
                                     )
-                                } else {
-                                    let mut ust: db::user_status::ActiveModel = user.into();
-                                    ust.user_state = Set(0);
-                                    ust.permission = Set(admin.role);
-                                    match ust.update(&pg_con).await {
-                                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
                                 }
                             }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
                         }
                     }
+                },
+                Err(e) => {
+                    log::error!("[ADMIN]: Database error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
                 }
-                AdminOperation::BanBurrow { burrow_id } => {
-                    match Burrow::find_by_id(burrow_id).one(&pg_con).await {
-                        Ok(burrow) => {
-                            match burrow {
-                                None => (
-                                    Status::BadRequest,
-                                    Err(Json(ErrorResponse::build(ErrorCode::BurrowNotExist, ""))),
-                                ),
-                                Some(burrow) => {
-                                    if admin.role < burrow.permission {
-                                        (
-                                            Status::Forbidden,
-                                            Err(Json(ErrorResponse::build(
-                                                ErrorCode::UserForbidden,
-                                                "Permission Denied.",
-                                            ))),
-                                        )
-                                    } else {
-                                        let burrow_state = burrow.burrow_state;
-                                        let burrow_id = burrow.burrow_id;
-                                        let uid = burrow.uid;
-                                        let mut bst: db::burrow::ActiveModel = burrow.into();
-                                        bst.burrow_state = Set(burrow_state + 1 - burrow_state % 2);
-                                        bst.permission = Set(admin.role);
-                                        match pg_con
-                                            .transaction::<_, (), DbErr>(|txn| {
-                                                Box::pin(async move {
-                                                    bst.update(txn).await?;
-                                                    let ust = UserStatus::find_by_id(uid).one(txn).await?;
-                                                    match ust {
-                                                        None => {
-                                                            log::error!("[ADMIN] User not found");
-                                                            Err(DbErr::RecordNotFound("User not found".to_string()))
-                                                        }
-                                                        Some(ust) => {
-                                                            let mut valid_burrows: Vec<i64> = get_burrow_list(&ust.valid_burrow);
-                                                            let mut banned_burrows: Vec<i64> = get_burrow_list(&ust.banned_burrow);
-                                                            let mut ust: db::user_status::ActiveModel = ust.into();
-                                                            if valid_burrows.contains(&burrow_id) {
-                                                                valid_burrows.remove(valid_burrows.binary_search(&burrow_id).unwrap());
-                                                                banned_burrows.push(burrow_id);
-                                                                banned_burrows = remove_duplicate(banned_burrows);
-                                                                let valid_burrows_str = valid_burrows
-                                                                    .iter()
-                                                                    .map(|x| x.to_string())
-                                                                    .collect::<Vec<String>>()
-                                                                    .join(",");
-                                                                let banned_burrows_str = banned_burrows
-                                                                    .iter()
-                                                                    .map(|x| x.to_string())
-                                                                    .collect::<Vec<String>>()
-                                                                    .join(",");
-                                                                ust.valid_burrow = Set(valid_burrows_str);
-                                                                ust.banned_burrow = Set(banned_burrows_str);
-                                                                ust.update(txn).await?;
-                                                            }
-                                                            Ok(())
-                                                        }
-                                                    }
-                                                })
-                                            })
-                                        .await
-                                    {
-                                        Ok(_) => {
-                                            let msg = PulsarSearchData::DeleteBurrow(burrow_id);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
-                                            (Status::Ok, Ok("Success".to_string()))
-                                        }
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
+            }
+        }
+        AdminOperation::ReopenPost { post_id } => {
+            match ContentPost::find_by_id(post_id).one(db).await {
+                Ok(post) => match post {
+                    None => (
+                        Status::BadRequest,
+                        Err(Json(ErrorResponse::build(ErrorCode::PostNotExist, ""))),
+                    ),
+                    Some(post) => {
+                        if admin.role < post.permission {
                             (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
+                                Status::Forbidden,
+                                Err(Json(ErrorResponse::build(
+                                    ErrorCode::UserForbidden,
+                                    "Permission Denied.",
+                                ))),
                             )
-                        }
-                    }
-                }
-                AdminOperation::ReopenBurrow { burrow_id } => {
-                    match Burrow::find_by_id(burrow_id).one(&pg_con).await {
-                        Ok(burrow) => {
-                            match burrow {
-                                None => (
-                                    Status::BadRequest,
-                                    Err(Json(ErrorResponse::build(ErrorCode::BurrowNotExist, ""))),
-                                ),
-                                Some(burrow) => {
-                                    if admin.role < burrow.permission {
-                                        (
-                                            Status::Forbidden,
-                                            Err(Json(ErrorResponse::build(
-                                                ErrorCode::UserForbidden,
-                                                "Permission Denied.",
-                                            ))),
-                                        )
-                                    } else {
-                                        let burrow_state = burrow.burrow_state;
-                                        let uid = burrow.uid;
-                                        let mut bst: db::burrow::ActiveModel = burrow.into();
-                                        bst.burrow_state = Set(burrow_state - burrow_state % 2);
-                                        bst.permission = Set(admin.role);
-                                        match pg_con
-                                            .transaction::<_, db::burrow::Model, DbErr>(|txn| {
-                                                Box::pin(async move {
-                                                    let bst = bst.update(txn).await?;
-                                                    let ust = UserStatus::find_by_id(uid).one(txn).await?;
-                                                    match ust {
-                                                        None => {
-                                                            log::error!("[ADMIN] User not found");
-                                                            Err(DbErr::RecordNotFound("User not found".to_string()))
-                                                        }
-                                                        Some(ust) => {
-                                                            let mut valid_burrows: Vec<i64> = get_burrow_list(&ust.valid_burrow);
-                                                            let mut banned_burrows: Vec<i64> = get_burrow_list(&ust.banned_burrow);
-                                                            let mut ust: db::user_status::ActiveModel = ust.into();
-                                                            if banned_burrows.contains(&burrow_id) {
-                                                                banned_burrows.remove(banned_burrows.binary_search(&burrow_id).unwrap());
-                                                                valid_burrows.push(burrow_id);
-                                                                valid_burrows = remove_duplicate(valid_burrows);
-                                                                let valid_burrows_str = valid_burrows
-                                                                    .iter()
-                                                                    .map(|x| x.to_string())
-                                                                    .collect::<Vec<String>>()
-                                                                    .join(",");
-                                                                let banned_burrows_str = banned_burrows
-                                                                    .iter()
-                                                                    .map(|x| x.to_string())
-                                                                    .collect::<Vec<String>>()
-                                                                    .join(",");
-                                                                ust.valid_burrow = Set(valid_burrows_str);
-                                                                ust.banned_burrow = Set(banned_burrows_str);
-                                                                ust.update(txn).await?;
-                                                            }
-                                                            Ok(bst)
-                                                        }
-                                                    }
-                                                })
-                                            })
-                                        .await
-                                    {
-                                        Ok(res) => {
-                                            let pulsar_burrow = PulsarSearchBurrowData {
-                                                burrow_id: res.burrow_id,
-                                                title: res.title,
-                                                description: res.description,
-                                                update_time: res.update_time,
-                                            };
-                                            let msg = PulsarSearchData::CreateBurrow(pulsar_burrow);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
-                                            (Status::Ok, Ok("Success".to_string()))
-                                        }
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
-                                    }
+                        } else {
+                            let mut pst: db::content_post::ActiveModel = post.into();
+                            pst.post_state = Set(0);
+                            pst.permission = Set(admin.role);
+                            let result = match pst.update(db).await {
+                                Ok(content) => {
+                                    let pulsar_post = PulsarSearchPostData {
+                                        post_id,
+                                        title: content.title,
+                                        burrow_id: content.burrow_id,
+                                        section: serde_json::from_str(&content.section).unwrap(),
+                                        tag: content.tag.split(',').map(str::to_string).collect(),
+                                        update_time: content.update_time,
+                                    };
+                                    let msg = PulsarSearchData::CreatePost(pulsar_post);
+                                    outbox::enqueue_search(db, msg).await
                                 }
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
-                        }
-                    }
-                }
-                AdminOperation::BanPost { post_id } => {
-                    match ContentPost::find_by_id(post_id).one(&pg_con).await {
-                        Ok(post) => match post {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::PostNotExist, ""))),
-                            ),
-                            Some(post) => {
-                                if admin.role < post.permission {
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                                Err(e) => {
+                                    log::error!("[ADMIN] Database Error: {:?}", e);
                                     (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
+                                        Status::InternalServerError,
+                                        Err(Json(ErrorResponse::default())),
                                     )
-                                } else {
-                                    let mut pst: db::content_post::ActiveModel = post.into();
-                                    pst.post_state = Set(1);
-                                    pst.permission = Set(admin.role);
-                                    match pst.update(&pg_con).await {
-                                        Ok(_) => {
-                                            let msg = PulsarSearchData::DeletePost(post_id);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
-                                            (Status::Ok, Ok("Success".to_string()))
-                                        }
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
                                 }
                             }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
                         }
                     }
+                },
+                Err(e) => {
+                    log::error!("[ADMIN]: Database error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
                 }
-                AdminOperation::ReopenPost { post_id } => {
-                    match ContentPost::find_by_id(post_id).one(&pg_con).await {
-                        Ok(post) => match post {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::PostNotExist, ""))),
-                            ),
-                            Some(post) => {
-                                if admin.role < post.permission {
-                                    (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
-                                    )
-                                } else {
-                                    let mut pst: db::content_post::ActiveModel = post.into();
-                                    pst.post_state = Set(0);
-                                    pst.permission = Set(admin.role);
-                                    match pst.update(&pg_con).await {
-                                        Ok(content) => {
-                                            let pulsar_post = PulsarSearchPostData {
-                                                post_id,
-                                                title: content.title,
-                                                burrow_id: content.burrow_id,
-                                                section: serde_json::from_str(&content.section)
-                                                    .unwrap(),
-                                                tag: content
-                                                    .tag
-                                                    .split(',')
-                                                    .map(str::to_string)
-                                                    .collect(),
-                                                update_time: content.update_time,
-                                            };
-                                            let msg = PulsarSearchData::CreatePost(pulsar_post);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
-                                            (Status::Ok, Ok("Success".to_string()))
-                                        }
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
+            }
+        }
+        AdminOperation::BanReply { post_id, reply_id } => {
+            match ContentReply::find_by_id((post_id, reply_id)).one(db).await {
+                Ok(reply) => match reply {
+                    None => (
+                        Status::BadRequest,
+                        Err(Json(ErrorResponse::build(ErrorCode::ReplyNotExist, ""))),
+                    ),
+                    Some(reply) => {
+                        if admin.role < reply.permission {
                             (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
+                                Status::Forbidden,
+                                Err(Json(ErrorResponse::build(
+                                    ErrorCode::UserForbidden,
+                                    "Permission Denied.",
+                                ))),
                             )
-                        }
-                    }
-                }
-                AdminOperation::BanReply { post_id, reply_id } => {
-                    match ContentReply::find_by_id((post_id, reply_id))
-                        .one(&pg_con)
-                        .await
-                    {
-                        Ok(reply) => match reply {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::ReplyNotExist, ""))),
-                            ),
-                            Some(reply) => {
-                                if admin.role < reply.permission {
+                        } else {
+                            let mut rst: db::content_reply::ActiveModel = reply.into();
+                            rst.reply_state = Set(1);
+                            rst.permission = Set(admin.role);
+                            let result = match rst.update(db).await {
+                                Ok(_) => {
+                                    let msg = PulsarSearchData::DeleteReply(post_id, reply_id);
+                                    outbox::enqueue_search(db, msg).await
+                                }
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                                Err(e) => {
+                                    log::error!("[ADMIN] Database Error: {:?}", e);
                                     (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
+                                        Status::InternalServerError,
+                                        Err(Json(ErrorResponse::default())),
                                     )
-                                } else {
-                                    let mut rst: db::content_reply::ActiveModel = reply.into();
-                                    rst.reply_state = Set(1);
-                                    rst.permission = Set(admin.role);
-                                    match rst.update(&pg_con).await {
-                                        Ok(_) => {
-                                            let msg =
-                                                PulsarSearchData::DeleteReply(post_id, reply_id);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
-                                            (Status::Ok, Ok("Success".to_string()))
-                                        }
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
                                 }
                             }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
                         }
                     }
+                },
+                Err(e) => {
+                    log::error!("[ADMIN]: Database error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
                 }
-                AdminOperation::ReopenReply { post_id, reply_id } => {
-                    match ContentReply::find_by_id((post_id, reply_id))
-                        .one(&pg_con)
-                        .await
-                    {
-                        Ok(reply) => match reply {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::ReplyNotExist, ""))),
-                            ),
-                            Some(reply) => {
-                                if admin.role < reply.permission {
+            }
+        }
+        AdminOperation::ReopenReply { post_id, reply_id } => {
+            match ContentReply::find_by_id((post_id, reply_id)).one(db).await {
+                Ok(reply) => match reply {
+                    None => (
+                        Status::BadRequest,
+                        Err(Json(ErrorResponse::build(ErrorCode::ReplyNotExist, ""))),
+                    ),
+                    Some(reply) => {
+                        if admin.role < reply.permission {
+                            (
+                                Status::Forbidden,
+                                Err(Json(ErrorResponse::build(
+                                    ErrorCode::UserForbidden,
+                                    "Permission Denied.",
+                                ))),
+                            )
+                        } else {
+                            let mut rst: db::content_reply::ActiveModel = reply.into();
+                            rst.reply_state = Set(0);
+                            rst.permission = Set(admin.role);
+                            let result = match rst.update(db).await {
+                                Ok(content) => {
+                                    let pulsar_reply = PulsarSearchReplyData {
+                                        post_id,
+                                        reply_id,
+                                        burrow_id: content.burrow_id,
+                                        content: content.content,
+                                        update_time: content.update_time,
+                                    };
+                                    let msg = PulsarSearchData::CreateReply(pulsar_reply);
+                                    outbox::enqueue_search(db, msg).await
+                                }
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                                Err(e) => {
+                                    log::error!("[ADMIN] Database Error: {:?}", e);
                                     (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
+                                        Status::InternalServerError,
+                                        Err(Json(ErrorResponse::default())),
                                     )
-                                } else {
-                                    let mut rst: db::content_reply::ActiveModel = reply.into();
-                                    rst.reply_state = Set(0);
-                                    rst.permission = Set(admin.role);
-                                    match rst.update(&pg_con).await {
-                                        Ok(content) => {
-                                            let pulsar_reply = PulsarSearchReplyData {
-                                                post_id,
-                                                reply_id,
-                                                burrow_id: content.burrow_id,
-                                                content: content.content,
-                                                update_time: content.update_time,
-                                            };
-                                            let msg = PulsarSearchData::CreateReply(pulsar_reply);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
-                                            (Status::Ok, Ok("Success".to_string()))
-                                        }
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
                                 }
                             }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
                         }
                     }
+                },
+                Err(e) => {
+                    log::error!("[ADMIN]: Database error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
+                }
+            }
+        }
+        AdminOperation::CreateAdmin { uid } => {
+            if admin.role > 0 {
+                let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+                let new_admin = db::admin::ActiveModel {
+                    uid: Set(uid),
+                    create_time: Set(now),
+                    ..Default::default()
+                };
+                match new_admin.insert(db).await {
+                    Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                    Err(e) => {
+                        log::error!("[ADMIN] Database Error: {:?}", e);
+                        (
+                            Status::InternalServerError,
+                            Err(Json(ErrorResponse::default())),
+                        )
+                    }
                 }
-                AdminOperation::CreateAdmin { uid } => {
-                    if admin.role > 0 {
-                        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
-                        let new_admin = db::admin::ActiveModel {
-                            uid: Set(uid),
-                            create_time: Set(now),
-                            ..Default::default()
-                        };
-                        match new_admin.insert(&pg_con).await {
+            } else {
+                (
+                    Status::Forbidden,
+                    Err(Json(ErrorResponse::build(
+                        ErrorCode::UserForbidden,
+                        "Permission Denied.",
+                    ))),
+                )
+            }
+        }
+        AdminOperation::DeleteAdmin { uid } => match Admin::find_by_id(uid).one(db).await {
+            Ok(admin_opt) => match admin_opt {
+                None => (
+                    Status::BadRequest,
+                    Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
+                ),
+                Some(admin_opt) => {
+                    if admin.role > admin_opt.role {
+                        let admin_opt: db::admin::ActiveModel = admin_opt.into();
+                        match admin_opt.delete(db).await {
                             Ok(_) => (Status::Ok, Ok("Success".to_string())),
                             Err(e) => {
                                 log::error!("[ADMIN] Database Error: {:?}", e);
@@ -585,78 +651,193 @@ pub async fn admin_operation(
                         )
                     }
                 }
-                AdminOperation::DeleteAdmin { uid } => {
-                    match Admin::find_by_id(uid).one(&pg_con).await {
-                        Ok(admin_opt) => match admin_opt {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
-                            ),
-                            Some(admin_opt) => {
-                                if admin.role > admin_opt.role {
-                                    let admin_opt: db::admin::ActiveModel = admin_opt.into();
-                                    match admin_opt.delete(&pg_con).await {
-                                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
-                                } else {
-                                    (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
-                                    )
-                                }
+            },
+            Err(e) => {
+                log::error!("[ADMIN] Database Error: {:?}", e);
+                (
+                    Status::InternalServerError,
+                    Err(Json(ErrorResponse::default())),
+                )
+            }
+        },
+        AdminOperation::SetAdminRole { uid, role } => match Admin::find_by_id(uid).one(db).await {
+            Ok(admin_opt) => match admin_opt {
+                None => (
+                    Status::BadRequest,
+                    Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
+                ),
+                Some(admin_opt) => {
+                    if admin.role > admin_opt.role && admin.role > role {
+                        let mut admin_opt: db::admin::ActiveModel = admin_opt.into();
+                        admin_opt.role = Set(role);
+                        match admin_opt.update(db).await {
+                            Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                            Err(e) => {
+                                log::error!("[ADMIN] Database Error: {:?}", e);
+                                (
+                                    Status::InternalServerError,
+                                    Err(Json(ErrorResponse::default())),
+                                )
                             }
-                        },
-                        Err(e) => {
-                            log::error!("[ADMIN] Database Error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
                         }
+                    } else {
+                        (
+                            Status::Forbidden,
+                            Err(Json(ErrorResponse::build(
+                                ErrorCode::UserForbidden,
+                                "Permission Denied.",
+                            ))),
+                        )
                     }
                 }
-                AdminOperation::SetAdminRole { uid, role } => {
-                    match Admin::find_by_id(uid).one(&pg_con).await {
-                        Ok(admin_opt) => match admin_opt {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
-                            ),
-                            Some(admin_opt) => {
-                                if admin.role > admin_opt.role && admin.role > role {
-                                    let mut admin_opt: db::admin::ActiveModel = admin_opt.into();
-                                    admin_opt.role = Set(role);
-                                    match admin_opt.update(&pg_con).await {
-                                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
-                                        Err(e) => {
-                                            log::error!("[ADMIN] Database Error: {:?}", e);
-                                            (
-                                                Status::InternalServerError,
-                                                Err(Json(ErrorResponse::default())),
-                                            )
-                                        }
-                                    }
-                                } else {
-                                    (
-                                        Status::Forbidden,
-                                        Err(Json(ErrorResponse::build(
-                                            ErrorCode::UserForbidden,
-                                            "Permission Denied.",
-                                        ))),
-                                    )
-                                }
-                            }
-                        },
+            },
+            Err(e) => {
+                log::error!("[ADMIN] Database Error: {:?}", e);
+                (
+                    Status::InternalServerError,
+                    Err(Json(ErrorResponse::default())),
+                )
+            }
+        },
+        AdminOperation::GetUserId { burrow_id } => {
+            match Burrow::find_by_id(burrow_id).one(db).await {
+                Ok(burrow) => match burrow {
+                    None => (
+                        Status::BadRequest,
+                        Err(Json(ErrorResponse::build(ErrorCode::BurrowNotExist, ""))),
+                    ),
+                    Some(burrow) => (Status::Ok, Ok(burrow.uid.to_string())),
+                },
+                Err(e) => {
+                    log::error!("[ADMIN]: Database error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
+                }
+            }
+        }
+        AdminOperation::AddFilterRule {
+            pattern,
+            is_regex,
+            action,
+        } => {
+            if pattern.is_empty() || (is_regex && Regex::new(&pattern).is_err()) {
+                return (
+                    Status::BadRequest,
+                    Err(Json(ErrorResponse::build(
+                        ErrorCode::FilterRuleInvalid,
+                        "Empty pattern or invalid regular expression.",
+                    ))),
+                );
+            }
+            let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+            let word = db::sensitive_word::ActiveModel {
+                pattern: Set(pattern),
+                is_regex: Set(is_regex),
+                action: Set(action.to_i32()),
+                uid: Set(admin.uid),
+                create_time: Set(now),
+                ..Default::default()
+            };
+            match word.insert(db).await {
+                Ok(word) => (Status::Ok, Ok(word.word_id.to_string())),
+                Err(e) => {
+                    log::error!("[ADMIN] Database Error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
+                }
+            }
+        }
+        AdminOperation::DeleteFilterRule { word_id } => {
+            match SensitiveWord::delete_by_id(word_id).exec(db).await {
+                Ok(res) => {
+                    if res.rows_affected == 0 {
+                        (
+                            Status::BadRequest,
+                            Err(Json(ErrorResponse::build(
+                                ErrorCode::FilterRuleInvalid,
+                                format!("Cannot find filter rule {}", word_id),
+                            ))),
+                        )
+                    } else {
+                        (Status::Ok, Ok("Success".to_string()))
+                    }
+                }
+                Err(e) => {
+                    log::error!("[ADMIN] Database Error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Ban or reopen a user or burrow, together with its ban records
+///
+/// `db` should be a transaction, so that a timed ban is never applied without
+/// the record by which task-executor lifts it.
+async fn set_ban_state<C: ConnectionTrait>(
+    admin: &db::admin::Model,
+    target: BanTarget,
+    target_id: i64,
+    banned: bool,
+    ban: Option<db::ban_record::ActiveModel>,
+    db: &C,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let res = match (target, banned) {
+        (BanTarget::User, _) => set_user_state(admin, target_id, banned as i32, db).await,
+        (BanTarget::Burrow, true) => ban_burrow(admin, target_id, db).await,
+        (BanTarget::Burrow, false) => reopen_burrow(admin, target_id, db).await,
+    };
+    if res.1.is_err() {
+        return res;
+    }
+    match update_ban_records(db, target, target_id, ban).await {
+        Ok(_) => res,
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Set `user_state` of a user, 0 for valid and 1 for banned
+async fn set_user_state<C: ConnectionTrait>(
+    admin: &db::admin::Model,
+    uid: i64,
+    user_state: i32,
+    db: &C,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    match UserStatus::find_by_id(uid).one(db).await {
+        Ok(user) => match user {
+            None => (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
+            ),
+            Some(user) => {
+                if admin.role < user.permission {
+                    (
+                        Status::Forbidden,
+                        Err(Json(ErrorResponse::build(
+                            ErrorCode::UserForbidden,
+                            "Permission Denied.",
+                        ))),
+                    )
+                } else {
+                    let mut ust: db::user_status::ActiveModel = user.into();
+                    ust.user_state = Set(user_state);
+                    ust.permission = Set(admin.role);
+                    match ust.update(db).await {
+                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
                         Err(e) => {
                             log::error!("[ADMIN] Database Error: {:?}", e);
                             (
@@ -666,17 +847,58 @@ pub async fn admin_operation(
                         }
                     }
                 }
-                AdminOperation::GetUserId { burrow_id } => {
-                    match Burrow::find_by_id(burrow_id).one(&pg_con).await {
-                        Ok(burrow) => match burrow {
-                            None => (
-                                Status::BadRequest,
-                                Err(Json(ErrorResponse::build(ErrorCode::BurrowNotExist, ""))),
-                            ),
-                            Some(burrow) => (Status::Ok, Ok(burrow.uid.to_string())),
-                        },
+            }
+        },
+        Err(e) => {
+            log::error!("[ADMIN]: Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Ban a burrow, keeping whether it is discarded
+///
+/// `db` should be a transaction, since the burrow is updated together with an
+/// event in the outbox.
+async fn ban_burrow<C: ConnectionTrait>(
+    admin: &db::admin::Model,
+    burrow_id: i64,
+    db: &C,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    match Burrow::find_by_id(burrow_id).one(db).await {
+        Ok(burrow) => match burrow {
+            None => (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(ErrorCode::BurrowNotExist, ""))),
+            ),
+            Some(burrow) => {
+                if admin.role < burrow.permission {
+                    (
+                        Status::Forbidden,
+                        Err(Json(ErrorResponse::build(
+                            ErrorCode::UserForbidden,
+                            "Permission Denied.",
+                        ))),
+                    )
+                } else {
+                    let burrow_state = burrow.burrow_state;
+                    let mut bst: db::burrow::ActiveModel = burrow.into();
+                    bst.burrow_state = Set(burrow_state + 1 - burrow_state % 2);
+                    bst.permission = Set(admin.role);
+                    let result = match bst.update(db).await {
+                        Ok(_) => {
+                            let msg = PulsarSearchData::DeleteBurrow(burrow_id);
+                            outbox::enqueue_search(db, msg).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
                         Err(e) => {
-                            log::error!("[ADMIN]: Database error: {:?}", e);
+                            log::error!("[ADMIN] Database Error: {:?}", e);
                             (
                                 Status::InternalServerError,
                                 Err(Json(ErrorResponse::default())),
@@ -684,17 +906,10 @@ pub async fn admin_operation(
                         }
                     }
                 }
-            },
-            None => (
-                Status::Forbidden,
-                Err(Json(ErrorResponse::build(
-                    ErrorCode::UserForbidden,
-                    "Permission denied.",
-                ))),
-            ),
+            }
         },
         Err(e) => {
-            log::error!("[ADMIN] Database Error: {:?}", e);
+            log::error!("[ADMIN]: Database error: {:?}", e);
             (
                 Status::InternalServerError,
                 Err(Json(ErrorResponse::default())),
@@ -703,6 +918,815 @@ pub async fn admin_operation(
     }
 }
 
+/// Reopen a burrow, keeping whether it is discarded
+///
+/// `db` should be a transaction, since the burrow is updated together with an
+/// event in the outbox.
+async fn reopen_burrow<C: ConnectionTrait>(
+    admin: &db::admin::Model,
+    burrow_id: i64,
+    db: &C,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    match Burrow::find_by_id(burrow_id).one(db).await {
+        Ok(burrow) => match burrow {
+            None => (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(ErrorCode::BurrowNotExist, ""))),
+            ),
+            Some(burrow) => {
+                if admin.role < burrow.permission {
+                    (
+                        Status::Forbidden,
+                        Err(Json(ErrorResponse::build(
+                            ErrorCode::UserForbidden,
+                            "Permission Denied.",
+                        ))),
+                    )
+                } else {
+                    let burrow_state = burrow.burrow_state;
+                    let mut bst: db::burrow::ActiveModel = burrow.into();
+                    bst.burrow_state = Set(burrow_state - burrow_state % 2);
+                    bst.permission = Set(admin.role);
+                    let result = match bst.update(db).await {
+                        Ok(res) => {
+                            let pulsar_burrow = PulsarSearchBurrowData {
+                                burrow_id: res.burrow_id,
+                                title: res.title,
+                                description: res.description,
+                                update_time: res.update_time,
+                            };
+                            let msg = PulsarSearchData::CreateBurrow(pulsar_burrow);
+                            outbox::enqueue_search(db, msg).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                        Err(e) => {
+                            log::error!("[ADMIN] Database Error: {:?}", e);
+                            (
+                                Status::InternalServerError,
+                                Err(Json(ErrorResponse::default())),
+                            )
+                        }
+                    }
+                }
+            }
+        },
+        Err(e) => {
+            log::error!("[ADMIN]: Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Build an active ban record which expires `duration` seconds later
+///
+/// ## Errors
+///
+/// - `ErrorCode::BanDurationInvalid`: `duration` is not in `1..=MAX_BAN_DURATION`
+fn new_ban_record(
+    admin: &db::admin::Model,
+    target: BanTarget,
+    target_id: i64,
+    duration: i64,
+    reason: String,
+) -> Result<db::ban_record::ActiveModel, Json<ErrorResponse>> {
+    if duration <= 0 || duration > MAX_BAN_DURATION {
+        return Err(Json(ErrorResponse::build(
+            ErrorCode::BanDurationInvalid,
+            format!(
+                "Ban duration must be between 1 and {} seconds.",
+                MAX_BAN_DURATION
+            ),
+        )));
+    }
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    Ok(db::ban_record::ActiveModel {
+        target_type: Set(target.to_i32()),
+        target_id: Set(target_id),
+        uid: Set(admin.uid),
+        reason: Set(reason),
+        ban_state: Set(BanState::Active.to_i32()),
+        create_time: Set(now),
+        expire_time: Set(now + Duration::seconds(duration)),
+        ..Default::default()
+    })
+}
+
+/// Update ban records after a ban or reopen of user or burrow
+///
+/// Active timed bans of the target are revoked, since the latest operation
+/// decides its state from now on. A new timed ban is then recorded, if any.
+async fn update_ban_records<C: ConnectionTrait>(
+    db: &C,
+    target: BanTarget,
+    target_id: i64,
+    ban: Option<db::ban_record::ActiveModel>,
+) -> Result<(), DbErr> {
+    BanRecord::update_many()
+        .col_expr(
+            db::ban_record::Column::BanState,
+            Expr::value(BanState::Revoked.to_i32()),
+        )
+        .filter(db::ban_record::Column::TargetType.eq(target.to_i32()))
+        .filter(db::ban_record::Column::TargetId.eq(target_id))
+        .filter(db::ban_record::Column::BanState.eq(BanState::Active.to_i32()))
+        .exec(db)
+        .await?;
+    if let Some(ban) = ban {
+        ban.insert(db).await?;
+    }
+    Ok(())
+}
+
+/// Get Report Queue
+///
+/// Show reports in the given state, oldest first, so that the pending ones
+/// are handled in order.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Option<usize>`: page number, default value 0
+/// - `Option<ReportState>`: state of reports, default value `pending`
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<ReportDisplay>>`: Reports of the page
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::DatabaseErr`
+#[get("/reports?<page>&<state>")]
+pub async fn get_reports(
+    auth: Auth,
+    db: Connection<PgDb>,
+    page: Option<usize>,
+    state: Option<ReportState>,
+) -> (
+    Status,
+    Result<Json<Vec<ReportDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let page = page.unwrap_or(0);
+    let state = state.unwrap_or(ReportState::Pending);
+    if let Err((status, e)) = get_admin(&pg_con, auth.id).await {
+        return (status, Err(e));
+    }
+    match Report::find()
+        .filter(db::report::Column::ReportState.eq(state.to_i32()))
+        .order_by_asc(db::report::Column::ReportId)
+        .paginate(&pg_con, REPORT_PER_PAGE)
+        .fetch_page(page)
+        .await
+    {
+        Ok(reports) => (
+            Status::Ok,
+            Ok(Json(reports.iter().map(|r| r.into()).collect())),
+        ),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Resolve a Report
+///
+/// `Ban` executes the `AdminOperation` linked to the report and resolves every
+/// pending report on the same target, `Dismiss` only resolves this report. The
+/// operation, its audit entry and the resolved reports are committed in one
+/// transaction. If the operation fails, only its audit entry is committed.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+/// - `i64`: Report id
+/// - `Json<ReportResolution>`: How to resolve the report
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::ReportNotExist`
+///   - `ErrorCode::BurrowNotExist`
+///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::ReplyNotExist`
+///   - `ErrorCode::TotpRequired`
+///   - `ErrorCode::DatabaseErr`
+#[post("/reports/<report_id>", data = "<resolution>", format = "json")]
+pub async fn resolve_report(
+    auth: Auth,
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+    report_id: i64,
+    resolution: Json<ReportResolution>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    let resolution = resolution.into_inner();
+    let admin = match get_admin(&pg_con, auth.id).await {
+        Ok(admin) => admin,
+        Err((status, e)) => return (status, Err(e)),
+    };
+    if let Err((status, e)) = check_admin_totp(&pg_con, kv_conn.as_mut(), &auth).await {
+        return (status, Err(e));
+    }
+    let txn = match pg_con.begin().await {
+        Ok(txn) => txn,
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    let report = match Report::find_by_id(report_id).one(&txn).await {
+        Ok(Some(report)) => report,
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Err(Json(ErrorResponse::build(ErrorCode::ReportNotExist, ""))),
+            )
+        }
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    if report.report_state != ReportState::Pending.to_i32() {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::ReportNotExist,
+                "Report has been resolved.",
+            ))),
+        );
+    }
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let state = match resolution {
+        ReportResolution::Ban => ReportState::Banned,
+        ReportResolution::Dismiss => ReportState::Dismissed,
+    };
+    if let ReportResolution::Ban = resolution {
+        let operation = ReportDisplay::from(&report).operation;
+        let (status, res) = match execute_admin_operation(&admin, operation, &txn).await {
+            Ok(response) => response,
+            Err((status, e)) => return (status, Err(e)),
+        };
+        if res.is_err() {
+            // keep the audit entry of the failed operation, the report stays pending
+            if let Err(e) = txn.commit().await {
+                log::error!("[ADMIN] Database Error: {:?}", e);
+                return (
+                    Status::InternalServerError,
+                    Err(Json(ErrorResponse::default())),
+                );
+            }
+            return (status, res);
+        }
+    }
+    // a concurrent resolution of the report waits for this transaction and then
+    // finds it resolved, which rolls back its operation
+    let pending = Condition::all()
+        .add(db::report::Column::ReportId.eq(report.report_id))
+        .add(db::report::Column::ReportState.eq(ReportState::Pending.to_i32()));
+    match resolve_reports(&txn, pending, state, admin.uid, now).await {
+        Ok(0) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::ReportNotExist,
+                    "Report has been resolved.",
+                ))),
+            )
+        }
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    if let ReportResolution::Ban = resolution {
+        let same_target = Condition::all()
+            .add(db::report::Column::ReportState.eq(ReportState::Pending.to_i32()))
+            .add(db::report::Column::TargetType.eq(report.target_type))
+            .add(db::report::Column::PostId.eq(report.post_id))
+            .add(db::report::Column::ReplyId.eq(report.reply_id))
+            .add(db::report::Column::BurrowId.eq(report.burrow_id));
+        if let Err(e) = resolve_reports(&txn, same_target, state, admin.uid, now).await {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    match txn.commit().await {
+        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Mark the reports matching `condition` as resolved by an admin
+///
+/// ## Returns
+///
+/// The number of reports resolved.
+async fn resolve_reports<C: ConnectionTrait>(
+    db: &C,
+    condition: Condition,
+    state: ReportState,
+    operator: i64,
+    now: DateTime<FixedOffset>,
+) -> Result<u64, DbErr> {
+    Report::update_many()
+        .col_expr(db::report::Column::ReportState, Expr::value(state.to_i32()))
+        .col_expr(db::report::Column::Operator, Expr::value(operator))
+        .col_expr(db::report::Column::ResolveTime, Expr::value(now))
+        .filter(condition)
+        .exec(db)
+        .await
+        .map(|res| res.rows_affected)
+}
+
+/// Get Admin Audit Log
+///
+/// Show admin operations, newest first. Only admins with a role above 0 can
+/// read the log, and only the entries of admins whose role is not higher than
+/// their own.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Option<usize>`: page number, default value 0
+/// - `Option<i64>`: only show operations of this admin
+/// - `Option<String>`: only show operations of this kind, e.g. `BanUser`
+/// - `Option<AuditTarget>`: only show operations on this type of target, e.g. `post`
+/// - `Option<i64>`: only show operations on this target id
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<AuditDisplay>>`: Audit log entries of the page
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::DatabaseErr`
+#[get("/audit?<page>&<uid>&<kind>&<target_type>&<target_id>")]
+pub async fn get_audit_log(
+    auth: Auth,
+    db: Connection<PgDb>,
+    page: Option<usize>,
+    uid: Option<i64>,
+    kind: Option<String>,
+    target_type: Option<AuditTarget>,
+    target_id: Option<i64>,
+) -> (Status, Result<Json<Vec<AuditDisplay>>, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let page = page.unwrap_or(0);
+    let admin = match get_admin(&pg_con, auth.id).await {
+        Ok(admin) if admin.role > 0 => admin,
+        Ok(_) => {
+            return (
+                Status::Forbidden,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::UserForbidden,
+                    "Permission denied.",
+                ))),
+            )
+        }
+        Err((status, e)) => return (status, Err(e)),
+    };
+    let mut condition = Condition::all().add(db::admin_audit::Column::Role.lte(admin.role));
+    if let Some(uid) = uid {
+        condition = condition.add(db::admin_audit::Column::Uid.eq(uid));
+    }
+    if let Some(kind) = kind {
+        condition = condition.add(db::admin_audit::Column::OperationKind.eq(kind));
+    }
+    if let Some(target_type) = target_type {
+        condition = condition.add(db::admin_audit::Column::TargetType.eq(target_type.to_i32()));
+    }
+    if let Some(target_id) = target_id {
+        condition = condition.add(db::admin_audit::Column::TargetId.eq(target_id));
+    }
+    match AdminAudit::find()
+        .filter(condition)
+        .order_by_desc(db::admin_audit::Column::AuditId)
+        .paginate(&pg_con, AUDIT_PER_PAGE)
+        .fetch_page(page)
+        .await
+    {
+        Ok(entries) => (
+            Status::Ok,
+            Ok(Json(entries.iter().map(|a| a.into()).collect())),
+        ),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Get Rules of Sensitive Word Filter
+///
+/// Rules are added and deleted through `AdminOperation::AddFilterRule` and
+/// `AdminOperation::DeleteFilterRule`.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<FilterRuleDisplay>>`: All the rules
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::DatabaseErr`
+#[get("/filters")]
+pub async fn get_filter_rules(
+    auth: Auth,
+    db: Connection<PgDb>,
+) -> (
+    Status,
+    Result<Json<Vec<FilterRuleDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    if let Err((status, e)) = get_admin(&pg_con, auth.id).await {
+        return (status, Err(e));
+    }
+    match SensitiveWord::find()
+        .order_by_asc(db::sensitive_word::Column::WordId)
+        .all(&pg_con)
+        .await
+    {
+        Ok(words) => (
+            Status::Ok,
+            Ok(Json(words.iter().map(|w| w.into()).collect())),
+        ),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Get Login Lockouts
+///
+/// Show the usernames and ips locked for too many failed logins, the longest
+/// lockout first.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<LockoutDisplay>>`: All the current lockouts
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::DatabaseErr`
+#[get("/lockouts")]
+pub async fn get_lockouts(
+    auth: Auth,
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+) -> (
+    Status,
+    Result<Json<Vec<LockoutDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    if let Err((status, e)) = get_admin(&pg_con, auth.id).await {
+        return (status, Err(e));
+    }
+    match login_guard::get_lockouts(kv_conn.as_mut()).await {
+        Ok(lockouts) => (Status::Ok, Ok(Json(lockouts))),
+        Err(e) => (Status::InternalServerError, Err(Json(e))),
+    }
+}
+
+/// Clear Login Lockout
+///
+/// Clear the failed logins and lockout of a username and/or an ip, so that
+/// logins are allowed again at once. The action is appended to the audit log as
+/// `ClearLockout`.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+/// - `Option<String>`: username to clear
+/// - `Option<String>`: ip to clear
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::EmptyField`
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::TotpRequired`
+///   - `ErrorCode::DatabaseErr`
+#[delete("/lockouts?<username>&<ip>")]
+pub async fn clear_lockout(
+    auth: Auth,
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+    username: Option<String>,
+    ip: Option<String>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    let admin = match get_admin(&pg_con, auth.id).await {
+        Ok(admin) => admin,
+        Err((status, e)) => return (status, Err(e)),
+    };
+    if let Err((status, e)) = check_admin_totp(&pg_con, kv_conn.as_mut(), &auth).await {
+        return (status, Err(e));
+    }
+    let operation = serde_json::json!({ "username": username, "ip": ip }).to_string();
+    let targets: Vec<(LockoutTarget, String)> =
+        vec![(LockoutTarget::User, username), (LockoutTarget::Ip, ip)]
+            .into_iter()
+            .filter_map(|(target, value)| value.map(|v| (target, v)))
+            .collect();
+    if targets.is_empty() {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::EmptyField,
+                "Username or ip is required.",
+            ))),
+        );
+    }
+    let mut res = Ok("Success".to_string());
+    let mut status = Status::Ok;
+    for (target, value) in targets {
+        match login_guard::clear_failures(target, &value, kv_conn.as_mut()).await {
+            Ok(_) => log::info!(
+                "[ADMIN] {} cleared lockout of {} {}",
+                auth.id,
+                target.to_str(),
+                value
+            ),
+            Err(e) => {
+                status = Status::InternalServerError;
+                res = Err(Json(e));
+                break;
+            }
+        }
+    }
+    write_audit(
+        &pg_con,
+        audit_entry(
+            &admin,
+            "ClearLockout",
+            operation,
+            AuditTarget::Lockout,
+            (0, 0),
+            status,
+            &res,
+        ),
+    )
+    .await;
+    (status, res)
+}
+
+/// Get Dead Letters
+///
+/// Show the messages which the consumers of task-executor failed to handle, the
+/// latest first.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Option<usize>`: page number, from 0
+/// - `Option<String>`: only show the dead letters of a topic, e.g. `search`
+/// - `Option<bool>`: only show the dead letters which have or have not been replayed
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<DeadLetterDisplay>>`: Dead letters of the page
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::DatabaseErr`
+#[get("/dead-letters?<page>&<topic>&<replayed>")]
+pub async fn get_dead_letters(
+    auth: Auth,
+    db: Connection<PgDb>,
+    page: Option<usize>,
+    topic: Option<String>,
+    replayed: Option<bool>,
+) -> (
+    Status,
+    Result<Json<Vec<DeadLetterDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let page = page.unwrap_or(0);
+    if let Err((status, e)) = get_admin(&pg_con, auth.id).await {
+        return (status, Err(e));
+    }
+    let mut condition = Condition::all();
+    if let Some(topic) = topic {
+        condition = condition.add(db::dead_letter::Column::Topic.eq(topic));
+    }
+    match replayed {
+        Some(true) => condition = condition.add(db::dead_letter::Column::ReplayTime.is_not_null()),
+        Some(false) => condition = condition.add(db::dead_letter::Column::ReplayTime.is_null()),
+        None => {}
+    }
+    match DeadLetter::find()
+        .filter(condition)
+        .order_by_desc(db::dead_letter::Column::Id)
+        .paginate(&pg_con, DEAD_LETTER_PER_PAGE)
+        .fetch_page(page)
+        .await
+    {
+        Ok(letters) => (
+            Status::Ok,
+            Ok(Json(letters.into_iter().map(|d| d.into()).collect())),
+        ),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Replay Dead Letter
+///
+/// Send a dead letter back to the topic it comes from through the outbox, after
+/// the cause of the failure has been fixed. A dead letter can be replayed more
+/// than once, the consumers tolerate duplicates. The replay is appended to the
+/// audit log as `ReplayDeadLetter` in the same transaction.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated admin
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+/// - `i64`: id of the dead letter
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<DeadLetterDisplay>`: The dead letter with the time of the replay
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::TotpRequired`
+///   - `ErrorCode::DeadLetterNotExist`
+///   - `ErrorCode::DatabaseErr`
+#[post("/dead-letters/<id>/replay")]
+pub async fn replay_dead_letter(
+    auth: Auth,
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+    id: i64,
+) -> (Status, Result<Json<DeadLetterDisplay>, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    let admin = match get_admin(&pg_con, auth.id).await {
+        Ok(admin) => admin,
+        Err((status, e)) => return (status, Err(e)),
+    };
+    if let Err((status, e)) = check_admin_totp(&pg_con, kv_conn.as_mut(), &auth).await {
+        return (status, Err(e));
+    }
+    let operation = serde_json::json!({ "id": id }).to_string();
+    let auditor = admin.clone();
+    let audit_operation = operation.clone();
+    let (status, res) = match pg_con
+        .transaction::<_, Option<db::dead_letter::Model>, DbErr>(|txn| {
+            Box::pin(async move {
+                let letter = match DeadLetter::find_by_id(id).one(txn).await? {
+                    Some(letter) => dead_letter::replay(txn, letter).await?,
+                    None => return Ok(None),
+                };
+                let res: Result<(), Json<ErrorResponse>> = Ok(());
+                audit_entry(
+                    &auditor,
+                    "ReplayDeadLetter",
+                    audit_operation,
+                    AuditTarget::DeadLetter,
+                    (id, 0),
+                    Status::Ok,
+                    &res,
+                )
+                .insert(txn)
+                .await?;
+                Ok(Some(letter))
+            })
+        })
+        .await
+    {
+        Ok(Some(letter)) => {
+            log::info!(
+                "[ADMIN] {} replayed dead letter {} to {}",
+                auth.id,
+                id,
+                letter.topic
+            );
+            return (Status::Ok, Ok(Json(letter.into())));
+        }
+        Ok(None) => (
+            Status::NotFound,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::DeadLetterNotExist,
+                format!("Dead letter {} does not exist.", id),
+            ))),
+        ),
+        Err(e) => {
+            log::error!("[ADMIN] Database Error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    };
+    write_audit(
+        &pg_con,
+        audit_entry(
+            &admin,
+            "ReplayDeadLetter",
+            operation,
+            AuditTarget::DeadLetter,
+            (id, 0),
+            status,
+            &res,
+        ),
+    )
+    .await;
+    (status, res)
+}
+
 /// Set Admin account when in test
 ///
 /// ## Parameters
@@ -5,15 +5,18 @@ use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::{Build, Rocket};
 use rocket_db_pools::Connection;
+use sea_orm::sea_query::Expr;
 use sea_orm::{entity::*, query::*, DbBackend, DbErr};
 
-use crate::config::burrow::{BURROW_CREATE_DURATION, BURROW_LIMIT};
+use crate::config::burrow::BURROW_LIMIT;
 use crate::config::content::REPLY_PER_PAGE;
+use crate::config::settings::CONFIG;
 use crate::db::{self, prelude::*};
-use crate::models::{burrow::*, content::Post, error::*, pulsar::*};
-use crate::pool::{PgDb, PulsarMq};
+use crate::models::{burrow::*, content::Post, cursor::Cursor, error::*, pulsar::*};
+use crate::pool::PgDb;
 use crate::utils::auth::Auth;
 use crate::utils::burrow_valid::*;
+use crate::utils::outbox;
 
 pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount(
@@ -83,7 +86,6 @@ pub async fn get_total_burrow_count(
 /// - `Auth`: Authenticated user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Json<BurrowInfo>`: Burrow information
-/// - `Connection<PulsarMq>`: Pulsar connection
 ///
 /// ## Returns
 ///
@@ -104,7 +106,6 @@ pub async fn get_total_burrow_count(
 pub async fn create_burrow(
     db: Connection<PgDb>,
     burrow_info: Json<BurrowInfo>,
-    mut producer: Connection<PulsarMq>,
     auth: Auth,
 ) -> (
     Status,
@@ -127,7 +128,7 @@ pub async fn create_burrow(
                 let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
                 if state
                     .update_time
-                    .checked_add_signed(Duration::seconds(*BURROW_CREATE_DURATION))
+                    .checked_add_signed(Duration::seconds(CONFIG.burrow.create_duration))
                     .unwrap()
                     > now
                 {
@@ -139,9 +140,17 @@ pub async fn create_burrow(
                         ))),
                     );
                 }
-                let valid_burrows = get_burrow_list(&state.valid_burrow);
-                let banned_burrows = get_burrow_list(&state.banned_burrow);
-                if banned_burrows.len() + valid_burrows.len() < BURROW_LIMIT {
+                let owned_burrows = match count_owned_burrows(&pg_con, auth.id).await {
+                    Ok(n) => n,
+                    Err(e) => {
+                        error!("[CREATE BURROW] Database Error: {:?}", e);
+                        return (
+                            Status::InternalServerError,
+                            Err(Json(ErrorResponse::default())),
+                        );
+                    }
+                };
+                if owned_burrows < BURROW_LIMIT {
                     // get burrow info from request
                     let burrow = burrow_info.into_inner();
                     // check if Burrow Title is empty, return corresponding error if so
@@ -179,27 +188,13 @@ pub async fn create_burrow(
                                 };
                                 let uid = res.uid;
                                 ust.update_time = Set(now);
-                                ust.valid_burrow = {
-                                    let mut valid_burrows: Vec<i64> =
-                                        get_burrow_list(&ust.valid_burrow.unwrap());
-                                    valid_burrows.push(burrow_id);
-                                    let valid_burrows_str = valid_burrows
-                                        .iter()
-                                        .map(|x| x.to_string())
-                                        .collect::<Vec<String>>()
-                                        .join(",");
-                                    Set(valid_burrows_str)
-                                };
                                 ust.update(txn).await?;
                                 info!(
                                     "[Create-Burrow] successfully create burrow {} for user {}",
                                     burrow_id, uid
                                 );
-                                // TODO: move them out of the transaction
                                 let msg = PulsarSearchData::CreateBurrow(pulsar_burrow);
-                                let _ = producer
-                                    .send("persistent://public/default/search", msg)
-                                    .await;
+                                outbox::enqueue_search(txn, msg).await?;
                                 Ok(BurrowCreateResponse { burrow_id })
                             })
                         })
@@ -259,7 +254,6 @@ pub async fn create_burrow(
 /// ## Errors
 ///
 /// - `ErrorResponse`: Error message
-///   - `ErrorCode::UserNotExist`
 ///   - `ErrorCode::UserForbidden`
 ///   - `ErrorCode::DatabaseErr`
 ///
@@ -270,103 +264,32 @@ pub async fn discard_burrow(
     auth: Auth,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
-    match UserStatus::find_by_id(auth.id).one(&pg_con).await {
-        Ok(opt_ust) => match opt_ust {
-            Some(state) => {
-                let mut valid_burrows: Vec<i64> = get_burrow_list(&state.valid_burrow);
-                let mut banned_burrows: Vec<i64> = get_burrow_list(&state.banned_burrow);
-                let mut ac_state: db::user_status::ActiveModel = state.into();
-                // update valid_burrow / banned_burrow in user_status table
-                // do some type-convert things, and fill in the row according to different situations
-                if valid_burrows.contains(&burrow_id) {
-                    valid_burrows.remove(valid_burrows.binary_search(&burrow_id).unwrap());
-                    let valid_burrows_str = valid_burrows
-                        .iter()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",");
-                    ac_state.valid_burrow = Set(valid_burrows_str);
-                    // update table user_status
-                    match pg_con
-                        .transaction::<_, (), DbErr>(|txn| {
-                            Box::pin(async move {
-                                ac_state.update(txn).await?;
-                                let ac_burrow: db::burrow::ActiveModel = db::burrow::ActiveModel {
-                                    burrow_id: Set(burrow_id),
-                                    burrow_state: Set(2),
-                                    ..Default::default()
-                                };
-                                ac_burrow.update(txn).await?;
-                                info!("[DISCARD-BURROW] Burrow {} discarded.", burrow_id);
-                                Ok(())
-                            })
-                        })
-                        .await
-                    {
-                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
-                        Err(e) => {
-                            error!("Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
-                        }
-                    }
-                } else if banned_burrows.contains(&burrow_id) {
-                    banned_burrows.remove(banned_burrows.binary_search(&burrow_id).unwrap());
-                    let banned_burrows_str = banned_burrows
-                        .iter()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",");
-                    ac_state.banned_burrow = Set(banned_burrows_str);
-                    // update table user_status
-                    match pg_con
-                        .transaction::<_, (), DbErr>(|txn| {
-                            Box::pin(async move {
-                                ac_state.update(txn).await?;
-                                let ac_burrow: db::burrow::ActiveModel = db::burrow::ActiveModel {
-                                    burrow_id: Set(burrow_id),
-                                    burrow_state: Set(3),
-                                    ..Default::default()
-                                };
-                                ac_burrow.update(txn).await?;
-                                info!("[DISCARD-BURROW] Burrow {} discarded.", burrow_id);
-                                Ok(())
-                            })
-                        })
-                        .await
-                    {
-                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
-                        Err(e) => {
-                            error!("Database error: {:?}", e);
-                            (
-                                Status::InternalServerError,
-                                Err(Json(ErrorResponse::default())),
-                            )
-                        }
-                    }
-                } else {
-                    info!(
-                        "[DEL-BURROW] Cannot delete burrow: Burrow doesn't belong to current user."
-                    );
-                    (
-                        Status::Forbidden,
-                        Err(Json(ErrorResponse::build(
-                            ErrorCode::UserForbidden,
-                            "Burrow doesn't belong to current user or already be discarded",
-                        ))),
-                    )
-                }
-            }
-            None => {
-                info!("[DEL-BURROW] Cannot find user_status by uid.");
-                (
-                    Status::BadRequest,
-                    Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
-                )
-            }
-        },
+    // mark the burrow as discarded if it belongs to the user and is not discarded yet
+    match Burrow::update_many()
+        .col_expr(
+            db::burrow::Column::BurrowState,
+            Expr::col(db::burrow::Column::BurrowState).add(DISCARDED_STATE),
+        )
+        .filter(db::burrow::Column::BurrowId.eq(burrow_id))
+        .filter(db::burrow::Column::Uid.eq(auth.id))
+        .filter(db::burrow::Column::BurrowState.lt(DISCARDED_STATE))
+        .exec(&pg_con)
+        .await
+    {
+        Ok(res) if res.rows_affected == 1 => {
+            info!("[DISCARD-BURROW] Burrow {} discarded.", burrow_id);
+            (Status::Ok, Ok("Success".to_string()))
+        }
+        Ok(_) => {
+            info!("[DEL-BURROW] Cannot delete burrow: Burrow doesn't belong to current user.");
+            (
+                Status::Forbidden,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::UserForbidden,
+                    "Burrow doesn't belong to current user or already be discarded",
+                ))),
+            )
+        }
         Err(e) => {
             error!("[DEL-BURROW] Database Error: {:?}", e);
             (
@@ -385,6 +308,7 @@ pub async fn discard_burrow(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `i64`: Burrow id
 /// - `Option<usize>`: Page number for burrow
+/// - `Option<String>`: Cursor returned with the previous page, `page` is ignored if set
 ///
 /// ## Returns
 ///
@@ -395,13 +319,15 @@ pub async fn discard_burrow(
 ///
 /// - `ErrorResponse`: Error message
 ///   - `ErrorCode::BurrowNotExist`
+///   - `ErrorCode::CursorInvalid`
 ///   - `ErrorCode::DatabaseErr`
 ///
-#[get("/<burrow_id>?<page>")]
+#[get("/<burrow_id>?<page>&<cursor>")]
 pub async fn show_burrow(
     db: Connection<PgDb>,
     burrow_id: i64,
     page: Option<usize>,
+    cursor: Option<String>,
     _auth: Auth,
 ) -> (
     Status,
@@ -409,21 +335,51 @@ pub async fn show_burrow(
 ) {
     let pg_con = db.into_inner();
     let page = page.unwrap_or(0);
+    let cursor = match cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(Cursor::Post { post_id })) => Some(post_id),
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::CursorInvalid,
+                    "Invalid cursor.",
+                ))),
+            )
+        }
+    };
     match Burrow::find_by_id(burrow_id).one(&pg_con).await {
         Ok(opt_burrow) => match opt_burrow {
             Some(burrow) => {
-                match ContentPost::find()
+                let post_select = ContentPost::find()
                     .filter(db::content_post::Column::BurrowId.eq(burrow_id))
-                    .order_by_desc(db::content_post::Column::PostId)
-                    .paginate(&pg_con, REPLY_PER_PAGE)
-                    .fetch_page(page)
-                    .await
-                {
+                    .order_by_desc(db::content_post::Column::PostId);
+                let posts = match cursor {
+                    Some(post_id) => {
+                        post_select
+                            .filter(db::content_post::Column::PostId.lt(post_id))
+                            .limit(REPLY_PER_PAGE as u64)
+                            .all(&pg_con)
+                            .await
+                    }
+                    None => {
+                        post_select
+                            .paginate(&pg_con, REPLY_PER_PAGE)
+                            .fetch_page(page)
+                            .await
+                    }
+                };
+                match posts {
                     Ok(posts) => (
                         Status::Ok,
                         Ok(Json(BurrowShowResponse {
                             title: burrow.title,
                             description: burrow.description,
+                            next_cursor: Cursor::next_token(
+                                posts.len(),
+                                REPLY_PER_PAGE,
+                                posts.last().map(|p| Cursor::Post { post_id: p.post_id }),
+                            ),
                             posts: {
                                 let posts_info: Vec<Post> =
                                     posts.iter().map(|post| post.into()).collect();
@@ -466,7 +422,6 @@ pub async fn show_burrow(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `i64`: Burrow id
 /// - `Json<BurrowInfo>`: Burrow information
-/// - `Connection<PulsarMq>`: Pulsar connection
 ///
 /// ## Returns
 ///
@@ -486,7 +441,6 @@ pub async fn update_burrow(
     db: Connection<PgDb>,
     burrow_id: i64,
     burrow_info: Json<BurrowInfo>,
-    mut producer: Connection<PulsarMq>,
     auth: Auth,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
@@ -503,6 +457,16 @@ pub async fn update_burrow(
     match UserStatus::find_by_id(auth.id).one(&pg_con).await {
         Ok(opt_ust) => match opt_ust {
             Some(state) => {
+                let valid_burrow = match is_valid_burrow(&pg_con, auth.id, burrow_id).await {
+                    Ok(valid) => valid,
+                    Err(e) => {
+                        error!("[UPDATE-BURROW] Database Error: {:?}", e);
+                        return (
+                            Status::InternalServerError,
+                            Err(Json(ErrorResponse::default())),
+                        );
+                    }
+                };
                 if state.user_state != 0 {
                     (
                         Status::Forbidden,
@@ -511,7 +475,7 @@ pub async fn update_burrow(
                             "User not in a valid state",
                         ))),
                     )
-                } else if is_valid_burrow(&state.valid_burrow, &burrow_id) {
+                } else if valid_burrow {
                     let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
                     let burrows = db::burrow::ActiveModel {
                         burrow_id: Set(burrow_id),
@@ -526,14 +490,17 @@ pub async fn update_burrow(
                         description: burrow.description,
                         update_time: now,
                     };
-                    match burrows.update(&pg_con).await {
-                        Ok(_) => {
-                            let msg = PulsarSearchData::UpdateBurrow(pulsar_burrow);
-                            let _ = producer
-                                .send("persistent://public/default/search", msg)
-                                .await;
-                            (Status::Ok, Ok("Success".to_string()))
-                        }
+                    match pg_con
+                        .transaction::<_, (), DbErr>(|txn| {
+                            Box::pin(async move {
+                                burrows.update(txn).await?;
+                                let msg = PulsarSearchData::UpdateBurrow(pulsar_burrow);
+                                outbox::enqueue_search(txn, msg).await
+                            })
+                        })
+                        .await
+                    {
+                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
                         Err(e) => {
                             error!("[UPDATE-BURROW] Database Error: {:?}", e);
                             (
@@ -5,23 +5,30 @@ use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::{Build, Rocket};
 use rocket_db_pools::Connection;
-use sea_orm::sea_query::Expr;
+use sea_orm::sea_query::{Alias, Expr};
 use sea_orm::{
-    entity::*, ActiveModelTrait, Condition, DbBackend, DbErr, PaginatorTrait, QueryFilter,
-    QueryOrder, Statement, TransactionTrait,
+    entity::*, ActiveModelTrait, Condition, DatabaseConnection, DbBackend, DbErr, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Select, SelectModel, Selector, Statement,
+    TransactionTrait,
 };
 use std::collections::HashMap;
 
 use crate::config::content::{
-    MAX_SECTION, MAX_TAG, POST_DELETE_DURATION, POST_PER_PAGE, REPLY_PER_PAGE,
+    MAX_POLL_OPTION, MAX_POLL_OPTION_LEN, MAX_REPORT_DESCRIPTION_LEN, MAX_SECTION, MAX_TAG,
+    POST_PER_PAGE, REPLY_PER_PAGE,
 };
+use crate::config::settings::CONFIG;
 use crate::db::{self, prelude::*};
 use crate::models::search::SearchPostData;
-use crate::models::{content::*, error::*, pulsar::*};
-use crate::pool::{PgDb, PulsarMq, Search, TypesenseSearch};
+use crate::models::{content::*, cursor::Cursor, error::*, pulsar::*, report::*};
+use crate::pool::{PgDb, Search, TypesenseSearch};
 use crate::utils::auth::Auth;
 use crate::utils::burrow_valid::is_valid_burrow;
+use crate::utils::content_filter::{filter_content, flag_content};
 use crate::utils::dedup::remove_duplicate;
+use crate::utils::markdown::render_content;
+use crate::utils::outbox;
+use crate::utils::rate_limit::{PostCreate, RateLimit, ReplyCreate};
 
 pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount(
@@ -32,9 +39,12 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
             update_post,
             delete_post,
             read_post_list,
+            read_feed,
             create_reply,
             update_reply,
             get_total_post_count,
+            create_report,
+            vote_poll,
         ],
     )
 }
@@ -92,9 +102,9 @@ pub async fn get_total_post_count(
 /// ## Parameters
 ///
 /// - `Auth`: Authenticated user
+/// - `RateLimit<PostCreate>`: Rate limit of the user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Json<PostInfo>`: Post information
-/// - `Connection<PulsarMq>`: Pulsar connection
 ///
 /// ## Returns
 ///
@@ -109,21 +119,26 @@ pub async fn get_total_post_count(
 ///   - `ErrorCode::UserNotExist`
 ///   - `ErrorCode::UserForbidden`
 ///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::ContentSensitive`
+///   - `ErrorCode::ImageInvalid`
+///   - `ErrorCode::PollInvalid`
 ///   - `ErrorCode::DatabaseErr`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///   - `ErrorCode::RateLimit`
 ///
 #[post("/posts", data = "<post_info>", format = "json")]
 pub async fn create_post(
     auth: Auth,
+    _limit: RateLimit<PostCreate>,
     db: Connection<PgDb>,
     post_info: Json<PostInfo>,
-    mut producer: Connection<PulsarMq>,
 ) -> (
     Status,
     Result<Json<PostCreateResponse>, Json<ErrorResponse>>,
 ) {
     let pg_con = db.into_inner();
     // get content info from request
-    let content = post_info.into_inner();
+    let mut content = post_info.into_inner();
     // check if title, author and section is empty
     if content.title.is_empty() {
         return (
@@ -152,6 +167,58 @@ pub async fn create_post(
             ))),
         );
     }
+    // check the options and close time of the poll
+    if let Some(poll) = &content.poll {
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        if poll.options.len() < 2
+            || poll.options.len() > MAX_POLL_OPTION
+            || poll
+                .options
+                .iter()
+                .any(|o| o.is_empty() || o.chars().count() > MAX_POLL_OPTION_LEN)
+            || poll.close_time.map_or(false, |t| t <= now)
+        {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::PollInvalid,
+                    "Wrong poll options or close time.",
+                ))),
+            );
+        }
+    }
+    // filter sensitive words
+    let title_flagged = match filter_content(&content.title) {
+        Ok((title, flagged)) => {
+            content.title = title;
+            flagged
+        }
+        Err((status, e)) => return (status, Err(e)),
+    };
+    let content_flagged = match filter_content(&content.content) {
+        Ok((text, flagged)) => {
+            content.content = text;
+            flagged
+        }
+        Err((status, e)) => return (status, Err(e)),
+    };
+    let mut poll_flagged = false;
+    if let Some(poll) = content.poll.as_mut() {
+        for option in poll.options.iter_mut() {
+            match filter_content(option) {
+                Ok((text, flagged)) => {
+                    *option = text;
+                    poll_flagged |= flagged;
+                }
+                Err((status, e)) => return (status, Err(e)),
+            }
+        }
+    }
+    // render markdown after checking the images it embeds
+    let content_html = match render_content(&pg_con, auth.id, &content.content).await {
+        Ok(html) => html,
+        Err((status, e)) => return (status, Err(e)),
+    };
     // check if user has been banned
     match UserStatus::find_by_id(auth.id).one(&pg_con).await {
         Ok(ust) => match ust {
@@ -163,6 +230,13 @@ pub async fn create_post(
                 )
             }
             Some(user_state_info) => {
+                let valid_burrow =
+                    match check_valid_burrow(&pg_con, auth.id, content.burrow_id, "CREATE-POST")
+                        .await
+                    {
+                        Ok(valid) => valid,
+                        Err((status, e)) => return (status, Err(e)),
+                    };
                 if user_state_info.user_state != 0 {
                     (
                         Status::Forbidden,
@@ -171,7 +245,11 @@ pub async fn create_post(
                             "User not in a valid state",
                         ))),
                     )
-                } else if is_valid_burrow(&user_state_info.valid_burrow, &content.burrow_id) {
+                } else if valid_burrow {
+                    let post_type = match content.poll {
+                        Some(_) => PostType::Poll,
+                        None => PostType::Normal,
+                    };
                     match pg_con
                         .transaction::<_, i64, DbErr>(|txn| {
                             Box::pin(async move {
@@ -187,12 +265,22 @@ pub async fn create_post(
                                     update_time: Set(now.to_owned()),
                                     section: Set(serde_json::to_string(&section).unwrap()),
                                     tag: Set(tag.join(",")),
+                                    post_type: Set(post_type.to_i32()),
                                     ..Default::default()
                                 };
                                 // insert the row in database
                                 let post_res = content_post.insert(txn).await?;
                                 let post_id = post_res.post_id;
                                 log::info!("[CREATE-POST] create post: {}", post_id);
+                                if let Some(poll) = content.poll {
+                                    let poll = db::poll::ActiveModel {
+                                        post_id: Set(post_id),
+                                        options: Set(serde_json::to_string(&poll.options).unwrap()),
+                                        multiple: Set(poll.multiple),
+                                        close_time: Set(poll.close_time),
+                                    };
+                                    poll.insert(txn).await?;
+                                }
                                 // fill the row in content_reply
                                 let content_reply = db::content_reply::ActiveModel {
                                     post_id: Set(post_id),
@@ -201,6 +289,7 @@ pub async fn create_post(
                                     create_time: Set(now.to_owned()),
                                     update_time: Set(now.to_owned()),
                                     content: Set(content.content.to_owned()),
+                                    content_html: Set(content_html),
                                     ..Default::default()
                                 };
                                 let reply_res = content_reply.insert(txn).await?;
@@ -239,19 +328,25 @@ pub async fn create_post(
                                     update_time: now,
                                 };
                                 let msg = PulsarSearchData::CreatePost(pulsar_post);
-                                let _ = producer
-                                    .send("persistent://public/default/search", msg)
-                                    .await;
+                                outbox::enqueue_search(txn, msg).await?;
                                 let msg = PulsarSearchData::CreateReply(pulsar_reply);
-                                let _ = producer
-                                    .send("persistent://public/default/search", msg)
-                                    .await;
+                                outbox::enqueue_search(txn, msg).await?;
+                                let msg = PulsarNotificationData::NewPost {
+                                    post_id,
+                                    burrow_id: content.burrow_id,
+                                };
+                                outbox::enqueue_notification(txn, msg).await?;
                                 Ok(post_id)
                             })
                         })
                         .await
                     {
-                        Ok(post_id) => (Status::Ok, Ok(Json(PostCreateResponse { post_id }))),
+                        Ok(post_id) => {
+                            if title_flagged || content_flagged || poll_flagged {
+                                flag_content(&pg_con, ReportTarget::Post { post_id }).await;
+                            }
+                            (Status::Ok, Ok(Json(PostCreateResponse { post_id })))
+                        }
                         Err(e) => {
                             log::error!("[CREATE-POST] Database error: {:?}", e);
                             (
@@ -286,6 +381,8 @@ pub async fn create_post(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `i64`: Post id
 /// - `Option<usize>`: Page number for post
+/// - `Option<ReplyView>`: View mode of replies, `flat` by default
+/// - `Option<String>`: Cursor of replies returned with the previous page, `page` is ignored if set
 ///
 /// ## Returns
 ///
@@ -296,17 +393,37 @@ pub async fn create_post(
 ///
 /// - `ErrorResponse`: Error message
 ///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::CursorInvalid`
 ///   - `ErrorCode::DatabaseErr`
 ///
-#[get("/posts/<post_id>?<page>")]
+#[get("/posts/<post_id>?<page>&<mode>&<cursor>")]
 pub async fn read_post(
     auth: Auth,
     db: Connection<PgDb>,
     post_id: i64,
     page: Option<usize>,
+    mode: Option<ReplyView>,
+    cursor: Option<String>,
 ) -> (Status, Result<Json<PostPage>, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
     let page = page.unwrap_or(0);
+    let mode = mode.unwrap_or(ReplyView::Flat);
+    let cursor = match cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(Cursor::Reply {
+            post_id: cursor_post_id,
+            reply_id,
+        })) if cursor_post_id == post_id => Some(reply_id),
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::CursorInvalid,
+                    "Invalid cursor.",
+                ))),
+            )
+        }
+    };
     // check if the post not exists, add corresponding error if so
     match ContentPost::find_by_id(post_id).one(&pg_con).await {
         Ok(r) => match r {
@@ -321,11 +438,25 @@ pub async fn read_post(
                 // get post metadata
                 let reply_page: Vec<Reply> = match post_info.post_state {
                     0 => {
-                        let reply_pages = ContentReply::find()
+                        let reply_select = ContentReply::find()
                             .filter(db::content_reply::Column::PostId.eq(post_id))
-                            .order_by_asc(db::content_reply::Column::ReplyId)
-                            .paginate(&pg_con, REPLY_PER_PAGE);
-                        let reply_info = match reply_pages.fetch_page(page).await {
+                            .order_by_asc(db::content_reply::Column::ReplyId);
+                        let reply_info = match cursor {
+                            Some(reply_id) => {
+                                reply_select
+                                    .filter(db::content_reply::Column::ReplyId.gt(reply_id))
+                                    .limit(REPLY_PER_PAGE as u64)
+                                    .all(&pg_con)
+                                    .await
+                            }
+                            None => {
+                                reply_select
+                                    .paginate(&pg_con, REPLY_PER_PAGE)
+                                    .fetch_page(page)
+                                    .await
+                            }
+                        };
+                        let reply_info = match reply_info {
                             Ok(reply_info) => reply_info,
                             Err(e) => {
                                 log::error!("[READ-POST] Database error: {:?}", e);
@@ -339,6 +470,67 @@ pub async fn read_post(
                     }
                     _ => Vec::new(),
                 };
+                let reply_thread = match mode {
+                    ReplyView::Flat => Vec::new(),
+                    ReplyView::Nested => build_reply_thread(reply_page.clone()),
+                    ReplyView::Quoted => {
+                        // answered replies may lie on previous pages
+                        let mut parents: HashMap<i32, Reply> =
+                            reply_page.iter().map(|r| (r.reply_id, r.clone())).collect();
+                        let missing: Vec<i32> = reply_page
+                            .iter()
+                            .filter_map(|r| r.parent_reply_id)
+                            .filter(|id| !parents.contains_key(id))
+                            .collect();
+                        if !missing.is_empty() {
+                            match ContentReply::find()
+                                .filter(
+                                    Condition::all()
+                                        .add(db::content_reply::Column::PostId.eq(post_id))
+                                        .add(db::content_reply::Column::ReplyId.is_in(missing)),
+                                )
+                                .all(&pg_con)
+                                .await
+                            {
+                                Ok(replies) => {
+                                    parents.extend(replies.iter().map(|r| (r.reply_id, r.into())))
+                                }
+                                Err(e) => {
+                                    log::error!("[READ-POST] Database error: {:?}", e);
+                                    return (
+                                        Status::InternalServerError,
+                                        Err(Json(ErrorResponse::default())),
+                                    );
+                                }
+                            }
+                        }
+                        build_reply_quotes(reply_page.clone(), &parents)
+                    }
+                };
+                let poll = if post_info.post_state == 0
+                    && post_info.post_type == PostType::Poll.to_i32()
+                {
+                    match read_poll(&pg_con, post_id, auth.id).await {
+                        Ok(poll) => poll,
+                        Err(e) => {
+                            log::error!("[READ-POST] Database error: {:?}", e);
+                            return (
+                                Status::InternalServerError,
+                                Err(Json(ErrorResponse::default())),
+                            );
+                        }
+                    }
+                } else {
+                    None
+                };
+                let next_cursor = Cursor::next_token(
+                    reply_page.len(),
+                    REPLY_PER_PAGE,
+                    reply_page.last().map(|r| Cursor::Reply {
+                        post_id,
+                        reply_id: r.reply_id,
+                    }),
+                );
                 let post_desc: Post = post_info.into();
                 // check if the user collect the post, if so, update the state is_update
                 let record = db::user_collection::ActiveModel {
@@ -376,6 +568,9 @@ pub async fn read_post(
                         page,
                         like,
                         collection,
+                        reply_thread,
+                        poll,
+                        next_cursor,
                     })),
                 )
             }
@@ -398,7 +593,6 @@ pub async fn read_post(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `i64`: Post id
 /// - `Json<PostUpdateInfo>`: Updated post information
-/// - `Connection<PulsarMq`: Pulsar MQ connection
 ///
 /// ## Returns
 ///
@@ -414,6 +608,7 @@ pub async fn read_post(
 ///   - `ErrorCode::UserNotExist`
 ///   - `ErrorCode::UserForbidden`
 ///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::ContentSensitive`
 ///   - `ErrorCode::DatabaseErr`
 ///
 #[patch("/posts/<post_id>", data = "<post_info>", format = "json")]
@@ -422,10 +617,9 @@ pub async fn update_post(
     db: Connection<PgDb>,
     post_id: i64,
     post_info: Json<PostUpdateInfo>,
-    mut producer: Connection<PulsarMq>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
-    let content = post_info.into_inner();
+    let mut content = post_info.into_inner();
     // check if title, author and section is empty
     if content.title.is_empty() {
         return (
@@ -454,6 +648,14 @@ pub async fn update_post(
             ))),
         );
     }
+    // filter sensitive words
+    let flagged = match filter_content(&content.title) {
+        Ok((title, flagged)) => {
+            content.title = title;
+            flagged
+        }
+        Err((status, e)) => return (status, Err(e)),
+    };
     let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
     // check if the post not exists, add corresponding error if so
     match ContentPost::find_by_id(post_id).one(&pg_con).await {
@@ -478,6 +680,17 @@ pub async fn update_post(
                 match UserStatus::find_by_id(auth.id).one(&pg_con).await {
                     Ok(opt_state) => match opt_state {
                         Some(state) => {
+                            let valid_burrow = match check_valid_burrow(
+                                &pg_con,
+                                auth.id,
+                                post_info.burrow_id,
+                                "UPDATE-POST",
+                            )
+                            .await
+                            {
+                                Ok(valid) => valid,
+                                Err((status, e)) => return (status, Err(e)),
+                            };
                             // check if this user create the post
                             if state.user_state != 0 {
                                 (
@@ -487,33 +700,42 @@ pub async fn update_post(
                                         "User not in a valid state",
                                     ))),
                                 )
-                            } else if is_valid_burrow(&state.valid_burrow, &post_info.burrow_id) {
+                            } else if valid_burrow {
                                 // get tag and section string and remove duplicate
                                 let section = remove_duplicate(content.section);
                                 let tag = remove_duplicate(content.tag);
+                                let title = content.title;
                                 let content_post = db::content_post::ActiveModel {
                                     post_id: Set(post_id),
-                                    title: Set(content.title.to_owned()),
+                                    title: Set(title.to_owned()),
                                     update_time: Set(now.to_owned()),
                                     section: Set(serde_json::to_string(&section).unwrap()),
                                     tag: Set(tag.join(",")),
                                     ..Default::default()
                                 };
-
-                                match content_post.update(&pg_con).await {
-                                    Ok(r) => {
-                                        let pulsar_post = PulsarSearchPostData {
-                                            post_id,
-                                            title: content.title,
-                                            burrow_id: r.burrow_id,
-                                            section,
-                                            tag,
-                                            update_time: now,
-                                        };
-                                        let msg = PulsarSearchData::UpdatePost(pulsar_post);
-                                        let _ = producer
-                                            .send("persistent://public/default/search", msg)
-                                            .await;
+                                match pg_con
+                                    .transaction::<_, (), DbErr>(|txn| {
+                                        Box::pin(async move {
+                                            let r = content_post.update(txn).await?;
+                                            let pulsar_post = PulsarSearchPostData {
+                                                post_id,
+                                                title,
+                                                burrow_id: r.burrow_id,
+                                                section,
+                                                tag,
+                                                update_time: now,
+                                            };
+                                            let msg = PulsarSearchData::UpdatePost(pulsar_post);
+                                            outbox::enqueue_search(txn, msg).await
+                                        })
+                                    })
+                                    .await
+                                {
+                                    Ok(_) => {
+                                        if flagged {
+                                            flag_content(&pg_con, ReportTarget::Post { post_id })
+                                                .await;
+                                        }
                                         (Status::Ok, Ok("Success".to_string()))
                                     }
                                     Err(e) => {
@@ -569,7 +791,6 @@ pub async fn update_post(
 /// - `Auth`: Authenticated user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `i64`: Post id
-/// - `Connection<PulsarMq>`: Pulsar connection
 ///
 /// ## Returns
 ///
@@ -590,7 +811,6 @@ pub async fn delete_post(
     auth: Auth,
     db: Connection<PgDb>,
     post_id: i64,
-    mut producer: Connection<PulsarMq>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
     let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
@@ -608,7 +828,7 @@ pub async fn delete_post(
                 //  check if time is within limit, if so, allow user to delete
                 if post_info
                     .create_time
-                    .checked_add_signed(Duration::seconds(*POST_DELETE_DURATION))
+                    .checked_add_signed(Duration::seconds(CONFIG.content.delete_duration))
                     .unwrap()
                     < now
                 {
@@ -631,6 +851,17 @@ pub async fn delete_post(
                 match UserStatus::find_by_id(auth.id).one(&pg_con).await {
                     Ok(opt_state) => match opt_state {
                         Some(state) => {
+                            let valid_burrow = match check_valid_burrow(
+                                &pg_con,
+                                auth.id,
+                                post_info.burrow_id,
+                                "DELETE-POST",
+                            )
+                            .await
+                            {
+                                Ok(valid) => valid,
+                                Err((status, e)) => return (status, Err(e)),
+                            };
                             // check if this user create the post
                             if state.user_state != 0 {
                                 (
@@ -640,31 +871,34 @@ pub async fn delete_post(
                                         "User not in a valid state",
                                     ))),
                                 )
-                            } else if is_valid_burrow(&state.valid_burrow, &post_info.burrow_id) {
+                            } else if valid_burrow {
                                 // delete data in content_subject
                                 let delete_post: db::content_post::ActiveModel = post_info.into();
                                 match pg_con
                                     .transaction::<_, (), DbErr>(|txn| {
                                         Box::pin(async move {
+                                            // replies, likes and collections of the post are
+                                            // deleted with it by foreign keys
                                             delete_post.delete(txn).await?;
-                                            ContentReply::delete_many()
-                                                .filter(
-                                                    db::content_reply::Column::PostId.eq(post_id),
-                                                )
+                                            Poll::delete_many()
+                                                .filter(db::poll::Column::PostId.eq(post_id))
+                                                .exec(txn)
+                                                .await?;
+                                            PollVote::delete_many()
+                                                .filter(db::poll_vote::Column::PostId.eq(post_id))
+                                                .exec(txn)
+                                                .await?;
+                                            PollVoter::delete_many()
+                                                .filter(db::poll_voter::Column::PostId.eq(post_id))
                                                 .exec(txn)
                                                 .await?;
-                                            Ok(())
+                                            let msg = PulsarSearchData::DeletePost(post_id);
+                                            outbox::enqueue_search(txn, msg).await
                                         })
                                     })
                                     .await
                                 {
-                                    Ok(_) => {
-                                        let msg = PulsarSearchData::DeletePost(post_id);
-                                        let _ = producer
-                                            .send("persistent://public/default/search", msg)
-                                            .await;
-                                        (Status::Ok, Ok("Success".to_string()))
-                                    }
+                                    Ok(_) => (Status::Ok, Ok("Success".to_string())),
                                     Err(e) => {
                                         log::error!("[DELETE-POST] Database error: {:?}", e);
                                         (
@@ -719,6 +953,7 @@ pub async fn delete_post(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Option<usize>`: Page number for post
 /// - `Vec<String>`: Section of Post
+/// - `Option<String>`: Cursor returned with the previous page, `page` is ignored if set
 /// - `Connection<TypesenseSearch>`: Typesense connection
 ///
 /// ## Returns
@@ -730,25 +965,56 @@ pub async fn delete_post(
 ///
 /// - `ErrorResponse`: Error message
 ///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::CursorInvalid`
 ///   - `ErrorCode::DatabaseErr`
 ///
-#[get("/posts/list?<page>&<section>")]
+#[get("/posts/list?<page>&<section>&<cursor>")]
 pub async fn read_post_list(
     auth: Auth,
     db: Connection<PgDb>,
     page: Option<usize>,
     section: Vec<String>,
+    cursor: Option<String>,
     conn: Connection<TypesenseSearch>,
 ) -> (Status, Result<Json<ListPage>, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
     let page = page.unwrap_or(0);
     let client = conn.into_inner();
-    let post_info = if section.is_empty() {
-        let post_pages = ContentPost::find()
-            .order_by_desc(db::content_post::Column::PostId)
-            .paginate(&pg_con, POST_PER_PAGE);
-        let post_info = match post_pages.fetch_page(page).await {
-            Ok(post_info) => post_info,
+    let cursor = match cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(Cursor::Post { post_id })) => Some(post_id),
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::CursorInvalid,
+                    "Invalid cursor.",
+                ))),
+            )
+        }
+    };
+    let post_page = if section.is_empty() {
+        let post_select = ContentPost::find().order_by_desc(db::content_post::Column::PostId);
+        let post_page = match cursor {
+            Some(post_id) => {
+                select_post_display(
+                    post_select
+                        .filter(db::content_post::Column::PostId.lt(post_id))
+                        .limit(POST_PER_PAGE as u64),
+                    auth.id,
+                )
+                .all(&pg_con)
+                .await
+            }
+            None => {
+                select_post_display(post_select, auth.id)
+                    .paginate(&pg_con, POST_PER_PAGE)
+                    .fetch_page(page)
+                    .await
+            }
+        };
+        match post_page {
+            Ok(post_page) => post_page,
             Err(e) => {
                 log::error!("[READ-POST] Database error: {:?}", e);
                 return (
@@ -756,15 +1022,20 @@ pub async fn read_post_list(
                     Err(Json(ErrorResponse::default())),
                 );
             }
-        };
-        post_info
+        }
     } else {
-        let page = page + 1;
         let tags = serde_json::to_string(&section).unwrap();
-        let uri = format!(
-                "/collections/posts/documents/search?q=*&query_by=title&filter_by=section:={}&sort_by=post_id:desc&page={}&per_page=20",
-                tags,page
-            );
+        // `%20%26%26%20` is ` && ` joining the filters
+        let uri = match cursor {
+            Some(post_id) => format!(
+                "/collections/posts/documents/search?q=*&query_by=title&filter_by=section:={}%20%26%26%20post_id:<{}&sort_by=post_id:desc&page=1&per_page={}",
+                tags, post_id, POST_PER_PAGE
+            ),
+            None => format!(
+                "/collections/posts/documents/search?q=*&query_by=title&filter_by=section:={}&sort_by=post_id:desc&page={}&per_page={}",
+                tags, page + 1, POST_PER_PAGE
+            ),
+        };
         let response = match client.build_get(&uri).send().await {
             Ok(r) => match r.json::<SearchPostData>().await {
                 Ok(r) => r,
@@ -794,18 +1065,22 @@ pub async fn read_post_list(
             return (
                 Status::Ok,
                 Ok(Json(ListPage {
-                    page: page - 1,
+                    page,
                     post_page: Vec::new(),
+                    next_cursor: None,
                 })),
             );
         }
-        match ContentPost::find()
-            .filter(Condition::all().add(db::content_post::Column::PostId.is_in(post_ids)))
-            .order_by_desc(db::content_post::Column::PostId)
-            .all(&pg_con)
-            .await
+        match select_post_display(
+            ContentPost::find()
+                .filter(Condition::all().add(db::content_post::Column::PostId.is_in(post_ids)))
+                .order_by_desc(db::content_post::Column::PostId),
+            auth.id,
+        )
+        .all(&pg_con)
+        .await
         {
-            Ok(post_info) => post_info,
+            Ok(post_page) => post_page,
             Err(e) => {
                 log::error!("[READ-POST] Database error: {:?}", e);
                 return (
@@ -815,79 +1090,185 @@ pub async fn read_post_list(
             }
         }
     };
-    // check if the user collect and like the posts
     // TODO: check if the post is banned?
-    let post_ids = post_info.iter().map(|r| r.post_id).collect::<Vec<i64>>();
-    if post_ids.is_empty() {
-        log::info!("[READ-POST-LIST] Cannot find post id by this section.");
-        return (
-            Status::Ok,
-            Ok(Json(ListPage {
-                page,
-                post_page: Vec::new(),
-            })),
-        );
-    }
-    let like_res = match db::user_like::Entity::find()
-        .filter(
-            Condition::all()
-                .add(db::user_like::Column::Uid.eq(auth.id))
-                .add(db::user_like::Column::PostId.is_in(post_ids.clone())),
-        )
-        .order_by_desc(db::user_like::Column::PostId)
+    let next_cursor = Cursor::next_token(
+        post_page.len(),
+        POST_PER_PAGE,
+        post_page.last().map(|p| Cursor::Post {
+            post_id: p.post.post_id,
+        }),
+    );
+    (
+        Status::Ok,
+        Ok(Json(ListPage {
+            post_page,
+            page,
+            next_cursor,
+        })),
+    )
+}
+
+/// Read the Home Feed
+///
+/// Posts of all the burrows followed by the user, newest first. Banned posts
+/// are left out. Pass the `next_cursor` of a page as `cursor` to read the page
+/// after it.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Option<String>`: Cursor returned with the previous page, start from the newest post if not set
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `FeedPage`: Posts of the page and the cursor of the next page
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::CursorInvalid`
+///   - `ErrorCode::DatabaseErr`
+///
+#[get("/feed?<cursor>")]
+pub async fn read_feed(
+    auth: Auth,
+    db: Connection<PgDb>,
+    cursor: Option<String>,
+) -> (Status, Result<Json<FeedPage>, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let cursor = match cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(Cursor::Post { post_id })) => Some(post_id),
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::CursorInvalid,
+                    "Invalid cursor.",
+                ))),
+            )
+        }
+    };
+    let burrow_ids: Vec<i64> = match UserFollow::find()
+        .filter(db::user_follow::Column::Uid.eq(auth.id))
         .all(&pg_con)
         .await
-        .map(|user_likes| {
-            let hm: HashMap<i64, bool> = user_likes.iter().map(|r| (r.post_id, true)).collect();
-            hm
-        }) {
-        Ok(hm) => hm,
+    {
+        Ok(follows) => follows.iter().map(|f| f.burrow_id).collect(),
         Err(e) => {
-            log::error!("[READ-POST-LIST] Database Error: {:?}", e.to_string());
+            log::error!("[READ-FEED] Database error: {:?}", e);
             return (
                 Status::InternalServerError,
                 Err(Json(ErrorResponse::default())),
             );
         }
     };
-    let collection_res = match db::user_collection::Entity::find()
-        .filter(
-            Condition::all()
-                .add(db::user_collection::Column::Uid.eq(auth.id))
-                .add(db::user_collection::Column::PostId.is_in(post_ids)),
-        )
-        .order_by_desc(db::user_collection::Column::PostId)
-        .all(&pg_con)
-        .await
-        .map(|user_collections| {
-            let hm: HashMap<i64, (bool, bool)> = user_collections
-                .iter()
-                .map(|r| (r.post_id, (true, r.is_update)))
-                .collect();
-            hm
-        }) {
-        Ok(hm) => hm,
+    if burrow_ids.is_empty() {
+        return (
+            Status::Ok,
+            Ok(Json(FeedPage {
+                post_page: Vec::new(),
+                next_cursor: None,
+            })),
+        );
+    }
+    let mut condition = Condition::all()
+        .add(db::content_post::Column::BurrowId.is_in(burrow_ids))
+        .add(db::content_post::Column::PostState.eq(0));
+    if let Some(cursor) = cursor {
+        condition = condition.add(db::content_post::Column::PostId.lt(cursor));
+    }
+    // post id grows with created time, so it orders the feed as well as the cursor
+    let post_select = ContentPost::find()
+        .filter(condition)
+        .order_by_desc(db::content_post::Column::PostId)
+        .limit(POST_PER_PAGE as u64);
+    match select_post_display(post_select, auth.id).all(&pg_con).await {
+        Ok(post_page) => {
+            let next_cursor = Cursor::next_token(
+                post_page.len(),
+                POST_PER_PAGE,
+                post_page.last().map(|p| Cursor::Post {
+                    post_id: p.post.post_id,
+                }),
+            );
+            (
+                Status::Ok,
+                Ok(Json(FeedPage {
+                    post_page,
+                    next_cursor,
+                })),
+            )
+        }
         Err(e) => {
-            log::error!("[READ-POST-LIST] Database Error: {:?}", e.to_string());
-            return (
+            log::error!("[READ-FEED] Database error: {:?}", e);
+            (
                 Status::InternalServerError,
                 Err(Json(ErrorResponse::default())),
-            );
+            )
         }
-    };
-    let post_page: Vec<PostDisplay> = post_info
-        .iter()
-        .map(|m| {
-            let c = *collection_res.get(&m.post_id).unwrap_or(&(false, false));
-            PostDisplay {
-                post: m.into(),
-                like: *like_res.get(&m.post_id).unwrap_or(&false),
-                collection: c.0,
-                is_update: c.1,
-            }
-        })
-        .collect();
-    (Status::Ok, Ok(Json(ListPage { post_page, page })))
+    }
+}
+
+/// Join the posts with the like and collection of the user
+///
+/// The user is part of the join condition, so every post is kept whether the user
+/// liked or collected it or not. A `Related` join cannot carry the extra
+/// condition, so the joins are built here.
+pub fn select_post_display(
+    mut select: Select<db::content_post::Entity>,
+    uid: i64,
+) -> Selector<SelectModel<PostDisplay>> {
+    QueryTrait::query(&mut select)
+        .left_join(
+            db::user_like::Entity,
+            Condition::all()
+                .add(
+                    Expr::tbl(db::user_like::Entity, db::user_like::Column::PostId)
+                        .equals(db::content_post::Entity, db::content_post::Column::PostId),
+                )
+                .add(Expr::tbl(db::user_like::Entity, db::user_like::Column::Uid).eq(uid)),
+        )
+        .left_join(
+            db::user_collection::Entity,
+            Condition::all()
+                .add(
+                    Expr::tbl(
+                        db::user_collection::Entity,
+                        db::user_collection::Column::PostId,
+                    )
+                    .equals(db::content_post::Entity, db::content_post::Column::PostId),
+                )
+                .add(
+                    Expr::tbl(
+                        db::user_collection::Entity,
+                        db::user_collection::Column::Uid,
+                    )
+                    .eq(uid),
+                ),
+        )
+        .expr_as(
+            Expr::tbl(db::user_like::Entity, db::user_like::Column::Uid).is_not_null(),
+            Alias::new("like"),
+        )
+        .expr_as(
+            Expr::tbl(
+                db::user_collection::Entity,
+                db::user_collection::Column::Uid,
+            )
+            .is_not_null(),
+            Alias::new("collection"),
+        )
+        .expr_as(
+            Expr::tbl(
+                db::user_collection::Entity,
+                db::user_collection::Column::IsUpdate,
+            ),
+            Alias::new("is_update"),
+        );
+    select.into_model::<PostDisplay>()
 }
 
 /// Create Reply
@@ -895,9 +1276,9 @@ pub async fn read_post_list(
 /// ## Parameters
 ///
 /// - `Auth`: Authenticated user
+/// - `RateLimit<ReplyCreate>`: Rate limit of the user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Json<ReplyInfo>`: Reply information
-/// - `Connection<PulsarMq>`: Pulsar connection
 ///
 /// ## Returns
 ///
@@ -908,24 +1289,42 @@ pub async fn read_post_list(
 ///
 /// - `ErrorResponse`: Error message
 ///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::ReplyNotExist`
 ///   - `ErrorCode::UserNotExist`
 ///   - `ErrorCode::UserForbidden`
 ///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::ContentSensitive`
+///   - `ErrorCode::ImageInvalid`
 ///   - `ErrorCode::DatabaseErr`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///   - `ErrorCode::RateLimit`
 ///
 #[post("/replies", data = "<reply_info>", format = "json")]
 pub async fn create_reply(
     auth: Auth,
+    _limit: RateLimit<ReplyCreate>,
     db: Connection<PgDb>,
     reply_info: Json<ReplyInfo>,
-    mut producer: Connection<PulsarMq>,
 ) -> (
     Status,
     Result<Json<ReplyCreateResponse>, Json<ErrorResponse>>,
 ) {
     let pg_con = db.into_inner();
     // get content info from request
-    let content = reply_info.into_inner();
+    let mut content = reply_info.into_inner();
+    // filter sensitive words
+    let flagged = match filter_content(&content.content) {
+        Ok((text, flagged)) => {
+            content.content = text;
+            flagged
+        }
+        Err((status, e)) => return (status, Err(e)),
+    };
+    // render markdown after checking the images it embeds
+    let content_html = match render_content(&pg_con, auth.id, &content.content).await {
+        Ok(html) => html,
+        Err((status, e)) => return (status, Err(e)),
+    };
     match UserStatus::find_by_id(auth.id).one(&pg_con).await {
         Ok(ust) => match ust {
             None => {
@@ -936,6 +1335,13 @@ pub async fn create_reply(
                 )
             }
             Some(user_state_info) => {
+                let valid_burrow =
+                    match check_valid_burrow(&pg_con, auth.id, content.burrow_id, "CREATE-REPLY")
+                        .await
+                    {
+                        Ok(valid) => valid,
+                        Err((status, e)) => return (status, Err(e)),
+                    };
                 if user_state_info.user_state != 0 {
                     (
                         Status::Forbidden,
@@ -944,7 +1350,7 @@ pub async fn create_reply(
                             "User not in a valid state",
                         ))),
                     )
-                } else if is_valid_burrow(&user_state_info.valid_burrow, &content.burrow_id) {
+                } else if valid_burrow {
                     match ContentPost::find_by_id(content.post_id).one(&pg_con).await {
                         Ok(r) => match r {
                             None => (
@@ -965,6 +1371,34 @@ pub async fn create_reply(
                                     );
                                 }
                                 let post_id = post_info.post_id;
+                                // check if the answered reply exists in the same post
+                                if let Some(parent_reply_id) = content.parent_reply_id {
+                                    match ContentReply::find_by_id((post_id, parent_reply_id))
+                                        .one(&pg_con)
+                                        .await
+                                    {
+                                        Ok(Some(_)) => {}
+                                        Ok(None) => {
+                                            return (
+                                                Status::NotFound,
+                                                Err(Json(ErrorResponse::build(
+                                                    ErrorCode::ReplyNotExist,
+                                                    format!(
+                                                        "Cannot find reply {}-{}",
+                                                        post_id, parent_reply_id
+                                                    ),
+                                                ))),
+                                            );
+                                        }
+                                        Err(e) => {
+                                            log::error!("[CREATE-REPLY] Database error: {:?}", e);
+                                            return (
+                                                Status::InternalServerError,
+                                                Err(Json(ErrorResponse::default())),
+                                            );
+                                        }
+                                    }
+                                }
                                 match pg_con
                                     .transaction::<_, i32, DbErr>(|txn| {
                                         Box::pin(async move {
@@ -979,6 +1413,8 @@ pub async fn create_reply(
                                                 create_time: Set(now.to_owned()),
                                                 update_time: Set(now.to_owned()),
                                                 content: Set(content.content.to_owned()),
+                                                content_html: Set(content_html),
+                                                parent_reply_id: Set(content.parent_reply_id),
                                                 ..Default::default()
                                             };
                                             // insert the row in database
@@ -1016,18 +1452,32 @@ pub async fn create_reply(
                                                 update_time: now,
                                             };
                                             let msg = PulsarSearchData::CreateReply(pulsar_reply);
-                                            let _ = producer
-                                                .send("persistent://public/default/search", msg)
-                                                .await;
+                                            outbox::enqueue_search(txn, msg).await?;
+                                            let msg = PulsarNotificationData::NewReply {
+                                                post_id: post_info.post_id,
+                                                reply_id,
+                                                burrow_id: content.burrow_id,
+                                                parent_reply_id: content.parent_reply_id,
+                                            };
+                                            outbox::enqueue_notification(txn, msg).await?;
                                             Ok(reply_id)
                                         })
                                     })
                                     .await
                                 {
-                                    Ok(reply_id) => (
-                                        Status::Ok,
-                                        Ok(Json(ReplyCreateResponse { post_id, reply_id })),
-                                    ),
+                                    Ok(reply_id) => {
+                                        if flagged {
+                                            flag_content(
+                                                &pg_con,
+                                                ReportTarget::Reply { post_id, reply_id },
+                                            )
+                                            .await;
+                                        }
+                                        (
+                                            Status::Ok,
+                                            Ok(Json(ReplyCreateResponse { post_id, reply_id })),
+                                        )
+                                    }
                                     Err(e) => {
                                         log::error!("[CREATE-POST] Database error: {:?}", e);
                                         (
@@ -1084,6 +1534,8 @@ pub async fn create_reply(
 ///   - `ErrorCode::UserNotExist`
 ///   - `ErrorCode::UserForbidden`
 ///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::ContentSensitive`
+///   - `ErrorCode::ImageInvalid`
 ///   - `ErrorCode::DatabaseErr`
 ///
 #[patch("/replies", data = "<reply_update_info>", format = "json")]
@@ -1091,11 +1543,24 @@ pub async fn update_reply(
     auth: Auth,
     db: Connection<PgDb>,
     reply_update_info: Json<ReplyUpdateInfo>,
-    mut producer: Connection<PulsarMq>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
     // get content info from request
-    let content = reply_update_info.into_inner();
+    let mut content = reply_update_info.into_inner();
+    // filter sensitive words
+    let flagged = match filter_content(&content.content) {
+        Ok((text, flagged)) => {
+            content.content = text;
+            flagged
+        }
+        Err((status, e)) => return (status, Err(e)),
+    };
+    // render markdown after checking the images it embeds
+    let content_html = match render_content(&pg_con, auth.id, &content.content).await {
+        Ok(html) => html,
+        Err((status, e)) => return (status, Err(e)),
+    };
+    let (post_id, reply_id) = (content.post_id, content.reply_id);
     match UserStatus::find_by_id(auth.id).one(&pg_con).await {
         Ok(ust) => match ust {
             None => {
@@ -1115,7 +1580,6 @@ pub async fn update_reply(
                         ))),
                     )
                 } else {
-                    // if is_valid_burrow(&user_state_info.valid_burrow, &content.burrow_id)
                     match ContentReply::find_by_id((content.post_id, content.reply_id))
                         .one(&pg_con)
                         .await
@@ -1141,10 +1605,18 @@ pub async fn update_reply(
                                         ))),
                                     );
                                 }
-                                if is_valid_burrow(
-                                    &user_state_info.valid_burrow,
-                                    &reply_info.burrow_id,
-                                ) {
+                                let valid_burrow = match check_valid_burrow(
+                                    &pg_con,
+                                    auth.id,
+                                    reply_info.burrow_id,
+                                    "UPDATE-REPLY",
+                                )
+                                .await
+                                {
+                                    Ok(valid) => valid,
+                                    Err((status, e)) => return (status, Err(e)),
+                                };
+                                if valid_burrow {
                                     match pg_con
                                         .transaction::<_, (), DbErr>(|txn| {
                                             Box::pin(async move {
@@ -1153,6 +1625,7 @@ pub async fn update_reply(
                                                 let mut content_reply: db::content_reply::ActiveModel =
                                                     reply_info.into();
                                                 content_reply.content = Set(content.content.to_owned());
+                                                content_reply.content_html = Set(content_html);
                                                 content_reply.update_time = Set(now);
                                                 let content_reply = content_reply.update(txn).await?;
                                                 let post_update = db::content_post::ActiveModel {
@@ -1182,15 +1655,17 @@ pub async fn update_reply(
                                                     update_time: now,
                                                 };
                                                 let msg = PulsarSearchData::UpdateReply(pulsar_reply);
-                                                let _ = producer
-                                                    .send("persistent://public/default/search", msg)
-                                                    .await;
-                                                Ok(())
+                                                outbox::enqueue_search(txn, msg).await
                                             })
                                     })
                                     .await
                                     {
-                                        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                                        Ok(_) => {
+                                            if flagged {
+                                                flag_content(&pg_con, ReportTarget::Reply { post_id, reply_id }).await;
+                                            }
+                                            (Status::Ok, Ok("Success".to_string()))
+                                        }
                                         Err(e) => {
                                             log::error!("[UPDATE-REPLY] Database error: {:?}", e);
                                             (Status::InternalServerError, Err(Json(ErrorResponse::default())))
@@ -1227,3 +1702,351 @@ pub async fn update_reply(
         }
     }
 }
+
+/// Read the poll of a post, with its tallies and the options chosen by the user
+pub async fn read_poll(
+    pg_con: &DatabaseConnection,
+    post_id: i64,
+    uid: i64,
+) -> Result<Option<PollDisplay>, DbErr> {
+    let poll = match Poll::find_by_id(post_id).one(pg_con).await? {
+        Some(poll) => poll,
+        None => return Ok(None),
+    };
+    let tallies: Vec<PollTally> = PollVote::find()
+        .select_only()
+        .column(db::poll_vote::Column::OptionId)
+        .column_as(Expr::col(db::poll_vote::Column::Uid).count(), "vote_num")
+        .filter(db::poll_vote::Column::PostId.eq(post_id))
+        .group_by(db::poll_vote::Column::OptionId)
+        .into_model::<PollTally>()
+        .all(pg_con)
+        .await?;
+    let voted: Vec<i32> = PollVote::find()
+        .filter(db::poll_vote::Column::PostId.eq(post_id))
+        .filter(db::poll_vote::Column::Uid.eq(uid))
+        .order_by_asc(db::poll_vote::Column::OptionId)
+        .all(pg_con)
+        .await?
+        .iter()
+        .map(|v| v.option_id)
+        .collect();
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    Ok(Some(PollDisplay::new(&poll, &tallies, voted, now)))
+}
+
+/// Vote in the Poll of a Post
+///
+/// The vote is checked here and then written by task-executor. Each user can
+/// only vote once in a poll, which task-executor enforces with the primary key
+/// of `poll_voter`, a vote which passes the check here at the same time as
+/// another one of the user is dropped there.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `i64`: Post id
+/// - `Json<VoteInfo>`: Options chosen by the user
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::PollInvalid`
+///   - `ErrorCode::PollClosed`
+///   - `ErrorCode::VoteDuplicate`
+///   - `ErrorCode::DatabaseErr`
+///
+#[post("/posts/<post_id>/vote", data = "<vote_info>", format = "json")]
+pub async fn vote_poll(
+    auth: Auth,
+    db: Connection<PgDb>,
+    post_id: i64,
+    vote_info: Json<VoteInfo>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let mut choices = vote_info.into_inner().choices;
+    choices.sort_unstable();
+    choices.dedup();
+    match ContentPost::find_by_id(post_id).one(&pg_con).await {
+        Ok(Some(post)) if post.post_state == 0 => {}
+        Ok(_) => {
+            return (
+                Status::NotFound,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::PostNotExist,
+                    format!("Cannot find post {}", post_id),
+                ))),
+            )
+        }
+        Err(e) => {
+            log::error!("[VOTE-POLL] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    let poll = match Poll::find_by_id(post_id).one(&pg_con).await {
+        Ok(Some(poll)) => poll,
+        Ok(None) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::PollInvalid,
+                    format!("Post {} has no poll", post_id),
+                ))),
+            )
+        }
+        Err(e) => {
+            log::error!("[VOTE-POLL] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    if poll.close_time.map_or(false, |t| t <= now) {
+        return (
+            Status::Forbidden,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::PollClosed,
+                "The poll has been closed.",
+            ))),
+        );
+    }
+    let option_num = serde_json::from_str::<Vec<String>>(&poll.options)
+        .map(|options| options.len())
+        .unwrap_or(0) as i32;
+    if choices.is_empty()
+        || (!poll.multiple && choices.len() > 1)
+        || choices.iter().any(|&c| c < 0 || c >= option_num)
+    {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::PollInvalid,
+                "Wrong choices of the poll.",
+            ))),
+        );
+    }
+    match PollVoter::find_by_id((post_id, auth.id)).one(&pg_con).await {
+        Ok(Some(_)) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::VoteDuplicate,
+                    "Already voted in the poll.",
+                ))),
+            )
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("[VOTE-POLL] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    let msg = PulsarRelationData::Vote(auth.id, post_id, choices);
+    match outbox::enqueue_relation(&pg_con, msg).await {
+        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+        Err(e) => {
+            log::error!("[VOTE-POLL] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Report a post, reply or burrow
+///
+/// Each user can only report the same target once.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Json<ReportInfo>`: Report information
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::ContentTooLong`
+///   - `ErrorCode::PostNotExist`
+///   - `ErrorCode::ReplyNotExist`
+///   - `ErrorCode::BurrowNotExist`
+///   - `ErrorCode::ReportDuplicate`
+///   - `ErrorCode::DatabaseErr`
+///
+#[post("/reports", data = "<report_info>", format = "json")]
+pub async fn create_report(
+    auth: Auth,
+    db: Connection<PgDb>,
+    report_info: Json<ReportInfo>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let report = report_info.into_inner();
+    if report.description.chars().count() > MAX_REPORT_DESCRIPTION_LEN {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::ContentTooLong,
+                format!(
+                    "Report description longer than {} chars.",
+                    MAX_REPORT_DESCRIPTION_LEN
+                ),
+            ))),
+        );
+    }
+    let duplicate = (
+        Status::Conflict,
+        Err(Json(ErrorResponse::build(
+            ErrorCode::ReportDuplicate,
+            "Already reported.",
+        ))),
+    );
+    let txn = match pg_con.begin().await {
+        Ok(txn) => txn,
+        Err(e) => {
+            log::error!("[CREATE-REPORT] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    // check if the reported target exists
+    let exist = match report.target {
+        ReportTarget::Post { post_id } => ContentPost::find_by_id(post_id)
+            .one(&txn)
+            .await
+            .map(|r| r.map(|_| ()).ok_or(ErrorCode::PostNotExist)),
+        ReportTarget::Reply { post_id, reply_id } => ContentReply::find_by_id((post_id, reply_id))
+            .one(&txn)
+            .await
+            .map(|r| r.map(|_| ()).ok_or(ErrorCode::ReplyNotExist)),
+        ReportTarget::Burrow { burrow_id } => Burrow::find_by_id(burrow_id)
+            .one(&txn)
+            .await
+            .map(|r| r.map(|_| ()).ok_or(ErrorCode::BurrowNotExist)),
+    };
+    match exist {
+        Ok(Ok(_)) => {}
+        Ok(Err(code)) => {
+            return (
+                Status::NotFound,
+                Err(Json(ErrorResponse::build(
+                    code,
+                    "Reported target not found",
+                ))),
+            )
+        }
+        Err(e) => {
+            log::error!("[CREATE-REPORT] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    let target_type = report.target.to_i32();
+    let (post_id, reply_id, burrow_id) = report.target.to_ids();
+    match Report::find()
+        .filter(db::report::Column::Uid.eq(auth.id))
+        .filter(db::report::Column::TargetType.eq(target_type))
+        .filter(db::report::Column::PostId.eq(post_id))
+        .filter(db::report::Column::ReplyId.eq(reply_id))
+        .filter(db::report::Column::BurrowId.eq(burrow_id))
+        .one(&txn)
+        .await
+    {
+        Ok(Some(_)) => return duplicate,
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("[CREATE-REPORT] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let new_report = db::report::ActiveModel {
+        uid: Set(auth.id),
+        target_type: Set(target_type),
+        post_id: Set(post_id),
+        reply_id: Set(reply_id),
+        burrow_id: Set(burrow_id),
+        reason: Set(report.reason.to_i32()),
+        description: Set(report.description),
+        report_state: Set(ReportState::Pending.to_i32()),
+        operator: Set(0),
+        create_time: Set(now),
+        resolve_time: Set(None),
+        ..Default::default()
+    };
+    let result = match new_report.insert(&txn).await {
+        Ok(_) => txn.commit().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+        // a concurrent report of the same target was inserted first
+        Err(e) if is_unique_violation(&e) => duplicate,
+        Err(e) => {
+            log::error!("[CREATE-REPORT] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Check if a burrow is a valid burrow of the user, i.e. one the user can post in
+///
+/// ## Parameters
+///
+/// - `tag`: Tag of the route in the error log, e.g. `CREATE-POST`
+///
+/// ## Errors
+///
+/// - `ErrorCode::DatabaseErr`
+async fn check_valid_burrow(
+    pg_con: &DatabaseConnection,
+    uid: i64,
+    burrow_id: i64,
+    tag: &str,
+) -> Result<bool, (Status, Json<ErrorResponse>)> {
+    match is_valid_burrow(pg_con, uid, burrow_id).await {
+        Ok(valid) => Ok(valid),
+        Err(e) => {
+            log::error!("[{}] Database error: {:?}", tag, e);
+            Err((Status::InternalServerError, Json(ErrorResponse::default())))
+        }
+    }
+}
+
+/// Check if an insert failed on a unique index, e.g. `idx-report-reporter`
+fn is_unique_violation(e: &DbErr) -> bool {
+    match e {
+        DbErr::Exec(s) | DbErr::Query(s) => s.contains("duplicate key"),
+        _ => false,
+    }
+}
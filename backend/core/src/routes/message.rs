@@ -0,0 +1,552 @@
+//! Routes for private message between burrows
+//!
+//! Messages are keyed by burrow, never by uid, so the anonymity of burrows holds.
+
+use chrono::{FixedOffset, Utc};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{Build, Rocket};
+use rocket_db_pools::Connection;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    entity::*, Condition, DatabaseConnection, DbBackend, DbErr, PaginatorTrait, QueryFilter,
+    QueryOrder, Statement,
+};
+
+use crate::config::message::*;
+use crate::db::{self, prelude::*};
+use crate::models::{error::*, message::*};
+use crate::pool::{PgDb, RedisDb};
+use crate::utils::auth::Auth;
+
+pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/messages",
+        routes![
+            send_message,
+            get_conversations,
+            get_messages,
+            block_burrow,
+            unblock_burrow,
+        ],
+    )
+}
+
+/// Find a burrow of the user which has not been discarded
+async fn get_owned_burrow(
+    pg_con: &DatabaseConnection,
+    uid: i64,
+    burrow_id: i64,
+) -> Result<db::burrow::Model, (Status, Json<ErrorResponse>)> {
+    match Burrow::find_by_id(burrow_id).one(pg_con).await {
+        Ok(Some(burrow)) if burrow.uid == uid && burrow.burrow_state < 2 => Ok(burrow),
+        Ok(_) => Err((
+            Status::Forbidden,
+            Json(ErrorResponse::build(
+                ErrorCode::BurrowInvalid,
+                "Not allowed to use this burrow",
+            )),
+        )),
+        Err(e) => {
+            log::error!("[MESSAGE] Database error: {:?}", e);
+            Err((Status::InternalServerError, Json(ErrorResponse::default())))
+        }
+    }
+}
+
+/// Send Message
+///
+/// Send a private message from a burrow of the user to another burrow.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+/// - `Json<MessageInfo>`: Message information
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<MessageCreateResponse>`: Response of send message
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::EmptyField`
+///   - `ErrorCode::ContentTooLong`
+///   - `ErrorCode::UserNotExist`
+///   - `ErrorCode::UserForbidden`
+///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::BurrowNotExist`
+///   - `ErrorCode::RateLimit`
+///   - `ErrorCode::DatabaseErr`
+#[post("/", data = "<message_info>", format = "json")]
+pub async fn send_message(
+    auth: Auth,
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+    message_info: Json<MessageInfo>,
+) -> (
+    Status,
+    Result<Json<MessageCreateResponse>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    let message = message_info.into_inner();
+    if message.content.is_empty() {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::EmptyField,
+                "Empty message content.",
+            ))),
+        );
+    }
+    if message.content.chars().count() > MAX_MESSAGE_LEN {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::ContentTooLong,
+                format!("Message content longer than {} chars.", MAX_MESSAGE_LEN),
+            ))),
+        );
+    }
+    if message.sender_burrow == message.receiver_burrow {
+        return (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::BurrowInvalid,
+                "Cannot send message to the same burrow",
+            ))),
+        );
+    }
+    // check if user has been banned
+    match UserStatus::find_by_id(auth.id).one(&pg_con).await {
+        Ok(Some(state)) => {
+            if state.user_state != 0 {
+                return (
+                    Status::Forbidden,
+                    Err(Json(ErrorResponse::build(
+                        ErrorCode::UserForbidden,
+                        "User not in a valid state",
+                    ))),
+                );
+            }
+        }
+        Ok(None) => {
+            log::info!("[SEND-MESSAGE] Cannot find user_status by uid.");
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(ErrorCode::UserNotExist, ""))),
+            );
+        }
+        Err(e) => {
+            log::error!("[SEND-MESSAGE] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    // banned burrows cannot send messages
+    match get_owned_burrow(&pg_con, auth.id, message.sender_burrow).await {
+        Ok(burrow) if burrow.burrow_state == 0 => {}
+        Ok(_) => {
+            return (
+                Status::Forbidden,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::BurrowInvalid,
+                    "Burrow not in a valid state",
+                ))),
+            )
+        }
+        Err((status, e)) => return (status, Err(e)),
+    }
+    match Burrow::find_by_id(message.receiver_burrow)
+        .one(&pg_con)
+        .await
+    {
+        Ok(Some(burrow)) => {
+            if burrow.burrow_state != 0 {
+                return (
+                    Status::Forbidden,
+                    Err(Json(ErrorResponse::build(
+                        ErrorCode::BurrowInvalid,
+                        "Receiver burrow not in a valid state",
+                    ))),
+                );
+            }
+        }
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::BurrowNotExist,
+                    format!("Cannot find burrow {}", message.receiver_burrow),
+                ))),
+            )
+        }
+        Err(e) => {
+            log::error!("[SEND-MESSAGE] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    // check if the receiver has blocked the sender
+    match BurrowBlock::find_by_id((message.receiver_burrow, message.sender_burrow))
+        .one(&pg_con)
+        .await
+    {
+        Ok(Some(_)) => {
+            return (
+                Status::Forbidden,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::UserForbidden,
+                    "Blocked by the receiver",
+                ))),
+            )
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("[SEND-MESSAGE] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    // check request rate of the sender burrow
+    let key = format!("message:{}", message.sender_burrow);
+    let op_times: Result<usize, redis::RedisError> = redis::cmd("INCR")
+        .arg(&key)
+        .query_async(kv_conn.as_mut())
+        .await;
+    match op_times {
+        Ok(op_times) => {
+            if op_times == 1 {
+                let _: Result<i32, redis::RedisError> = redis::cmd("EXPIRE")
+                    .arg(&key)
+                    .arg(MESSAGE_RATE_WINDOW)
+                    .query_async(kv_conn.as_mut())
+                    .await;
+            }
+            if op_times > MESSAGE_RATE_LIMIT {
+                return (
+                    Status::TooManyRequests,
+                    Err(Json(ErrorResponse::build(
+                        ErrorCode::RateLimit,
+                        "Send messages too frequently",
+                    ))),
+                );
+            }
+        }
+        Err(e) => {
+            log::error!("[SEND-MESSAGE] Redis error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    }
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let record = db::message::ActiveModel {
+        sender_burrow: Set(message.sender_burrow),
+        receiver_burrow: Set(message.receiver_burrow),
+        content: Set(message.content),
+        is_read: Set(false),
+        create_time: Set(now),
+        ..Default::default()
+    };
+    match record.insert(&pg_con).await {
+        Ok(res) => (
+            Status::Ok,
+            Ok(Json(MessageCreateResponse {
+                message_id: res.message_id,
+            })),
+        ),
+        Err(e) => {
+            log::error!("[SEND-MESSAGE] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Get Conversations
+///
+/// Show conversations of a burrow of the user, ordered by the latest message.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `i64`: Burrow id owned by the user
+/// - `Option<usize>`: page number, default value 0
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<ConversationDisplay>>`: Latest message and unread count of each conversation
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[get("/conversations?<burrow_id>&<page>")]
+pub async fn get_conversations(
+    auth: Auth,
+    db: Connection<PgDb>,
+    burrow_id: i64,
+    page: Option<usize>,
+) -> (
+    Status,
+    Result<Json<Vec<ConversationDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let page = page.unwrap_or(0);
+    if let Err((status, e)) = get_owned_burrow(&pg_con, auth.id, burrow_id).await {
+        return (status, Err(e));
+    }
+    match ConversationDisplay::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT "t".*, (
+            SELECT COUNT(*) FROM "message" AS "m"
+            WHERE "m"."sender_burrow" = "t"."peer_burrow"
+                AND "m"."receiver_burrow" = $1
+                AND NOT "m"."is_read"
+        ) AS "unread"
+        FROM (
+            SELECT DISTINCT ON ("peer_burrow")
+                CASE WHEN "sender_burrow" = $1 THEN "receiver_burrow" ELSE "sender_burrow" END AS "peer_burrow",
+                "message_id", "sender_burrow", "receiver_burrow", "content", "create_time"
+            FROM "message"
+            WHERE "sender_burrow" = $1 OR "receiver_burrow" = $1
+            ORDER BY "peer_burrow", "message_id" DESC
+        ) AS "t"
+        ORDER BY "t"."message_id" DESC
+        LIMIT $2 OFFSET $3"#,
+        vec![
+            burrow_id.into(),
+            (CONVERSATION_PER_PAGE as i64).into(),
+            ((page * CONVERSATION_PER_PAGE) as i64).into(),
+        ],
+    ))
+    .all(&pg_con)
+    .await
+    {
+        Ok(conversations) => (Status::Ok, Ok(Json(conversations))),
+        Err(e) => {
+            log::error!("[GET-CONVERSATION] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Get Messages
+///
+/// Show messages between a burrow of the user and a peer burrow, newest first.
+/// Messages sent by the peer are marked as read.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `i64`: Burrow id owned by the user
+/// - `i64`: Burrow id of the peer
+/// - `Option<usize>`: page number, default value 0
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<MessageDisplay>>`: Messages of the page
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[get("/<burrow_id>/<peer_burrow>?<page>")]
+pub async fn get_messages(
+    auth: Auth,
+    db: Connection<PgDb>,
+    burrow_id: i64,
+    peer_burrow: i64,
+    page: Option<usize>,
+) -> (
+    Status,
+    Result<Json<Vec<MessageDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let page = page.unwrap_or(0);
+    if let Err((status, e)) = get_owned_burrow(&pg_con, auth.id, burrow_id).await {
+        return (status, Err(e));
+    }
+    let messages = match Message::find()
+        .filter(
+            Condition::any()
+                .add(
+                    Condition::all()
+                        .add(db::message::Column::SenderBurrow.eq(burrow_id))
+                        .add(db::message::Column::ReceiverBurrow.eq(peer_burrow)),
+                )
+                .add(
+                    Condition::all()
+                        .add(db::message::Column::SenderBurrow.eq(peer_burrow))
+                        .add(db::message::Column::ReceiverBurrow.eq(burrow_id)),
+                ),
+        )
+        .order_by_desc(db::message::Column::MessageId)
+        .paginate(&pg_con, MESSAGE_PER_PAGE)
+        .fetch_page(page)
+        .await
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            log::error!("[GET-MESSAGE] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    let read_res: Result<_, DbErr> = Message::update_many()
+        .col_expr(db::message::Column::IsRead, Expr::value(true))
+        .filter(
+            Condition::all()
+                .add(db::message::Column::SenderBurrow.eq(peer_burrow))
+                .add(db::message::Column::ReceiverBurrow.eq(burrow_id))
+                .add(db::message::Column::IsRead.eq(false)),
+        )
+        .exec(&pg_con)
+        .await;
+    if let Err(e) = read_res {
+        log::error!("[GET-MESSAGE] Database error: {:?}", e);
+    }
+    (
+        Status::Ok,
+        Ok(Json(messages.iter().map(|m| m.into()).collect())),
+    )
+}
+
+/// Block Burrow
+///
+/// Stop a burrow from sending messages to a burrow of the user.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Json<BlockInfo>`: Block information
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[post("/blocks", data = "<block_info>", format = "json")]
+pub async fn block_burrow(
+    auth: Auth,
+    db: Connection<PgDb>,
+    block_info: Json<BlockInfo>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let block = block_info.into_inner();
+    if let Err((status, e)) = get_owned_burrow(&pg_con, auth.id, block.burrow_id).await {
+        return (status, Err(e));
+    }
+    match BurrowBlock::find_by_id((block.burrow_id, block.blocked_burrow_id))
+        .one(&pg_con)
+        .await
+    {
+        Ok(Some(_)) => (Status::Ok, Ok("Success".to_string())),
+        Ok(None) => {
+            let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+            let record = db::burrow_block::ActiveModel {
+                burrow_id: Set(block.burrow_id),
+                blocked_burrow_id: Set(block.blocked_burrow_id),
+                create_time: Set(now),
+            };
+            match record.insert(&pg_con).await {
+                Ok(_) => (Status::Ok, Ok("Success".to_string())),
+                Err(e) => {
+                    log::error!("[BLOCK-BURROW] Database error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            log::error!("[BLOCK-BURROW] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Unblock Burrow
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `i64`: Burrow id owned by the user
+/// - `i64`: Burrow id to unblock
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::BurrowInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[delete("/blocks/<burrow_id>/<blocked_burrow_id>")]
+pub async fn unblock_burrow(
+    auth: Auth,
+    db: Connection<PgDb>,
+    burrow_id: i64,
+    blocked_burrow_id: i64,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    if let Err((status, e)) = get_owned_burrow(&pg_con, auth.id, burrow_id).await {
+        return (status, Err(e));
+    }
+    match BurrowBlock::delete_many()
+        .filter(
+            Condition::all()
+                .add(db::burrow_block::Column::BurrowId.eq(burrow_id))
+                .add(db::burrow_block::Column::BlockedBurrowId.eq(blocked_burrow_id)),
+        )
+        .exec(&pg_con)
+        .await
+    {
+        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+        Err(e) => {
+            log::error!("[UNBLOCK-BURROW] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
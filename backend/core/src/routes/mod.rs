@@ -4,6 +4,8 @@ pub mod admin;
 pub mod burrow;
 pub mod content;
 pub mod health;
+pub mod message;
+pub mod notification;
 pub mod search;
 pub mod storage;
 pub mod trending;
@@ -22,4 +24,6 @@ pub async fn routes_init(rocket: Rocket<Build>) -> Rocket<Build> {
         .attach(AdHoc::on_ignite("mount_burrow", burrow::init))
         .attach(AdHoc::on_ignite("mount_trending", trending::init))
         .attach(AdHoc::on_ignite("mount_admin", admin::init))
+        .attach(AdHoc::on_ignite("mount_notification", notification::init))
+        .attach(AdHoc::on_ignite("mount_message", message::init))
 }
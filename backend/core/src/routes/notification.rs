@@ -0,0 +1,172 @@
+//! Routes for notification
+
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{Build, Rocket};
+use rocket_db_pools::Connection;
+use sea_orm::sea_query::Expr;
+use sea_orm::{entity::*, Condition, PaginatorTrait, QueryFilter, QueryOrder};
+
+use crate::config::user::NOTIFICATION_PER_PAGE;
+use crate::db::{self, prelude::*};
+use crate::models::error::*;
+use crate::models::notification::*;
+use crate::pool::PgDb;
+use crate::utils::auth::Auth;
+
+pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/users",
+        routes![
+            get_notifications,
+            get_unread_notification_count,
+            read_notifications
+        ],
+    )
+}
+
+/// Get Notifications
+///
+/// Show notifications of the user, newest first.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Option<usize>`: page number, default value 0
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<NotificationDisplay>>`: Notifications of the page
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::DatabaseErr`
+#[get("/notifications?<page>")]
+pub async fn get_notifications(
+    auth: Auth,
+    db: Connection<PgDb>,
+    page: Option<usize>,
+) -> (
+    Status,
+    Result<Json<Vec<NotificationDisplay>>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let page = page.unwrap_or(0);
+    match Notification::find()
+        .filter(db::notification::Column::Uid.eq(auth.id))
+        .order_by_desc(db::notification::Column::NotificationId)
+        .paginate(&pg_con, NOTIFICATION_PER_PAGE)
+        .fetch_page(page)
+        .await
+    {
+        Ok(notifications) => (
+            Status::Ok,
+            Ok(Json(notifications.iter().map(|n| n.into()).collect())),
+        ),
+        Err(e) => {
+            log::error!("[GET-NOTIFICATION] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Get Unread Notification Count
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<NotificationUnread>`: Number of unread notifications
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::DatabaseErr`
+#[get("/notifications/unread")]
+pub async fn get_unread_notification_count(
+    auth: Auth,
+    db: Connection<PgDb>,
+) -> (
+    Status,
+    Result<Json<NotificationUnread>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    match Notification::find()
+        .filter(
+            Condition::all()
+                .add(db::notification::Column::Uid.eq(auth.id))
+                .add(db::notification::Column::IsRead.eq(false)),
+        )
+        .count(&pg_con)
+        .await
+    {
+        Ok(unread) => (Status::Ok, Ok(Json(NotificationUnread { unread }))),
+        Err(e) => {
+            log::error!("[UNREAD-NOTIFICATION] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Mark Notifications as Read
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Json<NotificationReadInfo>`: All notifications or ids of notifications to mark
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::DatabaseErr`
+#[post("/notifications/read", data = "<read_info>", format = "json")]
+pub async fn read_notifications(
+    auth: Auth,
+    db: Connection<PgDb>,
+    read_info: Json<NotificationReadInfo>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let mut condition = Condition::all()
+        .add(db::notification::Column::Uid.eq(auth.id))
+        .add(db::notification::Column::IsRead.eq(false));
+    if let NotificationReadInfo::Ids(ids) = read_info.into_inner() {
+        if ids.is_empty() {
+            return (Status::Ok, Ok("Success".to_string()));
+        }
+        condition = condition.add(db::notification::Column::NotificationId.is_in(ids));
+    }
+    match Notification::update_many()
+        .col_expr(db::notification::Column::IsRead, Expr::value(true))
+        .filter(condition)
+        .exec(&pg_con)
+        .await
+    {
+        Ok(_) => (Status::Ok, Ok("Success".to_string())),
+        Err(e) => {
+            log::error!("[READ-NOTIFICATION] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
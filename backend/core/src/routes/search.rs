@@ -4,13 +4,15 @@ use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::{Build, Rocket};
 use rocket_db_pools::Connection;
-use sea_orm::{entity::*, ColumnTrait, DbErr, PaginatorTrait, QueryFilter, QueryOrder};
+use sea_orm::{entity::*, DbErr, PaginatorTrait, QueryOrder};
 
 use crate::config::content::REPLY_PER_PAGE;
 use crate::db::{self, prelude::*};
 use crate::models::{burrow::*, content::*, error::*, search::*};
 use crate::pool::{PgDb, Search, TypesenseSearch};
+use crate::routes::content::read_poll;
 use crate::utils::auth::Auth;
+use crate::utils::rate_limit::{RateLimit, SearchQuery};
 
 pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount("/", routes![search,])
@@ -21,6 +23,7 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
 /// ## Parameters
 ///
 /// - `Auth`: Authenticated User
+/// - `RateLimit<SearchQuery>`: Rate limit of the user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<TypesenseSearch>`: Search engine connection
 /// - `Json<SearchRequest>`: Search request struct in Json
@@ -38,10 +41,13 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
 ///     - `ErrorCode::BurrowNotExist`
 ///     - `ErrorCode::PostNotExist`
 ///     - `ErrorCode::EmptyField`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///     - `ErrorCode::RateLimit`
 ///
 #[post("/search?<page>", data = "<data>", format = "json")]
 async fn search(
     auth: Auth,
+    _limit: RateLimit<SearchQuery>,
     db: Connection<PgDb>,
     conn: Connection<TypesenseSearch>,
     data: Json<SearchRequest>,
@@ -55,8 +61,8 @@ async fn search(
             match db::burrow::Entity::find_by_id(burrow_id).one(&pg_con).await {
                 Ok(opt_burrow) => match opt_burrow {
                     Some(burrow) => {
-                        match db::content_post::Entity::find()
-                            .filter(db::content_post::Column::BurrowId.eq(burrow_id))
+                        match burrow
+                            .find_related(ContentPost)
                             .order_by_desc(db::content_post::Column::PostId)
                             .paginate(&pg_con, REPLY_PER_PAGE)
                             .fetch_page(page)
@@ -72,6 +78,7 @@ async fn search(
                                             posts.iter().map(|post| post.into()).collect();
                                         posts_info
                                     },
+                                    next_cursor: None,
                                 })
                                 .unwrap()),
                             ),
@@ -112,8 +119,8 @@ async fn search(
                         ))),
                     ),
                     Some(post_info) => {
-                        let reply_pages = ContentReply::find()
-                            .filter(db::content_reply::Column::PostId.eq(post_id))
+                        let reply_pages = post_info
+                            .find_related(ContentReply)
                             .order_by_asc(db::content_reply::Column::ReplyId)
                             .paginate(&pg_con, REPLY_PER_PAGE);
                         let reply_info = match reply_pages.fetch_page(page).await {
@@ -126,6 +133,22 @@ async fn search(
                                 );
                             }
                         };
+                        let poll = if post_info.post_state == 0
+                            && post_info.post_type == PostType::Poll.to_i32()
+                        {
+                            match read_poll(&pg_con, post_id, auth.id).await {
+                                Ok(poll) => poll,
+                                Err(e) => {
+                                    log::error!("[READ-POST] Database error: {:?}", e);
+                                    return (
+                                        Status::InternalServerError,
+                                        Err(Json(ErrorResponse::default())),
+                                    );
+                                }
+                            }
+                        } else {
+                            None
+                        };
                         // get post metadata
                         let post_desc: Post = post_info.into();
                         let reply_page: Vec<Reply> = reply_info.iter().map(|r| r.into()).collect();
@@ -166,6 +189,9 @@ async fn search(
                                 page,
                                 like,
                                 collection,
+                                reply_thread: Vec::new(),
+                                poll,
+                                next_cursor: None,
                             })
                             .unwrap()),
                         )
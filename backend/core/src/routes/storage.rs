@@ -9,12 +9,13 @@ use rocket::{Build, Rocket};
 use rocket_db_pools::Connection;
 use sea_orm::{entity::*, ActiveModelTrait};
 
-use crate::config::storage::*;
+use crate::config::settings::CONFIG;
 use crate::db::{self, image, prelude::*};
 use crate::models::error::*;
 use crate::models::storage::{ReferrerCheck, SaveImage};
 use crate::pool::{MinioImageStorage, PgDb};
 use crate::utils::auth::Auth;
+use crate::utils::rate_limit::{ImageUpload, RateLimit};
 
 pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount(
@@ -28,6 +29,7 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
 /// ## Parameters
 ///
 /// - `Auth`: Authenticated User
+/// - `RateLimit<ImageUpload>`: Rate limit of the user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<MinioImageStorage>`: Image storage connection
 ///
@@ -40,10 +42,13 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
 ///
 /// - `ErrorResponse`: Error message
 ///     - `ErrorCode::DatabaseErr`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///     - `ErrorCode::RateLimit`
 ///
 #[post("/images", data = "<image>")]
 async fn upload_image(
     auth: Auth,
+    _limit: RateLimit<ImageUpload>,
     db: Connection<PgDb>,
     bucket: Connection<MinioImageStorage>,
     image: SaveImage,
@@ -67,7 +72,7 @@ async fn upload_image(
     match UserStatus::find_by_id(auth.id).one(&pg_con).await {
         Ok(opt_state) => match opt_state {
             Some(state) => {
-                if state.file_num >= *MAX_IMAGE_NUM {
+                if state.file_num >= CONFIG.storage.max_image_num {
                     return (
                         Status::TooManyRequests,
                         Err(Json(ErrorResponse::build(
@@ -90,12 +95,18 @@ async fn upload_image(
                             last_download_time: Set(now),
                             ..Default::default()
                         };
+                        // the same file may be uploaded by several users
+                        let storage = db::user_storage::ActiveModel {
+                            uid: Set(auth.id),
+                            filename: Set(filename.to_owned()),
+                        };
                         let file_num = state.file_num;
                         let file_capacity = state.file_capacity;
                         let mut ust: db::user_status::ActiveModel = state.into();
                         ust.file_num = Set(file_num + 1);
                         ust.file_capacity = Set(file_capacity + image_size as i64);
                         let _ = record.insert(&pg_con).await;
+                        let _ = storage.insert(&pg_con).await;
                         let _ = ust.update(&pg_con).await;
                         (Status::Ok, Ok(filename))
                     }
@@ -1,33 +1,42 @@
 //! Routes for user
 
 use chrono::{FixedOffset, Utc};
-use crypto::digest::Digest;
-use crypto::sha3::Sha3;
 use idgenerator::IdInstance;
-use rand::distributions::Alphanumeric;
-use rand::{thread_rng, Rng};
 use rocket::http::{Cookie, CookieJar, Status};
 use rocket::serde::json::Json;
 use rocket::{Build, Rocket};
 use rocket_db_pools::Connection;
-use sea_orm::{entity::*, query::*, DbErr, QueryFilter};
-use std::collections::HashMap;
+use sea_orm::{entity::*, query::*, DatabaseConnection, DbErr, QueryFilter};
 
 use crate::config::burrow::BURROW_PER_PAGE;
 use crate::config::content::POST_PER_PAGE;
-use crate::config::user::SEND_EMAIL_LIMIT;
+use crate::config::user::{SEND_EMAIL_LIMIT, TOTP_LOGIN_EX};
 use crate::db::{self, prelude::*};
-use crate::models::{burrow::BurrowMetadata, content::Post, error::*, pulsar::*, user::*};
-use crate::pool::{PgDb, PulsarMq, RedisDb};
-use crate::utils::auth::{delete_token, set_token, Auth, CookieOptions};
+use crate::models::{
+    admin::LockoutTarget, burrow::BurrowMetadata, content::Post, cursor::Cursor, error::*,
+    pulsar::*, session::SessionDisplay, user::*,
+};
+use crate::pool::{Mq, PgDb, RedisDb};
+use crate::utils::auth::{
+    add_token_cookies, confirm_mfa_session, get_sessions, is_mfa_session, remove_token_cookies,
+    revoke_all_sessions, revoke_session, rotate_token, set_token, Auth, ClientInfo, CookieOptions,
+};
 use crate::utils::burrow_valid::*;
 use crate::utils::email;
+use crate::utils::login_guard::{check_lockout, clear_failures, record_failure};
+use crate::utils::outbox;
+use crate::utils::password::{hash_password, verify_password, PasswordCheck};
+use crate::utils::queue;
+use crate::utils::rate_limit::{RateLimit, RelationToggle};
+use crate::utils::totp::*;
 
 pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount(
         "/users",
         routes![
             user_log_in,
+            user_log_in_totp,
+            user_refresh,
             user_sign_up,
             user_logout,
             get_follow,
@@ -39,19 +48,16 @@ pub async fn init(rocket: Rocket<Build>) -> Rocket<Build> {
             user_reset,
             user_reset_email,
             user_change_password,
+            get_user_sessions,
+            revoke_user_session,
+            revoke_user_sessions,
+            totp_enroll,
+            totp_confirm,
+            totp_disable,
         ],
     )
 }
 
-async fn gen_salt() -> String {
-    let salt: String = std::iter::repeat(())
-        .map(|()| thread_rng().sample(Alphanumeric))
-        .map(char::from)
-        .take(8)
-        .collect();
-    salt
-}
-
 /// User Relation
 ///
 /// User likes/dislikes a post, adds a post to user collection, removes a post from user collection, follows/unfollows a burrow.
@@ -59,7 +65,8 @@ async fn gen_salt() -> String {
 /// ## Parameters
 ///
 /// - `Auth`: Authenticated user
-/// - `Connection<PulsarMq>`: Pulsar connection
+/// - `RateLimit<RelationToggle>`: Rate limit of the user
+/// - `Connection<PgDb>`: Postgres connection
 /// - `Json<RelationData>`: Json of relation between user and certain post/burrow
 ///
 /// ## Returns
@@ -71,21 +78,22 @@ async fn gen_salt() -> String {
 ///
 /// - `ErrorResponse`: Error message
 ///   - `ErrorCode::DatabaseErr`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///   - `ErrorCode::RateLimit`
 #[post("/relation", data = "<relation_info>", format = "json")]
 pub async fn user_relation(
     auth: Auth,
-    mut producer: Connection<PulsarMq>,
+    _limit: RateLimit<RelationToggle>,
+    db: Connection<PgDb>,
     relation_info: Json<RelationData>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
     let relation = relation_info.into_inner();
     let msg = relation.to_pulsar(auth.id);
-    match producer
-        .send("persistent://public/default/relation", msg)
-        .await
-    {
-        Ok(_) => log::info!("[RELATION] send data to pulsar successfully!"),
+    match outbox::enqueue_relation(&pg_con, msg).await {
+        Ok(_) => log::info!("[RELATION] add relation to outbox successfully!"),
         Err(e) => {
-            log::error!("[RELATION] PulsarErr: {:?}", e);
+            log::error!("[RELATION] Database error: {:?}", e);
             return (
                 Status::InternalServerError,
                 Err(Json(ErrorResponse::default())),
@@ -104,7 +112,7 @@ pub async fn user_relation(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<RedisDb>`: Redis connection
 /// - `Json<UserEmail>`: Json of user email
-/// - `Connection<PulsarMq>`: Pulsar connection
+/// - `Connection<Mq>`: Message queue connection
 ///
 /// ## Returns
 ///
@@ -123,7 +131,7 @@ pub async fn user_email_activate(
     db: Connection<PgDb>,
     kvdb: Connection<RedisDb>,
     email_info: Json<UserEmail>,
-    mut producer: Connection<PulsarMq>,
+    producer: Connection<Mq>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
     let mut kvdb_con = kvdb.into_inner();
@@ -184,10 +192,7 @@ pub async fn user_email_activate(
                     );
                 }
                 let msg = PulsarSendEmail::Sign { email };
-                match producer
-                    .send("persistent://public/default/email", msg)
-                    .await
-                {
+                match queue::publish_json(&**producer, outbox::EMAIL_TOPIC, &msg).await {
                     Ok(_) => (Status::Ok, Ok("Success".to_string())),
                     Err(e) => {
                         log::error!("[EMAIL-AC] Database error: {:?}", e);
@@ -218,7 +223,7 @@ pub async fn user_email_activate(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<RedisDb>`: Redis connection
 /// - `Json<UserEmail>`: Json of user email
-/// - `Connection<PulsarMq>`: Pulsar connection
+/// - `Connection<Mq>`: Message queue connection
 ///
 /// ## Returns
 ///
@@ -237,7 +242,7 @@ pub async fn user_reset_email(
     db: Connection<PgDb>,
     kvdb: Connection<RedisDb>,
     email_info: Json<UserEmail>,
-    mut producer: Connection<PulsarMq>,
+    producer: Connection<Mq>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
     let mut kvdb_con = kvdb.into_inner();
@@ -290,10 +295,7 @@ pub async fn user_reset_email(
                     );
                 }
                 let msg = PulsarSendEmail::Reset { email };
-                match producer
-                    .send("persistent://public/default/email", msg)
-                    .await
-                {
+                match queue::publish_json(&**producer, outbox::EMAIL_TOPIC, &msg).await {
                     Ok(_) => (Status::Ok, Ok("Success".to_string())),
                     Err(e) => {
                         log::error!("[EMAIL-RESET] Database error: {:?}", e);
@@ -490,12 +492,11 @@ pub async fn user_sign_up(
             }
         };
 
-        // generate salt
-        let salt = gen_salt().await;
-        // encrypt password
-        let mut hash_sha3 = Sha3::sha3_256();
-        hash_sha3.input_str(&(String::from(&salt) + user.password));
-        let password = hash_sha3.result_str();
+        // hash password, the salt is kept in the PHC string
+        let password = match hash_password(user.password).await {
+            Ok(p) => p,
+            Err(e) => return (Status::InternalServerError, Err(Json(e))),
+        };
         // generate uid
         let uid: i64 = IdInstance::next_id();
         // fill the row of table 'user' and 'user_status'
@@ -506,7 +507,7 @@ pub async fn user_sign_up(
             password: Set(password),
             email: Set(user.email.to_string()),
             create_time: Set(now.to_owned()),
-            salt: Set(salt),
+            salt: Set(String::new()),
         };
 
         let burrows = db::burrow::ActiveModel {
@@ -525,11 +526,9 @@ pub async fn user_sign_up(
                     users.insert(txn).await?;
                     let res = burrows.insert(txn).await?;
                     let burrow_id = res.burrow_id;
-                    let valid_burrows_str = burrow_id.to_string();
                     let users_status = db::user_status::ActiveModel {
                         uid: Set(uid),
                         update_time: Set(now),
-                        valid_burrow: Set(valid_burrows_str),
                         ..Default::default()
                     };
                     users_status.insert(txn).await?;
@@ -552,13 +551,14 @@ pub async fn user_sign_up(
 
 /// User Reset
 ///
-/// User Resets password in logout status, requires verification code from verification email sent by `user_email_service`.
+/// User Resets password in logout status, requires verification code from verification email sent by `user_email_service`. All the sessions of the user are logged out.
 ///
 /// ## Parameters
 ///
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<RedisDb>`: Redis connection
 /// - `CookieJar`: Collection of Cookie
+/// - `ClientInfo`: Client of the new session
 /// - `Json<UserResetInfo>`: Json of UserResetInfo, including password, email, verification code
 ///
 /// ## Returns
@@ -577,6 +577,7 @@ pub async fn user_reset(
     db: Connection<PgDb>,
     kvdb: Connection<RedisDb>,
     cookies: &CookieJar<'_>,
+    client: ClientInfo,
     user_info: Json<UserResetInfo<'_>>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
@@ -684,26 +685,28 @@ pub async fn user_reset(
             }
         };
 
-        // get salt
-        let salt = user_stored.salt.clone();
         let uid = user_stored.uid;
-        // encrypt password
-        let mut hash_sha3 = Sha3::sha3_256();
-        hash_sha3.input_str(&(salt + user.password));
-        let password = hash_sha3.result_str();
+        // hash password
+        let password = match hash_password(user.password).await {
+            Ok(p) => p,
+            Err(e) => return (Status::InternalServerError, Err(Json(e))),
+        };
         let mut users: db::user::ActiveModel = user_stored.into();
         users.password = Set(password);
+        users.salt = Set(String::new());
         // insert rows in database
         match users.update(&pg_con).await {
             Ok(_) => {
-                let token = match crate::utils::auth::set_token(uid, kv_conn.as_mut()).await {
+                // log out all the sessions with the old password
+                if let Err(e) = revoke_all_sessions(uid, kv_conn.as_mut()).await {
+                    return (Status::InternalServerError, Err(Json(e)));
+                }
+                let session_token = match set_token(uid, &client, false, kv_conn.as_mut()).await {
                     Ok(t) => t,
                     Err(e) => return (Status::InternalServerError, Err(Json(e))),
                 };
-                // build cookie
-                let cookie = Cookie::build("token", token).cookie_options().finish();
-                // set cookie
-                cookies.add_private(cookie);
+                // set cookies
+                add_token_cookies(cookies, &session_token);
                 info!("[RESET] User login complete.");
                 (Status::Ok, Ok("Success".to_string()))
             }
@@ -720,7 +723,7 @@ pub async fn user_reset(
 
 /// User Change Password
 ///
-/// User changes password in login status, no requirement for verification code.
+/// User changes password in login status, no requirement for verification code. All the other sessions of the user are logged out.
 ///
 /// ## Parameters
 ///
@@ -728,6 +731,7 @@ pub async fn user_reset(
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<RedisDb>`: Redis connection
 /// - `CookieJar`: Collection of Cookie
+/// - `ClientInfo`: Client of the new session
 /// - `Json<UserChangePassword>`: Json of UserChangePassword, including old password, new password
 ///
 /// ## Returns
@@ -747,6 +751,7 @@ pub async fn user_change_password(
     db: Connection<PgDb>,
     kvdb: Connection<RedisDb>,
     cookies: &CookieJar<'_>,
+    client: ClientInfo,
     user_info: Json<UserChangePassword<'_>>,
 ) -> (Status, Result<String, Json<ErrorResponse>>) {
     let pg_con = db.into_inner();
@@ -776,15 +781,12 @@ pub async fn user_change_password(
             );
         }
     };
-    // get salt
-    let salt = user_stored.salt.clone();
     let uid = user_stored.uid;
-    let old_password = user_stored.password.clone();
-    // encrypt password
-    let mut hash_sha3 = Sha3::sha3_256();
-    hash_sha3.input_str(&(salt.clone() + user.password));
-    let password = hash_sha3.result_str();
-    if password != old_password {
+    // check old password, legacy hashes are accepted as well
+    if !verify_password(&user_stored.password, &user_stored.salt, user.password)
+        .await
+        .is_valid()
+    {
         return (
             Status::BadRequest,
             Err(Json(ErrorResponse::build(
@@ -793,22 +795,31 @@ pub async fn user_change_password(
             ))),
         );
     }
-    let mut hash_sha3 = Sha3::sha3_256();
-    hash_sha3.input_str(&(salt + user.new_password));
-    let new_password = hash_sha3.result_str();
+    let new_password = match hash_password(user.new_password).await {
+        Ok(p) => p,
+        Err(e) => return (Status::InternalServerError, Err(Json(e))),
+    };
     let mut users: db::user::ActiveModel = user_stored.into();
     users.password = Set(new_password);
+    users.salt = Set(String::new());
     // insert rows in database
     match users.update(&pg_con).await {
         Ok(_) => {
-            let token = match crate::utils::auth::set_token(uid, kv_conn.as_mut()).await {
+            // the new session keeps whether the second factor was checked
+            let mfa = match is_mfa_session(uid, &auth.session_id, kv_conn.as_mut()).await {
+                Ok(mfa) => mfa,
+                Err(e) => return (Status::InternalServerError, Err(Json(e))),
+            };
+            // log out all the sessions with the old password
+            if let Err(e) = revoke_all_sessions(uid, kv_conn.as_mut()).await {
+                return (Status::InternalServerError, Err(Json(e)));
+            }
+            let session_token = match set_token(uid, &client, mfa, kv_conn.as_mut()).await {
                 Ok(t) => t,
                 Err(e) => return (Status::InternalServerError, Err(Json(e))),
             };
-            // build cookie
-            let cookie = Cookie::build("token", token).cookie_options().finish();
-            // set cookie
-            cookies.add_private(cookie);
+            // set cookies
+            add_token_cookies(cookies, &session_token);
             info!("[RESET] User login complete.");
             (Status::Ok, Ok("Success".to_string()))
         }
@@ -824,13 +835,21 @@ pub async fn user_change_password(
 
 /// User Log in
 ///
-/// Log in a user.
+/// Log in a user in a new session, other sessions of the user are kept.
+/// Legacy SHA3 password hashes are replaced with Argon2id on success.
+/// If TOTP is enabled, no session is created yet: status 202 with "TotpRequired"
+/// is returned, and the login continues in `user_log_in_totp`.
+///
+/// Failed logins are counted per username and per client ip. Once there are too
+/// many of them, the username or ip is locked for a while, and the lockout grows
+/// with every further failure. A successful login clears the failures of the username.
 ///
 /// ## Parameters
 ///
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Connection<RedisDb>`: Redis connection
 /// - `CookieJar`: Collection of Cookie
+/// - `ClientInfo`: Client of the new session
 /// - `Json<UserLoginInfo>`: Json of UserLoginInfo, including username, password
 ///
 /// ## Returns
@@ -843,48 +862,126 @@ pub async fn user_change_password(
 /// - `ErrorResponse`: Error message
 ///   - `ErrorCode::CredentialInvalid`
 ///   - `ErrorCode::DatabaseErr`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///   - `ErrorCode::RateLimit`
 #[post("/login", data = "<user_info>", format = "json")]
 pub async fn user_log_in(
     db: Connection<PgDb>,
     kvdb: Connection<RedisDb>,
     cookies: &CookieJar<'_>,
+    client: ClientInfo,
     user_info: Json<UserLoginInfo<'_>>,
-) -> (Status, Result<String, Json<ErrorResponse>>) {
+) -> Result<(Status, Result<String, Json<ErrorResponse>>), RateLimitResponse> {
+    let pg_con = db.into_inner();
     let mut con = kvdb.into_inner();
     // get user info from request
     let user = user_info.into_inner();
+    // check if the username or ip is locked
+    match check_lockout(user.username, &client.ip, con.as_mut()).await {
+        Ok(Some(retry_after)) => {
+            info!("[LOGIN] login is locked.");
+            return Err(RateLimitResponse::build(
+                retry_after,
+                "Too many failed login attempts.",
+            ));
+        }
+        Ok(None) => {}
+        Err(e) => return Ok((Status::InternalServerError, Err(Json(e)))),
+    }
+    let (status, result) = log_in(&pg_con, con.as_mut(), cookies, &client, &user).await;
+    match &result {
+        Err(e) if e.error.code == ErrorCode::CredentialInvalid => {
+            match record_failure(user.username, &client.ip, con.as_mut()).await {
+                Ok(Some(retry_after)) => {
+                    return Err(RateLimitResponse::build(
+                        retry_after,
+                        "Too many failed login attempts.",
+                    ))
+                }
+                Ok(None) => {}
+                Err(e) => return Ok((Status::InternalServerError, Err(Json(e)))),
+            }
+        }
+        // a pending TOTP step does not clear the failures
+        Ok(_) if status == Status::Ok => {
+            if let Err(e) = clear_failures(LockoutTarget::User, user.username, con.as_mut()).await {
+                error!("[LOGIN] Failed to clear login failures: {:?}", e);
+            }
+        }
+        _ => {}
+    }
+    Ok((status, result))
+}
+
+/// Check the credential of a user and create the session, see `user_log_in`
+async fn log_in(
+    pg_con: &DatabaseConnection,
+    kv_conn: &mut redis::aio::Connection,
+    cookies: &CookieJar<'_>,
+    client: &ClientInfo,
+    user: &UserLoginInfo<'_>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
     // check if username is existed, add corresponding error if so
     match User::find()
         .filter(db::user::Column::Username.eq(user.username))
-        .one(&db.into_inner())
+        .one(pg_con)
         .await
     {
         Ok(s) => match s {
             Some(matched_user) => {
                 info!("[LOGIN] username exists, continue...");
-                let salt = matched_user.salt;
-                // if salt.is_empty() {
-                //     error!("[LOGIN] cannot find user's salt.");
-                //     return (
-                //         Status::InternalServerError,
-                //         Err(Json(ErrorResponse::default())),
-                //     );
-                // }
-                // encrypt input password same as sign-up
-                let mut hash_sha3 = Sha3::sha3_256();
-                hash_sha3.input_str(&(salt + user.password));
-                let password = hash_sha3.result_str();
                 // check if password is wrong, add corresponding error if so
-                if matched_user.password.eq(&password) {
+                let check =
+                    verify_password(&matched_user.password, &matched_user.salt, user.password)
+                        .await;
+                if check.is_valid() {
                     info!("[LOGIN] password correct, continue...");
-                    let token = match set_token(matched_user.uid, con.as_mut()).await {
-                        Ok(t) => t,
-                        Err(e) => return (Status::InternalServerError, Err(Json(e))),
-                    };
-                    // build cookie
-                    let cookie = Cookie::build("token", token).cookie_options().finish();
-                    // set cookie
-                    cookies.add_private(cookie);
+                    // replace legacy SHA3 hash with Argon2id, login goes on if it fails
+                    if check == PasswordCheck::Legacy {
+                        match hash_password(user.password).await {
+                            Ok(password) => {
+                                let mut users: db::user::ActiveModel = matched_user.clone().into();
+                                users.password = Set(password);
+                                users.salt = Set(String::new());
+                                if let Err(e) = users.update(pg_con).await {
+                                    error!("[LOGIN] Database error: {:?}", e);
+                                }
+                            }
+                            Err(e) => error!("[LOGIN] Failed to rehash password: {:?}", e),
+                        }
+                    }
+                    // accounts with TOTP enabled need the second step in `user_log_in_totp`
+                    match get_enabled_totp(pg_con, matched_user.uid).await {
+                        Ok(Some(_)) => {
+                            let ticket = match create_login_ticket(matched_user.uid, kv_conn).await
+                            {
+                                Ok(t) => t,
+                                Err(e) => return (Status::InternalServerError, Err(Json(e))),
+                            };
+                            let cookie = Cookie::build("totp_ticket", ticket)
+                                .cookie_options()
+                                .max_age(time::Duration::seconds(TOTP_LOGIN_EX))
+                                .finish();
+                            cookies.add_private(cookie);
+                            info!("[LOGIN] waiting for TOTP code.");
+                            return (Status::Accepted, Ok("TotpRequired".to_string()));
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            error!("[LOGIN] Database error: {:?}", e);
+                            return (
+                                Status::InternalServerError,
+                                Err(Json(ErrorResponse::default())),
+                            );
+                        }
+                    }
+                    let session_token =
+                        match set_token(matched_user.uid, client, false, kv_conn).await {
+                            Ok(t) => t,
+                            Err(e) => return (Status::InternalServerError, Err(Json(e))),
+                        };
+                    // set cookies
+                    add_token_cookies(cookies, &session_token);
                     info!("[LOGIN] User login complete.");
                     (Status::Ok, Ok("Success".to_string()))
                 } else {
@@ -919,6 +1016,498 @@ pub async fn user_log_in(
     }
 }
 
+/// User Log in with TOTP
+///
+/// Second step of login for accounts with TOTP enabled, using the ticket in cookie
+/// set by `user_log_in`. The ticket expires after a few failed attempts, and the
+/// ticket is rejected if TOTP has been disabled since it was issued.
+///
+/// Wrong codes are counted as failed logins of the username and ip, so that once
+/// they are locked, neither this step nor `user_log_in` issuing a new ticket is
+/// allowed until the lockout is lifted.
+///
+/// ## Parameters
+///
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+/// - `CookieJar`: Collection of Cookie
+/// - `ClientInfo`: Client of the new session
+/// - `Json<TotpCode>`: Json of TotpCode, code of the authenticator app or a recovery code
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::AuthTokenMissing`
+///   - `ErrorCode::AuthTokenInvalid`
+///   - `ErrorCode::TotpInvalid`
+///   - `ErrorCode::DatabaseErr`
+/// - `RateLimitResponse`: Status 429 with header `Retry-After`
+///   - `ErrorCode::RateLimit`
+#[post("/login/totp", data = "<totp_code>", format = "json")]
+pub async fn user_log_in_totp(
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+    cookies: &CookieJar<'_>,
+    client: ClientInfo,
+    totp_code: Json<TotpCode<'_>>,
+) -> Result<(Status, Result<String, Json<ErrorResponse>>), RateLimitResponse> {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    let code = totp_code.into_inner().code;
+    let ticket = match cookies.get_private("totp_ticket") {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            return Ok((
+                Status::Unauthorized,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::AuthTokenMissing,
+                    "Login ticket is missing.",
+                ))),
+            ))
+        }
+    };
+    let invalid_ticket = (
+        Status::Unauthorized,
+        Err(Json(ErrorResponse::build(
+            ErrorCode::AuthTokenInvalid,
+            "Login ticket is invalid.",
+        ))),
+    );
+    let uid = match get_login_ticket(&ticket, kv_conn.as_mut()).await {
+        Ok(Some(uid)) => uid,
+        Ok(None) => return Ok(invalid_ticket),
+        Err(e) => return Ok((Status::InternalServerError, Err(Json(e)))),
+    };
+    let username = match User::find_by_id(uid).one(&pg_con).await {
+        Ok(Some(user)) => user.username,
+        Ok(None) => return Ok(invalid_ticket),
+        Err(e) => {
+            error!("[LOGIN] Database error: {:?}", e);
+            return Ok((
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            ));
+        }
+    };
+    // check if the username or ip is locked
+    match check_lockout(&username, &client.ip, kv_conn.as_mut()).await {
+        Ok(Some(retry_after)) => {
+            info!("[LOGIN] login is locked.");
+            return Err(RateLimitResponse::build(
+                retry_after,
+                "Too many failed login attempts.",
+            ));
+        }
+        Ok(None) => {}
+        Err(e) => return Ok((Status::InternalServerError, Err(Json(e)))),
+    }
+    let checked = match get_enabled_totp(&pg_con, uid).await {
+        Ok(Some(totp)) => check_totp(&pg_con, totp, code).await,
+        // TOTP has been disabled since the first step, the ticket is stale
+        Ok(None) => {
+            info!("[LOGIN] TOTP has been disabled since the ticket was issued.");
+            if let Err(e) = delete_login_ticket(&ticket, kv_conn.as_mut()).await {
+                return Ok((Status::InternalServerError, Err(Json(e))));
+            }
+            return Ok(invalid_ticket);
+        }
+        Err(e) => Err(e),
+    };
+    match checked {
+        Ok(true) => {
+            if let Err(e) = delete_login_ticket(&ticket, kv_conn.as_mut()).await {
+                return Ok((Status::InternalServerError, Err(Json(e))));
+            }
+            let mut cookie = Cookie::named("totp_ticket");
+            cookie.set_domain(".thuburrow.com");
+            cookies.remove_private(cookie);
+            if let Err(e) = clear_failures(LockoutTarget::User, &username, kv_conn.as_mut()).await {
+                error!("[LOGIN] Failed to clear login failures: {:?}", e);
+            }
+            let session_token = match set_token(uid, &client, true, kv_conn.as_mut()).await {
+                Ok(t) => t,
+                Err(e) => return Ok((Status::InternalServerError, Err(Json(e)))),
+            };
+            // set cookies
+            add_token_cookies(cookies, &session_token);
+            info!("[LOGIN] User login complete.");
+            Ok((Status::Ok, Ok("Success".to_string())))
+        }
+        Ok(false) => {
+            info!("[LOGIN] wrong TOTP code.");
+            if let Err(e) = fail_login_ticket(&ticket, kv_conn.as_mut()).await {
+                return Ok((Status::InternalServerError, Err(Json(e))));
+            }
+            match record_failure(&username, &client.ip, kv_conn.as_mut()).await {
+                Ok(Some(retry_after)) => {
+                    if let Err(e) = delete_login_ticket(&ticket, kv_conn.as_mut()).await {
+                        return Ok((Status::InternalServerError, Err(Json(e))));
+                    }
+                    return Err(RateLimitResponse::build(
+                        retry_after,
+                        "Too many failed login attempts.",
+                    ));
+                }
+                Ok(None) => {}
+                Err(e) => return Ok((Status::InternalServerError, Err(Json(e)))),
+            }
+            Ok((
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::TotpInvalid,
+                    "Wrong TOTP code.",
+                ))),
+            ))
+        }
+        Err(e) => {
+            error!("[LOGIN] Database error: {:?}", e);
+            Ok((
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            ))
+        }
+    }
+}
+
+/// Enroll TOTP
+///
+/// Generate a new TOTP secret for a user, which takes effect after `totp_confirm`.
+/// Enrolling again before confirmation replaces the secret.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<TotpEnrollResponse>`: Json of the secret and its `otpauth://` URI
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::TotpEnabled`
+///   - `ErrorCode::UserNotExist`
+///   - `ErrorCode::DatabaseErr`
+#[post("/totp/enroll")]
+pub async fn totp_enroll(
+    auth: Auth,
+    db: Connection<PgDb>,
+) -> (
+    Status,
+    Result<Json<TotpEnrollResponse>, Json<ErrorResponse>>,
+) {
+    let pg_con = db.into_inner();
+    let username = match User::find_by_id(auth.id).one(&pg_con).await {
+        Ok(Some(user)) => user.username,
+        Ok(None) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::UserNotExist,
+                    "User not exist.",
+                ))),
+            )
+        }
+        Err(e) => {
+            error!("[TOTP] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    let secret = gen_secret();
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let result = pg_con
+        .transaction::<_, bool, DbErr>(|txn| {
+            let secret = secret.clone();
+            Box::pin(async move {
+                match UserTotp::find_by_id(auth.id).one(txn).await? {
+                    Some(totp) if totp.enabled => return Ok(false),
+                    Some(totp) => {
+                        totp.delete(txn).await?;
+                    }
+                    None => {}
+                }
+                let totp = db::user_totp::ActiveModel {
+                    uid: Set(auth.id),
+                    secret: Set(secret),
+                    enabled: Set(false),
+                    recovery_codes: Set("[]".to_string()),
+                    last_step: Set(0),
+                    create_time: Set(now),
+                };
+                totp.insert(txn).await?;
+                Ok(true)
+            })
+        })
+        .await;
+    match result {
+        Ok(true) => (
+            Status::Ok,
+            Ok(Json(TotpEnrollResponse {
+                uri: otpauth_uri(&secret, &username),
+                secret,
+            })),
+        ),
+        Ok(false) => (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::TotpEnabled,
+                "TOTP is already enabled.",
+            ))),
+        ),
+        Err(e) => {
+            error!("[TOTP] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Confirm TOTP
+///
+/// Enable TOTP with a code of the enrolled secret, and generate recovery codes.
+/// The current session counts as checked with TOTP, and the other sessions of
+/// the user are revoked.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Connection<RedisDb>`: Redis connection
+/// - `Json<TotpCode>`: Json of TotpCode, code of the authenticator app
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<TotpRecoveryCodes>`: Json of recovery codes, which are only shown this time
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::TotpEnabled`
+///   - `ErrorCode::TotpInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[post("/totp/confirm", data = "<totp_code>", format = "json")]
+pub async fn totp_confirm(
+    auth: Auth,
+    db: Connection<PgDb>,
+    kvdb: Connection<RedisDb>,
+    totp_code: Json<TotpCode<'_>>,
+) -> (Status, Result<Json<TotpRecoveryCodes>, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let mut kv_conn = kvdb.into_inner();
+    let code = totp_code.into_inner().code;
+    let totp = match UserTotp::find_by_id(auth.id).one(&pg_con).await {
+        Ok(Some(totp)) if totp.enabled => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::TotpEnabled,
+                    "TOTP is already enabled.",
+                ))),
+            )
+        }
+        Ok(Some(totp)) => totp,
+        Ok(None) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::TotpInvalid,
+                    "TOTP is not enrolled.",
+                ))),
+            )
+        }
+        Err(e) => {
+            error!("[TOTP] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    let step = match verify_code(&totp.secret, code, Utc::now().timestamp(), totp.last_step) {
+        Some(step) => step,
+        None => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::TotpInvalid,
+                    "Wrong TOTP code.",
+                ))),
+            )
+        }
+    };
+    let recovery_codes = gen_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|c| hash_recovery_code(c))
+        .collect();
+    let mut totp: db::user_totp::ActiveModel = totp.into();
+    totp.enabled = Set(true);
+    totp.last_step = Set(step);
+    totp.recovery_codes = Set(serde_json::to_string(&hashes).unwrap());
+    match totp.update(&pg_con).await {
+        Ok(_) => {
+            if let Err(e) = confirm_mfa_session(auth.id, &auth.session_id, kv_conn.as_mut()).await {
+                return (Status::InternalServerError, Err(Json(e)));
+            }
+            (Status::Ok, Ok(Json(TotpRecoveryCodes { recovery_codes })))
+        }
+        Err(e) => {
+            error!("[TOTP] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// Disable TOTP
+///
+/// Disable TOTP with a code of the authenticator app or a recovery code.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<PgDb>`: Postgres connection
+/// - `Json<TotpCode>`: Json of TotpCode, code of the authenticator app or a recovery code
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::TotpInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[post("/totp/disable", data = "<totp_code>", format = "json")]
+pub async fn totp_disable(
+    auth: Auth,
+    db: Connection<PgDb>,
+    totp_code: Json<TotpCode<'_>>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let pg_con = db.into_inner();
+    let code = totp_code.into_inner().code;
+    let totp = match get_enabled_totp(&pg_con, auth.id).await {
+        Ok(Some(totp)) => totp,
+        Ok(None) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::TotpInvalid,
+                    "TOTP is not enabled.",
+                ))),
+            )
+        }
+        Err(e) => {
+            error!("[TOTP] Database error: {:?}", e);
+            return (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            );
+        }
+    };
+    match check_totp(&pg_con, totp, code).await {
+        Ok(true) => match UserTotp::delete_by_id(auth.id).exec(&pg_con).await {
+            Ok(_) => (Status::Ok, Ok("Success".to_string())),
+            Err(e) => {
+                error!("[TOTP] Database error: {:?}", e);
+                (
+                    Status::InternalServerError,
+                    Err(Json(ErrorResponse::default())),
+                )
+            }
+        },
+        Ok(false) => (
+            Status::BadRequest,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::TotpInvalid,
+                "Wrong TOTP code.",
+            ))),
+        ),
+        Err(e) => {
+            error!("[TOTP] Database error: {:?}", e);
+            (
+                Status::InternalServerError,
+                Err(Json(ErrorResponse::default())),
+            )
+        }
+    }
+}
+
+/// User Refresh
+///
+/// Exchange the refresh token in cookie for new tokens of the same session.
+/// Each refresh token can only be used once, replaying a used one revokes the session.
+///
+/// ## Parameters
+///
+/// - `Connection<RedisDb>`: Redis connection
+/// - `CookieJar`: Collection of Cookie
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::AuthTokenMissing`
+///   - `ErrorCode::AuthTokenInvalid`
+///   - `ErrorCode::DatabaseErr`
+#[post("/refresh")]
+pub async fn user_refresh(
+    kvdb: Connection<RedisDb>,
+    cookies: &CookieJar<'_>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let mut kv_conn = kvdb.into_inner();
+    let refresh_token = match cookies.get_private("refresh_token") {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            return (
+                Status::Unauthorized,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::AuthTokenMissing,
+                    "Refresh token is missing.",
+                ))),
+            )
+        }
+    };
+    match rotate_token(&refresh_token, kv_conn.as_mut()).await {
+        Ok(Some(session_token)) => {
+            add_token_cookies(cookies, &session_token);
+            info!("[REFRESH] User refresh complete.");
+            (Status::Ok, Ok("Success".to_string()))
+        }
+        Ok(None) => {
+            remove_token_cookies(cookies);
+            (
+                Status::Unauthorized,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::AuthTokenInvalid,
+                    "Refresh token is invalid.",
+                ))),
+            )
+        }
+        Err(e) => (Status::InternalServerError, Err(Json(e))),
+    }
+}
+
 /// User logout
 ///
 /// Logout a user.
@@ -947,45 +1536,138 @@ pub async fn user_logout(
     let mut kv_conn = kvdb.into_inner();
     // get user info from request
     let uid = auth.id;
-    match delete_token(uid, kv_conn.as_mut()).await {
+    match revoke_session(uid, &auth.session_id, kv_conn.as_mut()).await {
         Ok(_) => {
-            let delete_result: Result<i64, redis::RedisError> = redis::cmd("DEL")
-                .arg(uid)
-                .query_async(kv_conn.as_mut())
-                .await;
-            match delete_result {
-                Ok(1) => info!("[TOKEN] delete id->token"),
-                Ok(0) => info!("[TOKEN] no id->token found"),
-                Ok(_) => {
-                    error!("[TOKEN] failed to delete refresh_token -> id when login.");
-                    return (
-                        Status::InternalServerError,
-                        Err(Json(ErrorResponse::default())),
-                    );
-                }
-                Err(e) => {
-                    error!(
-                        "[TOKEN] failed to delete token -> id when login. RedisError: {:?}",
-                        e
-                    );
-                    return (
-                        Status::InternalServerError,
-                        Err(Json(ErrorResponse::default())),
-                    );
-                }
-            };
-            let mut cookie = Cookie::named("token");
-            cookie.set_domain(".thuburrow.com");
-            cookies.remove_private(cookie);
+            remove_token_cookies(cookies);
             (Status::Ok, Ok("Success".to_string()))
         }
         Err(e) => {
-            error!("[LOGOUT] Database error: {:?}", e);
-            (
-                Status::InternalServerError,
-                Err(Json(ErrorResponse::default())),
-            )
+            error!("[LOGOUT] Failed to revoke session: {:?}", e);
+            (Status::InternalServerError, Err(Json(e)))
+        }
+    }
+}
+
+/// Get Sessions
+///
+/// Get the login sessions of a user, the most recently seen first.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<RedisDb>`: Redis connection
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `Json<Vec<SessionDisplay>>`: Json of sessions, `current` marks the session making the request
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::DatabaseErr`
+#[get("/sessions")]
+pub async fn get_user_sessions(
+    auth: Auth,
+    kvdb: Connection<RedisDb>,
+) -> (
+    Status,
+    Result<Json<Vec<SessionDisplay>>, Json<ErrorResponse>>,
+) {
+    let mut kv_conn = kvdb.into_inner();
+    match get_sessions(auth.id, kv_conn.as_mut()).await {
+        Ok(sessions) => (
+            Status::Ok,
+            Ok(Json(
+                sessions
+                    .iter()
+                    .map(|s| s.display(&auth.session_id))
+                    .collect(),
+            )),
+        ),
+        Err(e) => (Status::InternalServerError, Err(Json(e))),
+    }
+}
+
+/// Revoke Session
+///
+/// Revoke a login session of a user, the client of it will have to log in again.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<RedisDb>`: Redis connection
+/// - `CookieJar`: Collection of Cookie
+/// - `session_id`: String, id of the session
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::SessionNotExist`
+///   - `ErrorCode::DatabaseErr`
+#[delete("/sessions/<session_id>")]
+pub async fn revoke_user_session(
+    auth: Auth,
+    kvdb: Connection<RedisDb>,
+    cookies: &CookieJar<'_>,
+    session_id: String,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let mut kv_conn = kvdb.into_inner();
+    match revoke_session(auth.id, &session_id, kv_conn.as_mut()).await {
+        Ok(true) => {
+            if session_id == auth.session_id {
+                remove_token_cookies(cookies);
+            }
+            (Status::Ok, Ok("Success".to_string()))
+        }
+        Ok(false) => (
+            Status::NotFound,
+            Err(Json(ErrorResponse::build(
+                ErrorCode::SessionNotExist,
+                "Session not exist.",
+            ))),
+        ),
+        Err(e) => (Status::InternalServerError, Err(Json(e))),
+    }
+}
+
+/// Revoke All Sessions
+///
+/// Revoke all the login sessions of a user, including the one making the request.
+///
+/// ## Parameters
+///
+/// - `Auth`: Authenticated user
+/// - `Connection<RedisDb>`: Redis connection
+/// - `CookieJar`: Collection of Cookie
+///
+/// ## Returns
+///
+/// - `Status`: HTTP status
+/// - `String`: String "Success"
+///
+/// ## Errors
+///
+/// - `ErrorResponse`: Error message
+///   - `ErrorCode::DatabaseErr`
+#[delete("/sessions")]
+pub async fn revoke_user_sessions(
+    auth: Auth,
+    kvdb: Connection<RedisDb>,
+    cookies: &CookieJar<'_>,
+) -> (Status, Result<String, Json<ErrorResponse>>) {
+    let mut kv_conn = kvdb.into_inner();
+    match revoke_all_sessions(auth.id, kv_conn.as_mut()).await {
+        Ok(_) => {
+            remove_token_cookies(cookies);
+            (Status::Ok, Ok("Success".to_string()))
         }
+        Err(e) => (Status::InternalServerError, Err(Json(e))),
     }
 }
 
@@ -1030,29 +1712,19 @@ pub async fn get_burrow(
         .await
     {
         Ok(opt_state) => match opt_state {
-            Some(state) => {
-                let valid_burrows = get_burrow_list(&state.valid_burrow);
-                let banned_burrows = get_burrow_list(&state.banned_burrow);
-                let burrow_ids = [valid_burrows, banned_burrows].concat();
-                match Burrow::find()
-                    .filter(Condition::any().add(db::burrow::Column::BurrowId.is_in(burrow_ids)))
-                    .order_by_desc(db::burrow::Column::BurrowId)
-                    .all(&pg_con)
-                    .await
-                {
-                    Ok(burrows) => (
-                        Status::Ok,
-                        Ok(Json(burrows.iter().map(|burrow| burrow.into()).collect())),
-                    ),
-                    Err(e) => {
-                        error!("[GET_BURROW] failed to get burrow list: {:?}", e);
-                        (
-                            Status::InternalServerError,
-                            Err(Json(ErrorResponse::default())),
-                        )
-                    }
+            Some(_) => match get_owned_burrows(&pg_con, auth.id).await {
+                Ok(burrows) => (
+                    Status::Ok,
+                    Ok(Json(burrows.iter().map(|burrow| burrow.into()).collect())),
+                ),
+                Err(e) => {
+                    error!("[GET_BURROW] failed to get burrow list: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
                 }
-            }
+            },
             None => {
                 info!("[GET-BURROW] Cannot find user_status by uid.");
                 (
@@ -1080,6 +1752,7 @@ pub async fn get_burrow(
 /// - `Auth`: Authenticated user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Option<usize>`: page number, default value 0
+/// - `Option<String>`: cursor of the last item read, `page` is ignored if set
 ///
 /// ## Returns
 ///
@@ -1089,81 +1762,74 @@ pub async fn get_burrow(
 /// ## Errors
 ///
 /// - `ErrorResponse`: Error message
+///   - `ErrorCode::CursorInvalid`
 ///   - `ErrorCode::DatabaseErr`
-#[get("/collection?<page>")]
+#[get("/collection?<page>&<cursor>")]
 pub async fn get_collection(
     db: Connection<PgDb>,
     auth: Auth,
     page: Option<usize>,
+    cursor: Option<String>,
 ) -> (
     Status,
     Result<Json<Vec<UserGetCollectionResponse>>, Json<ErrorResponse>>,
 ) {
     let pg_con = db.into_inner();
     let page = page.unwrap_or(0);
-    match UserCollection::find()
+    let cursor = match cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(Cursor::Post { post_id })) => Some(post_id),
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::CursorInvalid,
+                    "Invalid cursor.",
+                ))),
+            )
+        }
+    };
+    let select = UserCollection::find()
+        .find_also_related(ContentPost)
         .filter(db::user_collection::Column::Uid.eq(auth.id))
-        .order_by_desc(db::user_collection::Column::PostId)
-        .paginate(&pg_con, POST_PER_PAGE)
-        .fetch_page(page)
-        .await
-    {
-        Ok(results) => {
-            let post_ids = results.iter().map(|r| r.post_id).collect::<Vec<i64>>();
-            match ContentPost::find()
-                .filter(db::content_post::Column::PostId.is_in(post_ids))
-                .order_by_desc(db::content_post::Column::PostId)
+        .order_by_desc(db::user_collection::Column::PostId);
+    let results = match cursor {
+        Some(post_id) => {
+            select
+                .filter(db::user_collection::Column::PostId.lt(post_id))
+                .limit(POST_PER_PAGE as u64)
                 .all(&pg_con)
                 .await
-            {
-                Ok(posts) => {
-                    if posts.len() == results.len() {
-                        (
-                            Status::Ok,
-                            Ok(Json(
-                                posts
-                                    .iter()
-                                    .map(|post| {
-                                        let p: Post = post.into();
-                                        p
-                                    })
-                                    .zip(results.iter().map(|r| r.is_update))
-                                    .map(|(post, is_update)| UserGetCollectionResponse {
-                                        post,
-                                        is_update,
-                                    })
-                                    .collect(),
-                            )),
-                        )
-                    } else {
-                        let hm: HashMap<i64, bool> =
-                            results.iter().map(|r| (r.post_id, r.is_update)).collect();
-                        (
-                            Status::Ok,
-                            Ok(Json(
-                                posts
-                                    .iter()
-                                    .map(|p| {
-                                        let post: Post = p.into();
-                                        let is_update = *hm.get(&post.post_id).unwrap_or(&false);
-                                        UserGetCollectionResponse { post, is_update }
-                                    })
-                                    .collect(),
-                            )),
-                        )
-                    }
-                }
-                Err(e) => {
-                    error!("[GET-FOLLOW] Database Error: {:?}", e);
-                    (
-                        Status::InternalServerError,
-                        Err(Json(ErrorResponse::default())),
-                    )
-                }
-            }
         }
+        None => {
+            select
+                .paginate(&pg_con, POST_PER_PAGE)
+                .fetch_page(page)
+                .await
+        }
+    };
+    match results {
+        Ok(results) => (
+            Status::Ok,
+            Ok(Json(
+                results
+                    .into_iter()
+                    .filter_map(|(collection, post)| {
+                        let post: Post = post?.into();
+                        Some(UserGetCollectionResponse {
+                            cursor: Cursor::Post {
+                                post_id: post.post_id,
+                            }
+                            .encode(),
+                            post,
+                            is_update: collection.is_update,
+                        })
+                    })
+                    .collect(),
+            )),
+        ),
         Err(e) => {
-            error!("[GET-FOLLOW] Database Error: {:?}", e);
+            error!("[GET-COLLECTION] Database Error: {:?}", e);
             (
                 Status::InternalServerError,
                 Err(Json(ErrorResponse::default())),
@@ -1215,6 +1881,7 @@ pub async fn get_collection(
 /// - `Auth`: Authenticated user
 /// - `Connection<PgDb>`: Postgres connection
 /// - `Option<usize>`: page number, default value 0
+/// - `Option<String>`: cursor of the last item read, `page` is ignored if set
 ///
 /// ## Returns
 ///
@@ -1224,80 +1891,72 @@ pub async fn get_collection(
 /// ## Errors
 ///
 /// - `ErrorResponse`: Error message
+///   - `ErrorCode::CursorInvalid`
 ///   - `ErrorCode::DatabaseErr`
-#[get("/follow?<page>")]
+#[get("/follow?<page>&<cursor>")]
 pub async fn get_follow(
     db: Connection<PgDb>,
     auth: Auth,
     page: Option<usize>,
+    cursor: Option<String>,
 ) -> (
     Status,
     Result<Json<Vec<UserGetFollowResponse>>, Json<ErrorResponse>>,
 ) {
     let pg_con = db.into_inner();
     let page = page.unwrap_or(0);
-    match UserFollow::find()
+    let cursor = match cursor.as_deref().map(Cursor::decode) {
+        None => None,
+        Some(Some(Cursor::Burrow { burrow_id })) => Some(burrow_id),
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Err(Json(ErrorResponse::build(
+                    ErrorCode::CursorInvalid,
+                    "Invalid cursor.",
+                ))),
+            )
+        }
+    };
+    let select = UserFollow::find()
+        .find_also_related(Burrow)
         .filter(db::user_follow::Column::Uid.eq(auth.id))
-        .order_by_desc(db::user_follow::Column::BurrowId)
-        .paginate(&pg_con, BURROW_PER_PAGE)
-        .fetch_page(page)
-        .await
-    {
-        Ok(results) => {
-            let burrow_ids = results.iter().map(|r| r.burrow_id).collect::<Vec<i64>>();
-            match Burrow::find()
-                .filter(db::burrow::Column::BurrowId.is_in(burrow_ids))
-                .order_by_desc(db::burrow::Column::BurrowId)
+        .order_by_desc(db::user_follow::Column::BurrowId);
+    let results = match cursor {
+        Some(burrow_id) => {
+            select
+                .filter(db::user_follow::Column::BurrowId.lt(burrow_id))
+                .limit(BURROW_PER_PAGE as u64)
                 .all(&pg_con)
                 .await
-            {
-                Ok(burrows) => {
-                    if burrows.len() == results.len() {
-                        (
-                            Status::Ok,
-                            Ok(Json(
-                                burrows
-                                    .iter()
-                                    .map(|burrow| {
-                                        let meta: BurrowMetadata = burrow.into();
-                                        meta
-                                    })
-                                    .zip(results.iter().map(|r| r.is_update))
-                                    .map(|(burrow, is_update)| UserGetFollowResponse {
-                                        burrow,
-                                        is_update,
-                                    })
-                                    .collect(),
-                            )),
-                        )
-                    } else {
-                        let hm: HashMap<i64, bool> =
-                            results.iter().map(|r| (r.burrow_id, r.is_update)).collect();
-                        (
-                            Status::Ok,
-                            Ok(Json(
-                                burrows
-                                    .iter()
-                                    .map(|meta| {
-                                        let burrow: BurrowMetadata = meta.into();
-                                        let is_update =
-                                            *hm.get(&burrow.burrow_id).unwrap_or(&false);
-                                        UserGetFollowResponse { burrow, is_update }
-                                    })
-                                    .collect(),
-                            )),
-                        )
-                    }
-                }
-                Err(e) => {
-                    error!("[GET-FOLLOW] Database Error: {:?}", e);
-                    (
-                        Status::InternalServerError,
-                        Err(Json(ErrorResponse::default())),
-                    )
-                }
-            }
         }
+        None => {
+            select
+                .paginate(&pg_con, BURROW_PER_PAGE)
+                .fetch_page(page)
+                .await
+        }
+    };
+    match results {
+        Ok(results) => (
+            Status::Ok,
+            Ok(Json(
+                results
+                    .into_iter()
+                    .filter_map(|(follow, burrow)| {
+                        let burrow: BurrowMetadata = burrow?.into();
+                        Some(UserGetFollowResponse {
+                            cursor: Cursor::Burrow {
+                                burrow_id: burrow.burrow_id,
+                            }
+                            .encode(),
+                            burrow,
+                            is_update: follow.is_update,
+                        })
+                    })
+                    .collect(),
+            )),
+        ),
         Err(e) => {
             error!("[GET-FOLLOW] Database Error: {:?}", e);
             (
@@ -1335,7 +1994,16 @@ pub async fn get_user_valid_burrow(
     let pg_con = db.into_inner();
     match UserStatus::find_by_id(auth.id).one(&pg_con).await {
         Ok(opt_state) => match opt_state {
-            Some(state) => (Status::Ok, Ok(Json(get_burrow_list(&state.valid_burrow)))),
+            Some(_) => match get_valid_burrows(&pg_con, auth.id).await {
+                Ok(burrows) => (Status::Ok, Ok(Json(burrows))),
+                Err(e) => {
+                    error!("[GET-VALID-BURROW] Database Error: {:?}", e);
+                    (
+                        Status::InternalServerError,
+                        Err(Json(ErrorResponse::default())),
+                    )
+                }
+            },
             None => {
                 info!("[GET-VALID-BURROW] Cannot find user_status by uid.");
                 (
@@ -42,53 +42,520 @@ pub mod cors {
 pub mod postgres {
     use rocket::{fairing, Build, Rocket};
     use rocket_db_pools::Database;
-    use sea_orm::query::{ConnectionTrait, Statement};
     use sea_orm::sea_query::{
-        self, ColumnDef, Index, PostgresQueryBuilder, SchemaStatementBuilder,
+        self, Alias, ColumnDef, ForeignKey, ForeignKeyAction, ForeignKeyCreateStatement, Iden,
+        Index, PostgresQueryBuilder, SchemaStatementBuilder,
     };
-    use sea_orm::{error::*, DbConn, ExecResult};
 
     use crate::db;
+    use crate::migration;
     use crate::pool::PgDb;
 
+    /// Migrate the schema to the latest version before launching, refuse to launch
+    /// if the database has been migrated by a newer version of backend
     pub async fn postgres_table_setup(rocket: Rocket<Build>) -> fairing::Result {
         let conn = &PgDb::fetch(&rocket).unwrap().connection;
-        let _ = create_user_table(conn).await;
-        let _ = create_image_table(conn).await;
-        let _ = create_user_index_username(conn).await;
-        let _ = create_user_index_email(conn).await;
-        let _ = create_content_post_table(conn).await;
-        let _ = create_content_reply_table(conn).await;
-        let _ = create_user_like_table(conn).await;
-        let _ = create_user_collection_table(conn).await;
-        let _ = create_user_status_table(conn).await;
-        let _ = create_burrow_table(conn).await;
-        let _ = create_user_follow_table(conn).await;
-        let _ = create_admin_table(conn).await;
-        let _ = create_user_storage_table(conn).await;
-        // match t {
-        //     Ok(_) => {}
-        //     Err(e) => {
-        //         println!("{}", e);
-        //     }
-        // }
-        // let _ = alter_image_table(conn).await;
-        Ok(rocket)
-    }
-
-    async fn build_statement<T>(db: &DbConn, stmt: &T) -> Result<ExecResult, DbErr>
-    where
-        T: SchemaStatementBuilder,
-    {
-        let builder = db.get_database_backend();
-        db.execute(Statement::from_string(
-            builder,
-            stmt.build(PostgresQueryBuilder),
-        ))
-        .await
-    }
-
-    async fn create_user_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+        match migration::migrate_up(conn, None).await {
+            Ok(versions) => {
+                for version in versions {
+                    info!("[MIGRATION] migrated to version {}", version);
+                }
+                Ok(rocket)
+            }
+            Err(e) => {
+                error!("[MIGRATION] failed to migrate: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+
+    /// Statements of the schema before versioned migrations, in the order they were
+    /// run at startup. They may fail on databases set up back then.
+    pub fn baseline_up() -> Vec<String> {
+        vec![
+            create_user_table(),
+            create_image_table(),
+            create_user_index_username(),
+            create_user_index_email(),
+            create_content_post_table(),
+            create_content_post_index_burrow(),
+            create_content_reply_table(),
+            alter_content_reply_table(),
+            alter_content_reply_table_html(),
+            create_user_like_table(),
+            create_user_collection_table(),
+            create_user_status_table(),
+            create_burrow_table(),
+            create_user_follow_table(),
+            create_admin_table(),
+            create_user_storage_table(),
+            create_notification_table(),
+            create_notification_index_uid(),
+            create_message_table(),
+            create_message_index_burrow(),
+            create_message_index_receiver(),
+            create_burrow_block_table(),
+            create_report_table(),
+            create_report_index_reporter(),
+            create_report_index_state(),
+            create_admin_audit_table(),
+            create_admin_audit_index_uid(),
+            create_admin_audit_index_target(),
+            create_ban_record_table(),
+            create_ban_record_index_expire(),
+            create_ban_record_index_target(),
+            create_sensitive_word_table(),
+            create_poll_table(),
+            create_poll_vote_table(),
+            create_user_totp_table(),
+        ]
+    }
+
+    /// Drop all the tables of the baseline schema
+    pub fn baseline_down() -> Vec<String> {
+        vec![
+            drop_table(db::user_totp::Entity),
+            drop_table(db::poll_vote::Entity),
+            drop_table(db::poll::Entity),
+            drop_table(db::sensitive_word::Entity),
+            drop_table(db::ban_record::Entity),
+            drop_table(db::admin_audit::Entity),
+            drop_table(db::report::Entity),
+            drop_table(db::burrow_block::Entity),
+            drop_table(db::message::Entity),
+            drop_table(db::notification::Entity),
+            drop_table(db::user_storage::Entity),
+            drop_table(db::admin::Entity),
+            drop_table(db::user_follow::Entity),
+            drop_table(db::burrow::Entity),
+            drop_table(db::user_status::Entity),
+            drop_table(db::user_collection::Entity),
+            drop_table(db::user_like::Entity),
+            drop_table(db::content_reply::Entity),
+            drop_table(db::content_post::Entity),
+            drop_table(db::image::Entity),
+            drop_table(db::user::Entity),
+        ]
+    }
+
+    /// Derive burrows of users from table `burrow` instead of the comma-separated
+    /// `valid_burrow` and `banned_burrow` of table `user_status`
+    pub fn burrow_owner_up() -> Vec<String> {
+        let index = Index::create()
+            .name("idx-burrow-uid")
+            .table(db::burrow::Entity)
+            .col(db::burrow::Column::Uid)
+            .col(db::burrow::Column::BurrowState)
+            .to_owned();
+        let alter = sea_query::Table::alter()
+            .table(db::user_status::Entity)
+            .drop_column(Alias::new("valid_burrow"))
+            .drop_column(Alias::new("banned_burrow"))
+            .to_owned();
+        vec![
+            index.build(PostgresQueryBuilder),
+            alter.build(PostgresQueryBuilder),
+        ]
+    }
+
+    /// Restore `valid_burrow` and `banned_burrow` of table `user_status` from table
+    /// `burrow`
+    pub fn burrow_owner_down() -> Vec<String> {
+        let alter = sea_query::Table::alter()
+            .table(db::user_status::Entity)
+            .add_column(
+                ColumnDef::new(Alias::new("valid_burrow"))
+                    .text()
+                    .not_null()
+                    .default("".to_string()),
+            )
+            .add_column(
+                ColumnDef::new(Alias::new("banned_burrow"))
+                    .text()
+                    .not_null()
+                    .default("".to_string()),
+            )
+            .to_owned();
+        let fill = r#"UPDATE "user_status" SET
+            "valid_burrow" = COALESCE((SELECT string_agg("burrow_id"::text, ',' ORDER BY "burrow_id") FROM "burrow" WHERE "burrow"."uid" = "user_status"."uid" AND "burrow_state" = 0), ''),
+            "banned_burrow" = COALESCE((SELECT string_agg("burrow_id"::text, ',' ORDER BY "burrow_id") FROM "burrow" WHERE "burrow"."uid" = "user_status"."uid" AND "burrow_state" = 1), '')"#;
+        let index = Index::drop()
+            .name("idx-burrow-uid")
+            .table(db::burrow::Entity)
+            .to_owned();
+        vec![
+            alter.build(PostgresQueryBuilder),
+            fill.to_string(),
+            index.build(PostgresQueryBuilder),
+        ]
+    }
+
+    /// Foreign keys of the relations between entities
+    fn foreign_keys() -> Vec<ForeignKeyCreateStatement> {
+        vec![
+            ForeignKey::create()
+                .name("fk-content-post-burrow")
+                .from(db::content_post::Entity, db::content_post::Column::BurrowId)
+                .to(db::burrow::Entity, db::burrow::Column::BurrowId)
+                .on_delete(ForeignKeyAction::Restrict)
+                .to_owned(),
+            ForeignKey::create()
+                .name("fk-content-reply-post")
+                .from(db::content_reply::Entity, db::content_reply::Column::PostId)
+                .to(db::content_post::Entity, db::content_post::Column::PostId)
+                .on_delete(ForeignKeyAction::Cascade)
+                .to_owned(),
+            ForeignKey::create()
+                .name("fk-content-reply-burrow")
+                .from(
+                    db::content_reply::Entity,
+                    db::content_reply::Column::BurrowId,
+                )
+                .to(db::burrow::Entity, db::burrow::Column::BurrowId)
+                .on_delete(ForeignKeyAction::Restrict)
+                .to_owned(),
+            ForeignKey::create()
+                .name("fk-user-like-post")
+                .from(db::user_like::Entity, db::user_like::Column::PostId)
+                .to(db::content_post::Entity, db::content_post::Column::PostId)
+                .on_delete(ForeignKeyAction::Cascade)
+                .to_owned(),
+            ForeignKey::create()
+                .name("fk-user-collection-post")
+                .from(
+                    db::user_collection::Entity,
+                    db::user_collection::Column::PostId,
+                )
+                .to(db::content_post::Entity, db::content_post::Column::PostId)
+                .on_delete(ForeignKeyAction::Cascade)
+                .to_owned(),
+            ForeignKey::create()
+                .name("fk-user-follow-burrow")
+                .from(db::user_follow::Entity, db::user_follow::Column::BurrowId)
+                .to(db::burrow::Entity, db::burrow::Column::BurrowId)
+                .on_delete(ForeignKeyAction::Cascade)
+                .to_owned(),
+            ForeignKey::create()
+                .name("fk-image-user")
+                .from(db::image::Entity, db::image::Column::Uid)
+                .to(db::user::Entity, db::user::Column::Uid)
+                .on_delete(ForeignKeyAction::Restrict)
+                .to_owned(),
+        ]
+    }
+
+    /// Columns of the foreign keys in `foreign_keys`, as (name, table, column,
+    /// referenced table, referenced column)
+    const FOREIGN_KEY_COLUMNS: &[(&str, &str, &str, &str, &str)] = &[
+        (
+            "fk-content-post-burrow",
+            "content_post",
+            "burrow_id",
+            "burrow",
+            "burrow_id",
+        ),
+        (
+            "fk-content-reply-post",
+            "content_reply",
+            "post_id",
+            "content_post",
+            "post_id",
+        ),
+        (
+            "fk-content-reply-burrow",
+            "content_reply",
+            "burrow_id",
+            "burrow",
+            "burrow_id",
+        ),
+        (
+            "fk-user-like-post",
+            "user_like",
+            "post_id",
+            "content_post",
+            "post_id",
+        ),
+        (
+            "fk-user-collection-post",
+            "user_collection",
+            "post_id",
+            "content_post",
+            "post_id",
+        ),
+        (
+            "fk-user-follow-burrow",
+            "user_follow",
+            "burrow_id",
+            "burrow",
+            "burrow_id",
+        ),
+        ("fk-image-user", "image", "uid", "user", "uid"),
+    ];
+
+    /// Statement failing with the number of rows of `table` whose `column` refers
+    /// to no row of `ref_table`, the rows are left for the operator to inspect
+    fn check_orphans(
+        name: &str,
+        table: &str,
+        column: &str,
+        ref_table: &str,
+        ref_column: &str,
+    ) -> String {
+        format!(
+            r#"DO $$
+DECLARE orphans bigint;
+BEGIN
+    SELECT count(*) INTO orphans FROM "{table}" AS t
+        WHERE t."{column}" IS NOT NULL
+        AND NOT EXISTS (SELECT 1 FROM "{ref_table}" AS r WHERE r."{ref_column}" = t."{column}");
+    IF orphans > 0 THEN
+        RAISE EXCEPTION 'cannot add {name}: % rows of {table}.{column} refer to missing {ref_table}.{ref_column}, fix or remove them and migrate again', orphans;
+    END IF;
+END
+$$"#,
+            name = name,
+            table = table,
+            column = column,
+            ref_table = ref_table,
+            ref_column = ref_column,
+        )
+    }
+
+    /// Add foreign keys between entities, failing without changing anything if
+    /// any existing row would violate one of them
+    pub fn foreign_keys_up() -> Vec<String> {
+        let mut stmts: Vec<String> = FOREIGN_KEY_COLUMNS
+            .iter()
+            .map(|(name, table, column, ref_table, ref_column)| {
+                check_orphans(name, table, column, ref_table, ref_column)
+            })
+            .collect();
+        for fk in foreign_keys() {
+            stmts.push(fk.build(PostgresQueryBuilder));
+        }
+        stmts
+    }
+
+    /// Drop the foreign keys between entities
+    pub fn foreign_keys_down() -> Vec<String> {
+        vec![
+            drop_foreign_key("fk-image-user", db::image::Entity),
+            drop_foreign_key("fk-user-follow-burrow", db::user_follow::Entity),
+            drop_foreign_key("fk-user-collection-post", db::user_collection::Entity),
+            drop_foreign_key("fk-user-like-post", db::user_like::Entity),
+            drop_foreign_key("fk-content-reply-burrow", db::content_reply::Entity),
+            drop_foreign_key("fk-content-reply-post", db::content_reply::Entity),
+            drop_foreign_key("fk-content-post-burrow", db::content_post::Entity),
+        ]
+    }
+
+    fn drop_foreign_key<T: Iden + 'static>(name: &str, table: T) -> String {
+        ForeignKey::drop()
+            .name(name)
+            .table(table)
+            .to_owned()
+            .build(PostgresQueryBuilder)
+    }
+
+    /// Table of search and relation events written in the same transaction as the
+    /// content change, published to pulsar by the outbox relay of task-executor
+    pub fn outbox_up() -> Vec<String> {
+        let stmt = sea_query::Table::create()
+            .table(db::outbox::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::outbox::Column::Id)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(ColumnDef::new(db::outbox::Column::Topic).text().not_null())
+            .col(
+                ColumnDef::new(db::outbox::Column::Payload)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::outbox::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .col(ColumnDef::new(db::outbox::Column::SentTime).timestamp_with_time_zone())
+            .to_owned();
+        // the relay only scans the pending rows
+        let index = r#"CREATE INDEX IF NOT EXISTS "idx-outbox-pending" ON "outbox" ("id") WHERE "sent_time" IS NULL"#;
+        vec![stmt.build(PostgresQueryBuilder), index.to_string()]
+    }
+
+    /// Drop the outbox table, pending events in it are lost
+    pub fn outbox_down() -> Vec<String> {
+        vec![drop_table(db::outbox::Entity)]
+    }
+
+    /// Dead letters of the consumers of task-executor, archived for admins to
+    /// inspect and replay
+    pub fn dead_letter_up() -> Vec<String> {
+        let stmt = sea_query::Table::create()
+            .table(db::dead_letter::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::dead_letter::Column::Id)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::dead_letter::Column::Topic)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::dead_letter::Column::Payload)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::dead_letter::Column::Error)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::dead_letter::Column::Attempts)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::dead_letter::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .col(ColumnDef::new(db::dead_letter::Column::ReplayTime).timestamp_with_time_zone())
+            .to_owned();
+        vec![stmt.build(PostgresQueryBuilder)]
+    }
+
+    /// Drop the dead letter table, archived dead letters in it are lost
+    pub fn dead_letter_down() -> Vec<String> {
+        vec![drop_table(db::dead_letter::Entity)]
+    }
+
+    /// Record the type of the target of admin audit entries, as `target_id` may be
+    /// a uid, a burrow id, a post id or another id. Existing entries get the type
+    /// from their operation kind.
+    pub fn admin_audit_target_up() -> Vec<String> {
+        let alter = sea_query::Table::alter()
+            .table(db::admin_audit::Entity)
+            .add_column(
+                ColumnDef::new(db::admin_audit::Column::TargetType)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .to_owned();
+        let backfill = r#"UPDATE "admin_audit" SET "target_type" = CASE
+    WHEN "operation_kind" IN ('BanBurrow', 'ReopenBurrow', 'BanBurrowFor', 'GetUserId') THEN 1
+    WHEN "operation_kind" IN ('BanPost', 'ReopenPost') THEN 2
+    WHEN "operation_kind" IN ('BanReply', 'ReopenReply') THEN 3
+    WHEN "operation_kind" IN ('AddFilterRule', 'DeleteFilterRule') THEN 4
+    WHEN "operation_kind" = 'ClearLockout' THEN 5
+    WHEN "operation_kind" = 'ReplayDeadLetter' THEN 6
+    ELSE 0
+END"#;
+        let index = Index::create()
+            .name("idx-admin-audit-target-type")
+            .table(db::admin_audit::Entity)
+            .col(db::admin_audit::Column::TargetType)
+            .col(db::admin_audit::Column::TargetId)
+            .to_owned();
+        vec![
+            alter.build(PostgresQueryBuilder),
+            backfill.to_string(),
+            index.build(PostgresQueryBuilder),
+        ]
+    }
+
+    /// Drop the type of the target of admin audit entries
+    pub fn admin_audit_target_down() -> Vec<String> {
+        let index = Index::drop()
+            .name("idx-admin-audit-target-type")
+            .table(db::admin_audit::Entity)
+            .to_owned();
+        let alter = sea_query::Table::alter()
+            .table(db::admin_audit::Entity)
+            .drop_column(db::admin_audit::Column::TargetType)
+            .to_owned();
+        vec![
+            index.build(PostgresQueryBuilder),
+            alter.build(PostgresQueryBuilder),
+        ]
+    }
+
+    /// Table with a row per user who voted in a poll, whose primary key makes sure
+    /// that a user only votes once even if two votes are written at the same time.
+    /// The voters of existing votes are copied from `poll_vote`.
+    pub fn poll_voter_up() -> Vec<String> {
+        let stmt = sea_query::Table::create()
+            .table(db::poll_voter::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::poll_voter::Column::PostId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::poll_voter::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .primary_key(
+                Index::create()
+                    .col(db::poll_voter::Column::PostId)
+                    .col(db::poll_voter::Column::Uid),
+            )
+            .to_owned();
+        let backfill = r#"INSERT INTO "poll_voter" ("post_id", "uid") SELECT DISTINCT "post_id", "uid" FROM "poll_vote""#;
+        vec![stmt.build(PostgresQueryBuilder), backfill.to_string()]
+    }
+
+    /// Drop the voters of polls, the votes in `poll_vote` are kept
+    pub fn poll_voter_down() -> Vec<String> {
+        vec![drop_table(db::poll_voter::Entity)]
+    }
+
+    /// Table recording the applied migrations
+    pub fn create_schema_version_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::schema_version::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::schema_version::Column::Version)
+                    .big_integer()
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::schema_version::Column::Name)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::schema_version::Column::AppliedTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn drop_table<T: Iden + 'static>(table: T) -> String {
+        sea_query::Table::drop()
+            .table(table)
+            .if_exists()
+            .to_owned()
+            .build(PostgresQueryBuilder)
+    }
+
+    fn create_user_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::user::Entity)
             .if_not_exists()
@@ -108,28 +575,28 @@ pub mod postgres {
             )
             .col(ColumnDef::new(db::user::Column::Salt).text().not_null())
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_index_username(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_index_username() -> String {
         let stmt = Index::create()
             .name("idx-username")
             .table(db::user::Entity)
             .col(db::user::Column::Username)
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_index_email(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_index_email() -> String {
         let stmt = Index::create()
             .name("idx-email")
             .table(db::user::Entity)
             .col(db::user::Column::Email)
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_image_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_image_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::image::Entity)
             .if_not_exists()
@@ -169,10 +636,10 @@ pub mod postgres {
             )
             .to_owned();
         // println!("image table: {}", stmt.to_string(PostgresQueryBuilder));
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_status_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_status_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::user_status::Entity)
             .if_not_exists()
@@ -194,13 +661,13 @@ pub mod postgres {
                     .default(0),
             )
             .col(
-                ColumnDef::new(db::user_status::Column::ValidBurrow)
+                ColumnDef::new(Alias::new("valid_burrow"))
                     .text()
                     .not_null()
                     .default("".to_string()),
             )
             .col(
-                ColumnDef::new(db::user_status::Column::BannedBurrow)
+                ColumnDef::new(Alias::new("banned_burrow"))
                     .text()
                     .not_null()
                     .default("".to_string()),
@@ -224,10 +691,10 @@ pub mod postgres {
                     .default(0),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_content_post_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_content_post_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::content_post::Entity)
             .if_not_exists()
@@ -305,10 +772,20 @@ pub mod postgres {
             )
             .to_owned();
         // println!("user table: {}", stmt.to_string(PostgresQueryBuilder));
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_content_post_index_burrow() -> String {
+        let stmt = Index::create()
+            .name("idx-content-post-burrow")
+            .table(db::content_post::Entity)
+            .col(db::content_post::Column::BurrowId)
+            .col(db::content_post::Column::PostId)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_content_reply_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_content_reply_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::content_reply::Entity)
             .if_not_exists()
@@ -342,6 +819,12 @@ pub mod postgres {
                     .text()
                     .not_null(),
             )
+            .col(
+                ColumnDef::new(db::content_reply::Column::ContentHtml)
+                    .text()
+                    .not_null()
+                    .default(""),
+            )
             .col(
                 ColumnDef::new(db::content_reply::Column::ReplyState)
                     .integer()
@@ -354,6 +837,7 @@ pub mod postgres {
                     .not_null()
                     .default(0),
             )
+            .col(ColumnDef::new(db::content_reply::Column::ParentReplyId).integer())
             .primary_key(
                 Index::create()
                     .col(db::content_reply::Column::PostId)
@@ -361,10 +845,33 @@ pub mod postgres {
             )
             .to_owned();
         // println!("user table: {}", stmt.to_string(PostgresQueryBuilder));
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    // add the column to tables created before threaded replies were introduced
+    fn alter_content_reply_table() -> String {
+        let stmt = sea_query::Table::alter()
+            .table(db::content_reply::Entity)
+            .add_column(ColumnDef::new(db::content_reply::Column::ParentReplyId).integer())
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    // add the column to tables created before markdown bodies were introduced
+    fn alter_content_reply_table_html() -> String {
+        let stmt = sea_query::Table::alter()
+            .table(db::content_reply::Entity)
+            .add_column(
+                ColumnDef::new(db::content_reply::Column::ContentHtml)
+                    .text()
+                    .not_null()
+                    .default(""),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_like_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_like_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::user_like::Entity)
             .if_not_exists()
@@ -384,10 +891,10 @@ pub mod postgres {
                     .col(db::user_like::Column::PostId),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_collection_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_collection_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::user_collection::Entity)
             .if_not_exists()
@@ -413,10 +920,10 @@ pub mod postgres {
                     .col(db::user_collection::Column::PostId),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_burrow_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_burrow_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::burrow::Entity)
             .if_not_exists()
@@ -484,10 +991,10 @@ pub mod postgres {
                     .default(0),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_follow_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_follow_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::user_follow::Entity)
             .if_not_exists()
@@ -513,10 +1020,10 @@ pub mod postgres {
                     .col(db::user_follow::Column::BurrowId),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_admin_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_admin_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::admin::Entity)
             .if_not_exists()
@@ -538,10 +1045,10 @@ pub mod postgres {
                     .not_null(),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
     }
 
-    async fn create_user_storage_table(db: &DbConn) -> Result<ExecResult, DbErr> {
+    fn create_user_storage_table() -> String {
         let stmt = sea_query::Table::create()
             .table(db::user_storage::Entity)
             .if_not_exists()
@@ -561,19 +1068,533 @@ pub mod postgres {
                     .col(db::user_storage::Column::Filename),
             )
             .to_owned();
-        build_statement(db, &stmt).await
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_notification_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::notification::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::notification::Column::NotificationId)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::NotificationType)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::BurrowId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::PostId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::ReplyId)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::IsRead)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            )
+            .col(
+                ColumnDef::new(db::notification::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_notification_index_uid() -> String {
+        let stmt = Index::create()
+            .name("idx-notification-uid")
+            .table(db::notification::Entity)
+            .col(db::notification::Column::Uid)
+            .col(db::notification::Column::IsRead)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_message_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::message::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::message::Column::MessageId)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::message::Column::SenderBurrow)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::message::Column::ReceiverBurrow)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::message::Column::Content)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::message::Column::IsRead)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            )
+            .col(
+                ColumnDef::new(db::message::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_message_index_burrow() -> String {
+        let stmt = Index::create()
+            .name("idx-message-burrow")
+            .table(db::message::Entity)
+            .col(db::message::Column::SenderBurrow)
+            .col(db::message::Column::ReceiverBurrow)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
     }
 
-    // async fn alter_image_table(db: &DbConn) -> Result<ExecResult, DbErr> {
-    //     let stmt = sea_query::Table::alter()
-    //         .table(pgdb::image::Entity)
-    //         .add_column(
-    //             ColumnDef::new(pgdb::image::Column::Size)
-    //                 .integer()
-    //                 .not_null(),
-    //         )
-    //         .to_owned();
-    //     println!("image table: {}", stmt.to_string(PostgresQueryBuilder));
-    //     build_statement(db, &stmt).await
-    // }
+    fn create_message_index_receiver() -> String {
+        let stmt = Index::create()
+            .name("idx-message-receiver")
+            .table(db::message::Entity)
+            .col(db::message::Column::ReceiverBurrow)
+            .col(db::message::Column::IsRead)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_burrow_block_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::burrow_block::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::burrow_block::Column::BurrowId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::burrow_block::Column::BlockedBurrowId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::burrow_block::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .primary_key(
+                Index::create()
+                    .col(db::burrow_block::Column::BurrowId)
+                    .col(db::burrow_block::Column::BlockedBurrowId),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_report_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::report::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::report::Column::ReportId)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::TargetType)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::PostId)
+                    .big_integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::ReplyId)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::BurrowId)
+                    .big_integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::Reason)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::Description)
+                    .text()
+                    .not_null()
+                    .default("".to_string()),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::ReportState)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::Operator)
+                    .big_integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::report::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .col(ColumnDef::new(db::report::Column::ResolveTime).timestamp_with_time_zone())
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_report_index_reporter() -> String {
+        let stmt = Index::create()
+            .name("idx-report-reporter")
+            .table(db::report::Entity)
+            .col(db::report::Column::Uid)
+            .col(db::report::Column::TargetType)
+            .col(db::report::Column::PostId)
+            .col(db::report::Column::ReplyId)
+            .col(db::report::Column::BurrowId)
+            .unique()
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_report_index_state() -> String {
+        let stmt = Index::create()
+            .name("idx-report-state")
+            .table(db::report::Entity)
+            .col(db::report::Column::ReportState)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_admin_audit_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::admin_audit::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::admin_audit::Column::AuditId)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::Role)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::Operation)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::OperationKind)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::TargetId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::TargetSubId)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::Status)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::Result)
+                    .text()
+                    .not_null()
+                    .default("".to_string()),
+            )
+            .col(
+                ColumnDef::new(db::admin_audit::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_admin_audit_index_uid() -> String {
+        let stmt = Index::create()
+            .name("idx-admin-audit-uid")
+            .table(db::admin_audit::Entity)
+            .col(db::admin_audit::Column::Uid)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_admin_audit_index_target() -> String {
+        let stmt = Index::create()
+            .name("idx-admin-audit-target")
+            .table(db::admin_audit::Entity)
+            .col(db::admin_audit::Column::OperationKind)
+            .col(db::admin_audit::Column::TargetId)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_ban_record_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::ban_record::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::ban_record::Column::BanId)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::TargetType)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::TargetId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::Reason)
+                    .text()
+                    .not_null()
+                    .default("".to_string()),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::BanState)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::ban_record::Column::ExpireTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_ban_record_index_expire() -> String {
+        let stmt = Index::create()
+            .name("idx-ban-record-expire")
+            .table(db::ban_record::Entity)
+            .col(db::ban_record::Column::BanState)
+            .col(db::ban_record::Column::ExpireTime)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_ban_record_index_target() -> String {
+        let stmt = Index::create()
+            .name("idx-ban-record-target")
+            .table(db::ban_record::Entity)
+            .col(db::ban_record::Column::TargetType)
+            .col(db::ban_record::Column::TargetId)
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_sensitive_word_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::sensitive_word::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::sensitive_word::Column::WordId)
+                    .extra("bigserial".to_string())
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::sensitive_word::Column::Pattern)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::sensitive_word::Column::IsRegex)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            )
+            .col(
+                ColumnDef::new(db::sensitive_word::Column::Action)
+                    .integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::sensitive_word::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::sensitive_word::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_poll_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::poll::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::poll::Column::PostId)
+                    .big_integer()
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(ColumnDef::new(db::poll::Column::Options).text().not_null())
+            .col(
+                ColumnDef::new(db::poll::Column::Multiple)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            )
+            .col(ColumnDef::new(db::poll::Column::CloseTime).timestamp_with_time_zone())
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_poll_vote_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::poll_vote::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::poll_vote::Column::PostId)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::poll_vote::Column::Uid)
+                    .big_integer()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::poll_vote::Column::OptionId)
+                    .integer()
+                    .not_null(),
+            )
+            .primary_key(
+                Index::create()
+                    .col(db::poll_vote::Column::PostId)
+                    .col(db::poll_vote::Column::Uid)
+                    .col(db::poll_vote::Column::OptionId),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
+
+    fn create_user_totp_table() -> String {
+        let stmt = sea_query::Table::create()
+            .table(db::user_totp::Entity)
+            .if_not_exists()
+            .col(
+                ColumnDef::new(db::user_totp::Column::Uid)
+                    .big_integer()
+                    .not_null()
+                    .primary_key(),
+            )
+            .col(
+                ColumnDef::new(db::user_totp::Column::Secret)
+                    .text()
+                    .not_null(),
+            )
+            .col(
+                ColumnDef::new(db::user_totp::Column::Enabled)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            )
+            .col(
+                ColumnDef::new(db::user_totp::Column::RecoveryCodes)
+                    .text()
+                    .not_null()
+                    .default("[]"),
+            )
+            .col(
+                ColumnDef::new(db::user_totp::Column::LastStep)
+                    .big_integer()
+                    .not_null()
+                    .default(0),
+            )
+            .col(
+                ColumnDef::new(db::user_totp::Column::CreateTime)
+                    .timestamp_with_time_zone()
+                    .not_null(),
+            )
+            .to_owned();
+        stmt.build(PostgresQueryBuilder)
+    }
 }
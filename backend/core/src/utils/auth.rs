@@ -1,15 +1,19 @@
 //! Module for authentication
 
+use chrono::{FixedOffset, Utc};
 use crypto::digest::Digest;
 use crypto::sha3::Sha3;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use rocket::http::{private::cookie::CookieBuilder, Cookie, SameSite, Status};
+use rocket::http::{private::cookie::CookieBuilder, Cookie, CookieJar, SameSite, Status};
 use rocket::request::{self, FromRequest, Outcome, Request};
 use rocket::State;
+use std::collections::HashMap;
+use uuid::Uuid;
 
 use crate::config::user::*;
 use crate::models::error::*;
+use crate::models::session::{parse_session_value, session_value, SessionInfo};
 use crate::pool::RedisDb;
 
 /// Usage of Auth
@@ -59,8 +63,8 @@ use crate::pool::RedisDb;
 ///             ValidToken::Invalid => "Invalid token".to_string(),
 ///             ValidToken::Missing => "Missing token".to_string(),
 ///             ValidToken::DatabaseErr => "DatabaseErr token".to_string(),
-///             ValidToken::Valid(id) => format!("User Id found: {}", id),
-///             ValidToken::Refresh(id) => format!("User Id found: {}", id),
+///             ValidToken::Valid(id, _) => format!("User Id found: {}", id),
+///             ValidToken::Refresh(id, _) => format!("User Id found: {}", id),
 ///         },
 ///         None => "Valid token".to_string(),
 ///     }
@@ -76,8 +80,8 @@ use crate::pool::RedisDb;
 ///             ValidToken::Invalid => "Invalid token".to_string(),
 ///             ValidToken::Missing => "Missing token".to_string(),
 ///             ValidToken::DatabaseErr => "DatabaseErr token".to_string(),
-///             ValidToken::Valid(id) => format!("User Id found: {}", id),
-///             ValidToken::Refresh(id) => format!("User Id found: {}", id),
+///             ValidToken::Valid(id, _) => format!("User Id found: {}", id),
+///             ValidToken::Refresh(id, _) => format!("User Id found: {}", id),
 ///         },
 ///         None => "Valid token".to_string(),
 ///     }
@@ -85,6 +89,7 @@ use crate::pool::RedisDb;
 /// ```
 pub struct Auth {
     pub id: i64,
+    pub session_id: String,
 }
 
 /// Message of token check
@@ -94,156 +99,510 @@ pub struct Auth {
 /// - `ValidToken::Invalid`: Invalid token
 /// - `ValidToken::Missing`: Missing token
 /// - `ValidToken::DatabaseErr`: Database error
-/// - `ValidToken::Valid(id)`: Valid token, will provide uid
-/// - `ValidToken::Refresh(id)`: Refresh token, will provide uid
+/// - `ValidToken::Valid(id, session_id)`: Valid token, will provide uid and session id
+/// - `ValidToken::Refresh(id, session_id)`: Refresh token, will provide uid and session id
 pub enum ValidToken {
-    Valid(i64),
-    Refresh(i64),
+    Valid(i64, String),
+    Refresh(i64, String),
     Invalid,
     DatabaseErr,
     Missing,
 }
 
-pub async fn set_token(
-    uid: i64,
-    kv_conn: &mut redis::aio::Connection,
-) -> Result<String, ErrorResponse> {
-    // generate token and refresh token
-    let token: String = std::iter::repeat(())
-        .map(|()| thread_rng().sample(Alphanumeric))
+/// Client of a login session, taken from the `User-Agent` header and the remote address
+///
+/// ## Fields
+///
+/// - `device`: String, user agent of the client
+/// - `ip`: String, ip address of the client, empty if unknown
+pub struct ClientInfo {
+    pub device: String,
+    pub ip: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let device = request
+            .headers()
+            .get_one("User-Agent")
+            .unwrap_or("")
+            .chars()
+            .take(MAX_DEVICE_LEN)
+            .collect();
+        let ip = request
+            .client_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_default();
+        Outcome::Success(ClientInfo { device, ip })
+    }
+}
+
+/// Tokens of a session
+///
+/// ## Fields
+///
+/// - `uid`: i64, uid of the user
+/// - `session_id`: String, id of the session
+/// - `token`: String, access token, set as cookie `token`
+/// - `refresh_token`: String, refresh token, set as cookie `refresh_token`
+pub struct SessionToken {
+    pub uid: i64,
+    pub session_id: String,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+fn session_key(uid: i64) -> String {
+    format!("session:{}", uid)
+}
+
+/// Key of a refresh token, only the hash of it is kept in redis
+fn refresh_key(refresh_hash: &str) -> String {
+    format!("refresh:{}", refresh_hash)
+}
+
+/// Key of a refresh token which has been rotated, kept to detect reuse
+fn used_refresh_key(refresh_hash: &str) -> String {
+    format!("used-refresh:{}", refresh_hash)
+}
+
+fn gen_token(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
         .map(char::from)
-        .take(32)
-        .collect();
+        .collect()
+}
+
+fn hash_token(token: &str) -> String {
     let mut hash_sha3 = Sha3::sha3_384();
-    hash_sha3.input_str(&token);
-    let refresh_token = hash_sha3.result_str();
+    hash_sha3.input_str(token);
+    hash_sha3.result_str()
+}
+
+/// Set token -> id and refresh_token -> id of a session
+async fn set_token_pair(
+    uid: i64,
+    session_id: &str,
+    token: &str,
+    refresh_hash: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<(), ErrorResponse> {
+    let value = session_value(uid, session_id);
     // set token -> id
     let uid_result: Result<String, redis::RedisError> = redis::cmd("SETEX")
-        .arg(&token)
+        .arg(token)
         .arg(TOKEN_TO_ID_EX)
-        .arg(uid)
+        .arg(&value)
         .query_async(kv_conn)
         .await;
     match uid_result {
-        Ok(s) => info!("[LOGIN] setex token->id: {:?} -> {}", &token, s),
+        Ok(s) => info!("[TOKEN] setex token->id: {:?} -> {}", token, s),
         Err(e) => {
-            error!(
-                "[LOGIN] failed to set token -> id when login. RedisError: {:?}",
-                e
-            );
+            error!("[TOKEN] failed to set token -> id. RedisError: {:?}", e);
             return Err(ErrorResponse::default());
         }
     };
     // set refresh_token -> id
     let uid_result: Result<String, redis::RedisError> = redis::cmd("SETEX")
-        .arg(&refresh_token)
+        .arg(refresh_key(refresh_hash))
         .arg(REF_TOKEN_TO_ID_EX)
-        .arg(uid)
+        .arg(&value)
         .query_async(kv_conn)
         .await;
     match uid_result {
-        Ok(s) => info!(
-            "[LOGIN] setex refresh_token->id: {:?} -> {}",
-            &refresh_token, s
-        ),
+        Ok(s) => {
+            info!(
+                "[TOKEN] setex refresh_token->id: {:?} -> {}",
+                refresh_hash, s
+            );
+            Ok(())
+        }
         Err(e) => {
             error!(
-                "[LOGIN] failed to set refresh_token -> id when login. RedisError: {:?}",
+                "[TOKEN] failed to set refresh_token -> id. RedisError: {:?}",
                 e
             );
-            return Err(ErrorResponse::default());
+            Err(ErrorResponse::default())
         }
+    }
+}
+
+/// Create a new session for a user
+///
+/// Other sessions of the user are kept, except the least recently seen ones
+/// beyond `MAX_SESSION_NUM`.
+///
+/// ## Parameters
+///
+/// - `uid`: uid of the user
+/// - `client`: Client of the new session
+/// - `mfa`: if the second factor has been checked, see `SessionInfo`
+/// - `kv_conn`: Redis connection
+///
+/// ## Returns
+///
+/// The tokens of the new session.
+pub async fn set_token(
+    uid: i64,
+    client: &ClientInfo,
+    mfa: bool,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<SessionToken, ErrorResponse> {
+    // generate token and refresh token, which are independent of each other
+    let token = gen_token(32);
+    let refresh_token = gen_token(48);
+    let refresh_hash = hash_token(&refresh_token);
+    let session_id = Uuid::new_v4().to_string();
+    set_token_pair(uid, &session_id, &token, &refresh_hash, kv_conn).await?;
+    // revoke the least recently seen sessions beyond the limit
+    let sessions = get_sessions(uid, kv_conn).await?;
+    for session in sessions.iter().skip(MAX_SESSION_NUM - 1) {
+        revoke_session(uid, &session.session_id, kv_conn).await?;
+    }
+    // record the session
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let session = SessionInfo {
+        session_id,
+        token: token.clone(),
+        refresh_token: refresh_hash,
+        prev_refresh_token: String::new(),
+        rotate_time: None,
+        device: client.device.to_owned(),
+        ip: client.ip.to_owned(),
+        create_time: now,
+        last_seen: now,
+        mfa,
     };
-    // get old token and set new token by getset id -> token
-    let _ = delete_token(uid, kv_conn).await?;
-    let new_token_set: Result<String, redis::RedisError> = redis::cmd("SETEX")
-        .arg(uid)
-        .arg(ID_TO_TOKEN_EX)
-        .arg(&token)
+    save_session(uid, &session, kv_conn).await?;
+    info!("[LOGIN] set session: {} -> {}", uid, session.session_id);
+    Ok(SessionToken {
+        uid,
+        session_id: session.session_id,
+        token,
+        refresh_token,
+    })
+}
+
+/// Exchange a refresh token for new tokens of the same session
+///
+/// The refresh token can only be used once. If a refresh token which has
+/// already been rotated is presented again, it is regarded as stolen and the
+/// whole session is revoked, unless it was rotated within `REFRESH_REUSE_GRACE`
+/// seconds, which happens when concurrent requests of a client race to refresh.
+///
+/// ## Returns
+///
+/// The new tokens, `None` if the refresh token is invalid, expired or reused.
+pub async fn rotate_token(
+    refresh_token: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Option<SessionToken>, ErrorResponse> {
+    let refresh_hash = hash_token(refresh_token);
+    // claim the refresh token, only one request can succeed
+    let claim_result: Result<u32, redis::RedisError> = redis::cmd("RENAMENX")
+        .arg(refresh_key(&refresh_hash))
+        .arg(used_refresh_key(&refresh_hash))
         .query_async(kv_conn)
         .await;
-    match new_token_set {
-        Ok(_) => {
-            info!("[LOGIN] set id->token: {} -> {:?}", uid, token);
-            Ok(token)
+    let claimed = matches!(claim_result, Ok(1));
+    let get_result: Result<Option<String>, redis::RedisError> = redis::cmd("GET")
+        .arg(used_refresh_key(&refresh_hash))
+        .query_async(kv_conn)
+        .await;
+    let (uid, session_id) = match get_result {
+        Ok(Some(value)) => match parse_session_value(&value) {
+            Some(s) => s,
+            None => return Ok(None),
+        },
+        Ok(None) => {
+            info!("[REFRESH] refresh_token expired, need to re-login.");
+            return Ok(None);
         }
         Err(e) => {
-            error!(
-                "[LOGIN] failed to set id -> token when login. RedisError: {:?}",
-                e
+            error!("[REFRESH] failed to get refresh_token. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+    };
+    let mut session = match get_session(uid, &session_id, kv_conn).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            info!("[REFRESH] session has been revoked, need to re-login.");
+            return Ok(None);
+        }
+        Err(e) => {
+            error!("[REFRESH] failed to get session. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+    };
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    if !claimed || session.refresh_token != refresh_hash {
+        let in_grace = session.prev_refresh_token == refresh_hash
+            && session
+                .rotate_time
+                .map_or(false, |t| (now - t).num_seconds() < REFRESH_REUSE_GRACE);
+        if in_grace {
+            info!("[REFRESH] refresh_token has just been rotated.");
+        } else {
+            log::warn!(
+                "[REFRESH] reuse of refresh_token detected, revoke session {} of {}",
+                session_id,
+                uid
             );
+            revoke_session(uid, &session_id, kv_conn).await?;
+        }
+        return Ok(None);
+    }
+    // issue new tokens and clear old_token -> id
+    let token = gen_token(32);
+    let new_refresh_token = gen_token(48);
+    let new_refresh_hash = hash_token(&new_refresh_token);
+    set_token_pair(uid, &session_id, &token, &new_refresh_hash, kv_conn).await?;
+    let _: Result<i64, redis::RedisError> = redis::cmd("DEL")
+        .arg(&session.token)
+        .query_async(kv_conn)
+        .await;
+    session.token = token.clone();
+    session.prev_refresh_token = refresh_hash;
+    session.refresh_token = new_refresh_hash;
+    session.rotate_time = Some(now);
+    session.last_seen = now;
+    save_session(uid, &session, kv_conn).await?;
+    info!(
+        "[REFRESH] rotate tokens of session {} of {}",
+        session_id, uid
+    );
+    Ok(Some(SessionToken {
+        uid,
+        session_id,
+        token,
+        refresh_token: new_refresh_token,
+    }))
+}
+
+/// Delete token -> id and refresh_token -> id of a session
+async fn delete_token(
+    session: &SessionInfo,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<(), ErrorResponse> {
+    let delete_result: Result<i64, redis::RedisError> = redis::cmd("DEL")
+        .arg(&session.token)
+        .arg(refresh_key(&session.refresh_token))
+        .query_async(kv_conn)
+        .await;
+    match delete_result {
+        Ok(n) => {
+            info!("[TOKEN] delete {} of token->id and ref_token->id", n);
+            Ok(())
+        }
+        Err(e) => {
+            error!("[TOKEN] failed to delete token -> id. RedisError: {:?}", e);
             Err(ErrorResponse::default())
         }
     }
 }
 
-pub async fn delete_token(
+async fn save_session(
     uid: i64,
+    session: &SessionInfo,
     kv_conn: &mut redis::aio::Connection,
-) -> Result<String, ErrorResponse> {
-    let old_token_get: Result<Option<String>, redis::RedisError> =
-        redis::cmd("GET").arg(uid).query_async(kv_conn).await;
-    match old_token_get {
-        Ok(res) => match res {
-            // if old token -> id exists
-            Some(old_token) => {
-                info!("[TOKEN] find old token: {}, continue...", old_token);
-                // clear old token -> id
-                let delete_result: Result<i64, redis::RedisError> =
-                    redis::cmd("DEL").arg(&old_token).query_async(kv_conn).await;
-                match delete_result {
-                    Ok(1) => info!("[TOKEN] delete token->id"),
-                    Ok(0) => info!("[TOKEN] no token->id found"),
-                    Ok(_) => {
-                        error!("[TOKEN] failed to delete refresh_token -> id when login.");
-                        return Err(ErrorResponse::default());
-                    }
-                    Err(e) => {
-                        error!(
-                            "[TOKEN] failed to delete token -> id when login. RedisError: {:?}",
-                            e
-                        );
-                        return Err(ErrorResponse::default());
-                    }
-                };
-                // find old refresh_token by hashing old token
-                let mut hash_sha3 = Sha3::sha3_384();
-                hash_sha3.input_str(&old_token);
-                let old_refresh_token = hash_sha3.result_str();
-                // clear old refresh_token -> id
-                let delete_result: Result<i64, redis::RedisError> = redis::cmd("DEL")
-                    .arg(&old_refresh_token)
-                    .query_async(kv_conn)
-                    .await;
-                match delete_result {
-                    Ok(1) => info!("[TOKEN] delete ref_token->id"),
-                    Ok(0) => info!("[TOKEN] no ref_token->id found"),
-                    Ok(_) => {
-                        error!("[TOKEN] failed to delete refresh_token -> id when login.");
-                        return Err(ErrorResponse::default());
-                    }
-                    Err(e) => {
-                        error!("[TOKEN] failed to delete refresh_token -> id when login. RedisError: {:?}", e);
-                        return Err(ErrorResponse::default());
-                    }
-                };
-                Ok("Success".to_string())
-            }
-            None => {
-                info!("[LOGIN] no id -> token found");
-                Ok("Not-Found".to_string())
-            }
-        },
+) -> Result<(), ErrorResponse> {
+    let key = session_key(uid);
+    let value = match serde_json::to_string(session) {
+        Ok(v) => v,
         Err(e) => {
+            error!("[SESSION] failed to serialize session: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+    };
+    let set_result: Result<i64, redis::RedisError> = redis::cmd("HSET")
+        .arg(&key)
+        .arg(&session.session_id)
+        .arg(value)
+        .query_async(kv_conn)
+        .await;
+    if let Err(e) = set_result {
+        error!("[SESSION] failed to set session. RedisError: {:?}", e);
+        return Err(ErrorResponse::default());
+    }
+    let expire_result: Result<i64, redis::RedisError> = redis::cmd("EXPIRE")
+        .arg(&key)
+        .arg(SESSION_EX)
+        .query_async(kv_conn)
+        .await;
+    match expire_result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("[SESSION] failed to expire sessions. RedisError: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+async fn get_session(
+    uid: i64,
+    session_id: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Option<SessionInfo>, redis::RedisError> {
+    let session: Option<String> = redis::cmd("HGET")
+        .arg(session_key(uid))
+        .arg(session_id)
+        .query_async(kv_conn)
+        .await?;
+    Ok(session.and_then(|s| serde_json::from_str(&s).ok()))
+}
+
+/// Get the sessions of a user, the most recently seen first
+///
+/// Sessions whose refresh token must have expired are removed.
+pub async fn get_sessions(
+    uid: i64,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Vec<SessionInfo>, ErrorResponse> {
+    let key = session_key(uid);
+    let get_result: Result<HashMap<String, String>, redis::RedisError> =
+        redis::cmd("HGETALL").arg(&key).query_async(kv_conn).await;
+    let records = match get_result {
+        Ok(r) => r,
+        Err(e) => {
+            error!("[SESSION] failed to get sessions. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+    };
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let mut sessions = Vec::new();
+    let mut expired = Vec::new();
+    for (session_id, record) in records {
+        match serde_json::from_str::<SessionInfo>(&record) {
+            Ok(s) if (now - s.last_seen).num_seconds() < REF_TOKEN_TO_ID_EX as i64 => {
+                sessions.push(s)
+            }
+            _ => expired.push(session_id),
+        }
+    }
+    if !expired.is_empty() {
+        let delete_result: Result<i64, redis::RedisError> = redis::cmd("HDEL")
+            .arg(&key)
+            .arg(expired)
+            .query_async(kv_conn)
+            .await;
+        if let Err(e) = delete_result {
             error!(
-                "[LOGIN] failed to get id -> token when login. RedisError: {:?}",
+                "[SESSION] failed to delete expired sessions. RedisError: {:?}",
                 e
             );
+        }
+    }
+    sessions.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+    Ok(sessions)
+}
+
+/// Revoke a session of a user
+///
+/// ## Returns
+///
+/// `false` if the session does not exist.
+pub async fn revoke_session(
+    uid: i64,
+    session_id: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<bool, ErrorResponse> {
+    let session = match get_session(uid, session_id, kv_conn).await {
+        Ok(s) => s,
+        Err(e) => {
+            error!("[SESSION] failed to get session. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+    };
+    if let Some(session) = &session {
+        delete_token(session, kv_conn).await?;
+    }
+    let delete_result: Result<i64, redis::RedisError> = redis::cmd("HDEL")
+        .arg(session_key(uid))
+        .arg(session_id)
+        .query_async(kv_conn)
+        .await;
+    match delete_result {
+        Ok(n) => Ok(n > 0),
+        Err(e) => {
+            error!("[SESSION] failed to delete session. RedisError: {:?}", e);
             Err(ErrorResponse::default())
         }
     }
 }
 
+/// Check if the second factor was checked for a session, see `SessionInfo`
+pub async fn is_mfa_session(
+    uid: i64,
+    session_id: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<bool, ErrorResponse> {
+    match get_session(uid, session_id, kv_conn).await {
+        Ok(session) => Ok(session.map_or(false, |s| s.mfa)),
+        Err(e) => {
+            error!("[SESSION] failed to get session. RedisError: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+/// Mark a session as checked with the second factor, and revoke the other
+/// sessions of the user, which were not
+pub async fn confirm_mfa_session(
+    uid: i64,
+    session_id: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<(), ErrorResponse> {
+    for mut session in get_sessions(uid, kv_conn).await? {
+        if session.session_id == session_id {
+            session.mfa = true;
+            save_session(uid, &session, kv_conn).await?;
+        } else {
+            revoke_session(uid, &session.session_id, kv_conn).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Revoke all the sessions of a user
+pub async fn revoke_all_sessions(
+    uid: i64,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<(), ErrorResponse> {
+    for session in get_sessions(uid, kv_conn).await? {
+        delete_token(&session, kv_conn).await?;
+    }
+    let delete_result: Result<i64, redis::RedisError> = redis::cmd("DEL")
+        .arg(session_key(uid))
+        .query_async(kv_conn)
+        .await;
+    match delete_result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("[SESSION] failed to delete sessions. RedisError: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+/// Update the last seen time of a session, at most once per `SESSION_TOUCH_INTERVAL`
+async fn touch_session(uid: i64, session_id: &str, con: &mut redis::aio::Connection) {
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    match get_session(uid, session_id, con).await {
+        Ok(Some(mut session)) => {
+            if (now - session.last_seen).num_seconds() >= SESSION_TOUCH_INTERVAL {
+                session.last_seen = now;
+                let _ = save_session(uid, &session, con).await;
+            }
+        }
+        Ok(None) => info!("[SSO] session {} not found", session_id),
+        Err(e) => error!("[SSO] failed to get session. RedisError: {:?}", e),
+    }
+}
+
 async fn is_valid<'r>(
     request: &'r Request<'_>,
     token: &str,
@@ -251,107 +610,48 @@ async fn is_valid<'r>(
 ) -> ValidToken {
     let redis_result: Result<u32, redis::RedisError> = redis::cmd("EXPIRE")
         .arg(token)
-        .arg(4 * 3600)
+        .arg(TOKEN_TO_ID_EX)
         .query_async(con)
         .await;
     match redis_result {
         // token exists
         Ok(1) => {
-            let get_result: Result<i64, redis::RedisError> =
+            let get_result: Result<String, redis::RedisError> =
                 redis::cmd("GET").arg(token).query_async(con).await;
             match get_result {
-                Ok(id) => {
-                    info!("[SSO] token -> id exists");
-                    ValidToken::Valid(id)
-                }
+                Ok(value) => match parse_session_value(&value) {
+                    Some((id, session_id)) => {
+                        info!("[SSO] token -> id exists");
+                        touch_session(id, &session_id, con).await;
+                        ValidToken::Valid(id, session_id)
+                    }
+                    None => {
+                        info!("[SSO] token -> id has no session, need to re-login.");
+                        ValidToken::Invalid
+                    }
+                },
                 _ => ValidToken::DatabaseErr,
             }
         }
         // token does not exist
         Ok(_) => {
             info!("[SSO] token -> id has expired, try to find refresh_token...");
-            // hash token to refresh token
-            let mut hash_sha3 = Sha3::sha3_384();
-            hash_sha3.input_str(token);
-            let refresh_token = hash_sha3.result_str();
-            // generate new token
-            let new_token: String = thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(32)
-                .map(char::from)
-                .collect();
-            // hash new token to new refresh token
-            let mut hash_sha3 = Sha3::sha3_384();
-            hash_sha3.input_str(&new_token);
-            let new_refresh_token = hash_sha3.result_str();
-            // try to rename old refresh token to new refresh token
-            let refresh_result: Result<u32, redis::RedisError> = redis::cmd("RENAMENX")
-                .arg(&refresh_token)
-                .arg(&new_refresh_token)
-                .query_async(con)
-                .await;
-            match refresh_result {
-                // successfully rename refresh token to new refresh token
-                Ok(1) => {
-                    info!("[SSO] refresh_token exists.");
-                    // find id by get refresh_token
-                    let get_result: Result<i64, redis::RedisError> = redis::cmd("GET")
-                        .arg(&new_refresh_token)
-                        .query_async(con)
-                        .await;
-                    let id: i64 = match get_result {
-                        Ok(id) => {
-                            // set id -> new_token
-                            let old_token_get: Result<String, redis::RedisError> =
-                                redis::cmd("GETSET")
-                                    .arg(id)
-                                    .arg(&new_token)
-                                    .query_async(con)
-                                    .await;
-                            // clear old_token -> id
-                            match old_token_get {
-                                Ok(old_token) => {
-                                    info!("[SSO] set id -> new_token");
-                                    let _: Result<i64, redis::RedisError> =
-                                        redis::cmd("DEL").arg(&old_token).query_async(con).await;
-                                    info!("[SSO] delete old_token -> id");
-                                }
-                                _ => return ValidToken::DatabaseErr,
-                            };
-                            // set new_token -> id
-                            let refresh_set: Result<String, redis::RedisError> =
-                                redis::cmd("SETEX")
-                                    .arg(&new_token)
-                                    .arg(4 * 3600i32)
-                                    .arg(id)
-                                    .query_async(con)
-                                    .await;
-                            match refresh_set {
-                                Ok(_) => {
-                                    // set cookie to the new token
-                                    let cookie =
-                                        Cookie::build("token", new_token).cookie_options().finish();
-                                    request.cookies().add_private(cookie);
-                                    info!("[SSO] set new_token -> id");
-                                }
-                                _ => return ValidToken::DatabaseErr,
-                            };
-                            id
-                        }
-                        _ => return ValidToken::DatabaseErr,
-                    };
-                    ValidToken::Refresh(id)
+            let refresh_token = match request.cookies().get_private("refresh_token") {
+                Some(cookie) => cookie.value().to_string(),
+                None => {
+                    info!("[SSO] refresh_token is missing, need to re-login.");
+                    return ValidToken::Invalid;
                 }
-                // database error, new refresh token already exists
-                Ok(0) => {
-                    error!("[SSO] new_refresh_token already exists in redis.");
-                    ValidToken::DatabaseErr
-                }
-                // refresh token does not exist
-                _ => {
-                    info!("[SSO] refresh_token expired, need to re-login.");
-                    ValidToken::Invalid
+            };
+            match rotate_token(&refresh_token, con).await {
+                Ok(Some(session_token)) => {
+                    // set cookie to the new tokens
+                    add_token_cookies(request.cookies(), &session_token);
+                    info!("[SSO] set new_token -> id");
+                    ValidToken::Refresh(session_token.uid, session_token.session_id)
                 }
+                Ok(None) => ValidToken::Invalid,
+                Err(_) => ValidToken::DatabaseErr,
             }
         }
         // database connection error
@@ -378,8 +678,8 @@ pub async fn auth_token<'r>(request: &'r Request<'_>) -> Option<ValidToken> {
         None => Some(ValidToken::Missing),
         // get token from cookie and valid it
         Some(token) => match is_valid(request, token.value(), redis_manager.as_mut()).await {
-            ValidToken::Valid(id) => Some(ValidToken::Valid(id)),
-            ValidToken::Refresh(id) => Some(ValidToken::Refresh(id)),
+            ValidToken::Valid(id, session_id) => Some(ValidToken::Valid(id, session_id)),
+            ValidToken::Refresh(id, session_id) => Some(ValidToken::Refresh(id, session_id)),
             ValidToken::DatabaseErr => Some(ValidToken::DatabaseErr),
             _ => Some(ValidToken::Invalid),
         },
@@ -413,14 +713,39 @@ impl<'r> FromRequest<'r> for Auth {
                 ValidToken::DatabaseErr => {
                     Outcome::Failure((Status::InternalServerError, ErrorResponse::default()))
                 }
-                ValidToken::Refresh(id) => Outcome::Success(Auth { id: *id }),
-                ValidToken::Valid(id) => Outcome::Success(Auth { id: *id }),
+                ValidToken::Refresh(id, session_id) | ValidToken::Valid(id, session_id) => {
+                    Outcome::Success(Auth {
+                        id: *id,
+                        session_id: session_id.to_owned(),
+                    })
+                }
             },
             None => Outcome::Failure((Status::InternalServerError, ErrorResponse::default())),
         }
     }
 }
 
+/// Set the tokens of a session as cookies
+pub fn add_token_cookies(cookies: &CookieJar<'_>, session_token: &SessionToken) {
+    let cookie = Cookie::build("token", session_token.token.to_owned())
+        .cookie_options()
+        .finish();
+    cookies.add_private(cookie);
+    let cookie = Cookie::build("refresh_token", session_token.refresh_token.to_owned())
+        .cookie_options()
+        .finish();
+    cookies.add_private(cookie);
+}
+
+/// Remove the cookies of tokens
+pub fn remove_token_cookies(cookies: &CookieJar<'_>) {
+    for name in ["token", "refresh_token"] {
+        let mut cookie = Cookie::named(name);
+        cookie.set_domain(".thuburrow.com");
+        cookies.remove_private(cookie);
+    }
+}
+
 pub trait CookieOptions {
     fn cookie_options(self) -> Self;
 }
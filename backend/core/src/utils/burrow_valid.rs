@@ -1,42 +1,109 @@
 //! Module to valid burrow
 //!
-//! Split burrow list stored in database to burrow list
+//! Burrows of a user are derived from `uid` and `burrow_state` of table `burrow`,
+//! which are indexed together. The lowest bit of `burrow_state` is set when the
+//! burrow is banned by admin, and the second bit is set when it is discarded by
+//! its owner:
+//!
+//! - 0: valid
+//! - 1: banned
+//! - 2: discarded
+//! - 3: banned and discarded
+
+use sea_orm::{entity::*, query::*, ConnectionTrait, DbErr, PaginatorTrait};
+
+use crate::db::{burrow, prelude::*};
+
+/// State of a burrow which is neither banned nor discarded
+pub static VALID_STATE: i32 = 0;
+
+/// States below it are of burrows not discarded
+pub static DISCARDED_STATE: i32 = 2;
+
+/// Check if a burrow state is banned
+pub fn is_banned(burrow_state: i32) -> bool {
+    burrow_state % 2 == 1
+}
+
+/// Check if a burrow state is discarded
+pub fn is_discarded(burrow_state: i32) -> bool {
+    burrow_state >= DISCARDED_STATE
+}
 
-/// Split burrow list stored in database as string to burrow_id list
+/// Get valid burrows of a user
 ///
 /// ## Parameters
 ///
-/// - `burrow`: The burrow list stored in database as string.
+/// - `db`: Postgres connection or transaction.
+/// - `uid`: uid of the user.
 ///
 /// ## Returns
 ///
-/// A vector of burrow_id.
-pub fn get_burrow_list(burrows: &str) -> Vec<i64> {
-    if burrows.is_empty() {
-        return Vec::new();
-    }
-    burrows
-        .split(',')
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect()
+/// A vector of burrow_id, in ascending order.
+pub async fn get_valid_burrows<C: ConnectionTrait>(db: &C, uid: i64) -> Result<Vec<i64>, DbErr> {
+    let burrows = Burrow::find()
+        .filter(burrow::Column::Uid.eq(uid))
+        .filter(burrow::Column::BurrowState.eq(VALID_STATE))
+        .order_by_asc(burrow::Column::BurrowId)
+        .all(db)
+        .await?;
+    Ok(burrows.into_iter().map(|b| b.burrow_id).collect())
+}
+
+/// Get burrows owned by a user, which are valid or banned but not discarded
+///
+/// ## Parameters
+///
+/// - `db`: Postgres connection or transaction.
+/// - `uid`: uid of the user.
+///
+/// ## Returns
+///
+/// A vector of burrows, the newest first.
+pub async fn get_owned_burrows<C: ConnectionTrait>(
+    db: &C,
+    uid: i64,
+) -> Result<Vec<burrow::Model>, DbErr> {
+    Burrow::find()
+        .filter(burrow::Column::Uid.eq(uid))
+        .filter(burrow::Column::BurrowState.lt(DISCARDED_STATE))
+        .order_by_desc(burrow::Column::BurrowId)
+        .all(db)
+        .await
 }
 
-/// Check if a given burrow_id is valid
+/// Count burrows owned by a user, which are valid or banned but not discarded
+pub async fn count_owned_burrows<C: ConnectionTrait>(db: &C, uid: i64) -> Result<usize, DbErr> {
+    Burrow::find()
+        .filter(burrow::Column::Uid.eq(uid))
+        .filter(burrow::Column::BurrowState.lt(DISCARDED_STATE))
+        .count(db)
+        .await
+}
+
+/// Check if a given burrow_id is a valid burrow of a user
 ///
 /// ## Parameters
 ///
-/// - `burrow`: The burrow list stored in database as string.
+/// - `db`: Postgres connection or transaction.
+/// - `uid`: uid of the user.
 /// - `burrow_id`: The burrow_id to check.
 ///
 /// ## Returns
 ///
 /// A boolean value. True if the burrow_id is valid.
-pub fn is_valid_burrow(burrows: &str, burrow_id: &i64) -> bool {
-    if burrows.is_empty() {
-        return false;
-    }
-    let burrow_list = get_burrow_list(burrows);
-    burrow_list.contains(burrow_id)
+pub async fn is_valid_burrow<C: ConnectionTrait>(
+    db: &C,
+    uid: i64,
+    burrow_id: i64,
+) -> Result<bool, DbErr> {
+    let count = Burrow::find()
+        .filter(burrow::Column::BurrowId.eq(burrow_id))
+        .filter(burrow::Column::Uid.eq(uid))
+        .filter(burrow::Column::BurrowState.eq(VALID_STATE))
+        .count(db)
+        .await?;
+    Ok(count > 0)
 }
 
 #[cfg(test)]
@@ -44,19 +111,14 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_get_burrow_list() {
-        let v1: Vec<i64> = Vec::new();
-        assert_eq!(get_burrow_list(""), v1);
-        assert_eq!(get_burrow_list("1"), vec![1]);
-        assert_eq!(get_burrow_list("1,2"), vec![1, 2]);
-    }
-
-    #[test]
-    fn test_is_valid_burrow() {
-        assert_eq!(is_valid_burrow("", &1), false);
-        assert_eq!(is_valid_burrow("1", &1), true);
-        assert_eq!(is_valid_burrow("1,2", &1), true);
-        assert_eq!(is_valid_burrow("1,2", &2), true);
-        assert_eq!(is_valid_burrow("1,2", &3), false);
+    fn test_burrow_state() {
+        assert!(!is_banned(0));
+        assert!(is_banned(1));
+        assert!(!is_banned(2));
+        assert!(is_banned(3));
+        assert!(!is_discarded(0));
+        assert!(!is_discarded(1));
+        assert!(is_discarded(2));
+        assert!(is_discarded(3));
     }
 }
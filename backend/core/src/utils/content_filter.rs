@@ -0,0 +1,283 @@
+//! Module of sensitive word filter
+//!
+//! Rules are managed by admins in the `sensitive_word` table. Every backend
+//! instance compiles them into a matcher in memory, and reloads it periodically
+//! so that changes made through other instances take effect as well.
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use chrono::{FixedOffset, Utc};
+use regex::Regex;
+use rocket::futures::future::BoxFuture;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{Orbit, Rocket};
+use rocket_db_pools::Database;
+use sea_orm::{entity::*, DatabaseConnection, DbErr, QueryFilter};
+use std::sync::RwLock;
+use tokio::time::Duration;
+
+use crate::config::content::FILTER_RELOAD_INTERVAL;
+use crate::db::{self, prelude::*};
+use crate::models::error::*;
+use crate::models::filter::{FilterAction, FilterRule};
+use crate::models::report::{ReportReason, ReportState, ReportTarget};
+use crate::pool::PgDb;
+
+lazy_static::lazy_static! {
+    static ref CONTENT_FILTER: RwLock<ContentFilter> = RwLock::new(ContentFilter::new(&[]));
+}
+
+/// Result of checking a piece of text
+///
+/// ## Fields
+///
+/// - `FilterResult::Reject`: The text matches a rule with `FilterAction::Reject`
+/// - `FilterResult::Pass`: The text can be stored as `text`, with matches of
+///   `FilterAction::Mask` masked, and `flagged` set if a `FilterAction::Flag` rule matches
+///
+#[derive(Debug, PartialEq, Eq)]
+pub enum FilterResult {
+    Reject,
+    Pass { text: String, flagged: bool },
+}
+
+/// Matcher compiled from the rules of the filter
+pub struct ContentFilter {
+    keywords: Option<AhoCorasick>,
+    keyword_actions: Vec<FilterAction>,
+    regexes: Vec<(Regex, FilterAction)>,
+}
+
+impl ContentFilter {
+    /// Compile rules into a matcher
+    ///
+    /// Keywords are matched case-insensitively. Rules with empty pattern or
+    /// invalid regular expression are skipped.
+    pub fn new(rules: &[FilterRule]) -> ContentFilter {
+        let mut patterns: Vec<&str> = Vec::new();
+        let mut keyword_actions = Vec::new();
+        let mut regexes = Vec::new();
+        for rule in rules {
+            if rule.pattern.is_empty() {
+                continue;
+            }
+            if rule.is_regex {
+                match Regex::new(&rule.pattern) {
+                    Ok(re) => regexes.push((re, rule.action)),
+                    Err(e) => log::error!("[FILTER] Invalid regex {}: {}", rule.pattern, e),
+                }
+            } else {
+                patterns.push(&rule.pattern);
+                keyword_actions.push(rule.action);
+            }
+        }
+        let keywords = if patterns.is_empty() {
+            None
+        } else {
+            Some(
+                AhoCorasickBuilder::new()
+                    .ascii_case_insensitive(true)
+                    .build(patterns),
+            )
+        };
+        ContentFilter {
+            keywords,
+            keyword_actions,
+            regexes,
+        }
+    }
+
+    /// Check a piece of text against all the rules
+    pub fn check(&self, text: &str) -> FilterResult {
+        let mut matches: Vec<(usize, usize, FilterAction)> = Vec::new();
+        if let Some(keywords) = &self.keywords {
+            for m in keywords.find_overlapping_iter(text) {
+                matches.push((m.start(), m.end(), self.keyword_actions[m.pattern()]));
+            }
+        }
+        for (re, action) in &self.regexes {
+            for m in re.find_iter(text) {
+                matches.push((m.start(), m.end(), *action));
+            }
+        }
+        let mut masked = vec![false; text.len()];
+        let mut flagged = false;
+        for (start, end, action) in matches {
+            if start == end {
+                continue;
+            }
+            match action {
+                FilterAction::Reject => return FilterResult::Reject,
+                FilterAction::Mask => masked[start..end].iter_mut().for_each(|x| *x = true),
+                FilterAction::Flag => flagged = true,
+            }
+        }
+        let text = text
+            .char_indices()
+            .map(|(i, c)| if masked[i] { '*' } else { c })
+            .collect();
+        FilterResult::Pass { text, flagged }
+    }
+}
+
+/// Check a piece of text with the rules loaded in this instance
+pub fn check_content(text: &str) -> FilterResult {
+    CONTENT_FILTER.read().unwrap().check(text)
+}
+
+/// Filter a piece of user content
+///
+/// ## Returns
+///
+/// The text to store and whether it should be flagged for review.
+///
+/// ## Errors
+///
+/// - `ErrorCode::ContentSensitive` if the text matches a rule with `FilterAction::Reject`
+pub fn filter_content(text: &str) -> Result<(String, bool), (Status, Json<ErrorResponse>)> {
+    match check_content(text) {
+        FilterResult::Reject => Err((
+            Status::BadRequest,
+            Json(ErrorResponse::build(
+                ErrorCode::ContentSensitive,
+                "Content contains sensitive words.",
+            )),
+        )),
+        FilterResult::Pass { text, flagged } => Ok((text, flagged)),
+    }
+}
+
+/// Report flagged content for review on behalf of the system, whose uid is 0
+pub async fn flag_content(pg_con: &DatabaseConnection, target: ReportTarget) {
+    let target_type = target.to_i32();
+    let (post_id, reply_id, burrow_id) = target.to_ids();
+    match Report::find()
+        .filter(db::report::Column::Uid.eq(0))
+        .filter(db::report::Column::TargetType.eq(target_type))
+        .filter(db::report::Column::PostId.eq(post_id))
+        .filter(db::report::Column::ReplyId.eq(reply_id))
+        .filter(db::report::Column::BurrowId.eq(burrow_id))
+        .one(pg_con)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+            let report = db::report::ActiveModel {
+                uid: Set(0),
+                target_type: Set(target_type),
+                post_id: Set(post_id),
+                reply_id: Set(reply_id),
+                burrow_id: Set(burrow_id),
+                reason: Set(ReportReason::AutoFlagged.to_i32()),
+                description: Set("".to_string()),
+                report_state: Set(ReportState::Pending.to_i32()),
+                operator: Set(0),
+                create_time: Set(now),
+                resolve_time: Set(None),
+                ..Default::default()
+            };
+            if let Err(e) = report.insert(pg_con).await {
+                log::error!("[FILTER] Database error: {:?}", e);
+            }
+        }
+        Err(e) => {
+            log::error!("[FILTER] Database error: {:?}", e);
+        }
+    }
+}
+
+/// Load the rules from database and replace the matcher of this instance
+///
+/// ## Returns
+///
+/// Number of rules loaded.
+pub async fn reload_filter(pg_con: &DatabaseConnection) -> Result<usize, DbErr> {
+    let rules: Vec<FilterRule> = SensitiveWord::find()
+        .all(pg_con)
+        .await?
+        .iter()
+        .map(|w| w.into())
+        .collect();
+    let filter = ContentFilter::new(&rules);
+    *CONTENT_FILTER.write().unwrap() = filter;
+    Ok(rules.len())
+}
+
+/// Spawn the task which reloads the filter periodically
+pub fn filter_reload_init(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+    let pg_con = PgDb::fetch(rocket).map(|db| db.connection.clone());
+    Box::pin(async move {
+        match pg_con {
+            Some(pg_con) => {
+                tokio::spawn(async move {
+                    let mut interval =
+                        tokio::time::interval(Duration::from_secs(FILTER_RELOAD_INTERVAL));
+                    loop {
+                        interval.tick().await;
+                        if let Err(e) = reload_filter(&pg_con).await {
+                            log::error!("[FILTER] Database error: {:?}", e);
+                        }
+                    }
+                });
+            }
+            None => log::error!("[FILTER] Cannot get postgres connection."),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, is_regex: bool, action: FilterAction) -> FilterRule {
+        FilterRule {
+            pattern: pattern.to_string(),
+            is_regex,
+            action,
+        }
+    }
+
+    #[test]
+    fn test_content_filter() {
+        let filter = ContentFilter::new(&[
+            rule("bad", false, FilterAction::Reject),
+            rule("spam", false, FilterAction::Mask),
+            rule("广告", false, FilterAction::Mask),
+            rule(r"\d{11}", true, FilterAction::Flag),
+            rule("", false, FilterAction::Reject),
+            rule("(", true, FilterAction::Reject),
+        ]);
+        assert_eq!(FilterResult::Reject, filter.check("a BAD word"));
+        assert_eq!(FilterResult::Reject, filter.check("badge"));
+        assert_eq!(
+            FilterResult::Pass {
+                text: "no **** here, 看**".to_string(),
+                flagged: false
+            },
+            filter.check("no Spam here, 看广告")
+        );
+        assert_eq!(
+            FilterResult::Pass {
+                text: "call 13800000000".to_string(),
+                flagged: true
+            },
+            filter.check("call 13800000000")
+        );
+        assert_eq!(
+            FilterResult::Pass {
+                text: "hello".to_string(),
+                flagged: false
+            },
+            filter.check("hello")
+        );
+        let filter = ContentFilter::new(&[]);
+        assert_eq!(
+            FilterResult::Pass {
+                text: "bad".to_string(),
+                flagged: false
+            },
+            filter.check("bad")
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Module of the retry policy and dead-letter queues of consumers
+//!
+//! A consumer of task-executor acks a message only after it is handled. A message
+//! failed with `TaskError::Retry` is negatively acked with a backoff delay, so that
+//! the message queue redelivers it after the delay, until it has been tried
+//! `retry.max_attempts` times as counted by the message queue.
+//! Such a message, or one failed with `TaskError::Fatal`, is published to the
+//! dead-letter topic of the consumer, e.g. `search-DLQ`, and then acked.
+//!
+//! `utils::mq::pulsar_dead_letter` archives the dead letters into table
+//! `dead_letter`, where admins inspect them and replay them to the original topic
+//! through the outbox.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::{FixedOffset, Utc};
+use sea_orm::{entity::*, ConnectionTrait, DbErr};
+use tokio::time::Duration;
+
+use super::outbox;
+use super::queue::{Delivery, MessageQueue, QueueConsumer, QueueError};
+use crate::config::settings::CONFIG;
+use crate::db::dead_letter;
+
+/// Subscription of the consumers of task-executor, durable with pulsar and redis so
+/// that unacked messages are delivered again after a consumer restarts
+pub const SUBSCRIPTION: &str = "task-executor";
+
+/// Topics of the consumers of task-executor, each with a dead-letter topic
+pub const CONSUMER_TOPICS: [&str; 4] = [
+    outbox::SEARCH_TOPIC,
+    outbox::RELATION_TOPIC,
+    outbox::EMAIL_TOPIC,
+    outbox::NOTIFICATION_TOPIC,
+];
+
+/// Suffix of the dead-letter topic of a topic
+pub const DEAD_LETTER_SUFFIX: &str = "-DLQ";
+
+/// Property of a dead letter: short name of the topic it comes from
+pub const PROPERTY_TOPIC: &str = "origin-topic";
+
+/// Property of a dead letter: the last error of handling it
+pub const PROPERTY_ERROR: &str = "error";
+
+/// Property of a dead letter: times it has been tried
+pub const PROPERTY_ATTEMPTS: &str = "attempts";
+
+/// Error of handling a message in a consumer
+///
+/// - `TaskError::Retry`: the message may succeed later, e.g. a database or search
+///   engine is unavailable, it is redelivered with backoff
+/// - `TaskError::Fatal`: the message can never succeed, e.g. it can not be
+///   deserialized, it is dead-lettered at once
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskError {
+    Retry(String),
+    Fatal(String),
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Retry(e) => write!(f, "{}", e),
+            TaskError::Fatal(e) => write!(f, "fatal: {}", e),
+        }
+    }
+}
+
+impl From<DbErr> for TaskError {
+    fn from(e: DbErr) -> TaskError {
+        match e {
+            DbErr::Conn(_) | DbErr::Exec(_) | DbErr::Query(_) => TaskError::Retry(e.to_string()),
+            _ => TaskError::Fatal(e.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for TaskError {
+    fn from(e: reqwest::Error) -> TaskError {
+        TaskError::Retry(e.to_string())
+    }
+}
+
+impl From<redis::RedisError> for TaskError {
+    fn from(e: redis::RedisError) -> TaskError {
+        TaskError::Retry(e.to_string())
+    }
+}
+
+/// Short name of the dead-letter topic of a topic
+pub fn dead_letter_topic(topic: &str) -> String {
+    format!("{}{}", topic, DEAD_LETTER_SUFFIX)
+}
+
+/// Delay before the next try after `attempt` failed tries
+///
+/// The delay starts from `retry.base_delay` and doubles for each failed try, up to
+/// `retry.max_delay`.
+pub fn backoff(attempt: u32, base_delay: u64, max_delay: u64) -> Duration {
+    let factor = 1u64
+        .checked_shl(attempt.saturating_sub(1))
+        .unwrap_or(u64::MAX);
+    Duration::from_millis(base_delay.saturating_mul(factor).min(max_delay))
+}
+
+/// Retry policy and dead-letter producer of a consumer
+pub struct DeadLetterQueue {
+    topic: String,
+    queue: Arc<dyn MessageQueue>,
+}
+
+impl DeadLetterQueue {
+    /// Dead-letter queue of the consumer of `topic`
+    pub fn new(queue: Arc<dyn MessageQueue>, topic: &str) -> DeadLetterQueue {
+        DeadLetterQueue {
+            topic: topic.to_string(),
+            queue,
+        }
+    }
+
+    /// Ack, redeliver or dead-letter a message by the result of handling it
+    ///
+    /// The attempt is counted from `Delivery::redelivery_count`, and a message to
+    /// retry is nacked at once with the backoff as its delay.
+    ///
+    /// ## Errors
+    ///
+    /// `QueueError` of acking the message or publishing the dead letter, the
+    /// message is not acked and is delivered again after the consumer restarts.
+    pub async fn settle(
+        &self,
+        consumer: &mut dyn QueueConsumer,
+        delivery: &Delivery,
+        result: Result<(), TaskError>,
+    ) -> Result<(), QueueError> {
+        let e = match result {
+            Ok(_) => {
+                consumer.ack(delivery).await?;
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        let attempt = delivery.redelivery_count.saturating_add(1);
+        let retry = &CONFIG.retry;
+        if matches!(e, TaskError::Retry(_)) && attempt < retry.max_attempts {
+            let delay = backoff(attempt, retry.base_delay, retry.max_delay);
+            log::warn!(
+                "[DEAD-LETTER] Attempt {} of a {} message failed: {}. Retry in {:?}",
+                attempt,
+                self.topic,
+                e,
+                delay
+            );
+            consumer.nack(delivery, delay).await?;
+            return Ok(());
+        }
+        log::error!(
+            "[DEAD-LETTER] A {} message failed after {} attempts: {}. Move it to {}",
+            self.topic,
+            attempt,
+            e,
+            dead_letter_topic(&self.topic)
+        );
+        let properties = HashMap::from([
+            (PROPERTY_TOPIC.to_string(), self.topic.to_owned()),
+            (PROPERTY_ERROR.to_string(), e.to_string()),
+            (PROPERTY_ATTEMPTS.to_string(), attempt.to_string()),
+        ]);
+        self.queue
+            .publish(
+                &dead_letter_topic(&self.topic),
+                delivery.payload.to_owned(),
+                properties,
+            )
+            .await?;
+        consumer.ack(delivery).await?;
+        Ok(())
+    }
+}
+
+/// Archive a dead letter read from a dead-letter topic
+///
+/// ## Parameters
+///
+/// - `db`: Postgres connection
+/// - `delivery`: The dead letter, with the properties set by
+///   `DeadLetterQueue::settle`. Its topic is used when it has no `origin-topic`
+///   property.
+pub async fn archive<C: ConnectionTrait>(db: &C, delivery: &Delivery) -> Result<(), DbErr> {
+    let properties = &delivery.properties;
+    let topic = match properties.get(PROPERTY_TOPIC) {
+        Some(topic) => topic.to_owned(),
+        None => origin_topic(&delivery.topic).to_string(),
+    };
+    let letter = dead_letter::ActiveModel {
+        topic: Set(topic),
+        payload: Set(String::from_utf8_lossy(&delivery.payload).into_owned()),
+        error: Set(properties.get(PROPERTY_ERROR).cloned().unwrap_or_default()),
+        attempts: Set(properties
+            .get(PROPERTY_ATTEMPTS)
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(0)),
+        create_time: Set(Utc::now().with_timezone(&FixedOffset::east(8 * 3600))),
+        replay_time: Set(None),
+        ..Default::default()
+    };
+    letter.insert(db).await?;
+    Ok(())
+}
+
+/// Short name of the topic of a dead-letter topic
+///
+/// e.g. `search` of `search-DLQ`
+pub fn origin_topic(dlq_topic: &str) -> &str {
+    dlq_topic
+        .strip_suffix(DEAD_LETTER_SUFFIX)
+        .unwrap_or(dlq_topic)
+}
+
+/// Send a dead letter back to its topic through the outbox, and mark it replayed
+///
+/// ## Parameters
+///
+/// - `txn`: Postgres transaction, so that the dead letter is marked if and only
+///   if it is enqueued
+/// - `letter`: The dead letter
+pub async fn replay<C: ConnectionTrait>(
+    txn: &C,
+    letter: dead_letter::Model,
+) -> Result<dead_letter::Model, DbErr> {
+    outbox::enqueue_payload(txn, &letter.topic, letter.payload.to_owned()).await?;
+    let mut letter: dead_letter::ActiveModel = letter.into();
+    letter.replay_time = Set(Some(Utc::now().with_timezone(&FixedOffset::east(8 * 3600))));
+    letter.update(txn).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(Duration::from_millis(500), backoff(1, 500, 60000));
+        assert_eq!(Duration::from_millis(1000), backoff(2, 500, 60000));
+        assert_eq!(Duration::from_millis(8000), backoff(5, 500, 60000));
+        assert_eq!(Duration::from_millis(60000), backoff(10, 500, 60000));
+        assert_eq!(Duration::from_millis(60000), backoff(100, 500, 60000));
+    }
+
+    #[test]
+    fn test_dead_letter_topic() {
+        assert_eq!("search-DLQ", dead_letter_topic(outbox::SEARCH_TOPIC));
+        assert_eq!("relation", origin_topic("relation-DLQ"));
+        assert_eq!("email", origin_topic("email"));
+        assert!(matches!(
+            TaskError::from(DbErr::Conn("closed".to_string())),
+            TaskError::Retry(_)
+        ));
+        assert!(matches!(
+            TaskError::from(DbErr::RecordNotFound("post".to_string())),
+            TaskError::Fatal(_)
+        ));
+    }
+}
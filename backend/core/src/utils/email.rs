@@ -8,7 +8,7 @@ use lazy_static::lazy_static;
 use reqwest::header::HeaderMap;
 use serde::Serialize;
 
-use crate::config::email::*;
+use crate::config::settings::CONFIG;
 
 lazy_static! {
     static ref MAIL_DOMAINS: Vec<String> = vec![
@@ -168,8 +168,8 @@ pub fn assemble_headers(timestamp: String) -> HeaderMap {
 
 pub fn signature(param: &Body, timestamp: String, date: String) -> String {
     // define signature parameters
-    let secret_id = &*SECRET_ID;
-    let secret_key = &*SECRET_KEY;
+    let secret_id = &CONFIG.email.secret_id;
+    let secret_key = &CONFIG.email.secret_key;
     let host = "ses.tencentcloudapi.com".to_string();
 
     let service = "ses".to_string();
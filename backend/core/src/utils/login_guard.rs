@@ -0,0 +1,295 @@
+//! Module of login throttling
+//!
+//! Failed logins are counted per username and per client ip in a sliding window of
+//! `LOGIN_FAIL_WINDOW` seconds, kept in redis as sorted sets of failure timestamps.
+//! Once the failures reach the limit, the username or ip is locked for
+//! `LOGIN_LOCK_BASE` seconds, and every further failure doubles the lockout up to
+//! `LOGIN_LOCK_MAX` seconds.
+
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+
+use crate::config::settings::CONFIG;
+use crate::config::user::*;
+use crate::models::admin::{LockoutDisplay, LockoutTarget};
+use crate::models::error::*;
+
+fn fail_key(target: LockoutTarget, value: &str) -> String {
+    format!("login-fail:{}:{}", target.to_str(), value)
+}
+
+fn lock_key(target: LockoutTarget, value: &str) -> String {
+    format!("login-lock:{}:{}", target.to_str(), value)
+}
+
+/// Parse a lock key into `(target, value)`, the value may contain `:` itself
+fn parse_lock_key(key: &str) -> Option<(LockoutTarget, String)> {
+    let (target, value) = key.strip_prefix("login-lock:")?.split_once(':')?;
+    Some((LockoutTarget::parse(target)?, value.to_string()))
+}
+
+/// Targets to count failures of, the ip is skipped if unknown
+fn targets<'a>(username: &'a str, ip: &'a str) -> Vec<(LockoutTarget, &'a str, i64)> {
+    let mut targets = vec![(
+        LockoutTarget::User,
+        username,
+        CONFIG.user.login_fail_user_limit,
+    )];
+    if !ip.is_empty() {
+        targets.push((LockoutTarget::Ip, ip, CONFIG.user.login_fail_ip_limit));
+    }
+    targets
+}
+
+/// Duration of the lockout after `failures` failed logins in the window
+///
+/// ## Returns
+///
+/// Seconds to lock, `None` if the failures are below the limit.
+pub fn lock_duration(failures: i64, limit: i64) -> Option<i64> {
+    if failures < limit {
+        return None;
+    }
+    let exp = (failures - limit).min(32) as u32;
+    Some(LOGIN_LOCK_BASE.saturating_mul(1 << exp).min(LOGIN_LOCK_MAX))
+}
+
+async fn get_ttl(key: &str, kv_conn: &mut redis::aio::Connection) -> Result<i64, ErrorResponse> {
+    let ttl_result: Result<i64, redis::RedisError> =
+        redis::cmd("TTL").arg(key).query_async(kv_conn).await;
+    ttl_result.map_err(|e| {
+        log::error!("[LOGIN-GUARD] failed to get ttl. RedisError: {:?}", e);
+        ErrorResponse::default()
+    })
+}
+
+/// Check whether a login of the username from the ip is locked
+///
+/// ## Returns
+///
+/// Seconds until the lockout is lifted, `None` if not locked.
+pub async fn check_lockout(
+    username: &str,
+    ip: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Option<i64>, ErrorResponse> {
+    let mut retry_after = None;
+    for (target, value, _) in targets(username, ip) {
+        // TTL is negative if the key does not exist
+        let ttl = get_ttl(&lock_key(target, value), kv_conn).await?;
+        if ttl > 0 && retry_after.map_or(true, |r| ttl > r) {
+            retry_after = Some(ttl);
+        }
+    }
+    Ok(retry_after)
+}
+
+/// Count a failed login of the username from the ip, and lock them if the
+/// failures reach the limit
+///
+/// ## Returns
+///
+/// Seconds of the new lockout, `None` if not locked.
+pub async fn record_failure(
+    username: &str,
+    ip: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Option<i64>, ErrorResponse> {
+    let now = Utc::now().timestamp_millis();
+    let mut retry_after = None;
+    for (target, value, limit) in targets(username, ip) {
+        let key = fail_key(target, value);
+        // drop the failures out of the window
+        let remove_result: Result<i64, redis::RedisError> = redis::cmd("ZREMRANGEBYSCORE")
+            .arg(&key)
+            .arg("-inf")
+            .arg(now - LOGIN_FAIL_WINDOW * 1000)
+            .query_async(kv_conn)
+            .await;
+        if let Err(e) = remove_result {
+            log::error!("[LOGIN-GUARD] failed to slide window. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+        // members must be unique, failures in the same millisecond are all kept
+        let member = format!("{}-{}", now, thread_rng().gen::<u32>());
+        let add_result: Result<i64, redis::RedisError> = redis::cmd("ZADD")
+            .arg(&key)
+            .arg(now)
+            .arg(member)
+            .query_async(kv_conn)
+            .await;
+        if let Err(e) = add_result {
+            log::error!("[LOGIN-GUARD] failed to add failure. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+        let _: Result<i64, redis::RedisError> = redis::cmd("EXPIRE")
+            .arg(&key)
+            .arg(LOGIN_FAIL_WINDOW)
+            .query_async(kv_conn)
+            .await;
+        let count_result: Result<i64, redis::RedisError> =
+            redis::cmd("ZCARD").arg(&key).query_async(kv_conn).await;
+        let failures = match count_result {
+            Ok(n) => n,
+            Err(e) => {
+                log::error!(
+                    "[LOGIN-GUARD] failed to count failures. RedisError: {:?}",
+                    e
+                );
+                return Err(ErrorResponse::default());
+            }
+        };
+        if let Some(duration) = lock_duration(failures, limit) {
+            let lock_result: Result<String, redis::RedisError> = redis::cmd("SETEX")
+                .arg(lock_key(target, value))
+                .arg(duration)
+                .arg(failures)
+                .query_async(kv_conn)
+                .await;
+            if let Err(e) = lock_result {
+                log::error!("[LOGIN-GUARD] failed to set lockout. RedisError: {:?}", e);
+                return Err(ErrorResponse::default());
+            }
+            log::warn!(
+                "[LOGIN-GUARD] lock {} {} for {}s after {} failures",
+                target.to_str(),
+                value,
+                duration,
+                failures
+            );
+            if retry_after.map_or(true, |r| duration > r) {
+                retry_after = Some(duration);
+            }
+        }
+    }
+    Ok(retry_after)
+}
+
+/// Clear the failures and lockout of a username or ip
+///
+/// ## Returns
+///
+/// `false` if there is nothing to clear.
+pub async fn clear_failures(
+    target: LockoutTarget,
+    value: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<bool, ErrorResponse> {
+    let delete_result: Result<i64, redis::RedisError> = redis::cmd("DEL")
+        .arg(fail_key(target, value))
+        .arg(lock_key(target, value))
+        .query_async(kv_conn)
+        .await;
+    match delete_result {
+        Ok(n) => Ok(n > 0),
+        Err(e) => {
+            log::error!(
+                "[LOGIN-GUARD] failed to clear failures. RedisError: {:?}",
+                e
+            );
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+/// Get all the current lockouts, the longest first
+pub async fn get_lockouts(
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Vec<LockoutDisplay>, ErrorResponse> {
+    let mut keys = Vec::new();
+    let mut cursor: u64 = 0;
+    loop {
+        let scan_result: Result<(u64, Vec<String>), redis::RedisError> = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg("login-lock:*")
+            .arg("COUNT")
+            .arg(100)
+            .query_async(kv_conn)
+            .await;
+        match scan_result {
+            Ok((next, mut batch)) => {
+                keys.append(&mut batch);
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+            Err(e) => {
+                log::error!("[LOGIN-GUARD] failed to scan lockouts. RedisError: {:?}", e);
+                return Err(ErrorResponse::default());
+            }
+        }
+    }
+    // SCAN may return a key more than once
+    keys.sort();
+    keys.dedup();
+    let mut lockouts = Vec::new();
+    for key in keys {
+        let (target, value) = match parse_lock_key(&key) {
+            Some(t) => t,
+            None => continue,
+        };
+        let retry_after = get_ttl(&key, kv_conn).await?;
+        // the lockout has expired since the scan
+        if retry_after <= 0 {
+            continue;
+        }
+        let count_result: Result<i64, redis::RedisError> = redis::cmd("ZCARD")
+            .arg(fail_key(target, &value))
+            .query_async(kv_conn)
+            .await;
+        let failures = match count_result {
+            Ok(n) => n,
+            Err(e) => {
+                log::error!(
+                    "[LOGIN-GUARD] failed to count failures. RedisError: {:?}",
+                    e
+                );
+                return Err(ErrorResponse::default());
+            }
+        };
+        lockouts.push(LockoutDisplay {
+            target,
+            value,
+            failures,
+            retry_after,
+        });
+    }
+    lockouts.sort_by(|a, b| b.retry_after.cmp(&a.retry_after));
+    Ok(lockouts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_duration() {
+        assert_eq!(None, lock_duration(0, 5));
+        assert_eq!(None, lock_duration(4, 5));
+        assert_eq!(Some(LOGIN_LOCK_BASE), lock_duration(5, 5));
+        assert_eq!(Some(LOGIN_LOCK_BASE * 2), lock_duration(6, 5));
+        assert_eq!(Some(LOGIN_LOCK_BASE * 8), lock_duration(8, 5));
+        assert_eq!(Some(LOGIN_LOCK_MAX), lock_duration(100, 5));
+    }
+
+    #[test]
+    fn test_lock_key() {
+        let key = lock_key(LockoutTarget::User, "user:name");
+        assert_eq!("login-lock:user:user:name", key);
+        assert_eq!(
+            Some((LockoutTarget::User, "user:name".to_string())),
+            parse_lock_key(&key)
+        );
+        let key = lock_key(LockoutTarget::Ip, "::1");
+        assert_eq!(
+            Some((LockoutTarget::Ip, "::1".to_string())),
+            parse_lock_key(&key)
+        );
+        assert_eq!(None, parse_lock_key("login-fail:ip:::1"));
+        assert_eq!(None, parse_lock_key("login-lock:burrow:1"));
+        assert_eq!(2, targets("user", "::1").len());
+        assert_eq!(1, targets("user", "").len());
+    }
+}
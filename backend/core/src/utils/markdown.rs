@@ -0,0 +1,174 @@
+//! Module of markdown rendering
+//!
+//! Posts and replies are written in Markdown. The source is stored as it is,
+//! together with the sanitized HTML rendered from it, so that clients do not
+//! need a renderer of their own.
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use sea_orm::{entity::*, DatabaseConnection, QueryFilter};
+
+use crate::config::storage::IMAGE_URL_PREFIX;
+use crate::db::{self, prelude::*};
+use crate::models::error::*;
+
+fn parse(src: &str) -> Parser {
+    Parser::new_ext(src, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH)
+}
+
+fn is_valid_filename(filename: &str) -> bool {
+    !filename.is_empty()
+        && filename
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+/// Filenames of the images embedded in a piece of markdown, without duplicates
+///
+/// ## Errors
+///
+/// The first image link which does not point to `/storage/images/<filename>`.
+pub fn image_filenames(src: &str) -> Result<Vec<String>, String> {
+    let mut filenames: Vec<String> = Vec::new();
+    for event in parse(src) {
+        if let Event::Start(Tag::Image(_, url, _)) = event {
+            match url.strip_prefix(IMAGE_URL_PREFIX) {
+                Some(filename) if is_valid_filename(filename) => {
+                    if !filenames.iter().any(|f| f == filename) {
+                        filenames.push(filename.to_string());
+                    }
+                }
+                _ => return Err(url.to_string()),
+            }
+        }
+    }
+    Ok(filenames)
+}
+
+/// Render a piece of markdown into sanitized HTML
+///
+/// Raw HTML in the source is escaped and shown as text.
+pub fn render_markdown(src: &str) -> String {
+    let events = parse(src).map(|event| match event {
+        Event::Html(raw) => Event::Text(raw),
+        _ => event,
+    });
+    let mut output = String::new();
+    html::push_html(&mut output, events);
+    Builder::default().clean(&output).to_string()
+}
+
+/// Check the images embedded in a piece of markdown and render it
+///
+/// ## Returns
+///
+/// The sanitized HTML to store along with the source.
+///
+/// ## Errors
+///
+/// - `ErrorCode::ImageInvalid` if an image does not point to `/storage/images/<filename>`,
+///   or neither `image` nor `user_storage` records the user as its uploader
+/// - `ErrorCode::DatabaseErr`
+pub async fn render_content(
+    pg_con: &DatabaseConnection,
+    uid: i64,
+    src: &str,
+) -> Result<String, (Status, Json<ErrorResponse>)> {
+    let filenames = match image_filenames(src) {
+        Ok(filenames) => filenames,
+        Err(url) => {
+            return Err((
+                Status::BadRequest,
+                Json(ErrorResponse::build(
+                    ErrorCode::ImageInvalid,
+                    format!("Invalid image link {}", url),
+                )),
+            ))
+        }
+    };
+    if !filenames.is_empty() {
+        // the first uploader of an image is kept in `image`, the later ones only in
+        // `user_storage`, whose insert may have failed for either
+        let owned = match UserStorage::find()
+            .filter(db::user_storage::Column::Uid.eq(uid))
+            .filter(db::user_storage::Column::Filename.is_in(filenames.clone()))
+            .all(pg_con)
+            .await
+        {
+            Ok(stored) => match Image::find()
+                .filter(db::image::Column::Uid.eq(uid))
+                .filter(db::image::Column::Filename.is_in(filenames.clone()))
+                .all(pg_con)
+                .await
+            {
+                Ok(uploaded) => stored
+                    .into_iter()
+                    .map(|s| s.filename)
+                    .chain(uploaded.into_iter().map(|i| i.filename))
+                    .collect::<Vec<String>>(),
+                Err(e) => {
+                    log::error!("[MARKDOWN] Database error: {:?}", e);
+                    return Err((Status::InternalServerError, Json(ErrorResponse::default())));
+                }
+            },
+            Err(e) => {
+                log::error!("[MARKDOWN] Database error: {:?}", e);
+                return Err((Status::InternalServerError, Json(ErrorResponse::default())));
+            }
+        };
+        if let Some(filename) = filenames.iter().find(|f| !owned.contains(*f)) {
+            return Err((
+                Status::BadRequest,
+                Json(ErrorResponse::build(
+                    ErrorCode::ImageInvalid,
+                    format!("Image {} is not uploaded by the user", filename),
+                )),
+            ));
+        }
+    }
+    Ok(render_markdown(src))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_filenames() {
+        assert_eq!(Ok(vec![]), image_filenames("no image here"));
+        assert_eq!(
+            Ok(vec!["a.png".to_string(), "b.jpg".to_string()]),
+            image_filenames(
+                "![a](/storage/images/a.png) ![b](/storage/images/b.jpg)\n\n![a](/storage/images/a.png)"
+            )
+        );
+        assert_eq!(
+            Err("https://example.com/a.png".to_string()),
+            image_filenames("![a](https://example.com/a.png)")
+        );
+        assert_eq!(
+            Err("/storage/images/../a.png".to_string()),
+            image_filenames("![a](/storage/images/../a.png)")
+        );
+        assert_eq!(
+            Err("/storage/images/".to_string()),
+            image_filenames("![a](/storage/images/)")
+        );
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        assert_eq!(
+            "<p><strong>bold</strong> <em>it</em></p>\n",
+            render_markdown("**bold** *it*")
+        );
+        assert!(render_markdown("![a](/storage/images/a.png)")
+            .contains("<img src=\"/storage/images/a.png\" alt=\"a\">"));
+        let html = render_markdown("<script>alert(1)</script>");
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(!render_markdown("[x](javascript:alert(1))").contains("javascript"));
+    }
+}
@@ -1,5 +1,15 @@
 pub mod auth;
 pub mod burrow_valid;
+pub mod content_filter;
+pub mod dead_letter;
 pub mod dedup;
 pub mod email;
+pub mod login_guard;
+pub mod markdown;
 pub mod mq;
+pub mod outbox;
+pub mod password;
+pub mod queue;
+pub mod rate_limit;
+pub mod reindex;
+pub mod totp;
@@ -3,20 +3,34 @@
 //! This module contains a bunch of functions, each of which represents a background
 //! task behind Message Queue executed by task_executor.
 
-use futures::TryStreamExt;
-use pulsar::{Consumer, Pulsar, SubType, TokioExecutor};
+use chrono::{FixedOffset, Utc};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use sea_orm::prelude::DateTimeWithTimeZone;
 use sea_orm::sea_query::Expr;
-use sea_orm::{entity::*, Database, DatabaseConnection, DbErr, QueryFilter, TransactionTrait};
-use serde_json::json;
-use tokio::time::Duration;
+use sea_orm::{
+    entity::*, ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbErr, QueryFilter,
+    TransactionError, TransactionTrait,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::time::{Duration, Instant};
 
+use super::burrow_valid::{is_banned, is_discarded};
+use super::dead_letter::{self, DeadLetterQueue, TaskError};
 use super::email::{self, check_email_exist};
-use crate::config::mq::*;
-use crate::config::user::SEND_EMAIL_LIMIT;
+use super::outbox;
+use super::queue::{self, MessageQueue, QueueError};
+use super::reindex::{get_alias, IndexCollection};
+use crate::config::settings::CONFIG;
+use crate::config::user::{EMAIL_TOKEN_EX, SEND_EMAIL_LIMIT};
 use crate::config::BACKEND_TEST_MODE;
-use crate::db::{content_post, prelude::*, user_collection, user_follow, user_like};
+use crate::db::{
+    ban_record, burrow, content_post, notification, poll_vote, poll_voter, prelude::*,
+    user_collection, user_follow, user_like, user_status,
+};
+use crate::models::admin::{BanState, BanTarget};
+use crate::models::error::ErrorCode;
+use crate::models::notification::NotificationKind;
 use crate::models::{pulsar::*, search::*};
 use crate::routes::trending::select_trending;
 
@@ -25,69 +39,64 @@ pub trait Typesense {
     fn build_post(&self, uri: &str) -> reqwest::RequestBuilder;
     fn build_delete(&self, uri: &str) -> reqwest::RequestBuilder;
     fn build_patch(&self, uri: &str) -> reqwest::RequestBuilder;
+    fn build_put(&self, uri: &str) -> reqwest::RequestBuilder;
 }
 
 impl Typesense for reqwest::Client {
     fn build_get(&self, uri: &str) -> reqwest::RequestBuilder {
-        let typesense_api_key: &str = &TYPESENSE_API_KEY;
-        let typesense_addr: String = TYPESENSE_ADDR.to_owned();
+        let typesense_api_key: &str = CONFIG.typesense_api_key();
+        let typesense_addr: String = CONFIG.typesense_addr().to_owned();
         self.get(typesense_addr + uri)
             .header("X-TYPESENSE-API-KEY", typesense_api_key)
     }
 
     fn build_post(&self, uri: &str) -> reqwest::RequestBuilder {
-        let typesense_api_key: &str = &TYPESENSE_API_KEY;
-        let typesense_addr: String = TYPESENSE_ADDR.to_owned();
+        let typesense_api_key: &str = CONFIG.typesense_api_key();
+        let typesense_addr: String = CONFIG.typesense_addr().to_owned();
         self.post(typesense_addr + uri)
             .header("X-TYPESENSE-API-KEY", typesense_api_key)
     }
 
     fn build_delete(&self, uri: &str) -> reqwest::RequestBuilder {
-        let typesense_api_key: &str = &TYPESENSE_API_KEY;
-        let typesense_addr: String = TYPESENSE_ADDR.to_owned();
+        let typesense_api_key: &str = CONFIG.typesense_api_key();
+        let typesense_addr: String = CONFIG.typesense_addr().to_owned();
         self.delete(typesense_addr + uri)
             .header("X-TYPESENSE-API-KEY", typesense_api_key)
     }
 
     fn build_patch(&self, uri: &str) -> reqwest::RequestBuilder {
-        let typesense_api_key: &str = &TYPESENSE_API_KEY;
-        let typesense_addr: String = TYPESENSE_ADDR.to_owned();
+        let typesense_api_key: &str = CONFIG.typesense_api_key();
+        let typesense_addr: String = CONFIG.typesense_addr().to_owned();
         self.patch(typesense_addr + uri)
             .header("X-TYPESENSE-API-KEY", typesense_api_key)
     }
+
+    fn build_put(&self, uri: &str) -> reqwest::RequestBuilder {
+        let typesense_api_key: &str = CONFIG.typesense_api_key();
+        let typesense_addr: String = CONFIG.typesense_addr().to_owned();
+        self.put(typesense_addr + uri)
+            .header("X-TYPESENSE-API-KEY", typesense_api_key)
+    }
 }
 
 async fn create_typesense_collections() -> Result<(), reqwest::Error> {
     //create typesense collections
-    let collection_burrows = json!({
-        "name": "burrows",
-        "fields": [
-            {"name": "burrow_id", "type": "int64"},
-            {"name": "title", "type": "string", "locale": "zh"},
-            {"name": "description", "type": "string", "locale": "zh"},
-        ]
-    });
-    let collection_posts = json!({
-        "name": "posts",
-        "fields": [
-            {"name": "post_id", "type": "int64"},
-            {"name": "burrow_id", "type": "int64"},
-            {"name": "title", "type": "string", "locale": "zh"},
-            {"name": "section", "type": "string[]", "facet": true},
-            {"name": "tag", "type": "string[]", "facet": true},
-        ]
-    });
-    let collection_replies = json!({
-        "name": "replies",
-        "fields": [
-            {"name": "post_id", "type": "int64", "facet": true},
-            {"name": "reply_id", "type": "int32", "index": false , "optional": true},
-            {"name": "burrow_id", "type": "int64"},
-            {"name": "content", "type": "string", "locale": "zh"},
-        ]
-    });
     let client = reqwest::Client::new();
-    for each in [collection_burrows, collection_posts, collection_replies].iter() {
+    for collection in IndexCollection::all() {
+        // the collection has been rebuilt by reindex, and is reached by an alias
+        match get_alias(&client, collection.name()).await {
+            Ok(Some(name)) => {
+                log::warn!(
+                    "Collection {} is an alias of {}. Skip creation.",
+                    collection.name(),
+                    name
+                );
+                continue;
+            }
+            Ok(None) => (),
+            Err(e) => panic!("Err when get typesense alias,{}", e),
+        }
+        let each = collection.schema(collection.name());
         match client.build_post("/collections").json(&each).send().await {
             Ok(r) => match r.status().as_u16() {
                 201 => {
@@ -128,19 +137,13 @@ async fn create_typesense_collections() -> Result<(), reqwest::Error> {
     Ok(())
 }
 
-pub async fn pulsar_typesense() -> Result<(), pulsar::Error> {
-    // setup pulsar consumer
-    let pulsar_addr: String = PULSAR_ADDR.to_owned();
-    let addr = pulsar_addr;
-    let topic = "persistent://public/default/search".to_string();
-    let builder = Pulsar::builder(addr, TokioExecutor);
-    let pulsar: Pulsar<_> = builder.build().await?;
-    let mut consumer: Consumer<PulsarSearchData, _> = pulsar
-        .consumer()
-        .with_topic(topic)
-        .with_subscription_type(SubType::Exclusive)
-        .build()
+pub async fn pulsar_typesense() -> Result<(), QueueError> {
+    // setup message queue consumer
+    let queue = queue::connect(&CONFIG.databases.pulsar_mq.url).await?;
+    let mut consumer = queue
+        .subscribe(&[outbox::SEARCH_TOPIC], dead_letter::SUBSCRIPTION)
         .await?;
+    let dlq = DeadLetterQueue::new(queue, outbox::SEARCH_TOPIC);
 
     match create_typesense_collections().await {
         Ok(_) => {
@@ -151,276 +154,156 @@ pub async fn pulsar_typesense() -> Result<(), pulsar::Error> {
         }
     };
     let client = reqwest::Client::new();
-    while let Some(msg) = consumer.try_next().await? {
-        consumer.ack(&msg).await?;
-        // println!("metadata: {:?},id: {:?}", msg.metadata(), msg.message_id());
-        let data = match msg.deserialize() {
-            Ok(data) => data,
+    while let Some(delivery) = consumer.next().await? {
+        let result = match delivery.deserialize() {
+            Ok(data) => handle_search(&client, data).await,
             Err(e) => {
-                log::error!("could not deserialize message: {:?}", e);
-                continue;
+                log::error!("[PULSAR-SEARCH] Could not deserialize message: {:?}", e);
+                Err(TaskError::Fatal(e.to_string()))
             }
         };
-        match data {
-            PulsarSearchData::CreateBurrow(burrow) => {
-                let data: TypesenseBurrowData = burrow.into();
-                match client
-                    .build_post("/collections/burrows/documents")
-                    .json(&data)
-                    .send()
-                    .await
-                {
-                    Ok(r) => match r.status().as_u16() {
-                        201 => log::info!("[PULSAR-SEARCH] 201: Add new burrow."),
-                        409 => log::info!("[PULSAR-SEARCH] 409: Burrow already exist."),
-                        _ => log::error!(
-                            "[PULSAR-SEARCH] {}:  Failed to add new burrow. {}",
-                            r.status().as_u16(),
-                            r.text().await.unwrap()
-                        ),
-                    },
-                    Err(e) => log::error!("add new burrow failed {:?}", e),
-                }
-            }
-            PulsarSearchData::CreatePost(post) => {
-                let data: TypesensePostData = post.into();
+        dlq.settle(&mut *consumer, &delivery, result).await?;
+    }
+    Ok(())
+}
 
-                match client
-                    .build_post("/collections/posts/documents")
-                    .json(&data)
-                    .send()
-                    .await
-                {
-                    Ok(r) => match r.status().as_u16() {
-                        201 => log::info!("[PULSAR-SEARCH] 201: Add new post."),
-                        409 => log::info!("[PULSAR-SEARCH] 409: Post already exist."),
-                        _ => log::error!(
-                            "[PULSAR-SEARCH] {}:  Failed to add new post. {}",
-                            r.status().as_u16(),
-                            r.text().await.unwrap()
-                        ),
-                    },
-                    Err(e) => log::error!("add new post failed {:?}", e),
-                }
-            }
-            PulsarSearchData::CreateReply(reply) => {
-                let data: TypesenseReplyData = reply.into();
+/// Error of an unexpected response of typesense
+///
+/// Typesense responds 503 before it is ready, and 5xx or 429 may also succeed
+/// later. Other statuses mean the request is rejected.
+async fn typesense_error(r: reqwest::Response, action: &str) -> TaskError {
+    let status = r.status();
+    let message = format!(
+        "{}: Failed to {}. {}",
+        status.as_u16(),
+        action,
+        r.text().await.unwrap_or_default()
+    );
+    if status.is_server_error() || status.as_u16() == 429 {
+        TaskError::Retry(message)
+    } else {
+        TaskError::Fatal(message)
+    }
+}
 
-                match client
-                    .build_post("/collections/replies/documents")
-                    .json(&data)
-                    .send()
-                    .await
-                {
-                    Ok(r) => match r.status().as_u16() {
-                        201 => log::info!("[PULSAR-SEARCH] 201: Add new reply."),
-                        409 => log::info!("[PULSAR-SEARCH] 409: Reply already exist."),
-                        _ => log::error!(
-                            "[PULSAR-SEARCH] {}:  Failed to add new reply. {}",
-                            r.status().as_u16(),
-                            r.text().await.unwrap()
-                        ),
-                    },
-                    Err(e) => log::error!("add new reply failed {:?}", e),
-                }
-            }
-            PulsarSearchData::UpdateBurrow(burrow) => {
-                let burrow_id = burrow.burrow_id;
-                let data: TypesenseBurrowData = burrow.into();
-                match client
-                    .build_get(&format!("/collections/burrows/documents/{}", burrow_id))
-                    .send()
-                    .await
-                {
-                    Ok(r) => match r.status().as_u16() {
-                        200 => {
-                            let response = r.json::<TypesenseBurrowData>().await.unwrap();
-                            if response.update_time < data.update_time {
-                                match client
-                                    .build_patch(&format!(
-                                        "/collections/burrows/documents/{}",
-                                        burrow_id
-                                    ))
-                                    .json(&data)
-                                    .send()
-                                    .await
-                                {
-                                    Ok(r) => match r.status().as_u16() {
-                                        201 => log::info!("[PULSAR-SEARCH] 201: Update burrow."),
-                                        _ => log::error!(
-                                            "[PULSAR-SEARCH] {}:  Failed to update burrow. {}",
-                                            r.status().as_u16(),
-                                            r.text().await.unwrap()
-                                        ),
-                                    },
-                                    Err(e) => {
-                                        log::error!("[PULSAR-SEARCH] Update burrow failed {:?}", e)
-                                    }
-                                }
-                            } else {
-                                log::info!("[PULSAR-SEARCH] Burrow is up to date.");
-                            }
-                        }
-                        404 => log::info!("[PULSAR-SEARCH] 404: Burrow does not exist."),
-                        _ => log::error!(
-                            "[PULSAR-SEARCH] {}:  Failed to update burrow. {}",
-                            r.status().as_u16(),
-                            r.text().await.unwrap()
-                        ),
-                    },
-                    Err(e) => log::error!("[PULSAR-SEARCH] Update burrow failed {:?}", e),
-                }
-            }
-            PulsarSearchData::UpdatePost(post) => {
-                let post_id = post.post_id;
-                let data: TypesensePostData = post.into();
-                match client
-                    .build_get(&format!("/collections/posts/documents/{}", post_id))
-                    .send()
-                    .await
-                {
-                    Ok(r) => match r.status().as_u16() {
-                        200 => {
-                            let response = r.json::<TypesensePostData>().await.unwrap();
-                            if response.update_time < data.update_time {
-                                match client
-                                    .build_patch(&format!(
-                                        "/collections/posts/documents/{}",
-                                        post_id
-                                    ))
-                                    .json(&data)
-                                    .send()
-                                    .await
-                                {
-                                    Ok(r) => match r.status().as_u16() {
-                                        201 => log::info!("[PULSAR-SEARCH] 201: Update post."),
-                                        _ => log::error!(
-                                            "[PULSAR-SEARCH] {}:  Failed to update post. {}",
-                                            r.status().as_u16(),
-                                            r.text().await.unwrap()
-                                        ),
-                                    },
-                                    Err(e) => {
-                                        log::error!("[PULSAR-SEARCH] Update post failed {:?}", e)
-                                    }
-                                }
-                            } else {
-                                log::info!("[PULSAR-SEARCH] Post is up to date.");
-                            }
-                        }
-                        404 => log::info!("[PULSAR-SEARCH] 404: post does not exist."),
-                        _ => log::error!(
-                            "[PULSAR-SEARCH] {}:  Failed to update post. {}",
-                            r.status().as_u16(),
-                            r.text().await.unwrap()
-                        ),
-                    },
-                    Err(e) => log::error!("[PULSAR-SEARCH] Update post failed {:?}", e),
-                }
-            }
-            PulsarSearchData::UpdateReply(reply) => {
-                let post_id = reply.post_id;
-                let reply_id = reply.reply_id;
-                let data: TypesenseReplyData = reply.into();
-                match client
-                    .build_get(&format!(
-                        "/collections/replies/documents/{}-{}",
-                        post_id, reply_id
-                    ))
-                    .send()
-                    .await
-                {
-                    Ok(r) => match r.status().as_u16() {
-                        200 => {
-                            let response = r.json::<TypesenseReplyData>().await.unwrap();
-                            if response.update_time < data.update_time {
-                                match client
-                                    .build_patch(&format!(
-                                        "/collections/replies/documents/{}-{}",
-                                        post_id, reply_id
-                                    ))
-                                    .json(&data)
-                                    .send()
-                                    .await
-                                {
-                                    Ok(r) => match r.status().as_u16() {
-                                        201 => log::info!("[PULSAR-SEARCH] 201: Update reply."),
-                                        _ => log::error!(
-                                            "[PULSAR-SEARCH] {}:  Failed to update reply. {}",
-                                            r.status().as_u16(),
-                                            r.text().await.unwrap()
-                                        ),
-                                    },
-                                    Err(e) => {
-                                        log::error!("[PULSAR-SEARCH] Update reply failed {:?}", e)
-                                    }
-                                }
-                            } else {
-                                log::info!("[PULSAR-SEARCH] Reply is up to date.");
-                            }
-                        }
-                        404 => log::info!("[PULSAR-SEARCH] 404: reply does not exist."),
-                        _ => log::error!(
-                            "[PULSAR-SEARCH] {}:  Failed to update reply. {}",
-                            r.status().as_u16(),
-                            r.text().await.unwrap()
-                        ),
-                    },
-                    Err(e) => log::error!("[PULSAR-SEARCH] Update reply failed {:?}", e),
-                }
-            }
-            PulsarSearchData::DeleteBurrow(burrow_id) => {
-                match client
-                    .build_delete(&format!("/collections/burrows/documents/{}", burrow_id))
-                    .send()
-                    .await
-                {
-                    Ok(_) => log::info!("[PULSAR-SEARCH] Delete burrow successfully"),
-                    Err(e) => log::error!("[PULSAR-SEARCH] Delete burrow failed: {:?}", e),
-                }
-            }
-            PulsarSearchData::DeletePost(post_id) => {
-                match client
-                    .build_delete(&format!("/collections/posts/documents/{}", post_id))
-                    .send()
-                    .await
-                {
-                    Ok(_) => println!("[PULSAR-SEARCH] Delete post successfully"),
-                    Err(e) => println!("[PULSAR-SEARCH] Delete post failed: {:?}", e),
-                }
-            }
-            PulsarSearchData::DeleteReply(post_id, reply_id) => {
-                match client
-                    .build_delete(&format!(
-                        "/collections/replies/documents/{}-{}",
-                        post_id, reply_id
-                    ))
-                    .send()
-                    .await
-                {
-                    Ok(_) => println!("[PULSAR-SEARCH] Delete reply successfully"),
-                    Err(e) => println!("[PULSAR-SEARCH] Delete reply failed: {:?}", e),
+/// Add a document to a collection of typesense
+async fn create_document<T: Serialize>(
+    client: &reqwest::Client,
+    collection: &str,
+    document: &T,
+    kind: &str,
+) -> Result<(), TaskError> {
+    let r = client
+        .build_post(&format!("/collections/{}/documents", collection))
+        .json(document)
+        .send()
+        .await?;
+    match r.status().as_u16() {
+        201 => log::info!("[PULSAR-SEARCH] 201: Add new {}.", kind),
+        409 => log::info!("[PULSAR-SEARCH] 409: {} already exist.", kind),
+        _ => return Err(typesense_error(r, &format!("add new {}", kind)).await),
+    }
+    Ok(())
+}
+
+/// Update a document of typesense if it is older than `document`
+async fn update_document<T: Serialize + DeserializeOwned>(
+    client: &reqwest::Client,
+    uri: &str,
+    document: &T,
+    update_time: impl Fn(&T) -> DateTimeWithTimeZone,
+    kind: &str,
+) -> Result<(), TaskError> {
+    let r = client.build_get(uri).send().await?;
+    match r.status().as_u16() {
+        200 => {
+            let response = r.json::<T>().await?;
+            if update_time(&response) < update_time(document) {
+                let r = client.build_patch(uri).json(document).send().await?;
+                if r.status().is_success() {
+                    log::info!("[PULSAR-SEARCH] {}: Update {}.", r.status().as_u16(), kind);
+                } else {
+                    return Err(typesense_error(r, &format!("update {}", kind)).await);
                 }
+            } else {
+                log::info!("[PULSAR-SEARCH] {} is up to date.", kind);
             }
         }
+        404 => log::info!("[PULSAR-SEARCH] 404: {} does not exist.", kind),
+        _ => return Err(typesense_error(r, &format!("update {}", kind)).await),
     }
     Ok(())
 }
 
-pub async fn pulsar_relation() -> Result<(), pulsar::Error> {
-    // setup pulsar consumer
-    let pulsar_addr: String = PULSAR_ADDR.to_owned();
-    let addr = pulsar_addr;
-    let topic = "persistent://public/default/relation".to_string();
-    let builder = Pulsar::builder(addr, TokioExecutor);
-    let pulsar: Pulsar<_> = builder.build().await?;
-    let mut consumer: Consumer<PulsarRelationData, _> = pulsar
-        .consumer()
-        .with_topic(topic)
-        .with_subscription_type(SubType::Exclusive)
-        .build()
+/// Delete a document of typesense
+async fn delete_document(client: &reqwest::Client, uri: &str, kind: &str) -> Result<(), TaskError> {
+    let r = client.build_delete(uri).send().await?;
+    match r.status().as_u16() {
+        200 => log::info!("[PULSAR-SEARCH] Delete {} successfully", kind),
+        404 => log::info!("[PULSAR-SEARCH] 404: {} does not exist.", kind),
+        _ => return Err(typesense_error(r, &format!("delete {}", kind)).await),
+    }
+    Ok(())
+}
+
+/// Apply an event of the `search` topic to typesense
+pub async fn handle_search(
+    client: &reqwest::Client,
+    data: PulsarSearchData,
+) -> Result<(), TaskError> {
+    match data {
+        PulsarSearchData::CreateBurrow(burrow) => {
+            let data: TypesenseBurrowData = burrow.into();
+            create_document(client, "burrows", &data, "burrow").await
+        }
+        PulsarSearchData::CreatePost(post) => {
+            let data: TypesensePostData = post.into();
+            create_document(client, "posts", &data, "post").await
+        }
+        PulsarSearchData::CreateReply(reply) => {
+            let data: TypesenseReplyData = reply.into();
+            create_document(client, "replies", &data, "reply").await
+        }
+        PulsarSearchData::UpdateBurrow(burrow) => {
+            let uri = format!("/collections/burrows/documents/{}", burrow.burrow_id);
+            let data: TypesenseBurrowData = burrow.into();
+            update_document(client, &uri, &data, |d| d.update_time, "burrow").await
+        }
+        PulsarSearchData::UpdatePost(post) => {
+            let uri = format!("/collections/posts/documents/{}", post.post_id);
+            let data: TypesensePostData = post.into();
+            update_document(client, &uri, &data, |d| d.update_time, "post").await
+        }
+        PulsarSearchData::UpdateReply(reply) => {
+            let uri = format!(
+                "/collections/replies/documents/{}-{}",
+                reply.post_id, reply.reply_id
+            );
+            let data: TypesenseReplyData = reply.into();
+            update_document(client, &uri, &data, |d| d.update_time, "reply").await
+        }
+        PulsarSearchData::DeleteBurrow(burrow_id) => {
+            let uri = format!("/collections/burrows/documents/{}", burrow_id);
+            delete_document(client, &uri, "burrow").await
+        }
+        PulsarSearchData::DeletePost(post_id) => {
+            let uri = format!("/collections/posts/documents/{}", post_id);
+            delete_document(client, &uri, "post").await
+        }
+        PulsarSearchData::DeleteReply(post_id, reply_id) => {
+            let uri = format!("/collections/replies/documents/{}-{}", post_id, reply_id);
+            delete_document(client, &uri, "reply").await
+        }
+    }
+}
+
+pub async fn pulsar_relation() -> Result<(), QueueError> {
+    // setup message queue consumer
+    let queue = queue::connect(&CONFIG.databases.pulsar_mq.url).await?;
+    let mut consumer = queue
+        .subscribe(&[outbox::RELATION_TOPIC], dead_letter::SUBSCRIPTION)
         .await?;
-    let postgres_addr: &str = &POSTGRES_ADDR;
+    let postgres_addr: &str = &CONFIG.databases.pgdb.url;
     let db: DatabaseConnection = match Database::connect(postgres_addr).await {
         Ok(db) => db,
         Err(e) => {
@@ -428,178 +311,420 @@ pub async fn pulsar_relation() -> Result<(), pulsar::Error> {
             panic!("pulsar relation database connection failed");
         }
     };
-    while let Some(msg) = consumer.try_next().await? {
-        consumer.ack(&msg).await?;
-        let data = match msg.deserialize() {
-            Ok(data) => data,
+    let dlq = DeadLetterQueue::new(queue.clone(), outbox::RELATION_TOPIC);
+    while let Some(delivery) = consumer.next().await? {
+        let result = match delivery.deserialize() {
+            Ok(data) => handle_relation(&db, &*queue, data).await,
             Err(e) => {
                 log::error!("[PULSAR-RELATION] Could not deserialize message: {:?}", e);
-                continue;
+                Err(TaskError::Fatal(e.to_string()))
             }
         };
-        match data {
-            PulsarRelationData::ActivateLike(uid, post_id) => {
-                let like = user_like::ActiveModel {
-                    uid: Set(uid),
-                    post_id: Set(post_id),
-                };
-                match db
-                    .transaction::<_, (), DbErr>(|txn| {
-                        Box::pin(async move {
-                            like.insert(txn).await?;
-                            let update_res = ContentPost::update_many()
-                                .col_expr(
-                                    content_post::Column::LikeNum,
-                                    Expr::col(content_post::Column::LikeNum).add(1),
-                                )
-                                .filter(content_post::Column::PostId.eq(post_id))
-                                .exec(txn)
-                                .await?;
-                            if update_res.rows_affected != 1 {
-                                return Err(DbErr::RecordNotFound("post not found".to_string()));
-                            }
-                            Ok(())
-                        })
+        dlq.settle(&mut *consumer, &delivery, result).await?;
+    }
+    Ok(())
+}
+
+/// Drop a relation event failed to apply, unless it may succeed later
+///
+/// Relation events are delivered at least once, so a relation which already
+/// exists and a relation of a deleted post or burrow are expected and dropped.
+/// Other errors of the database are retried.
+fn relation_error(e: DbErr, action: &str) -> Result<(), TaskError> {
+    let dropped = match &e {
+        DbErr::RecordNotFound(_) | DbErr::Custom(_) => true,
+        DbErr::Exec(s) | DbErr::Query(s) => {
+            s.contains("duplicate key") || s.contains("foreign key constraint")
+        }
+        _ => false,
+    };
+    if dropped {
+        log::info!("[PULSAR-RELATION] {} skipped: {}", action, e);
+        Ok(())
+    } else {
+        log::warn!("[PULSAR-RELATION] {} failed {:?}", action, e);
+        Err(e.into())
+    }
+}
+
+/// The database error of a failed transaction
+fn transaction_error(e: TransactionError<DbErr>) -> DbErr {
+    match e {
+        TransactionError::Connection(e) | TransactionError::Transaction(e) => e,
+    }
+}
+
+/// Apply an event of the `relation` topic to the database
+async fn handle_relation(
+    db: &DatabaseConnection,
+    queue: &dyn MessageQueue,
+    data: PulsarRelationData,
+) -> Result<(), TaskError> {
+    match data {
+        PulsarRelationData::ActivateLike(uid, post_id) => {
+            let like = user_like::ActiveModel {
+                uid: Set(uid),
+                post_id: Set(post_id),
+            };
+            match db
+                .transaction::<_, (), DbErr>(|txn| {
+                    Box::pin(async move {
+                        like.insert(txn).await?;
+                        let update_res = ContentPost::update_many()
+                            .col_expr(
+                                content_post::Column::LikeNum,
+                                Expr::col(content_post::Column::LikeNum).add(1),
+                            )
+                            .filter(content_post::Column::PostId.eq(post_id))
+                            .exec(txn)
+                            .await?;
+                        if update_res.rows_affected != 1 {
+                            return Err(DbErr::RecordNotFound("post not found".to_string()));
+                        }
+                        Ok(())
                     })
-                    .await
-                {
-                    Ok(_) => log::info!("[PULSAR-RELATION] Insert like success"),
-                    Err(e) => log::error!("[PULSAR-RELATION] Insert like failed {:?}", e),
+                })
+                .await
+            {
+                Ok(_) => {
+                    log::info!("[PULSAR-RELATION] Insert like success");
+                    let msg = PulsarNotificationData::NewLike { uid, post_id };
+                    let _ = queue::publish_json(queue, outbox::NOTIFICATION_TOPIC, &msg).await;
                 }
+                Err(e) => return relation_error(transaction_error(e), "Insert like"),
             }
-            PulsarRelationData::DeactivateLike(uid, post_id) => {
-                let like = user_like::ActiveModel {
-                    uid: Set(uid),
-                    post_id: Set(post_id),
-                };
-                match like.delete(&db).await {
-                    Ok(res) => {
-                        log::info!(
-                            "[PULSAR-RELATION] Delete like success {}",
-                            res.rows_affected
-                        );
-                        if res.rows_affected == 1 {
-                            let _ = ContentPost::update_many()
-                                .col_expr(
-                                    content_post::Column::LikeNum,
-                                    Expr::col(content_post::Column::LikeNum).sub(1),
-                                )
-                                .filter(content_post::Column::PostId.eq(post_id))
-                                .exec(&db)
-                                .await;
-                        }
+        }
+        PulsarRelationData::DeactivateLike(uid, post_id) => {
+            let like = user_like::ActiveModel {
+                uid: Set(uid),
+                post_id: Set(post_id),
+            };
+            match like.delete(db).await {
+                Ok(res) => {
+                    log::info!(
+                        "[PULSAR-RELATION] Delete like success {}",
+                        res.rows_affected
+                    );
+                    if res.rows_affected == 1 {
+                        let _ = ContentPost::update_many()
+                            .col_expr(
+                                content_post::Column::LikeNum,
+                                Expr::col(content_post::Column::LikeNum).sub(1),
+                            )
+                            .filter(content_post::Column::PostId.eq(post_id))
+                            .exec(db)
+                            .await;
                     }
-                    Err(e) => log::error!("[PULSAR-RELATION] Delete like failed {:?}", e),
                 }
+                Err(e) => return relation_error(e, "Delete like"),
             }
-            PulsarRelationData::ActivateCollection(uid, post_id) => {
-                let collection = user_collection::ActiveModel {
-                    uid: Set(uid),
-                    post_id: Set(post_id),
-                    ..Default::default()
-                };
-                match db
-                    .transaction::<_, (), DbErr>(|txn| {
-                        Box::pin(async move {
-                            collection.insert(txn).await?;
-                            let update_res = ContentPost::update_many()
-                                .col_expr(
-                                    content_post::Column::CollectionNum,
-                                    Expr::col(content_post::Column::CollectionNum).add(1),
-                                )
-                                .filter(content_post::Column::PostId.eq(post_id))
-                                .exec(txn)
-                                .await?;
-                            if update_res.rows_affected != 1 {
-                                return Err(DbErr::RecordNotFound("post not found".to_string()));
-                            }
-                            Ok(())
-                        })
+        }
+        PulsarRelationData::ActivateCollection(uid, post_id) => {
+            let collection = user_collection::ActiveModel {
+                uid: Set(uid),
+                post_id: Set(post_id),
+                ..Default::default()
+            };
+            match db
+                .transaction::<_, (), DbErr>(|txn| {
+                    Box::pin(async move {
+                        collection.insert(txn).await?;
+                        let update_res = ContentPost::update_many()
+                            .col_expr(
+                                content_post::Column::CollectionNum,
+                                Expr::col(content_post::Column::CollectionNum).add(1),
+                            )
+                            .filter(content_post::Column::PostId.eq(post_id))
+                            .exec(txn)
+                            .await?;
+                        if update_res.rows_affected != 1 {
+                            return Err(DbErr::RecordNotFound("post not found".to_string()));
+                        }
+                        Ok(())
                     })
-                    .await
-                {
-                    Ok(_) => log::info!("[PULSAR-RELATION] Insert collection success"),
-                    Err(e) => log::error!("[PULSAR-RELATION] Insert collection failed {:?}", e),
-                }
+                })
+                .await
+            {
+                Ok(_) => log::info!("[PULSAR-RELATION] Insert collection success"),
+                Err(e) => return relation_error(transaction_error(e), "Insert collection"),
             }
-            PulsarRelationData::DeactivateCollection(uid, post_id) => {
-                let collection = user_collection::ActiveModel {
-                    uid: Set(uid),
-                    post_id: Set(post_id),
-                    ..Default::default()
-                };
-                match collection.delete(&db).await {
-                    Ok(res) => {
-                        log::info!(
-                            "[PULSAR-RELATION] Delete collection success {}",
-                            res.rows_affected
-                        );
-                        if res.rows_affected == 1 {
-                            let _ = ContentPost::update_many()
-                                .col_expr(
-                                    content_post::Column::CollectionNum,
-                                    Expr::col(content_post::Column::CollectionNum).sub(1),
-                                )
-                                .filter(content_post::Column::PostId.eq(post_id))
-                                .exec(&db)
-                                .await;
-                        }
+        }
+        PulsarRelationData::DeactivateCollection(uid, post_id) => {
+            let collection = user_collection::ActiveModel {
+                uid: Set(uid),
+                post_id: Set(post_id),
+                ..Default::default()
+            };
+            match collection.delete(db).await {
+                Ok(res) => {
+                    log::info!(
+                        "[PULSAR-RELATION] Delete collection success {}",
+                        res.rows_affected
+                    );
+                    if res.rows_affected == 1 {
+                        let _ = ContentPost::update_many()
+                            .col_expr(
+                                content_post::Column::CollectionNum,
+                                Expr::col(content_post::Column::CollectionNum).sub(1),
+                            )
+                            .filter(content_post::Column::PostId.eq(post_id))
+                            .exec(db)
+                            .await;
                     }
-                    Err(e) => log::error!("[PULSAR-RELATION] Delete collection failed {:?}", e),
                 }
+                Err(e) => return relation_error(e, "Delete collection"),
             }
-            PulsarRelationData::ActivateFollow(uid, burrow_id) => {
-                let follow = user_follow::ActiveModel {
-                    uid: Set(uid),
-                    burrow_id: Set(burrow_id),
-                    ..Default::default()
-                };
-                match db
-                    .transaction::<_, (), DbErr>(|txn| {
-                        Box::pin(async move {
-                            follow.insert(txn).await?;
-                            let res = Burrow::find_by_id(burrow_id).one(txn).await?;
-                            match res {
-                                Some(_) => Ok(()),
-                                None => Err(DbErr::RecordNotFound("burrow not found".to_string())),
-                            }
-                        })
+        }
+        PulsarRelationData::ActivateFollow(uid, burrow_id) => {
+            let follow = user_follow::ActiveModel {
+                uid: Set(uid),
+                burrow_id: Set(burrow_id),
+                ..Default::default()
+            };
+            match db
+                .transaction::<_, (), DbErr>(|txn| {
+                    Box::pin(async move {
+                        follow.insert(txn).await?;
+                        let res = Burrow::find_by_id(burrow_id).one(txn).await?;
+                        match res {
+                            Some(_) => Ok(()),
+                            None => Err(DbErr::RecordNotFound("burrow not found".to_string())),
+                        }
                     })
-                    .await
-                {
-                    Ok(_) => log::info!("[PULSAR-RELATION] Insert follow success"),
-                    Err(e) => log::error!("[PULSAR-RELATION] Insert follow failed {:?}", e),
+                })
+                .await
+            {
+                Ok(_) => log::info!("[PULSAR-RELATION] Insert follow success"),
+                Err(e) => return relation_error(transaction_error(e), "Insert follow"),
+            }
+        }
+        PulsarRelationData::DeactivateFollow(uid, burrow_id) => {
+            let follow = user_follow::ActiveModel {
+                uid: Set(uid),
+                burrow_id: Set(burrow_id),
+                ..Default::default()
+            };
+            match follow.delete(db).await {
+                Ok(res) => {
+                    log::info!(
+                        "[PULSAR-RELATION] Delete follow success {}",
+                        res.rows_affected
+                    );
                 }
+                Err(e) => return relation_error(e, "Delete follow"),
             }
-            PulsarRelationData::DeactivateFollow(uid, burrow_id) => {
-                let follow = user_follow::ActiveModel {
+        }
+        PulsarRelationData::Vote(uid, post_id, choices) => {
+            let voter = poll_voter::ActiveModel {
+                post_id: Set(post_id),
+                uid: Set(uid),
+            };
+            let votes: Vec<poll_vote::ActiveModel> = choices
+                .into_iter()
+                .map(|option_id| poll_vote::ActiveModel {
+                    post_id: Set(post_id),
                     uid: Set(uid),
-                    burrow_id: Set(burrow_id),
-                    ..Default::default()
-                };
-                match follow.delete(&db).await {
-                    Ok(res) => {
+                    option_id: Set(option_id),
+                })
+                .collect();
+            match db
+                .transaction::<_, (), DbErr>(|txn| {
+                    Box::pin(async move {
+                        // a user can only vote once, whatever options are chosen, the
+                        // primary key of the voter rejects a second vote even if it
+                        // is written at the same time
+                        PollVoter::insert(voter).exec(txn).await?;
+                        PollVote::insert_many(votes).exec(txn).await?;
+                        Ok(())
+                    })
+                })
+                .await
+            {
+                Ok(_) => log::info!("[PULSAR-RELATION] Insert vote success"),
+                Err(e) => match transaction_error(e) {
+                    DbErr::Exec(s) | DbErr::Query(s) if s.contains("duplicate key") => {
                         log::info!(
-                            "[PULSAR-RELATION] Delete follow success {}",
-                            res.rows_affected
+                            "[PULSAR-RELATION] Insert vote skipped: {:?} of {} in poll {}",
+                            ErrorCode::VoteDuplicate,
+                            uid,
+                            post_id
                         );
                     }
-                    Err(e) => log::error!("[PULSAR-RELATION] Delete follow failed {:?}", e),
+                    e => return relation_error(e, "Insert vote"),
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn get_burrow_owner<C>(db: &C, burrow_id: i64) -> Result<Option<i64>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Ok(Burrow::find_by_id(burrow_id).one(db).await?.map(|b| b.uid))
+}
+
+async fn get_post_owner<C>(db: &C, post_id: i64) -> Result<Option<(i64, i64)>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    match ContentPost::find_by_id(post_id).one(db).await? {
+        Some(post) => Ok(get_burrow_owner(db, post.burrow_id)
+            .await?
+            .map(|uid| (uid, post.burrow_id))),
+        None => Ok(None),
+    }
+}
+
+async fn insert_notifications(
+    db: &DatabaseConnection,
+    notifications: Vec<notification::ActiveModel>,
+) -> Result<(), DbErr> {
+    if !notifications.is_empty() {
+        Notification::insert_many(notifications).exec(db).await?;
+    }
+    Ok(())
+}
+
+async fn save_notification(
+    db: &DatabaseConnection,
+    data: PulsarNotificationData,
+) -> Result<(), DbErr> {
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let build = |uid: i64, kind: NotificationKind, burrow_id: i64, post_id: i64, reply_id: i32| {
+        notification::ActiveModel {
+            uid: Set(uid),
+            notification_type: Set(kind.to_i32()),
+            burrow_id: Set(burrow_id),
+            post_id: Set(post_id),
+            reply_id: Set(reply_id),
+            is_read: Set(false),
+            create_time: Set(now),
+            ..Default::default()
+        }
+    };
+    match data {
+        PulsarNotificationData::NewReply {
+            post_id,
+            reply_id,
+            burrow_id,
+            parent_reply_id,
+        } => {
+            let replier = get_burrow_owner(db, burrow_id).await?;
+            let mut notifications = Vec::new();
+            // the author of the answered reply gets a more specific notification
+            let parent_author = match parent_reply_id {
+                Some(parent_reply_id) => {
+                    match ContentReply::find_by_id((post_id, parent_reply_id))
+                        .one(db)
+                        .await?
+                    {
+                        Some(parent) => get_burrow_owner(db, parent.burrow_id).await?,
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+            if let Some(uid) = parent_author {
+                if Some(uid) != replier {
+                    notifications.push(build(
+                        uid,
+                        NotificationKind::ReplyToReply,
+                        burrow_id,
+                        post_id,
+                        reply_id,
+                    ));
+                }
+            }
+            if let Some((uid, _)) = get_post_owner(db, post_id).await? {
+                if Some(uid) != replier && Some(uid) != parent_author {
+                    notifications.push(build(
+                        uid,
+                        NotificationKind::Reply,
+                        burrow_id,
+                        post_id,
+                        reply_id,
+                    ));
                 }
             }
+            insert_notifications(db, notifications).await
+        }
+        PulsarNotificationData::NewPost { post_id, burrow_id } => {
+            let owner = get_burrow_owner(db, burrow_id).await?;
+            let notifications = UserFollow::find()
+                .filter(user_follow::Column::BurrowId.eq(burrow_id))
+                .all(db)
+                .await?
+                .iter()
+                .filter(|f| Some(f.uid) != owner)
+                .map(|f| build(f.uid, NotificationKind::FollowedPost, burrow_id, post_id, 0))
+                .collect();
+            insert_notifications(db, notifications).await
+        }
+        PulsarNotificationData::NewLike { uid, post_id } => {
+            // the liker is never recorded, so that likes stay anonymous
+            match get_post_owner(db, post_id).await? {
+                Some((author, burrow_id)) if author != uid => {
+                    insert_notifications(
+                        db,
+                        vec![build(author, NotificationKind::Like, burrow_id, post_id, 0)],
+                    )
+                    .await
+                }
+                _ => Ok(()),
+            }
         }
     }
+}
 
+pub async fn pulsar_notification() -> Result<(), QueueError> {
+    // setup message queue consumer
+    let queue = queue::connect(&CONFIG.databases.pulsar_mq.url).await?;
+    let mut consumer = queue
+        .subscribe(&[outbox::NOTIFICATION_TOPIC], dead_letter::SUBSCRIPTION)
+        .await?;
+    let postgres_addr: &str = &CONFIG.databases.pgdb.url;
+    let db: DatabaseConnection = match Database::connect(postgres_addr).await {
+        Ok(db) => db,
+        Err(e) => {
+            log::error!("[PULSAR-NOTIFICATION] Database Error {:?}", e);
+            panic!("pulsar notification database connection failed");
+        }
+    };
+    let dlq = DeadLetterQueue::new(queue, outbox::NOTIFICATION_TOPIC);
+    while let Some(delivery) = consumer.next().await? {
+        let result = match delivery.deserialize() {
+            Ok(data) => match save_notification(&db, data).await {
+                Ok(_) => {
+                    log::info!("[PULSAR-NOTIFICATION] Insert notification success");
+                    Ok(())
+                }
+                Err(e) => {
+                    log::warn!("[PULSAR-NOTIFICATION] Insert notification failed {:?}", e);
+                    Err(e.into())
+                }
+            },
+            Err(e) => {
+                log::error!(
+                    "[PULSAR-NOTIFICATION] Could not deserialize message: {:?}",
+                    e
+                );
+                Err(TaskError::Fatal(e.to_string()))
+            }
+        };
+        dlq.settle(&mut *consumer, &delivery, result).await?;
+    }
     Ok(())
 }
 
 pub async fn generate_trending() -> redis::RedisResult<()> {
     // setup pulsar consumer
-    let redis_addr: String = REDIS_ADDR.to_owned();
+    let redis_addr: String = CONFIG.databases.redis.url.to_owned();
     let addr = redis_addr;
     let client = redis::Client::open(addr)?;
     let mut kv_conn = client.get_async_connection().await?;
-    let postgres_addr: &str = &POSTGRES_ADDR;
+    let postgres_addr: &str = &CONFIG.databases.pgdb.url;
     let pg_con: DatabaseConnection = match Database::connect(postgres_addr).await {
         Ok(db) => db,
         Err(e) => {
@@ -623,6 +748,101 @@ pub async fn generate_trending() -> redis::RedisResult<()> {
     }
 }
 
+/// Lift an expired ban and mark its record as expired
+///
+/// A burrow visible again is put back into search through the outbox.
+async fn lift_ban(
+    pg_con: &DatabaseConnection,
+    record: ban_record::Model,
+) -> Result<(), TransactionError<DbErr>> {
+    pg_con
+        .transaction::<_, (), DbErr>(|txn| {
+            Box::pin(async move {
+                let target_type = record.target_type;
+                let target_id = record.target_id;
+                let mut rec: ban_record::ActiveModel = record.into();
+                rec.ban_state = Set(BanState::Expired.to_i32());
+                rec.update(txn).await?;
+                match BanTarget::from_i32(target_type) {
+                    Some(BanTarget::User) => {
+                        UserStatus::update_many()
+                            .col_expr(user_status::Column::UserState, Expr::value(0))
+                            .filter(user_status::Column::Uid.eq(target_id))
+                            .filter(user_status::Column::UserState.eq(1))
+                            .exec(txn)
+                            .await?;
+                        Ok(())
+                    }
+                    Some(BanTarget::Burrow) => {
+                        let burrow = match Burrow::find_by_id(target_id).one(txn).await? {
+                            Some(burrow) if is_banned(burrow.burrow_state) => burrow,
+                            _ => return Ok(()),
+                        };
+                        let burrow_state = burrow.burrow_state;
+                        let mut bst: burrow::ActiveModel = burrow.into();
+                        bst.burrow_state = Set(burrow_state - 1);
+                        let bst = bst.update(txn).await?;
+                        // discarded burrows stay out of search
+                        if !is_discarded(burrow_state) {
+                            let msg = PulsarSearchData::CreateBurrow(PulsarSearchBurrowData {
+                                burrow_id: bst.burrow_id,
+                                title: bst.title,
+                                description: bst.description,
+                                update_time: bst.update_time,
+                            });
+                            outbox::enqueue_search(txn, msg).await?;
+                        }
+                        Ok(())
+                    }
+                    None => {
+                        log::error!("[BAN-EXPIRE] Unknown ban target type {}", target_type);
+                        Ok(())
+                    }
+                }
+            })
+        })
+        .await
+}
+
+pub async fn lift_expired_bans() -> Result<(), QueueError> {
+    let postgres_addr: &str = &CONFIG.databases.pgdb.url;
+    let pg_con: DatabaseConnection = match Database::connect(postgres_addr).await {
+        Ok(db) => db,
+        Err(e) => {
+            log::error!("[BAN-EXPIRE] Database Error{:?}", e);
+            panic!("ban expire database connection failed");
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(CONFIG.admin.ban_expire_interval));
+    loop {
+        interval.tick().await;
+        let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+        let records = match BanRecord::find()
+            .filter(ban_record::Column::BanState.eq(BanState::Active.to_i32()))
+            .filter(ban_record::Column::ExpireTime.lte(now))
+            .all(&pg_con)
+            .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                log::error!("[BAN-EXPIRE] Database Error: {:?}", e);
+                continue;
+            }
+        };
+        for record in records {
+            let ban_id = record.ban_id;
+            match lift_ban(&pg_con, record).await {
+                Ok(_) => {
+                    log::info!("[BAN-EXPIRE] Lift ban {}", ban_id);
+                }
+                Err(e) => {
+                    log::error!("[BAN-EXPIRE] Failed to lift ban {}: {:?}", ban_id, e);
+                }
+            }
+        }
+    }
+}
+
 async fn get_set_redis(
     kv_conn: &mut redis::aio::Connection,
     email: &str,
@@ -651,9 +871,9 @@ async fn get_set_redis(
     Ok("Success".to_string())
 }
 
-pub async fn pulsar_email() -> Result<(), pulsar::Error> {
+pub async fn pulsar_email() -> Result<(), QueueError> {
     // setup pulsar consumer
-    let redis_addr: String = REDIS_ADDR.to_owned();
+    let redis_addr: String = CONFIG.databases.redis.url.to_owned();
     let addr = redis_addr;
     let client = match redis::Client::open(addr) {
         Ok(c) => c,
@@ -662,93 +882,238 @@ pub async fn pulsar_email() -> Result<(), pulsar::Error> {
         }
     };
     let mut kv_conn = match client.get_async_connection().await {
-        Ok(c) => c,
+        Ok(c) => Some(c),
         Err(e) => {
             panic!("[PULSAR-EMAIL] Redis Error: {:?}", e);
         }
     };
-    let pulsar_addr: String = PULSAR_ADDR.to_owned();
-    let addr = pulsar_addr;
-    let topic = "persistent://public/default/email".to_string();
-    let builder = Pulsar::builder(addr, TokioExecutor);
-    let pulsar: Pulsar<_> = builder.build().await?;
-    let mut consumer: Consumer<PulsarSendEmail, _> = pulsar
-        .consumer()
-        .with_topic(topic)
-        .with_subscription_type(SubType::Exclusive)
-        .build()
+    let queue = queue::connect(&CONFIG.databases.pulsar_mq.url).await?;
+    let mut consumer = queue
+        .subscribe(&[outbox::EMAIL_TOPIC], dead_letter::SUBSCRIPTION)
         .await?;
-    while let Some(msg) = consumer.try_next().await? {
-        consumer.ack(&msg).await?;
-        let data = match msg.deserialize() {
-            Ok(data) => data,
+    let dlq = DeadLetterQueue::new(queue, outbox::EMAIL_TOPIC);
+    while let Some(delivery) = consumer.next().await? {
+        let result = match delivery.deserialize() {
+            Ok(data) => {
+                if kv_conn.is_none() {
+                    kv_conn = match client.get_async_connection().await {
+                        Ok(c) => Some(c),
+                        Err(e) => {
+                            log::error!("[PULSAR-EMAIL] Redis Error: {:?}", e);
+                            None
+                        }
+                    };
+                }
+                match kv_conn.as_mut() {
+                    Some(conn) => handle_email(conn, data).await,
+                    None => Err(TaskError::Retry("redis is unavailable".to_string())),
+                }
+            }
             Err(e) => {
-                log::error!("[PULSAR-RELATION] Could not deserialize message: {:?}", e);
-                continue;
+                log::error!("[PULSAR-EMAIL] Could not deserialize message: {:?}", e);
+                Err(TaskError::Fatal(e.to_string()))
             }
         };
-        let (email, repeat_times) = match data {
-            PulsarSendEmail::Sign { email } => (email, 6),
-            PulsarSendEmail::Reset { email } => (email, 10),
-        };
-        if *BACKEND_TEST_MODE {
-            let verification_code = "6".repeat(repeat_times);
-            match get_set_redis(&mut kv_conn, &email, &verification_code).await {
-                Ok(_) => log::info!("[PULSAR-EMAIL] Redis get & set success, Email send success"),
-                Err(e) => match e.kind() {
-                    redis::ErrorKind::ExtensionError => {
-                        log::info!("[PULSAR-EMAIL] User sent too many emails, refuse to send");
-                        continue;
+        // only errors of redis are retried, reconnect before the next try
+        if matches!(result, Err(TaskError::Retry(_))) {
+            kv_conn = None;
+        }
+        dlq.settle(&mut *consumer, &delivery, result).await?;
+    }
+    Ok(())
+}
+
+/// Send a verification email of the `email` topic
+///
+/// A failed email is dead-lettered at once rather than retried, since each try
+/// counts against the rate limit of the address.
+async fn handle_email(
+    kv_conn: &mut redis::aio::Connection,
+    data: PulsarSendEmail,
+) -> Result<(), TaskError> {
+    let (email, repeat_times) = match data {
+        PulsarSendEmail::Sign { email } => (email, 6),
+        PulsarSendEmail::Reset { email } => (email, 10),
+    };
+    if *BACKEND_TEST_MODE {
+        let verification_code = "6".repeat(repeat_times);
+        match get_set_redis(kv_conn, &email, &verification_code).await {
+            Ok(_) => log::info!("[PULSAR-EMAIL] Redis get & set success, Email send success"),
+            Err(e) => match e.kind() {
+                redis::ErrorKind::ExtensionError => {
+                    log::info!("[PULSAR-EMAIL] User sent too many emails, refuse to send");
+                }
+                _ => {
+                    log::error!("[PULSAR-EMAIL] Redis get/set failed {:?}", e);
+                    return Err(e.into());
+                }
+            },
+        }
+    } else if check_email_exist(&email).await.0 {
+        // generate verification code
+        let verification_code: String = std::iter::repeat(())
+            .map(|()| thread_rng().sample(Alphanumeric))
+            .map(char::from)
+            .take(repeat_times)
+            .collect();
+        match get_set_redis(kv_conn, &email, &verification_code).await {
+            Ok(_) => {
+                log::info!("[PULSAR-EMAIL] Redis get & set success");
+                match email::send(email, verification_code).await {
+                    Ok(res) => {
+                        log::info!("[PULSAR-EMAIL] Email send success, response: {}", res);
                     }
-                    _ => {
-                        log::error!("[PULSAR-EMAIL] Redis get/set failed {:?}", e);
-                        continue;
+                    Err(e) => {
+                        log::error!("[PULSAR-EMAIL] Email send failed: {}", e);
+                        return Err(TaskError::Fatal(e.to_string()));
                     }
-                },
+                };
             }
-        } else if check_email_exist(&email).await.0 {
-            // generate verification code
-            let verification_code: String = std::iter::repeat(())
-                .map(|()| thread_rng().sample(Alphanumeric))
-                .map(char::from)
-                .take(repeat_times)
-                .collect();
-            // println!("{}", verification_code);
-            match get_set_redis(&mut kv_conn, &email, &verification_code).await {
-                Ok(_) => {
-                    log::info!("[PULSAR-EMAIL] Redis get & set success");
-                    match email::send(email, verification_code).await {
-                        Ok(res) => {
-                            log::info!("[PULSAR-EMAIL] Email send success, response: {}", res);
-                            // println!("{}", res);
-                        }
-                        Err(e) => {
-                            log::error!("[PULSAR-EMAIL] Email send failed: {}", e);
-                        }
-                    };
+            Err(e) => match e.kind() {
+                redis::ErrorKind::ExtensionError => {
+                    log::info!("[PULSAR-EMAIL] User sent too many emails, refuse to send");
                 }
-                Err(e) => match e.kind() {
-                    redis::ErrorKind::ExtensionError => {
-                        log::info!("[PULSAR-EMAIL] User sent too many emails, refuse to send");
-                        continue;
-                    }
-                    _ => {
-                        log::error!("[PULSAR-EMAIL] Redis get/set failed {:?}", e);
-                        continue;
+                _ => {
+                    log::error!("[PULSAR-EMAIL] Redis get/set failed {:?}", e);
+                    return Err(e.into());
+                }
+            },
+        }
+    } else {
+        let verification_code = "6".repeat(repeat_times);
+        let set_redis_result: Result<String, redis::RedisError> = redis::cmd("SETEX")
+            .arg(&email)
+            .arg(EMAIL_TOKEN_EX)
+            .arg((SEND_EMAIL_LIMIT + 1).to_string() + ":" + &verification_code)
+            .query_async(kv_conn)
+            .await;
+        match set_redis_result {
+            Ok(_) => log::info!("[PULSAR-EMAIL] Redis set success"),
+            Err(e) => {
+                log::error!("[PULSAR-EMAIL] Redis set failed {:?}", e);
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Publish a batch of pending events in the outbox and mark them sent
+///
+/// Stops at the first event failed to publish, which is retried by the next batch.
+/// The caller holds the lock of the relay, see `outbox::try_lock_relay`.
+///
+/// ## Returns
+///
+/// Number of events published.
+pub async fn relay_outbox(
+    pg_con: &DatabaseConnection,
+    queue: &dyn MessageQueue,
+) -> Result<usize, DbErr> {
+    let rows = outbox::find_pending(pg_con, CONFIG.outbox.batch_size).await?;
+    let mut sent = Vec::new();
+    for row in rows.iter() {
+        match outbox::publish(queue, row).await {
+            Ok(_) => sent.push(row.id),
+            Err(e) => {
+                log::error!("[OUTBOX] Failed to publish event {}: {:?}", row.id, e);
+                break;
+            }
+        }
+    }
+    let count = sent.len();
+    outbox::mark_sent(pg_con, sent).await?;
+    Ok(count)
+}
+
+pub async fn pulsar_outbox() -> Result<(), QueueError> {
+    // setup message queue producer
+    let queue = queue::connect(&CONFIG.databases.pulsar_mq.url).await?;
+    // a single session, which takes and releases the advisory lock of the relay
+    let mut options = ConnectOptions::new(CONFIG.databases.pgdb.url.to_owned());
+    options.max_connections(1);
+    let pg_con: DatabaseConnection = match Database::connect(options).await {
+        Ok(db) => db,
+        Err(e) => {
+            log::error!("[OUTBOX] Database Error{:?}", e);
+            panic!("outbox relay database connection failed");
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_millis(CONFIG.outbox.relay_interval));
+    let mut last_purge = Instant::now();
+    loop {
+        interval.tick().await;
+        match outbox::try_lock_relay(&pg_con).await {
+            Ok(true) => {}
+            // another replica is relaying
+            Ok(false) => continue,
+            Err(e) => {
+                log::error!("[OUTBOX] Database Error: {:?}", e);
+                continue;
+            }
+        }
+        // keep publishing until the outbox is drained or an event fails
+        loop {
+            match relay_outbox(&pg_con, &*queue).await {
+                Ok(count) if count as u64 == CONFIG.outbox.batch_size => continue,
+                Ok(count) => {
+                    if count > 0 {
+                        log::info!("[OUTBOX] Published {} events", count);
                     }
-                },
+                    break;
+                }
+                Err(e) => {
+                    log::error!("[OUTBOX] Database Error: {:?}", e);
+                    break;
+                }
             }
-        } else {
-            let verification_code = "6".repeat(repeat_times);
-            let set_redis_result: Result<String, redis::RedisError> = redis::cmd("SETEX")
-                .arg(&email)
-                .arg(EMAIL_TOKEN_EX)
-                .arg((SEND_EMAIL_LIMIT + 1).to_string() + ":" + &verification_code)
-                .query_async(&mut kv_conn)
-                .await;
-            match set_redis_result {
-                Ok(_) => log::info!("[PULSAR-EMAIL] Redis set success"),
-                Err(e) => log::error!("[PULSAR-EMAIL] Redis set failed {:?}", e),
+        }
+        if let Err(e) = outbox::unlock_relay(&pg_con).await {
+            log::error!("[OUTBOX] Database Error: {:?}", e);
+        }
+        // sent events are only kept for troubleshooting, purge them once an hour
+        if last_purge.elapsed() >= Duration::from_secs(60 * 60) {
+            last_purge = Instant::now();
+            match outbox::purge_sent(&pg_con, CONFIG.outbox.retention).await {
+                Ok(count) => log::info!("[OUTBOX] Purged {} sent events", count),
+                Err(e) => log::error!("[OUTBOX] Database Error: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Archive the dead letters of all the consumers into table `dead_letter`
+///
+/// Admins inspect the archived dead letters and replay them, see
+/// `routes::admin::get_dead_letters` and `routes::admin::replay_dead_letter`.
+pub async fn pulsar_dead_letter() -> Result<(), QueueError> {
+    // setup message queue consumer
+    let queue = queue::connect(&CONFIG.databases.pulsar_mq.url).await?;
+    let topics: Vec<String> = dead_letter::CONSUMER_TOPICS
+        .iter()
+        .map(|topic| dead_letter::dead_letter_topic(topic))
+        .collect();
+    let topics: Vec<&str> = topics.iter().map(|topic| topic.as_str()).collect();
+    let mut consumer = queue.subscribe(&topics, dead_letter::SUBSCRIPTION).await?;
+    let postgres_addr: &str = &CONFIG.databases.pgdb.url;
+    let db: DatabaseConnection = match Database::connect(postgres_addr).await {
+        Ok(db) => db,
+        Err(e) => {
+            log::error!("[DEAD-LETTER] Database Error {:?}", e);
+            panic!("dead letter database connection failed");
+        }
+    };
+    while let Some(delivery) = consumer.next().await? {
+        match dead_letter::archive(&db, &delivery).await {
+            Ok(_) => {
+                log::info!("[DEAD-LETTER] Archived a dead letter of {}", delivery.topic);
+                consumer.ack(&delivery).await?;
+            }
+            Err(e) => {
+                log::error!("[DEAD-LETTER] Database Error {:?}", e);
+                consumer
+                    .nack(&delivery, Duration::from_millis(CONFIG.retry.max_delay))
+                    .await?;
             }
         }
     }
@@ -0,0 +1,210 @@
+//! Module of the transactional outbox
+//!
+//! Routes do not send search, relation and notification events to the message
+//! queue directly.
+//! The events are inserted into table `outbox` with the same connection or
+//! transaction as the content change, so that an event is kept if and only if the
+//! change is committed.
+//! The relay `utils::mq::pulsar_outbox` run by task-executor publishes the pending
+//! rows to the message queue in the order of ids and then marks them sent. No
+//! transaction is held open while publishing, instead the relay holds an advisory
+//! lock, so that only one replica of task-executor relays at a time and the events
+//! keep their order.
+//!
+//! The delivery is at-least-once: a row published right before the relay fails to
+//! mark it is published again, so consumers of the topics must tolerate duplicates.
+//!
+//! Dead letters replayed by admins are also sent back through the outbox, see
+//! `utils::dead_letter`.
+
+use std::collections::HashMap;
+
+use chrono::{FixedOffset, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{entity::*, query::*, ConnectionTrait, DbBackend, DbErr, Statement};
+use serde::Serialize;
+
+use super::queue::{MessageQueue, QueueError};
+use crate::db::{outbox, prelude::*};
+use crate::models::pulsar::{PulsarNotificationData, PulsarRelationData, PulsarSearchData};
+
+/// Topic of the events consumed by `utils::mq::pulsar_typesense`
+pub const SEARCH_TOPIC: &str = "search";
+
+/// Topic of the events consumed by `utils::mq::pulsar_relation`
+pub const RELATION_TOPIC: &str = "relation";
+
+/// Topic of the events consumed by `utils::mq::pulsar_email`
+pub const EMAIL_TOPIC: &str = "email";
+
+/// Topic of the events consumed by `utils::mq::pulsar_notification`
+pub const NOTIFICATION_TOPIC: &str = "notification";
+
+/// Insert an event into the outbox
+///
+/// ## Parameters
+///
+/// - `db`: Postgres connection or transaction, use the transaction of the content
+///   change so that they are committed together.
+/// - `topic`: Short name of the topic, e.g. `SEARCH_TOPIC`.
+/// - `msg`: The event, serialized as json like `SerializeMessage` of pulsar.
+///
+/// ## Errors
+///
+/// - `DbErr::Custom` if the event can not be serialized.
+/// - `DbErr` of the insertion.
+pub async fn enqueue<C: ConnectionTrait, T: Serialize>(
+    db: &C,
+    topic: &str,
+    msg: &T,
+) -> Result<(), DbErr> {
+    let payload = serde_json::to_string(msg).map_err(|e| DbErr::Custom(e.to_string()))?;
+    enqueue_payload(db, topic, payload).await
+}
+
+/// Insert an event which has been serialized into the outbox
+pub async fn enqueue_payload<C: ConnectionTrait>(
+    db: &C,
+    topic: &str,
+    payload: String,
+) -> Result<(), DbErr> {
+    let row = outbox::ActiveModel {
+        topic: Set(topic.to_string()),
+        payload: Set(payload),
+        create_time: Set(Utc::now().with_timezone(&FixedOffset::east(8 * 3600))),
+        sent_time: Set(None),
+        ..Default::default()
+    };
+    row.insert(db).await?;
+    Ok(())
+}
+
+/// Insert an event of the `search` topic into the outbox
+pub async fn enqueue_search<C: ConnectionTrait>(
+    db: &C,
+    msg: PulsarSearchData,
+) -> Result<(), DbErr> {
+    enqueue(db, SEARCH_TOPIC, &msg).await
+}
+
+/// Insert an event of the `relation` topic into the outbox
+pub async fn enqueue_relation<C: ConnectionTrait>(
+    db: &C,
+    msg: PulsarRelationData,
+) -> Result<(), DbErr> {
+    enqueue(db, RELATION_TOPIC, &msg).await
+}
+
+/// Insert an event of the `notification` topic into the outbox
+pub async fn enqueue_notification<C: ConnectionTrait>(
+    db: &C,
+    msg: PulsarNotificationData,
+) -> Result<(), DbErr> {
+    enqueue(db, NOTIFICATION_TOPIC, &msg).await
+}
+
+/// Key of the advisory lock held by the relay
+const RELAY_LOCK: i64 = 0x6f75_7462_6f78;
+
+/// Try to take the lock of the relay, held by the session until `unlock_relay`
+///
+/// ## Parameters
+///
+/// - `db`: Postgres connection with a single session, so that the lock is taken
+///   and released by the same session.
+///
+/// ## Returns
+///
+/// `false` if another relay holds the lock.
+pub async fn try_lock_relay<C: ConnectionTrait>(db: &C) -> Result<bool, DbErr> {
+    let row = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_try_advisory_lock($1) AS locked",
+            vec![RELAY_LOCK.into()],
+        ))
+        .await?;
+    match row {
+        Some(row) => row.try_get("", "locked"),
+        None => Ok(false),
+    }
+}
+
+/// Release the lock taken by `try_lock_relay`
+pub async fn unlock_relay<C: ConnectionTrait>(db: &C) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT pg_advisory_unlock($1)",
+        vec![RELAY_LOCK.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Get the oldest pending events
+///
+/// ## Parameters
+///
+/// - `db`: Postgres connection.
+/// - `limit`: Max number of rows.
+pub async fn find_pending<C: ConnectionTrait>(
+    db: &C,
+    limit: u64,
+) -> Result<Vec<outbox::Model>, DbErr> {
+    Outbox::find()
+        .filter(outbox::Column::SentTime.is_null())
+        .order_by_asc(outbox::Column::Id)
+        .limit(limit)
+        .all(db)
+        .await
+}
+
+/// Mark events as sent
+pub async fn mark_sent<C: ConnectionTrait>(db: &C, ids: Vec<i64>) -> Result<u64, DbErr> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let now = Utc::now().with_timezone(&FixedOffset::east(8 * 3600));
+    let res = Outbox::update_many()
+        .col_expr(outbox::Column::SentTime, Expr::value(now))
+        .filter(outbox::Column::Id.is_in(ids))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
+
+/// Delete events sent more than `retention` seconds ago
+pub async fn purge_sent<C: ConnectionTrait>(db: &C, retention: i64) -> Result<u64, DbErr> {
+    let before = Utc::now().with_timezone(&FixedOffset::east(8 * 3600))
+        - chrono::Duration::seconds(retention);
+    let res = Outbox::delete_many()
+        .filter(outbox::Column::SentTime.lt(before))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
+
+/// Publish an event and wait until the message queue stores it
+pub async fn publish(queue: &dyn MessageQueue, row: &outbox::Model) -> Result<(), QueueError> {
+    queue
+        .publish(
+            &row.topic,
+            row.payload.to_owned().into_bytes(),
+            HashMap::new(),
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outbox_topic() {
+        assert_eq!("search", SEARCH_TOPIC);
+        assert_eq!("relation", RELATION_TOPIC);
+        let msg = PulsarRelationData::ActivateLike(1, 2);
+        let payload = serde_json::to_string(&msg).unwrap();
+        assert_eq!(msg, serde_json::from_str(&payload).unwrap());
+    }
+}
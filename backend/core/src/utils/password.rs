@@ -0,0 +1,153 @@
+//! Module of password hashing
+//!
+//! Passwords are hashed with Argon2id and stored in `user.password` as PHC strings,
+//! which carry their own salt and parameters. Accounts created before that store
+//! `sha3_256(salt + password)` in hex with the salt in `user.salt`; such hashes are
+//! still accepted and should be replaced with an Argon2id hash once verified.
+//!
+//! Argon2id takes tens of milliseconds of CPU by design, so it runs on the blocking
+//! threads of tokio instead of the workers serving requests.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use crypto::digest::Digest;
+use crypto::sha3::Sha3;
+use rand::thread_rng;
+use tokio::task::spawn_blocking;
+
+use crate::models::error::*;
+
+/// Result of verifying a password
+///
+/// ## Fields
+///
+/// - `PasswordCheck::Valid`: The password matches an Argon2id hash
+/// - `PasswordCheck::Legacy`: The password matches a legacy SHA3 hash, which should be rehashed
+/// - `PasswordCheck::Invalid`: The password does not match
+///
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PasswordCheck {
+    Valid,
+    Legacy,
+    Invalid,
+}
+
+impl PasswordCheck {
+    pub fn is_valid(self) -> bool {
+        self != PasswordCheck::Invalid
+    }
+}
+
+/// Hash a password with Argon2id
+///
+/// ## Returns
+///
+/// The PHC string to store in `user.password`.
+///
+/// ## Errors
+///
+/// - `ErrorCode::Unknown` if the password cannot be hashed
+pub async fn hash_password(password: &str) -> Result<String, ErrorResponse> {
+    let password = password.to_owned();
+    match spawn_blocking(move || hash_password_blocking(&password)).await {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("[PASSWORD] Failed to hash password: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+fn hash_password_blocking(password: &str) -> Result<String, ErrorResponse> {
+    let salt = SaltString::generate(&mut thread_rng());
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(e) => {
+            log::error!("[PASSWORD] Failed to hash password: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+/// Verify a password against the stored hash
+///
+/// ## Parameters
+///
+/// - `stored`: Value of `user.password`, either a PHC string or a legacy SHA3 hash
+/// - `salt`: Value of `user.salt`, only used by legacy SHA3 hashes
+/// - `password`: The password to verify
+pub async fn verify_password(stored: &str, salt: &str, password: &str) -> PasswordCheck {
+    let (stored, salt, password) = (stored.to_owned(), salt.to_owned(), password.to_owned());
+    match spawn_blocking(move || verify_password_blocking(&stored, &salt, &password)).await {
+        Ok(check) => check,
+        Err(e) => {
+            log::error!("[PASSWORD] Failed to verify password: {:?}", e);
+            PasswordCheck::Invalid
+        }
+    }
+}
+
+fn verify_password_blocking(stored: &str, salt: &str, password: &str) -> PasswordCheck {
+    if stored.starts_with('$') {
+        let hash = match PasswordHash::new(stored) {
+            Ok(hash) => hash,
+            Err(e) => {
+                log::error!("[PASSWORD] Invalid password hash: {:?}", e);
+                return PasswordCheck::Invalid;
+            }
+        };
+        match Argon2::default().verify_password(password.as_bytes(), &hash) {
+            Ok(_) => PasswordCheck::Valid,
+            Err(_) => PasswordCheck::Invalid,
+        }
+    } else {
+        let mut hash_sha3 = Sha3::sha3_256();
+        hash_sha3.input_str(&(salt.to_owned() + password));
+        if hash_sha3.result_str() == stored {
+            PasswordCheck::Legacy
+        } else {
+            PasswordCheck::Invalid
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_password() {
+        let hash = hash_password("password").await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("password").await.unwrap());
+        assert_eq!(
+            PasswordCheck::Valid,
+            verify_password(&hash, "", "password").await
+        );
+        assert_eq!(
+            PasswordCheck::Invalid,
+            verify_password(&hash, "", "Password").await
+        );
+        assert_eq!(
+            PasswordCheck::Invalid,
+            verify_password("$invalid", "", "password").await
+        );
+        let mut hash_sha3 = Sha3::sha3_256();
+        hash_sha3.input_str("saltpassword");
+        let legacy = hash_sha3.result_str();
+        assert_eq!(
+            PasswordCheck::Legacy,
+            verify_password(&legacy, "salt", "password").await
+        );
+        assert_eq!(
+            PasswordCheck::Invalid,
+            verify_password(&legacy, "salt", "passwd").await
+        );
+        assert_eq!(
+            PasswordCheck::Invalid,
+            verify_password(&legacy, "", "password").await
+        );
+        assert!(PasswordCheck::Legacy.is_valid());
+        assert!(!PasswordCheck::Invalid.is_valid());
+    }
+}
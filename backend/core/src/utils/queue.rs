@@ -0,0 +1,871 @@
+//! Module of the message queue between backend and task-executor
+//!
+//! Producers and consumers of the topics, e.g. `search`, `relation` and `email`,
+//! only see the traits `MessageQueue` and `QueueConsumer`. The scheme of
+//! `databases.pulsar-mq.url` selects the implementation:
+//!
+//! - `pulsar://`: topics of Apache Pulsar, for deployments with a broker
+//! - `redis://`: Redis Streams with consumer groups, for a single node which already
+//!   runs redis
+//! - `memory://`: tokio primitives in the current process, for tests and for running
+//!   the consumers in the same process as the routes. Messages are lost when the
+//!   process exits.
+//!
+//! Messages are delivered at least once with all the implementations: a message is
+//! delivered again if it is negatively acked, or if the consumer restarts before it
+//! is acked. A negatively acked message is delivered again after a delay, and the
+//! later messages are delivered in the meantime. The broker counts the deliveries of
+//! a message, so that the count survives restarts of the consumer.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use pulsar::consumer::Message;
+use pulsar::{
+    message::proto, producer, Consumer, MultiTopicProducer, Pulsar, SubType, TokioExecutor,
+};
+use redis::streams::{StreamClaimReply, StreamId, StreamReadReply};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+use tokio::time::{sleep_until, Duration, Instant};
+
+/// Approximate max length of a stream of `redis://`, older messages are trimmed
+pub const STREAM_MAX_LEN: usize = 100000;
+
+/// Milliseconds a consumer of `redis://` blocks on the streams at a time
+const STREAM_BLOCK: usize = 5000;
+
+lazy_static! {
+    static ref MEMORY_QUEUE: MemoryQueue = MemoryQueue::default();
+}
+
+/// Error of the message queue
+#[derive(Debug)]
+pub enum QueueError {
+    Pulsar(pulsar::Error),
+    Redis(redis::RedisError),
+    Json(serde_json::Error),
+    Url(String),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Pulsar(e) => write!(f, "Pulsar error: {}", e),
+            QueueError::Redis(e) => write!(f, "Redis error: {}", e),
+            QueueError::Json(e) => write!(f, "Json error: {}", e),
+            QueueError::Url(url) => write!(f, "Unsupported message queue url: {}", url),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+impl From<pulsar::Error> for QueueError {
+    fn from(e: pulsar::Error) -> QueueError {
+        QueueError::Pulsar(e)
+    }
+}
+
+impl From<pulsar::error::ConsumerError> for QueueError {
+    fn from(e: pulsar::error::ConsumerError) -> QueueError {
+        QueueError::Pulsar(e.into())
+    }
+}
+
+impl From<redis::RedisError> for QueueError {
+    fn from(e: redis::RedisError) -> QueueError {
+        QueueError::Redis(e)
+    }
+}
+
+impl From<serde_json::Error> for QueueError {
+    fn from(e: serde_json::Error) -> QueueError {
+        QueueError::Json(e)
+    }
+}
+
+/// A message received by a consumer
+///
+/// ## Fields
+///
+/// - `id`: String, id of the message, kept when it is delivered again
+/// - `topic`: String, short name of the topic, e.g. `search`
+/// - `payload`: Vec<u8>, the message, json for all the topics of this crate
+/// - `properties`: HashMap<String, String>, properties set by the producer
+/// - `redelivery_count`: u32, times the message has been delivered before, counted
+///   by the message queue
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub id: String,
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub properties: HashMap<String, String>,
+    pub redelivery_count: u32,
+}
+
+impl Delivery {
+    /// Deserialize the json payload
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.payload)
+    }
+}
+
+/// Producer and consumer factory of a message queue
+#[rocket::async_trait]
+pub trait MessageQueue: Send + Sync {
+    /// Publish a message to a topic and wait until it is stored
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: HashMap<String, String>,
+    ) -> Result<(), QueueError>;
+
+    /// Subscribe to topics
+    ///
+    /// Consumers of the same `subscription` share the messages, consumers of
+    /// different subscriptions each get all of them.
+    async fn subscribe(
+        &self,
+        topics: &[&str],
+        subscription: &str,
+    ) -> Result<Box<dyn QueueConsumer>, QueueError>;
+}
+
+/// Consumer of a message queue
+#[rocket::async_trait]
+pub trait QueueConsumer: Send {
+    /// Wait for the next message, `None` if the consumer is closed
+    async fn next(&mut self) -> Result<Option<Delivery>, QueueError>;
+
+    /// Mark a message as handled
+    async fn ack(&mut self, delivery: &Delivery) -> Result<(), QueueError>;
+
+    /// Mark a message as failed, so that it is delivered again after `delay`
+    ///
+    /// The call does not wait for the delay, later messages are delivered first.
+    async fn nack(&mut self, delivery: &Delivery, delay: Duration) -> Result<(), QueueError>;
+}
+
+/// Connect to the message queue of a url, see the module document
+///
+/// ## Errors
+///
+/// - `QueueError::Url` if the scheme is unknown
+/// - `QueueError` of connecting to pulsar or redis
+pub async fn connect(url: &str) -> Result<Arc<dyn MessageQueue>, QueueError> {
+    if url.starts_with("pulsar://") || url.starts_with("pulsar+ssl://") {
+        Ok(Arc::new(PulsarQueue::connect(url).await?))
+    } else if url.starts_with("redis://") || url.starts_with("rediss://") {
+        Ok(Arc::new(RedisQueue::connect(url).await?))
+    } else if url.starts_with("memory://") {
+        Ok(Arc::new(MEMORY_QUEUE.clone()))
+    } else {
+        Err(QueueError::Url(url.to_string()))
+    }
+}
+
+/// Publish a message serialized as json, like `SerializeMessage` of
+/// `models::pulsar`
+pub async fn publish_json<T: Serialize>(
+    queue: &dyn MessageQueue,
+    topic: &str,
+    msg: &T,
+) -> Result<(), QueueError> {
+    let payload = serde_json::to_vec(msg)?;
+    queue.publish(topic, payload, HashMap::new()).await
+}
+
+/// Full name of a topic in pulsar
+pub fn topic_url(topic: &str) -> String {
+    format!("persistent://public/default/{}", topic)
+}
+
+/// Short name of a topic of pulsar, e.g. `search` of
+/// `persistent://public/default/search`
+fn short_topic(topic: &str) -> &str {
+    topic.rsplit('/').next().unwrap_or(topic)
+}
+
+/// Property of a message published again by `PulsarConsumer::nack`: times it has
+/// been delivered before
+const PROPERTY_REDELIVERY: &str = "redelivery-count";
+
+/// Property of a message published again by `PulsarConsumer::nack`: unix time in
+/// milliseconds before which it is not delivered
+const PROPERTY_RETRY_AT: &str = "retry-at";
+
+type PulsarProducer = Arc<tokio::sync::Mutex<MultiTopicProducer<TokioExecutor>>>;
+
+/// Message queue of Apache Pulsar
+pub struct PulsarQueue {
+    pulsar: Pulsar<TokioExecutor>,
+    producer: PulsarProducer,
+}
+
+impl PulsarQueue {
+    pub async fn connect(url: &str) -> Result<PulsarQueue, QueueError> {
+        let pulsar = Pulsar::builder(url, TokioExecutor).build().await?;
+        let producer = pulsar
+            .producer()
+            .with_options(producer::ProducerOptions {
+                schema: Some(proto::Schema {
+                    r#type: proto::schema::Type::String as i32,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .build_multi_topic();
+        Ok(PulsarQueue {
+            pulsar,
+            producer: Arc::new(tokio::sync::Mutex::new(producer)),
+        })
+    }
+}
+
+/// Publish a message to a topic of pulsar and wait until it is stored
+async fn pulsar_send(
+    producer: &PulsarProducer,
+    topic: &str,
+    payload: Vec<u8>,
+    properties: HashMap<String, String>,
+) -> Result<(), QueueError> {
+    let msg = producer::Message {
+        payload,
+        properties,
+        ..Default::default()
+    };
+    let mut producer = producer.lock().await;
+    producer.send(topic_url(topic), msg).await?.await?;
+    Ok(())
+}
+
+#[rocket::async_trait]
+impl MessageQueue for PulsarQueue {
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: HashMap<String, String>,
+    ) -> Result<(), QueueError> {
+        pulsar_send(&self.producer, topic, payload, properties).await
+    }
+
+    async fn subscribe(
+        &self,
+        topics: &[&str],
+        subscription: &str,
+    ) -> Result<Box<dyn QueueConsumer>, QueueError> {
+        // failover keeps the order of messages, and lets a restarted consumer take over
+        let consumer: Consumer<Vec<u8>, _> = self
+            .pulsar
+            .consumer()
+            .with_topics(
+                topics
+                    .iter()
+                    .map(|topic| topic_url(topic))
+                    .collect::<Vec<_>>(),
+            )
+            .with_subscription(subscription)
+            .with_subscription_type(SubType::Failover)
+            .build()
+            .await?;
+        Ok(Box::new(PulsarConsumer {
+            consumer,
+            producer: self.producer.clone(),
+            pending: HashMap::new(),
+            delayed: Vec::new(),
+        }))
+    }
+}
+
+/// Consumer of `PulsarQueue`, keeping the received messages until they are acked
+///
+/// The broker does not count redeliveries for a failover subscription, so a nacked
+/// message is published again to the end of its topic with the count and the time
+/// to retry in its properties, and the original is acked. A received message to
+/// retry later is held until then, and is delivered again by the broker if the
+/// consumer restarts in the meantime.
+pub struct PulsarConsumer {
+    consumer: Consumer<Vec<u8>, TokioExecutor>,
+    producer: PulsarProducer,
+    pending: HashMap<String, Message<Vec<u8>>>,
+    delayed: Vec<(Instant, Delivery)>,
+}
+
+#[rocket::async_trait]
+impl QueueConsumer for PulsarConsumer {
+    async fn next(&mut self) -> Result<Option<Delivery>, QueueError> {
+        loop {
+            let now = Instant::now();
+            if let Some(i) = self.delayed.iter().position(|(at, _)| *at <= now) {
+                return Ok(Some(self.delayed.remove(i).1));
+            }
+            let msg = match self.delayed.iter().map(|(at, _)| *at).min() {
+                Some(at) => tokio::select! {
+                    msg = self.consumer.try_next() => msg?,
+                    _ = sleep_until(at) => continue,
+                },
+                None => self.consumer.try_next().await?,
+            };
+            let msg = match msg {
+                Some(msg) => msg,
+                None => return Ok(None),
+            };
+            let id = msg.message_id();
+            let mut properties: HashMap<String, String> = msg
+                .metadata()
+                .properties
+                .iter()
+                .map(|p| (p.key.to_owned(), p.value.to_owned()))
+                .collect();
+            let redelivery_count = properties
+                .remove(PROPERTY_REDELIVERY)
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(0);
+            let retry_at: i64 = properties
+                .remove(PROPERTY_RETRY_AT)
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(0);
+            let delivery = Delivery {
+                id: format!(
+                    "{}:{}:{}",
+                    id.ledger_id,
+                    id.entry_id,
+                    id.batch_index.unwrap_or(-1)
+                ),
+                topic: short_topic(&msg.topic).to_string(),
+                payload: msg.payload.data.to_owned(),
+                properties,
+                redelivery_count,
+            };
+            self.pending.insert(delivery.id.to_owned(), msg);
+            let wait = retry_at - Utc::now().timestamp_millis();
+            if wait > 0 {
+                self.delayed
+                    .push((now + Duration::from_millis(wait as u64), delivery));
+                continue;
+            }
+            return Ok(Some(delivery));
+        }
+    }
+
+    async fn ack(&mut self, delivery: &Delivery) -> Result<(), QueueError> {
+        if let Some(msg) = self.pending.remove(&delivery.id) {
+            self.consumer.ack(&msg).await?;
+        }
+        Ok(())
+    }
+
+    async fn nack(&mut self, delivery: &Delivery, delay: Duration) -> Result<(), QueueError> {
+        let msg = match self.pending.remove(&delivery.id) {
+            Some(msg) => msg,
+            None => return Ok(()),
+        };
+        let mut properties = delivery.properties.to_owned();
+        properties.insert(
+            PROPERTY_REDELIVERY.to_string(),
+            (delivery.redelivery_count + 1).to_string(),
+        );
+        properties.insert(
+            PROPERTY_RETRY_AT.to_string(),
+            (Utc::now().timestamp_millis() + delay.as_millis() as i64).to_string(),
+        );
+        // the original is acked only after the copy is stored, so that it is not
+        // lost if publishing fails
+        pulsar_send(
+            &self.producer,
+            &delivery.topic,
+            delivery.payload.to_owned(),
+            properties,
+        )
+        .await?;
+        self.consumer.ack(&msg).await?;
+        Ok(())
+    }
+}
+
+/// Message queue of Redis Streams
+///
+/// A topic is the stream `mq:<topic>`, and a subscription is a consumer group of it.
+/// Each subscription has a single consumer named after it, which is enough for a
+/// single node.
+pub struct RedisQueue {
+    client: redis::Client,
+    conn: redis::aio::MultiplexedConnection,
+}
+
+impl RedisQueue {
+    pub async fn connect(url: &str) -> Result<RedisQueue, QueueError> {
+        let client = redis::Client::open(url)?;
+        let conn = client.get_multiplexed_tokio_connection().await?;
+        Ok(RedisQueue { client, conn })
+    }
+}
+
+/// Key of the stream of a topic
+fn stream_key(topic: &str) -> String {
+    format!("mq:{}", topic)
+}
+
+/// Field of a property in an entry of a stream
+const PROPERTY_PREFIX: &str = "property:";
+
+#[rocket::async_trait]
+impl MessageQueue for RedisQueue {
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: HashMap<String, String>,
+    ) -> Result<(), QueueError> {
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg(stream_key(topic))
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(STREAM_MAX_LEN)
+            .arg("*")
+            .arg("payload")
+            .arg(payload);
+        for (key, value) in properties {
+            cmd.arg(format!("{}{}", PROPERTY_PREFIX, key)).arg(value);
+        }
+        let mut conn = self.conn.clone();
+        let _: String = cmd.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn subscribe(
+        &self,
+        topics: &[&str],
+        subscription: &str,
+    ) -> Result<Box<dyn QueueConsumer>, QueueError> {
+        let mut conn = self.client.get_async_connection().await?;
+        for topic in topics {
+            // a new group starts from the beginning, so that no message published
+            // before the first consumer is lost
+            let res: redis::RedisResult<String> = redis::cmd("XGROUP")
+                .arg("CREATE")
+                .arg(stream_key(topic))
+                .arg(subscription)
+                .arg("0")
+                .arg("MKSTREAM")
+                .query_async(&mut conn)
+                .await;
+            match res {
+                Err(e) if e.code() != Some("BUSYGROUP") => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(Box::new(RedisConsumer {
+            conn,
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            group: subscription.to_string(),
+            pending_from: Some(vec!["0".to_string(); topics.len()]),
+            received: VecDeque::new(),
+            delayed: Vec::new(),
+        }))
+    }
+}
+
+/// Consumer of `RedisQueue`
+///
+/// Messages delivered but not acked stay in the pending list of the group, where
+/// redis counts their deliveries. They are read first when the consumer starts. A
+/// nacked message is claimed again from the pending list after its delay.
+///
+/// ## Fields
+///
+/// - `pending_from`: Option<Vec<String>>, id of the last pending entry read of
+///   each topic, `None` once all of them are read
+/// - `received`: VecDeque<(String, StreamId)>, entries read at once with others,
+///   as the topic and the entry
+/// - `delayed`: Vec<(Instant, String, String)>, nacked entries, as the time to
+///   claim them, the topic and the id
+pub struct RedisConsumer {
+    conn: redis::aio::Connection,
+    topics: Vec<String>,
+    group: String,
+    pending_from: Option<Vec<String>>,
+    received: VecDeque<(String, StreamId)>,
+    delayed: Vec<(Instant, String, String)>,
+}
+
+impl RedisConsumer {
+    /// Times an entry of the pending list has been delivered before
+    async fn redelivery_count(&mut self, topic: &str, id: &str) -> Result<u32, QueueError> {
+        // entries of the reply are [id, consumer, idle time, delivery count]
+        let pending: Vec<(String, String, u64, u32)> = redis::cmd("XPENDING")
+            .arg(stream_key(topic))
+            .arg(&self.group)
+            .arg(id)
+            .arg(id)
+            .arg(1)
+            .query_async(&mut self.conn)
+            .await?;
+        Ok(pending.first().map_or(0, |p| p.3.saturating_sub(1)))
+    }
+
+    /// Read the entries of the pending list or new entries of the streams into
+    /// `received`
+    async fn read(&mut self) -> Result<(), QueueError> {
+        let mut cmd = redis::cmd("XREADGROUP");
+        cmd.arg("GROUP")
+            .arg(&self.group)
+            .arg(&self.group)
+            .arg("COUNT")
+            .arg(1);
+        if self.pending_from.is_none() {
+            // wake up in time for the next nacked entry
+            let block = match self.delayed.iter().map(|(at, _, _)| *at).min() {
+                Some(at) => (at.saturating_duration_since(Instant::now()).as_millis() as usize)
+                    .clamp(1, STREAM_BLOCK),
+                None => STREAM_BLOCK,
+            };
+            cmd.arg("BLOCK").arg(block);
+        }
+        cmd.arg("STREAMS");
+        for topic in &self.topics {
+            cmd.arg(stream_key(topic));
+        }
+        match &self.pending_from {
+            Some(ids) => {
+                for id in ids {
+                    cmd.arg(id);
+                }
+            }
+            None => {
+                for _ in &self.topics {
+                    cmd.arg(">");
+                }
+            }
+        }
+        let reply: Option<StreamReadReply> = cmd.query_async(&mut self.conn).await?;
+        for key in reply.into_iter().flat_map(|r| r.keys) {
+            let topic = key.key.strip_prefix("mq:").unwrap_or(&key.key).to_string();
+            for entry in key.ids {
+                if let Some(ids) = self.pending_from.as_mut() {
+                    if let Some(i) = self.topics.iter().position(|t| *t == topic) {
+                        ids[i] = entry.id.to_owned();
+                    }
+                }
+                self.received.push_back((topic.to_owned(), entry));
+            }
+        }
+        if self.received.is_empty() && self.pending_from.is_some() {
+            // the pending list is all read, wait for new messages
+            self.pending_from = None;
+        }
+        Ok(())
+    }
+}
+
+/// Message of an entry of a stream
+fn parse_entry(
+    topic: String,
+    entry: StreamId,
+    redelivery_count: u32,
+) -> Result<Delivery, QueueError> {
+    let mut payload = Vec::new();
+    let mut properties = HashMap::new();
+    for (field, value) in entry.map {
+        if field == "payload" {
+            payload = redis::from_redis_value(&value)?;
+        } else if let Some(name) = field.strip_prefix(PROPERTY_PREFIX) {
+            properties.insert(name.to_string(), redis::from_redis_value(&value)?);
+        }
+    }
+    Ok(Delivery {
+        id: entry.id,
+        topic,
+        payload,
+        properties,
+        redelivery_count,
+    })
+}
+
+#[rocket::async_trait]
+impl QueueConsumer for RedisConsumer {
+    async fn next(&mut self) -> Result<Option<Delivery>, QueueError> {
+        loop {
+            let now = Instant::now();
+            if let Some(i) = self.delayed.iter().position(|(at, _, _)| *at <= now) {
+                let (_, topic, id) = self.delayed.remove(i);
+                // claiming an entry counts a delivery of it
+                let reply: StreamClaimReply = redis::cmd("XCLAIM")
+                    .arg(stream_key(&topic))
+                    .arg(&self.group)
+                    .arg(&self.group)
+                    .arg(0)
+                    .arg(&id)
+                    .query_async(&mut self.conn)
+                    .await?;
+                // the entry may have been trimmed from the stream
+                if let Some(entry) = reply.ids.into_iter().next() {
+                    let count = self.redelivery_count(&topic, &id).await?;
+                    return Ok(Some(parse_entry(topic, entry, count)?));
+                }
+                continue;
+            }
+            if let Some((topic, entry)) = self.received.pop_front() {
+                let count = if self.pending_from.is_some() {
+                    self.redelivery_count(&topic, &entry.id).await?
+                } else {
+                    0
+                };
+                return Ok(Some(parse_entry(topic, entry, count)?));
+            }
+            self.read().await?;
+        }
+    }
+
+    async fn ack(&mut self, delivery: &Delivery) -> Result<(), QueueError> {
+        let _: i64 = redis::cmd("XACK")
+            .arg(stream_key(&delivery.topic))
+            .arg(&self.group)
+            .arg(&delivery.id)
+            .query_async(&mut self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn nack(&mut self, delivery: &Delivery, delay: Duration) -> Result<(), QueueError> {
+        // the message is still pending, claim it again later
+        self.delayed.push((
+            Instant::now() + delay,
+            delivery.topic.to_owned(),
+            delivery.id.to_owned(),
+        ));
+        Ok(())
+    }
+}
+
+/// Message queue in the current process
+///
+/// All the consumers of a topic share its messages whatever their subscriptions
+/// are, since there is only one subscription per topic in this crate. Messages
+/// published before the first consumer subscribes are kept.
+#[derive(Clone, Default)]
+pub struct MemoryQueue {
+    inner: Arc<MemoryTopics>,
+}
+
+#[derive(Default)]
+struct MemoryTopics {
+    topics: Mutex<HashMap<String, VecDeque<Delivery>>>,
+    /// Nacked messages and the instants they are delivered again at
+    delayed: Mutex<Vec<(Instant, Delivery)>>,
+    notify: Notify,
+    next_id: AtomicU64,
+}
+
+impl MemoryTopics {
+    /// Put a message into the queue of its topic, `front` for a message delivered
+    /// again
+    fn push(&self, delivery: Delivery, front: bool) {
+        {
+            let mut topics = self.topics.lock().unwrap();
+            let queue = topics.entry(delivery.topic.to_owned()).or_default();
+            if front {
+                queue.push_front(delivery);
+            } else {
+                queue.push_back(delivery);
+            }
+        }
+        self.notify.notify_waiters();
+    }
+
+    /// Keep a nacked message until `at`
+    fn delay(&self, delivery: Delivery, at: Instant) {
+        self.delayed.lock().unwrap().push((at, delivery));
+        self.notify.notify_waiters();
+    }
+
+    /// Take the first message of any of the topics, the due nacked messages first
+    fn pop(&self, topics: &[String]) -> Option<Delivery> {
+        let now = Instant::now();
+        let mut queues = self.topics.lock().unwrap();
+        let mut delayed = self.delayed.lock().unwrap();
+        let mut i = 0;
+        while i < delayed.len() {
+            if delayed[i].0 <= now {
+                let (_, delivery) = delayed.swap_remove(i);
+                queues
+                    .entry(delivery.topic.to_owned())
+                    .or_default()
+                    .push_front(delivery);
+            } else {
+                i += 1;
+            }
+        }
+        topics
+            .iter()
+            .find_map(|topic| queues.get_mut(topic).and_then(|queue| queue.pop_front()))
+    }
+
+    /// The earliest instant a nacked message of the topics is due at
+    fn next_due(&self, topics: &[String]) -> Option<Instant> {
+        self.delayed
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, delivery)| topics.contains(&delivery.topic))
+            .map(|(at, _)| *at)
+            .min()
+    }
+}
+
+#[rocket::async_trait]
+impl MessageQueue for MemoryQueue {
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: HashMap<String, String>,
+    ) -> Result<(), QueueError> {
+        let delivery = Delivery {
+            id: self
+                .inner
+                .next_id
+                .fetch_add(1, Ordering::Relaxed)
+                .to_string(),
+            topic: topic.to_string(),
+            payload,
+            properties,
+            redelivery_count: 0,
+        };
+        self.inner.push(delivery, false);
+        Ok(())
+    }
+
+    async fn subscribe(
+        &self,
+        topics: &[&str],
+        _subscription: &str,
+    ) -> Result<Box<dyn QueueConsumer>, QueueError> {
+        Ok(Box::new(MemoryConsumer {
+            queue: self.inner.clone(),
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            in_flight: Vec::new(),
+        }))
+    }
+}
+
+/// Consumer of `MemoryQueue`
+///
+/// The messages it has received but not acked or nacked are put back into the
+/// queue when it is dropped, e.g. with the runtime of its task, like the broker
+/// does for a consumer which disconnects.
+pub struct MemoryConsumer {
+    queue: Arc<MemoryTopics>,
+    topics: Vec<String>,
+    in_flight: Vec<Delivery>,
+}
+
+#[rocket::async_trait]
+impl QueueConsumer for MemoryConsumer {
+    async fn next(&mut self) -> Result<Option<Delivery>, QueueError> {
+        loop {
+            // wakeups are received from the creation of the future, so that no
+            // message pushed after the check is missed
+            let notified = self.queue.notify.notified();
+            if let Some(delivery) = self.queue.pop(&self.topics) {
+                self.in_flight.push(delivery.to_owned());
+                return Ok(Some(delivery));
+            }
+            match self.queue.next_due(&self.topics) {
+                Some(at) => tokio::select! {
+                    _ = notified => {}
+                    _ = sleep_until(at) => {}
+                },
+                None => notified.await,
+            }
+        }
+    }
+
+    async fn ack(&mut self, delivery: &Delivery) -> Result<(), QueueError> {
+        self.in_flight.retain(|d| d.id != delivery.id);
+        Ok(())
+    }
+
+    async fn nack(&mut self, delivery: &Delivery, delay: Duration) -> Result<(), QueueError> {
+        self.in_flight.retain(|d| d.id != delivery.id);
+        let mut delivery = delivery.to_owned();
+        delivery.redelivery_count += 1;
+        self.queue.delay(delivery, Instant::now() + delay);
+        Ok(())
+    }
+}
+
+impl Drop for MemoryConsumer {
+    fn drop(&mut self) {
+        // in reverse, so that they keep their order at the front of the queues
+        for mut delivery in self.in_flight.drain(..).rev() {
+            delivery.redelivery_count += 1;
+            self.queue.push(delivery, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::pulsar::PulsarSendEmail;
+
+    #[test]
+    fn test_queue_topic() {
+        assert_eq!(
+            "persistent://public/default/search",
+            topic_url(crate::utils::outbox::SEARCH_TOPIC)
+        );
+        assert_eq!(
+            "search-DLQ",
+            short_topic("persistent://public/default/search-DLQ")
+        );
+        assert_eq!("email", short_topic("email"));
+        assert_eq!("mq:relation", stream_key("relation"));
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue() {
+        let queue: Arc<dyn MessageQueue> = Arc::new(MemoryQueue::default());
+        let msg = PulsarSendEmail::Sign {
+            email: "a@mails.tsinghua.edu.cn".to_string(),
+        };
+        // messages published before subscribing are kept
+        publish_json(&*queue, "email", &msg).await.unwrap();
+        let mut consumer = queue.subscribe(&["email"], "test").await.unwrap();
+        let delivery = consumer.next().await.unwrap().unwrap();
+        assert_eq!("email", delivery.topic);
+        assert_eq!(msg, delivery.deserialize().unwrap());
+        assert_eq!(0, delivery.redelivery_count);
+        // a nacked message does not block the later ones during its delay
+        publish_json(&*queue, "email", &msg).await.unwrap();
+        consumer
+            .nack(&delivery, Duration::from_millis(50))
+            .await
+            .unwrap();
+        let next = consumer.next().await.unwrap().unwrap();
+        assert_ne!(delivery.id, next.id);
+        consumer.ack(&next).await.unwrap();
+        let redelivery = consumer.next().await.unwrap().unwrap();
+        assert_eq!(delivery.id, redelivery.id);
+        assert_eq!(1, redelivery.redelivery_count);
+        consumer.ack(&redelivery).await.unwrap();
+        // a message received but not settled is delivered again after a drop
+        publish_json(&*queue, "email", &msg).await.unwrap();
+        let delivery = consumer.next().await.unwrap().unwrap();
+        drop(consumer);
+        let mut consumer = queue.subscribe(&["email"], "test").await.unwrap();
+        let redelivery = consumer.next().await.unwrap().unwrap();
+        assert_eq!(delivery.id, redelivery.id);
+        assert_eq!(1, redelivery.redelivery_count);
+        consumer.ack(&redelivery).await.unwrap();
+    }
+}
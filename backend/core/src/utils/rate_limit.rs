@@ -0,0 +1,221 @@
+//! Module of request rate limiting
+//!
+//! Requests are limited per user and per class of route with token buckets kept
+//! in redis. Add `RateLimit<C>` after `Auth` in the parameters of a route to limit
+//! it, where `C` is the class of the route:
+//!
+//! ```ignore
+//! #[post("/posts", data = "<post_info>", format = "json")]
+//! pub async fn create_post(
+//!     auth: Auth,
+//!     _limit: RateLimit<PostCreate>,
+//!     post_info: Json<PostInfo>,
+//! ) -> (Status, Result<String, Json<ErrorResponse>>) {
+//!     ...
+//! }
+//! ```
+//!
+//! Rejected requests are answered by the catcher `too_many_requests` with status
+//! 429, `ErrorCode::RateLimit` and a `Retry-After` header.
+
+use chrono::Utc;
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use rocket::State;
+use std::marker::PhantomData;
+
+use crate::config::settings::{BucketConfig, CONFIG};
+use crate::models::error::*;
+use crate::pool::RedisDb;
+use crate::utils::auth::Auth;
+
+/// Take a token from a bucket, refilled by one token every `interval` milliseconds
+///
+/// Returns 0 if a token is taken, otherwise milliseconds until the next token.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1])
+local ts = tonumber(bucket[2])
+if tokens == nil or ts == nil then
+    tokens = capacity
+    ts = now
+end
+local refill = math.floor((now - ts) / interval)
+if refill > 0 then
+    tokens = math.min(capacity, tokens + refill)
+    ts = ts + refill * interval
+end
+if tokens >= capacity then
+    ts = now
+end
+local wait = 0
+if tokens > 0 then
+    tokens = tokens - 1
+else
+    wait = ts + interval - now
+end
+redis.call('HSET', KEYS[1], 'tokens', tokens, 'ts', ts)
+redis.call('PEXPIRE', KEYS[1], capacity * interval)
+return wait
+"#;
+
+/// Class of routes sharing a token bucket
+pub trait RateLimitClass: Send + Sync + 'static {
+    /// Name of the class, used in the redis key of buckets
+    const NAME: &'static str;
+
+    fn bucket() -> &'static BucketConfig;
+}
+
+/// Creating posts
+pub struct PostCreate;
+
+/// Creating replies
+pub struct ReplyCreate;
+
+/// Liking, collecting posts and following burrows
+pub struct RelationToggle;
+
+/// Searching
+pub struct SearchQuery;
+
+/// Uploading images
+pub struct ImageUpload;
+
+impl RateLimitClass for PostCreate {
+    const NAME: &'static str = "post";
+
+    fn bucket() -> &'static BucketConfig {
+        &CONFIG.rate_limit.post_create
+    }
+}
+
+impl RateLimitClass for ReplyCreate {
+    const NAME: &'static str = "reply";
+
+    fn bucket() -> &'static BucketConfig {
+        &CONFIG.rate_limit.reply_create
+    }
+}
+
+impl RateLimitClass for RelationToggle {
+    const NAME: &'static str = "relation";
+
+    fn bucket() -> &'static BucketConfig {
+        &CONFIG.rate_limit.relation
+    }
+}
+
+impl RateLimitClass for SearchQuery {
+    const NAME: &'static str = "search";
+
+    fn bucket() -> &'static BucketConfig {
+        &CONFIG.rate_limit.search
+    }
+}
+
+impl RateLimitClass for ImageUpload {
+    const NAME: &'static str = "image";
+
+    fn bucket() -> &'static BucketConfig {
+        &CONFIG.rate_limit.image_upload
+    }
+}
+
+/// Request guard of rate limiting, see the module document for usage
+pub struct RateLimit<C: RateLimitClass>(PhantomData<C>);
+
+/// Rejection of a request kept for the catcher
+struct RateLimited(Option<RateLimitResponse>);
+
+fn bucket_key(class: &str, uid: i64) -> String {
+    format!("rate-limit:{}:{}", class, uid)
+}
+
+/// Seconds to wait, rounded up, from the milliseconds returned by the script
+fn retry_after(wait: i64) -> i64 {
+    (wait + 999) / 1000
+}
+
+/// Take a token from the bucket of a user
+///
+/// ## Returns
+///
+/// Milliseconds to wait, 0 if the request is allowed.
+pub async fn take_token(
+    class: &str,
+    bucket: &BucketConfig,
+    uid: i64,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<i64, redis::RedisError> {
+    redis::Script::new(TOKEN_BUCKET_SCRIPT)
+        .key(bucket_key(class, uid))
+        .arg(bucket.capacity)
+        .arg(bucket.interval)
+        .arg(Utc::now().timestamp_millis())
+        .invoke_async(kv_conn)
+        .await
+}
+
+#[rocket::async_trait]
+impl<'r, C: RateLimitClass> FromRequest<'r> for RateLimit<C> {
+    type Error = ErrorResponse;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let auth = match request.guard::<Auth>().await {
+            Outcome::Success(auth) => auth,
+            Outcome::Failure(f) => return Outcome::Failure(f),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+        let db = match request.guard::<&State<RedisDb>>().await.succeeded() {
+            Some(db) => db,
+            None => {
+                return Outcome::Failure((Status::InternalServerError, ErrorResponse::default()))
+            }
+        };
+        let mut kv_conn = RedisDb::get_redis_con(db).await;
+        match take_token(C::NAME, C::bucket(), auth.id, kv_conn.as_mut()).await {
+            Ok(wait) if wait > 0 => {
+                info!("[RATE-LIMIT] {} of {} is limited.", C::NAME, auth.id);
+                let response = RateLimitResponse::build(retry_after(wait), "Too many requests.");
+                request.local_cache(|| RateLimited(Some(response.clone())));
+                Outcome::Failure((Status::TooManyRequests, response.error))
+            }
+            Ok(_) => Outcome::Success(RateLimit(PhantomData)),
+            // let requests through rather than fail them all when redis is down
+            Err(e) => {
+                error!("[RATE-LIMIT] failed to take token. RedisError: {:?}", e);
+                Outcome::Success(RateLimit(PhantomData))
+            }
+        }
+    }
+}
+
+/// Catcher of requests rejected by `RateLimit`
+#[catch(429)]
+pub fn too_many_requests(request: &Request<'_>) -> RateLimitResponse {
+    match &request.local_cache(|| RateLimited(None)).0 {
+        Some(response) => response.clone(),
+        None => RateLimitResponse::build(1, "Too many requests."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_class() {
+        assert_eq!("rate-limit:post:1", bucket_key(PostCreate::NAME, 1));
+        assert_eq!(CONFIG.rate_limit.reply_create, *ReplyCreate::bucket());
+        assert_eq!(CONFIG.rate_limit.search, *SearchQuery::bucket());
+        assert_eq!(0, retry_after(0));
+        assert_eq!(1, retry_after(1));
+        assert_eq!(1, retry_after(1000));
+        assert_eq!(2, retry_after(1001));
+    }
+}
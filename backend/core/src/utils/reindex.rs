@@ -0,0 +1,942 @@
+//! Module of rebuilding and checking the typesense index
+//!
+//! The collections `burrows`, `posts` and `replies` are reached through aliases of
+//! the same names. A reindex creates a new version of a collection named
+//! `<name>_<timestamp>`, streams all the searchable rows from Postgres into it in
+//! batches with the import api of typesense, then points the alias at it and drops
+//! the old version. A collection created before aliases existed is dropped right
+//! before the alias is created, so searches of it fail for a moment.
+//!
+//! Events published while a collection is rebuilt are applied to the old version,
+//! so the search events enqueued into the outbox since the rebuild started are
+//! applied again to the new version once the alias points at it.
+//!
+//! The diff is a dry run, which compares the index with Postgres and reports the
+//! documents missing from, stale in or unknown to the index. Both sides are read
+//! in pages of ids, so that neither is held in memory as a whole.
+//!
+//! Both are run as a subcommand of task-executor:
+//!
+//! ```text
+//! task-executor reindex [--dry-run] [burrows | posts | replies]...
+//! ```
+
+use chrono::{FixedOffset, Utc};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::{
+    entity::*, query::*, Condition, ConnectionTrait, Database, DatabaseConnection, DbErr,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::burrow_valid::VALID_STATE;
+use super::mq::{handle_search, Typesense};
+use super::outbox::SEARCH_TOPIC;
+use crate::config::search::{DIFF_REPORT_LIMIT, REINDEX_BATCH, REINDEX_CATCH_UP_MARGIN};
+use crate::config::settings::{check_config, CONFIG};
+use crate::db::{burrow, content_post, content_reply, outbox, prelude::*};
+use crate::models::pulsar::*;
+use crate::models::search::*;
+
+/// A collection of the typesense index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexCollection {
+    Burrows,
+    Posts,
+    Replies,
+}
+
+impl IndexCollection {
+    pub fn all() -> Vec<IndexCollection> {
+        vec![
+            IndexCollection::Burrows,
+            IndexCollection::Posts,
+            IndexCollection::Replies,
+        ]
+    }
+
+    /// Name of the alias used by search and the search events
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexCollection::Burrows => "burrows",
+            IndexCollection::Posts => "posts",
+            IndexCollection::Replies => "replies",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<IndexCollection> {
+        IndexCollection::all()
+            .into_iter()
+            .find(|c| c.name() == name)
+    }
+
+    /// Schema of a collection of this kind with the given name
+    pub fn schema(&self, name: &str) -> serde_json::Value {
+        match self {
+            IndexCollection::Burrows => json!({
+                "name": name,
+                "fields": [
+                    {"name": "burrow_id", "type": "int64"},
+                    {"name": "title", "type": "string", "locale": "zh"},
+                    {"name": "description", "type": "string", "locale": "zh"},
+                ]
+            }),
+            IndexCollection::Posts => json!({
+                "name": name,
+                "fields": [
+                    {"name": "post_id", "type": "int64"},
+                    {"name": "burrow_id", "type": "int64"},
+                    {"name": "title", "type": "string", "locale": "zh"},
+                    {"name": "section", "type": "string[]", "facet": true},
+                    {"name": "tag", "type": "string[]", "facet": true},
+                ]
+            }),
+            IndexCollection::Replies => json!({
+                "name": name,
+                "fields": [
+                    {"name": "post_id", "type": "int64", "facet": true},
+                    {"name": "reply_id", "type": "int32", "index": false , "optional": true},
+                    {"name": "burrow_id", "type": "int64"},
+                    {"name": "content", "type": "string", "locale": "zh"},
+                ]
+            }),
+        }
+    }
+
+    /// Field of the documents which the diff pages them by, the first part of
+    /// `RowKey`
+    pub fn range_field(&self) -> &'static str {
+        match self {
+            IndexCollection::Burrows => "burrow_id",
+            IndexCollection::Posts | IndexCollection::Replies => "post_id",
+        }
+    }
+
+    /// Collection changed by a search event
+    pub fn of_event(data: &PulsarSearchData) -> IndexCollection {
+        match data {
+            PulsarSearchData::CreateBurrow(_)
+            | PulsarSearchData::UpdateBurrow(_)
+            | PulsarSearchData::DeleteBurrow(_) => IndexCollection::Burrows,
+            PulsarSearchData::CreatePost(_)
+            | PulsarSearchData::UpdatePost(_)
+            | PulsarSearchData::DeletePost(_) => IndexCollection::Posts,
+            PulsarSearchData::CreateReply(_)
+            | PulsarSearchData::UpdateReply(_)
+            | PulsarSearchData::DeleteReply(..) => IndexCollection::Replies,
+        }
+    }
+
+    /// Parse a document exported from a collection of this kind
+    pub fn parse_document(&self, line: &str) -> Result<IndexDocument, serde_json::Error> {
+        Ok(match self {
+            IndexCollection::Burrows => IndexDocument::Burrow(serde_json::from_str(line)?),
+            IndexCollection::Posts => IndexDocument::Post(serde_json::from_str(line)?),
+            IndexCollection::Replies => IndexDocument::Reply(serde_json::from_str(line)?),
+        })
+    }
+}
+
+/// A document of the typesense index
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum IndexDocument {
+    Burrow(TypesenseBurrowData),
+    Post(TypesensePostData),
+    Reply(TypesenseReplyData),
+}
+
+impl IndexDocument {
+    pub fn id(&self) -> &str {
+        match self {
+            IndexDocument::Burrow(d) => &d.id,
+            IndexDocument::Post(d) => &d.id,
+            IndexDocument::Reply(d) => &d.id,
+        }
+    }
+
+    /// Value of the field `IndexCollection::range_field`
+    pub fn range_key(&self) -> i64 {
+        match self {
+            IndexDocument::Burrow(d) => d.burrow_id,
+            IndexDocument::Post(d) => d.post_id,
+            IndexDocument::Reply(d) => d.post_id,
+        }
+    }
+}
+
+impl From<burrow::Model> for IndexDocument {
+    fn from(burrow: burrow::Model) -> IndexDocument {
+        let data = PulsarSearchBurrowData {
+            burrow_id: burrow.burrow_id,
+            title: burrow.title,
+            description: burrow.description,
+            update_time: burrow.update_time,
+        };
+        IndexDocument::Burrow(data.into())
+    }
+}
+
+impl From<content_post::Model> for IndexDocument {
+    fn from(post: content_post::Model) -> IndexDocument {
+        let data = PulsarSearchPostData {
+            post_id: post.post_id,
+            title: post.title,
+            burrow_id: post.burrow_id,
+            section: serde_json::from_str(&post.section).unwrap_or_default(),
+            tag: post
+                .tag
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+            update_time: post.update_time,
+        };
+        IndexDocument::Post(data.into())
+    }
+}
+
+impl From<content_reply::Model> for IndexDocument {
+    fn from(reply: content_reply::Model) -> IndexDocument {
+        let data = PulsarSearchReplyData {
+            post_id: reply.post_id,
+            reply_id: reply.reply_id,
+            burrow_id: reply.burrow_id,
+            content: reply.content,
+            update_time: reply.update_time,
+        };
+        IndexDocument::Reply(data.into())
+    }
+}
+
+/// Key of a row to continue a scan after, `(post_id, reply_id)` of replies and
+/// `(id, 0)` of the others
+pub type RowKey = (i64, i32);
+
+/// Fetch a batch of the searchable rows of a collection, in the order of keys
+///
+/// Banned or discarded burrows and banned posts and replies are not searchable.
+///
+/// ## Parameters
+///
+/// - `db`: Postgres connection.
+/// - `collection`: Collection to fetch documents of.
+/// - `after`: Key of the last row of the previous batch, `None` for the first.
+/// - `limit`: Max number of rows.
+pub async fn fetch_documents<C: ConnectionTrait>(
+    db: &C,
+    collection: IndexCollection,
+    after: Option<RowKey>,
+    limit: u64,
+) -> Result<Vec<(RowKey, IndexDocument)>, DbErr> {
+    Ok(match collection {
+        IndexCollection::Burrows => Burrow::find()
+            .filter(burrow::Column::BurrowState.eq(VALID_STATE))
+            .filter(burrow::Column::BurrowId.gt(after.map_or(i64::MIN, |k| k.0)))
+            .order_by_asc(burrow::Column::BurrowId)
+            .limit(limit)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|b| ((b.burrow_id, 0), b.into()))
+            .collect(),
+        IndexCollection::Posts => ContentPost::find()
+            .filter(content_post::Column::PostState.eq(0))
+            .filter(content_post::Column::PostId.gt(after.map_or(i64::MIN, |k| k.0)))
+            .order_by_asc(content_post::Column::PostId)
+            .limit(limit)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|p| ((p.post_id, 0), p.into()))
+            .collect(),
+        IndexCollection::Replies => {
+            let mut select = ContentReply::find().filter(content_reply::Column::ReplyState.eq(0));
+            if let Some((post_id, reply_id)) = after {
+                select = select.filter(
+                    Condition::any()
+                        .add(content_reply::Column::PostId.gt(post_id))
+                        .add(
+                            Condition::all()
+                                .add(content_reply::Column::PostId.eq(post_id))
+                                .add(content_reply::Column::ReplyId.gt(reply_id)),
+                        ),
+                );
+            }
+            select
+                .order_by_asc(content_reply::Column::PostId)
+                .order_by_asc(content_reply::Column::ReplyId)
+                .limit(limit)
+                .all(db)
+                .await?
+                .into_iter()
+                .map(|r| ((r.post_id, r.reply_id), r.into()))
+                .collect()
+        }
+    })
+}
+
+/// Error of reindexing or diffing
+#[derive(Debug)]
+pub enum ReindexError {
+    Db(DbErr),
+    Http(reqwest::Error),
+    Typesense(String),
+}
+
+impl fmt::Display for ReindexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReindexError::Db(e) => write!(f, "database error: {}", e),
+            ReindexError::Http(e) => write!(f, "failed to request typesense: {}", e),
+            ReindexError::Typesense(e) => write!(f, "typesense error: {}", e),
+        }
+    }
+}
+
+impl From<DbErr> for ReindexError {
+    fn from(e: DbErr) -> ReindexError {
+        ReindexError::Db(e)
+    }
+}
+
+impl From<reqwest::Error> for ReindexError {
+    fn from(e: reqwest::Error) -> ReindexError {
+        ReindexError::Http(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct AliasData {
+    collection_name: String,
+}
+
+#[derive(Deserialize)]
+struct CollectionData {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ImportResult {
+    success: bool,
+    #[serde(default)]
+    error: String,
+}
+
+async fn typesense_error(action: &str, r: reqwest::Response) -> ReindexError {
+    let status = r.status().as_u16();
+    let text = r.text().await.unwrap_or_default();
+    ReindexError::Typesense(format!("failed to {}, {}: {}", action, status, text))
+}
+
+/// Get the collection an alias points at, `None` if the alias does not exist
+pub async fn get_alias(
+    client: &reqwest::Client,
+    name: &str,
+) -> Result<Option<String>, ReindexError> {
+    let r = client
+        .build_get(&format!("/aliases/{}", name))
+        .send()
+        .await?;
+    match r.status().as_u16() {
+        200 => Ok(Some(r.json::<AliasData>().await?.collection_name)),
+        404 => Ok(None),
+        _ => Err(typesense_error("get alias", r).await),
+    }
+}
+
+/// Check if a collection exists under its own name rather than an alias
+async fn is_collection(client: &reqwest::Client, name: &str) -> Result<bool, ReindexError> {
+    let r = client
+        .build_get(&format!("/collections/{}", name))
+        .send()
+        .await?;
+    match r.status().as_u16() {
+        // typesense resolves aliases here too
+        200 => Ok(r.json::<CollectionData>().await?.name == name),
+        404 => Ok(false),
+        _ => Err(typesense_error("get collection", r).await),
+    }
+}
+
+async fn create_collection(
+    client: &reqwest::Client,
+    schema: &serde_json::Value,
+) -> Result<(), ReindexError> {
+    let r = client
+        .build_post("/collections")
+        .json(schema)
+        .send()
+        .await?;
+    match r.status().as_u16() {
+        201 => Ok(()),
+        _ => Err(typesense_error("create collection", r).await),
+    }
+}
+
+async fn delete_collection(client: &reqwest::Client, name: &str) -> Result<(), ReindexError> {
+    let r = client
+        .build_delete(&format!("/collections/{}", name))
+        .send()
+        .await?;
+    match r.status().as_u16() {
+        200 | 404 => Ok(()),
+        _ => Err(typesense_error("delete collection", r).await),
+    }
+}
+
+async fn put_alias(
+    client: &reqwest::Client,
+    name: &str,
+    collection_name: &str,
+) -> Result<(), ReindexError> {
+    let r = client
+        .build_put(&format!("/aliases/{}", name))
+        .json(&json!({ "collection_name": collection_name }))
+        .send()
+        .await?;
+    match r.status().as_u16() {
+        200 => Ok(()),
+        _ => Err(typesense_error("update alias", r).await),
+    }
+}
+
+/// Import documents into a collection
+///
+/// ## Returns
+///
+/// Errors of the documents failed to import, empty if all are imported.
+async fn import_documents(
+    client: &reqwest::Client,
+    name: &str,
+    documents: &[(RowKey, IndexDocument)],
+) -> Result<Vec<String>, ReindexError> {
+    let mut body = String::new();
+    for (_, doc) in documents.iter() {
+        body += &serde_json::to_string(doc).map_err(|e| ReindexError::Typesense(e.to_string()))?;
+        body.push('\n');
+    }
+    let r = client
+        .build_post(&format!(
+            "/collections/{}/documents/import?action=create",
+            name
+        ))
+        .body(body)
+        .send()
+        .await?;
+    if r.status().as_u16() != 200 {
+        return Err(typesense_error("import documents", r).await);
+    }
+    Ok(parse_import_results(&r.text().await?))
+}
+
+/// Errors in the response of an import, one result per line
+fn parse_import_results(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| match serde_json::from_str::<ImportResult>(line) {
+            Ok(res) if res.success => None,
+            Ok(res) => Some(res.error),
+            Err(_) => Some(line.to_string()),
+        })
+        .collect()
+}
+
+/// Export the documents of a collection, one per line
+///
+/// ## Parameters
+///
+/// - `client`: Typesense client.
+/// - `collection`: Collection to export.
+/// - `after`: Only export the documents whose `range_field` is above it.
+/// - `upto`: Only export the documents whose `range_field` is not above it.
+async fn export_documents(
+    client: &reqwest::Client,
+    collection: IndexCollection,
+    after: Option<i64>,
+    upto: Option<i64>,
+) -> Result<String, ReindexError> {
+    let field = collection.range_field();
+    let filter: Vec<String> = after
+        .map(|a| format!("{}:>{}", field, a))
+        .into_iter()
+        .chain(upto.map(|u| format!("{}:<={}", field, u)))
+        .collect();
+    let mut request = client.build_get(&format!(
+        "/collections/{}/documents/export",
+        collection.name()
+    ));
+    if !filter.is_empty() {
+        request = request.query(&[("filter_by", filter.join(" && "))]);
+    }
+    let r = request.send().await?;
+    match r.status().as_u16() {
+        200 => Ok(r.text().await?),
+        _ => Err(typesense_error("export documents", r).await),
+    }
+}
+
+async fn import_all(
+    db: &DatabaseConnection,
+    client: &reqwest::Client,
+    collection: IndexCollection,
+    name: &str,
+) -> Result<usize, ReindexError> {
+    let mut imported = 0;
+    let mut after = None;
+    loop {
+        let batch = fetch_documents(db, collection, after, REINDEX_BATCH).await?;
+        let last = match batch.last() {
+            Some((key, _)) => *key,
+            None => break,
+        };
+        let errors = import_documents(client, name, &batch).await?;
+        if !errors.is_empty() {
+            return Err(ReindexError::Typesense(format!(
+                "{} documents failed to import into {}, the first error: {}",
+                errors.len(),
+                name,
+                errors[0]
+            )));
+        }
+        imported += batch.len();
+        log::info!("[REINDEX] Imported {} documents into {}", imported, name);
+        if (batch.len() as u64) < REINDEX_BATCH {
+            break;
+        }
+        after = Some(last);
+    }
+    Ok(imported)
+}
+
+/// Rebuild a collection from Postgres and swap its alias to the new version
+///
+/// ## Returns
+///
+/// Name of the new version and number of documents imported.
+///
+/// ## Errors
+///
+/// The alias is kept unchanged if any document fails to import, and the new
+/// version is dropped.
+pub async fn reindex(
+    db: &DatabaseConnection,
+    client: &reqwest::Client,
+    collection: IndexCollection,
+) -> Result<(String, usize), ReindexError> {
+    let alias = collection.name();
+    let name = format!("{}_{}", alias, Utc::now().timestamp());
+    let since = Utc::now().with_timezone(&FixedOffset::east(8 * 3600))
+        - chrono::Duration::seconds(REINDEX_CATCH_UP_MARGIN);
+    create_collection(client, &collection.schema(&name)).await?;
+    let imported = match import_all(db, client, collection, &name).await {
+        Ok(n) => n,
+        Err(e) => {
+            if let Err(e) = delete_collection(client, &name).await {
+                log::error!("[REINDEX] Failed to drop {}: {}", name, e);
+            }
+            return Err(e);
+        }
+    };
+    let old = get_alias(client, alias).await?;
+    if old.is_none() && is_collection(client, alias).await? {
+        log::warn!(
+            "[REINDEX] Drop collection {} to replace it by an alias",
+            alias
+        );
+        delete_collection(client, alias).await?;
+    }
+    put_alias(client, alias, &name).await?;
+    let replayed = catch_up(db, client, collection, since).await?;
+    log::info!("[REINDEX] Replayed {} search events to {}", replayed, name);
+    let elapsed = Utc::now().with_timezone(&FixedOffset::east(8 * 3600)) - since;
+    if elapsed.num_seconds() > CONFIG.outbox.retention {
+        log::warn!(
+            "[REINDEX] Reindex of {} took longer than the outbox retention, events may have been purged, run a diff",
+            alias
+        );
+    }
+    if let Some(old) = old {
+        delete_collection(client, &old).await?;
+    }
+    Ok((name, imported))
+}
+
+/// Apply the search events of a collection enqueued into the outbox since
+/// `since` to the collection behind its alias, in order
+///
+/// Applying an event twice is harmless: creating an existing document, updating
+/// a newer one or deleting a missing one changes nothing.
+///
+/// ## Returns
+///
+/// Number of events applied.
+async fn catch_up(
+    db: &DatabaseConnection,
+    client: &reqwest::Client,
+    collection: IndexCollection,
+    since: DateTimeWithTimeZone,
+) -> Result<usize, ReindexError> {
+    let mut applied = 0;
+    let mut after = 0;
+    loop {
+        let events = Outbox::find()
+            .filter(outbox::Column::Topic.eq(SEARCH_TOPIC))
+            .filter(outbox::Column::CreateTime.gte(since))
+            .filter(outbox::Column::Id.gt(after))
+            .order_by_asc(outbox::Column::Id)
+            .limit(REINDEX_BATCH)
+            .all(db)
+            .await?;
+        let done = (events.len() as u64) < REINDEX_BATCH;
+        for event in events {
+            after = event.id;
+            let data: PulsarSearchData = match serde_json::from_str(&event.payload) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("[REINDEX] Skip outbox event {}: {}", event.id, e);
+                    continue;
+                }
+            };
+            if IndexCollection::of_event(&data) != collection {
+                continue;
+            }
+            handle_search(client, data)
+                .await
+                .map_err(|e| ReindexError::Typesense(e.to_string()))?;
+            applied += 1;
+        }
+        if done {
+            break;
+        }
+    }
+    Ok(applied)
+}
+
+/// Differences between a collection and Postgres, ids sorted
+///
+/// ## Fields
+///
+/// - `collection`: IndexCollection, the collection compared
+/// - `indexed`: usize, number of documents in the index
+/// - `missing`: Vec<String>, ids of searchable rows not in the index
+/// - `stale`: Vec<String>, ids of documents differing from their rows
+/// - `extra`: Vec<String>, ids of documents without searchable rows
+#[derive(Debug, PartialEq)]
+pub struct IndexDiff {
+    pub collection: IndexCollection,
+    pub indexed: usize,
+    pub missing: Vec<String>,
+    pub stale: Vec<String>,
+    pub extra: Vec<String>,
+}
+
+impl IndexDiff {
+    pub fn new(collection: IndexCollection) -> IndexDiff {
+        IndexDiff {
+            collection,
+            indexed: 0,
+            missing: Vec::new(),
+            stale: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.missing.is_empty() && self.stale.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Compare the expected documents of a range of ids with the ones exported from
+/// the same range, adding the differences to `diff`
+fn compare_documents(
+    diff: &mut IndexDiff,
+    mut expected: HashMap<String, IndexDocument>,
+    exported: &str,
+) {
+    let collection = diff.collection;
+    for line in exported.lines().filter(|l| !l.trim().is_empty()) {
+        diff.indexed += 1;
+        // documents of an older schema are stale, as long as the id can be read
+        let id = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(v) => match v["id"].as_str() {
+                Some(id) => id.to_string(),
+                None => continue,
+            },
+            Err(_) => continue,
+        };
+        match expected.remove(&id) {
+            Some(doc) => {
+                if collection.parse_document(line).ok().as_ref() != Some(&doc) {
+                    diff.stale.push(id);
+                }
+            }
+            None => diff.extra.push(id),
+        }
+    }
+    diff.missing.extend(expected.into_keys());
+}
+
+/// Compare a collection with Postgres without changing either
+///
+/// Rows are fetched in batches in the order of keys, and each range of
+/// `range_field` they complete is compared with the documents exported from the
+/// same range. Rows of the last value of a batch may continue in the next one,
+/// so they are kept until it is fetched.
+///
+/// Search events not yet consumed show up as differences too.
+pub async fn diff(
+    db: &DatabaseConnection,
+    client: &reqwest::Client,
+    collection: IndexCollection,
+) -> Result<IndexDiff, ReindexError> {
+    let mut diff = IndexDiff::new(collection);
+    let mut expected: HashMap<String, IndexDocument> = HashMap::new();
+    let mut compared: Option<i64> = None;
+    let mut after = None;
+    loop {
+        let batch = fetch_documents(db, collection, after, REINDEX_BATCH).await?;
+        let done = (batch.len() as u64) < REINDEX_BATCH;
+        for (key, doc) in batch {
+            after = Some(key);
+            expected.insert(doc.id().to_string(), doc);
+        }
+        // the range is open-ended after the last batch
+        let upto = match after {
+            Some((last, _)) if !done => Some(last - 1),
+            _ => None,
+        };
+        if matches!((compared, upto), (Some(c), Some(u)) if u <= c) {
+            continue;
+        }
+        let (range, rest): (HashMap<_, _>, HashMap<_, _>) = expected
+            .into_iter()
+            .partition(|(_, doc)| upto.map_or(true, |u| doc.range_key() <= u));
+        expected = rest;
+        let exported = export_documents(client, collection, compared, upto).await?;
+        compare_documents(&mut diff, range, &exported);
+        if done {
+            break;
+        }
+        compared = upto;
+    }
+    diff.missing.sort();
+    diff.stale.sort();
+    diff.extra.sort();
+    Ok(diff)
+}
+
+/// Subcommand `reindex` of task-executor
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Reindex(Vec<IndexCollection>),
+    Diff(Vec<IndexCollection>),
+}
+
+const USAGE: &str = "usage: task-executor reindex [--dry-run] [burrows | posts | replies]...";
+
+/// Parse the arguments after `reindex`, all the collections if none is given
+pub fn parse_command(args: &[String]) -> Result<Command, String> {
+    let mut dry_run = false;
+    let mut collections = Vec::new();
+    for arg in args.iter() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            name => match IndexCollection::parse(name) {
+                Some(c) if !collections.contains(&c) => collections.push(c),
+                Some(_) => (),
+                None => return Err(format!("unknown collection {}\n{}", name, USAGE)),
+            },
+        }
+    }
+    if collections.is_empty() {
+        collections = IndexCollection::all();
+    }
+    if dry_run {
+        Ok(Command::Diff(collections))
+    } else {
+        Ok(Command::Reindex(collections))
+    }
+}
+
+fn print_ids(kind: &str, ids: &[String]) {
+    if ids.is_empty() {
+        return;
+    }
+    let shown: Vec<&str> = ids
+        .iter()
+        .take(DIFF_REPORT_LIMIT)
+        .map(|s| s.as_str())
+        .collect();
+    let more = if ids.len() > DIFF_REPORT_LIMIT {
+        format!(" and {} more", ids.len() - DIFF_REPORT_LIMIT)
+    } else {
+        String::new()
+    };
+    println!("  {:<8} {}{}", kind, shown.join(", "), more);
+}
+
+/// Run the subcommand `reindex` with the arguments after it
+///
+/// ## Returns
+///
+/// The exit code of the process.
+pub async fn run_command(args: &[String]) -> i32 {
+    let command = match parse_command(args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    if let Err(e) = check_config() {
+        eprintln!("invalid config: {}", e);
+        return 1;
+    }
+    let db = match Database::connect(&CONFIG.databases.pgdb.url).await {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("failed to connect to postgres: {:?}", e);
+            return 1;
+        }
+    };
+    let client = reqwest::Client::new();
+    let mut code = 0;
+    match command {
+        Command::Reindex(collections) => {
+            for collection in collections {
+                match reindex(&db, &client, collection).await {
+                    Ok((name, imported)) => println!(
+                        "reindexed {} into {} with {} documents",
+                        collection.name(),
+                        name,
+                        imported
+                    ),
+                    Err(e) => {
+                        eprintln!("failed to reindex {}: {}", collection.name(), e);
+                        code = 1;
+                    }
+                }
+            }
+        }
+        Command::Diff(collections) => {
+            for collection in collections {
+                match diff(&db, &client, collection).await {
+                    Ok(d) => {
+                        println!(
+                            "{}: {} indexed, {} missing, {} stale, {} extra",
+                            collection.name(),
+                            d.indexed,
+                            d.missing.len(),
+                            d.stale.len(),
+                            d.extra.len()
+                        );
+                        print_ids("missing", &d.missing);
+                        print_ids("stale", &d.stale);
+                        print_ids("extra", &d.extra);
+                    }
+                    Err(e) => {
+                        eprintln!("failed to diff {}: {}", collection.name(), e);
+                        code = 1;
+                    }
+                }
+            }
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    fn burrow_document(burrow_id: i64, title: &str) -> IndexDocument {
+        let burrow = burrow::Model {
+            burrow_id,
+            uid: 1,
+            title: title.to_string(),
+            description: "description".to_string(),
+            create_time: FixedOffset::east(8 * 3600).timestamp(0, 0),
+            update_time: FixedOffset::east(8 * 3600).timestamp(0, 0),
+            burrow_state: VALID_STATE,
+            post_num: 0,
+            credit: 0,
+            badge: String::new(),
+            avatar: String::new(),
+            permission: 0,
+        };
+        burrow.into()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            Ok(Command::Reindex(IndexCollection::all())),
+            parse_command(&args(""))
+        );
+        assert_eq!(
+            Ok(Command::Diff(IndexCollection::all())),
+            parse_command(&args("--dry-run"))
+        );
+        assert_eq!(
+            Ok(Command::Diff(vec![
+                IndexCollection::Posts,
+                IndexCollection::Burrows
+            ])),
+            parse_command(&args("posts --dry-run burrows posts"))
+        );
+        assert!(parse_command(&args("users")).is_err());
+    }
+
+    #[test]
+    fn test_collection_schema() {
+        for collection in IndexCollection::all() {
+            assert_eq!(Some(collection), IndexCollection::parse(collection.name()));
+            let schema = collection.schema("name_1");
+            assert_eq!("name_1", schema["name"]);
+        }
+        assert_eq!(
+            vec!["unknown".to_string()],
+            parse_import_results("{\"success\":true}\n{\"success\":false,\"error\":\"unknown\"}")
+        );
+    }
+
+    #[test]
+    fn test_compare_documents() {
+        let mut expected = HashMap::new();
+        for doc in [
+            burrow_document(1, "synced"),
+            burrow_document(2, "updated"),
+            burrow_document(3, "missing"),
+        ] {
+            expected.insert(doc.id().to_string(), doc);
+        }
+        let exported = [
+            burrow_document(1, "synced"),
+            burrow_document(2, "outdated"),
+            burrow_document(4, "extra"),
+        ]
+        .iter()
+        .map(|d| serde_json::to_string(d).unwrap())
+        .collect::<Vec<String>>()
+        .join("\n");
+        let mut diff = IndexDiff::new(IndexCollection::Burrows);
+        compare_documents(&mut diff, expected, &exported);
+        assert_eq!(3, diff.indexed);
+        assert_eq!(vec!["3".to_string()], diff.missing);
+        assert_eq!(vec!["2".to_string()], diff.stale);
+        assert_eq!(vec!["4".to_string()], diff.extra);
+        assert!(!diff.is_synced());
+        assert_eq!(3, burrow_document(3, "missing").range_key());
+        assert_eq!(
+            IndexCollection::Replies,
+            IndexCollection::of_event(&PulsarSearchData::DeleteReply(1, 2))
+        );
+    }
+}
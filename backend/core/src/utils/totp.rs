@@ -0,0 +1,364 @@
+//! Module of TOTP two-factor authentication
+//!
+//! Codes are generated as described in RFC 6238 with HMAC-SHA1, 30 second steps
+//! and 6 digits, which is what common authenticator apps expect. Each enabled
+//! account also gets one-time recovery codes, of which only hashes are stored.
+
+use chrono::Utc;
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha1::Sha1;
+use crypto::sha3::Sha3;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng, RngCore};
+use sea_orm::sea_query::Expr;
+use sea_orm::{entity::*, ConnectionTrait, DatabaseConnection, DbErr, QueryFilter};
+
+use crate::config::user::*;
+use crate::db::{self, prelude::*};
+use crate::models::error::*;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Encode bytes with RFC 4648 base32, without padding
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut result = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    result
+}
+
+/// Decode RFC 4648 base32, padding and case are ignored
+pub fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in data.trim_end_matches('=').chars() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+/// Generate a random secret, encoded in base32
+pub fn gen_secret() -> String {
+    let mut secret = [0u8; 20];
+    thread_rng().fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// Code of a secret at a time step
+pub fn totp_code(secret: &[u8], step: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::new(Sha1::new(), secret);
+    mac.input(&step.to_be_bytes());
+    let result = mac.result();
+    let hash = result.code();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+    binary % 10u32.pow(digits)
+}
+
+/// Verify a code against a base32 secret at unix time `now`
+///
+/// Codes of `TOTP_SKEW` steps around `now` are accepted to tolerate clock drift,
+/// but only those after `last_step`, so that a code cannot be used twice.
+///
+/// ## Returns
+///
+/// The time step of the code if it is valid.
+pub fn verify_code(secret: &str, code: &str, now: i64, last_step: i64) -> Option<i64> {
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = base32_decode(secret)?;
+    let current = now / TOTP_STEP;
+    (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .filter(|&step| step > last_step && step >= 0)
+        .find(|&step| totp_code(&secret, step as u64, TOTP_DIGITS) == code)
+}
+
+/// The `otpauth://` URI to enroll a secret in authenticator apps
+pub fn otpauth_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = percent_encode(TOTP_ISSUER),
+        user = percent_encode(username),
+        secret = secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP,
+    )
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+/// Generate recovery codes in the form of `XXXXX-XXXXX`
+pub fn gen_recovery_codes() -> Vec<String> {
+    (0..TOTP_RECOVERY_CODE_NUM)
+        .map(|_| {
+            let code: String = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| char::from(c).to_ascii_uppercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Hash of a recovery code, hyphens and case are ignored
+pub fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let mut hash_sha3 = Sha3::sha3_256();
+    hash_sha3.input_str(&code);
+    hash_sha3.result_str()
+}
+
+/// Get the TOTP settings of a user if TOTP is enabled
+pub async fn get_enabled_totp(
+    pg_con: &DatabaseConnection,
+    uid: i64,
+) -> Result<Option<db::user_totp::Model>, DbErr> {
+    Ok(UserTotp::find_by_id(uid)
+        .one(pg_con)
+        .await?
+        .filter(|totp| totp.enabled))
+}
+
+/// Check a TOTP code or a recovery code of a user, and consume it if valid
+///
+/// The code is consumed with a conditional update on the row read in `totp`, so
+/// that of concurrent requests with the same code only one succeeds: a TOTP code
+/// only if no code of the same or a later step has been used since, a recovery
+/// code only if the recovery codes have not changed since.
+pub async fn check_totp<C: ConnectionTrait>(
+    db: &C,
+    totp: db::user_totp::Model,
+    code: &str,
+) -> Result<bool, DbErr> {
+    let now = Utc::now().timestamp();
+    if let Some(step) = verify_code(&totp.secret, code, now, totp.last_step) {
+        let res = UserTotp::update_many()
+            .col_expr(db::user_totp::Column::LastStep, Expr::value(step))
+            .filter(db::user_totp::Column::Uid.eq(totp.uid))
+            .filter(db::user_totp::Column::LastStep.lt(step))
+            .exec(db)
+            .await?;
+        return Ok(res.rows_affected == 1);
+    }
+    let mut recovery_codes: Vec<String> =
+        serde_json::from_str(&totp.recovery_codes).unwrap_or_default();
+    let hash = hash_recovery_code(code);
+    match recovery_codes.iter().position(|c| *c == hash) {
+        Some(i) => {
+            recovery_codes.remove(i);
+            let res = UserTotp::update_many()
+                .col_expr(
+                    db::user_totp::Column::RecoveryCodes,
+                    Expr::value(serde_json::to_string(&recovery_codes).unwrap()),
+                )
+                .filter(db::user_totp::Column::Uid.eq(totp.uid))
+                .filter(db::user_totp::Column::RecoveryCodes.eq(totp.recovery_codes))
+                .exec(db)
+                .await?;
+            Ok(res.rows_affected == 1)
+        }
+        None => Ok(false),
+    }
+}
+
+fn login_ticket_key(ticket: &str) -> String {
+    format!("totp-login:{}", ticket)
+}
+
+fn login_attempt_key(ticket: &str) -> String {
+    format!("totp-login-attempt:{}", ticket)
+}
+
+/// Create a ticket for a user who passed the password check but still needs the second step
+pub async fn create_login_ticket(
+    uid: i64,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<String, ErrorResponse> {
+    let ticket: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let set_result: Result<String, redis::RedisError> = redis::cmd("SETEX")
+        .arg(login_ticket_key(&ticket))
+        .arg(TOTP_LOGIN_EX)
+        .arg(uid)
+        .query_async(kv_conn)
+        .await;
+    match set_result {
+        Ok(_) => Ok(ticket),
+        Err(e) => {
+            log::error!("[TOTP] failed to set login ticket. RedisError: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+/// Get the uid of a login ticket, `None` if it has expired
+pub async fn get_login_ticket(
+    ticket: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<Option<i64>, ErrorResponse> {
+    let get_result: Result<Option<i64>, redis::RedisError> = redis::cmd("GET")
+        .arg(login_ticket_key(ticket))
+        .query_async(kv_conn)
+        .await;
+    get_result.map_err(|e| {
+        log::error!("[TOTP] failed to get login ticket. RedisError: {:?}", e);
+        ErrorResponse::default()
+    })
+}
+
+/// Record a failed attempt of a login ticket, the ticket is dropped after
+/// `TOTP_LOGIN_ATTEMPT` failures
+///
+/// The failures are also counted per username by `utils::login_guard`, see
+/// `routes::user::user_log_in_totp`, since a new ticket is issued on every
+/// login with the password.
+pub async fn fail_login_ticket(
+    ticket: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<(), ErrorResponse> {
+    let incr_result: Result<i64, redis::RedisError> = redis::cmd("INCR")
+        .arg(login_attempt_key(ticket))
+        .query_async(kv_conn)
+        .await;
+    let attempts = match incr_result {
+        Ok(n) => n,
+        Err(e) => {
+            log::error!("[TOTP] failed to count login attempt. RedisError: {:?}", e);
+            return Err(ErrorResponse::default());
+        }
+    };
+    let _: Result<i64, redis::RedisError> = redis::cmd("EXPIRE")
+        .arg(login_attempt_key(ticket))
+        .arg(TOTP_LOGIN_EX)
+        .query_async(kv_conn)
+        .await;
+    if attempts >= TOTP_LOGIN_ATTEMPT {
+        delete_login_ticket(ticket, kv_conn).await?;
+    }
+    Ok(())
+}
+
+/// Delete a login ticket
+pub async fn delete_login_ticket(
+    ticket: &str,
+    kv_conn: &mut redis::aio::Connection,
+) -> Result<(), ErrorResponse> {
+    let delete_result: Result<i64, redis::RedisError> = redis::cmd("DEL")
+        .arg(login_ticket_key(ticket))
+        .arg(login_attempt_key(ticket))
+        .query_async(kv_conn)
+        .await;
+    match delete_result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("[TOTP] failed to delete login ticket. RedisError: {:?}", e);
+            Err(ErrorResponse::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base32() {
+        assert_eq!("", base32_encode(b""));
+        assert_eq!("MY", base32_encode(b"f"));
+        assert_eq!("MZXW6YTBOI", base32_encode(b"foobar"));
+        assert_eq!(Some(b"foobar".to_vec()), base32_decode("MZXW6YTBOI"));
+        assert_eq!(Some(b"fo".to_vec()), base32_decode("mzxq===="));
+        assert_eq!(None, base32_decode("MZXW1"));
+        let secret = gen_secret();
+        assert_eq!(32, secret.len());
+        assert_eq!(20, base32_decode(&secret).unwrap().len());
+    }
+
+    #[test]
+    fn test_totp_code() {
+        // test vectors of RFC 6238 for SHA1
+        let secret = b"12345678901234567890";
+        assert_eq!(94287082, totp_code(secret, 59 / 30, 8));
+        assert_eq!(7081804, totp_code(secret, 1111111109 / 30, 8));
+        assert_eq!(14050471, totp_code(secret, 1111111111 / 30, 8));
+        assert_eq!(89005924, totp_code(secret, 1234567890 / 30, 8));
+        assert_eq!(69279037, totp_code(secret, 2000000000 / 30, 8));
+        assert_eq!(287082, totp_code(secret, 59 / 30, 6));
+    }
+
+    #[test]
+    fn test_verify_code() {
+        let secret = base32_encode(b"12345678901234567890");
+        let now = 1111111109;
+        assert_eq!(Some(now / 30), verify_code(&secret, "081804", now, 0));
+        // codes of adjacent steps are accepted
+        assert_eq!(Some(now / 30), verify_code(&secret, "081804", now + 30, 0));
+        assert_eq!(None, verify_code(&secret, "081804", now + 60, 0));
+        // a code cannot be used twice
+        assert_eq!(None, verify_code(&secret, "081804", now, now / 30));
+        assert_eq!(None, verify_code(&secret, "81804", now, 0));
+        assert_eq!(None, verify_code(&secret, "08180a", now, 0));
+        assert_eq!(None, verify_code("1", "081804", now, 0));
+    }
+
+    #[test]
+    fn test_otpauth_uri_and_recovery_code() {
+        assert_eq!(
+            "otpauth://totp/THUBurrow:a%20b?secret=ABC&issuer=THUBurrow&algorithm=SHA1&digits=6&period=30",
+            otpauth_uri("ABC", "a b")
+        );
+        let codes = gen_recovery_codes();
+        assert_eq!(TOTP_RECOVERY_CODE_NUM, codes.len());
+        assert_eq!(11, codes[0].len());
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&codes[0].replace('-', "").to_lowercase())
+        );
+        assert_ne!(hash_recovery_code(&codes[0]), hash_recovery_code(&codes[1]));
+    }
+}
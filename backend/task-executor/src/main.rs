@@ -1,21 +1,79 @@
+use std::fmt::Debug;
+use std::future::Future;
+
+use backend::config::settings::CONFIG;
+use backend::migration::MigrationError;
+use backend::utils::dead_letter::backoff;
 use backend::utils::mq::*;
 use tokio::signal::{
     self,
     unix::{signal, SignalKind},
 };
 use tokio::sync::broadcast;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
 
 #[tokio::main]
 async fn main() {
     backend::log_init();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|s| s.as_str()) == Some("reindex") {
+        std::process::exit(backend::utils::reindex::run_command(&args[2..]).await);
+    }
+    if let Err(e) = backend::config::settings::check_config() {
+        log::error!("[TASK-EXEC] Invalid config: {}", e);
+        std::process::exit(1);
+    }
+    match backend::migration::check_schema().await {
+        Err(e @ MigrationError::UnknownVersion(_)) => {
+            log::error!("[TASK-EXEC] Unsupported schema: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => log::warn!("[TASK-EXEC] Failed to check schema: {}", e),
+        Ok(_) => (),
+    }
     let (notify_shutdown, _): (broadcast::Sender<()>, _) = broadcast::channel(1);
     let mut shutdown_recv = signal(SignalKind::terminate()).unwrap();
 
-    let _ = tokio::spawn(trending_executor(notify_shutdown.subscribe()));
-    let _ = tokio::spawn(relation_executor(notify_shutdown.subscribe()));
-    let _ = tokio::spawn(search_executor(notify_shutdown.subscribe()));
-    let _ = tokio::spawn(email_executor(notify_shutdown.subscribe()));
+    let _ = tokio::spawn(supervise(
+        "Trending",
+        generate_trending,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Relation",
+        pulsar_relation,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Typesense",
+        pulsar_typesense,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Email",
+        pulsar_email,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Notification",
+        pulsar_notification,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Ban",
+        lift_expired_bans,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Outbox",
+        pulsar_outbox,
+        notify_shutdown.subscribe(),
+    ));
+    let _ = tokio::spawn(supervise(
+        "Dead letter",
+        pulsar_dead_letter,
+        notify_shutdown.subscribe(),
+    ));
     // futures::future::join_all(handles).await;
     // futures::future::join_all(scheduler).await;
     tokio::select! {
@@ -36,46 +94,51 @@ async fn main() {
     }
 }
 
-async fn trending_executor(mut shutdown: broadcast::Receiver<()>) {
-    tokio::select! {
-        output = generate_trending() => {
-            log::error!("[TASK-EXEC] Trending executor result: {:?}", output);
-        },
-        _ = shutdown.recv() => {
-            log::warn!("[TASK-EXEC] Trending executor is shutdown.");
-        },
-    }
-}
-
-async fn relation_executor(mut shutdown: broadcast::Receiver<()>) {
-    tokio::select! {
-        output = pulsar_typesense() => {
-            log::error!("[TASK-EXEC] Relation executor result: {:?}", output);
-        },
-        _ = shutdown.recv() => {
-            log::warn!("[TASK-EXEC] Relation executor is shutdown.");
-        },
-    }
-}
-
-async fn search_executor(mut shutdown: broadcast::Receiver<()>) {
-    tokio::select! {
-        output = pulsar_email() => {
-            log::error!("[TASK-EXEC] Typesense executor result: {:?}", output);
-        },
-        _ = shutdown.recv() => {
-            log::warn!("[TASK-EXEC] Typesense executor is shutdown.");
-        },
-    }
-}
-
-async fn email_executor(mut shutdown: broadcast::Receiver<()>) {
-    tokio::select! {
-        output = pulsar_relation() => {
-            log::error!("[TASK-EXEC] Email executor result: {:?}", output);
-        },
-        _ = shutdown.recv() => {
-            log::warn!("[TASK-EXEC] Email executor is shutdown.");
-        },
+/// Run an executor until shutdown, and restart it whenever it returns or panics
+///
+/// The restart is delayed with the backoff of the retry settings, which starts
+/// over once the executor has run for a minute.
+async fn supervise<F, Fut, T>(
+    name: &'static str,
+    executor: F,
+    mut shutdown: broadcast::Receiver<()>,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Debug + Send + 'static,
+{
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        // a panic only fails the spawned task, so that it can be restarted
+        let mut handle = tokio::spawn(executor());
+        let stopped = tokio::select! {
+            output = &mut handle => {
+                match output {
+                    Ok(output) => log::error!("[TASK-EXEC] {} executor result: {:?}", name, output),
+                    Err(e) => log::error!("[TASK-EXEC] {} executor panicked: {:?}", name, e),
+                }
+                false
+            },
+            _ = shutdown.recv() => true,
+        };
+        if stopped {
+            handle.abort();
+            log::warn!("[TASK-EXEC] {} executor is shutdown.", name);
+            return;
+        }
+        if started.elapsed() >= Duration::from_secs(60) {
+            attempt = 0;
+        }
+        attempt += 1;
+        let delay = backoff(attempt, CONFIG.retry.base_delay, CONFIG.retry.max_delay);
+        log::warn!("[TASK-EXEC] Restart {} executor in {:?}", name, delay);
+        tokio::select! {
+            _ = sleep(delay) => (),
+            _ = shutdown.recv() => {
+                log::warn!("[TASK-EXEC] {} executor is shutdown.", name);
+                return;
+            },
+        }
     }
 }
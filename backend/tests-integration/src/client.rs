@@ -5,6 +5,9 @@ use rocket::local::blocking::Client;
 use once_cell::sync::OnceCell;
 // use tokio::runtime::Runtime;
 
+/// Searches a user can send in a row, set low so that the rate limit is tested
+pub const SEARCH_CAPACITY: usize = 30;
+
 pub fn get_client() -> &'static Mutex<Client> {
     static INSTANCE: OnceCell<Mutex<Client>> = OnceCell::new();
     INSTANCE.get_or_init(|| {
@@ -18,6 +21,12 @@ pub fn get_client() -> &'static Mutex<Client> {
         //     tokio::spawn(pulsar_typesense()),
         // ];
         // tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        // before the settings are loaded by the first use
+        std::env::set_var(
+            "BACKEND_RATE_LIMIT__SEARCH__CAPACITY",
+            SEARCH_CAPACITY.to_string(),
+        );
+        std::env::set_var("BACKEND_RATE_LIMIT__SEARCH__INTERVAL", "60000");
         let rocket = backend::rocket_init();
         let client = Client::tracked(rocket).expect("valid rocket instance");
 
@@ -1,3 +1,3 @@
 pub mod client;
 
-pub use client::get_client;
+pub use client::{get_client, SEARCH_CAPACITY};
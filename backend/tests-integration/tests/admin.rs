@@ -1,4 +1,7 @@
+use backend::models::error::*;
+use backend::models::user::TotpEnrollResponse;
 use backend::utils::mq::*;
+use backend::utils::totp::{base32_decode, totp_code};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use rocket::http::Status;
@@ -16,6 +19,7 @@ fn test_admin() {
     let h2 = rt.spawn(pulsar_relation());
     let h3 = rt.spawn(pulsar_typesense());
     let h4 = rt.spawn(pulsar_email());
+    let h5 = rt.spawn(pulsar_outbox());
     std::thread::sleep(std::time::Duration::from_secs(1));
     // generate a random name
     let name: String = std::iter::repeat(())
@@ -132,6 +136,35 @@ fn test_admin() {
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Success");
+    // perform admin operation: perform a wrong action (TOTP is not enabled)
+    let response = client
+        .post("/admin")
+        .json(&json!({ "GetUserId": {"burrow_id": burrow_id} }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap().error.code,
+        ErrorCode::TotpRequired
+    );
+    // enable TOTP, the current session counts as checked with TOTP
+    let response = client
+        .post("/users/totp/enroll")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response.into_json::<TotpEnrollResponse>().unwrap();
+    let secret = base32_decode(&res.secret).unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let response = client
+        .post("/users/totp/confirm")
+        .json(&json!({ "code": format!("{:06}", totp_code(&secret, now / 30, 6)) }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
     // Get Uid of the burrow
     let response = client
         .post("/admin")
@@ -156,6 +189,37 @@ fn test_admin() {
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Success");
+    // Ban the user with uid for a while
+    let response = client
+        .post("/admin")
+        .json(&json!({ "BanUserFor": {"uid": uid, "duration": 0, "reason": "test"} }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client
+        .post("/admin")
+        .json(&json!({ "BanUserFor": {"uid": uid, "duration": i64::MAX, "reason": "test"} }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap().error.code,
+        ErrorCode::BanDurationInvalid
+    );
+    let response = client
+        .post("/admin")
+        .json(&json!({ "BanUserFor": {"uid": uid, "duration": 3600, "reason": "test"} }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "Success");
+    let response = client
+        .post("/admin")
+        .json(&json!({ "ReopenUser": {"uid": uid} }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "Success");
     // Ban the burrow with burrow_id
     let response = client
         .post("/admin")
@@ -240,6 +304,84 @@ fn test_admin() {
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Success");
 
+    // Check the audit log of the operations above
+    let response = client
+        .get(format!("/admin/audit?kind=BanUser&target_id={}", uid))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response
+        .into_json::<Vec<backend::models::admin::AuditDisplay>>()
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(
+        res[0].operation,
+        Some(backend::models::admin::AdminOperation::BanUser { uid })
+    );
+    assert_eq!(res[0].result, "Success");
+    let response = client
+        .get(format!("/admin/audit?target_type=user&target_id={}", uid))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response
+        .into_json::<Vec<backend::models::admin::AuditDisplay>>()
+        .unwrap();
+    assert_eq!(res[0].kind, "CreateAdmin");
+    assert_eq!(
+        res[0].target_type,
+        Some(backend::models::admin::AuditTarget::User)
+    );
+    // A failed operation is rolled back but still audited with its error
+    let response = client
+        .post("/admin")
+        .json(&json!({ "BanPost": {"post_id": -1} }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client
+        .get("/admin/audit?kind=BanPost&target_type=post&target_id=-1")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response
+        .into_json::<Vec<backend::models::admin::AuditDisplay>>()
+        .unwrap();
+    assert_eq!(res[0].status, 400);
+    assert_eq!(res[0].result, "PostNotExist");
+
+    // Clearing a lockout is audited too
+    let response = client
+        .delete(format!("/admin/lockouts?username={}", name))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .get("/admin/audit?kind=ClearLockout")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response
+        .into_json::<Vec<backend::models::admin::AuditDisplay>>()
+        .unwrap();
+    assert_eq!(res[0].operation, None);
+    assert_eq!(res[0].result, "Success");
+    // Replaying a missing dead letter is audited with its error
+    let response = client
+        .post("/admin/dead-letters/-1/replay")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client
+        .get("/admin/audit?kind=ReplayDeadLetter&target_id=-1")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response
+        .into_json::<Vec<backend::models::admin::AuditDisplay>>()
+        .unwrap();
+    assert_eq!(res[0].result, "DeadLetterNotExist");
+
     // user log out
     let response = client
         .get("/users/logout")
@@ -257,6 +399,31 @@ fn test_admin() {
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Success");
+    // enable TOTP, so that the checks below are the ones of the operations
+    let response = client
+        .post("/users/totp/enroll")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response.into_json::<TotpEnrollResponse>().unwrap();
+    let secret = base32_decode(&res.secret).unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let response = client
+        .post("/users/totp/confirm")
+        .json(&json!({ "code": format!("{:06}", totp_code(&secret, now / 30, 6)) }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Admin with role 0 cannot read the audit log
+    let response = client
+        .get("/admin/audit")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
 
     // Ban the user with uid
     let response = client
@@ -422,6 +589,7 @@ fn test_admin() {
     h2.abort();
     h3.abort();
     h4.abort();
+    h5.abort();
     std::thread::sleep(std::time::Duration::from_secs(1));
     // ---------- Clean up ----------
 }
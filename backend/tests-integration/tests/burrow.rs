@@ -1,3 +1,4 @@
+use backend::models::burrow::BurrowShowResponse;
 use backend::models::error::*;
 use backend::utils::mq::*;
 use rand::distributions::Alphanumeric;
@@ -17,6 +18,7 @@ fn test_burrow() {
     let h2 = rt.spawn(pulsar_relation());
     let h3 = rt.spawn(pulsar_typesense());
     let h4 = rt.spawn(pulsar_email());
+    let h5 = rt.spawn(pulsar_outbox());
     std::thread::sleep(std::time::Duration::from_secs(1));
     // generate a random name
     let name: String = std::iter::repeat(())
@@ -728,6 +730,114 @@ fn test_burrow() {
     h2.abort();
     h3.abort();
     h4.abort();
+    h5.abort();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    // ---------- Clean up ----------
+}
+
+#[test]
+fn test_burrow_cursor() {
+    // ---------- Prepare ----------
+    // Init background task executor
+    let client = get_client().lock();
+    let rt = Runtime::new().unwrap();
+    let h1 = rt.spawn(pulsar_relation());
+    let h2 = rt.spawn(pulsar_outbox());
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    // generate a random name
+    let name: String = std::iter::repeat(())
+        .map(|()| thread_rng().sample(Alphanumeric))
+        .map(char::from)
+        .take(12)
+        .collect();
+    // ---------- Prepare ----------
+
+    // set verification code
+    client
+        .post("/users/email")
+        .json(&json!({
+            "email": format!("{}@mails.tsinghua.edu.cn", name)
+        }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    // sign up a user
+    let response = client
+        .post("/users/sign-up")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword",
+            "email": format!("{}@mails.tsinghua.edu.cn", name),
+            "verification_code": "666666"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let burrow_id = response
+        .into_json::<backend::models::user::UserResponse>()
+        .unwrap()
+        .default_burrow;
+    // user login
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    // fill one page of the burrow and one more post
+    for i in 0..21 {
+        let response = client
+            .post("/content/posts")
+            .json(&json!({
+                "title": format!("Burrow post {}", i),
+                "burrow_id": burrow_id,
+                "section": ["Learning"],
+                "tag": ["NoTag"],
+                "content": format!("This is burrow post no.{}", i)}))
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    // show burrow: the first page has a cursor to the next one
+    let response = client
+        .get(format!("/burrows/{}", burrow_id))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let first = response.into_json::<BurrowShowResponse>().unwrap();
+    assert_eq!(first.posts.len(), 20);
+    let cursor = first.next_cursor.unwrap();
+    // show burrow: the second page holds the oldest post only
+    let response = client
+        .get(format!("/burrows/{}?cursor={}", burrow_id, cursor))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let second = response.into_json::<BurrowShowResponse>().unwrap();
+    assert_eq!(second.posts.len(), 1);
+    assert!(second.posts[0].post_id < first.posts.last().unwrap().post_id);
+    assert_eq!(second.next_cursor, None);
+    // show burrow: perform a wrong action (malformed cursor)
+    let response = client
+        .get(format!("/burrows/{}?cursor=zz", burrow_id))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap(),
+        ErrorResponse::build(ErrorCode::CursorInvalid, "Invalid cursor.")
+    );
+    // user log out
+    let response = client
+        .get("/users/logout")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // ---------- Clean up ----------
+    h1.abort();
+    h2.abort();
     std::thread::sleep(std::time::Duration::from_secs(1));
     // ---------- Clean up ----------
 }
@@ -1,6 +1,8 @@
 mod admin;
 mod burrow;
+mod content;
 mod health;
+mod outbox;
 mod search;
 mod storage;
 mod user;
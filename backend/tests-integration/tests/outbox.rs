@@ -0,0 +1,66 @@
+use backend::config::settings::CONFIG;
+use backend::utils::mq::relay_outbox;
+use backend::utils::{outbox, queue};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sea_orm::{ConnectOptions, Database, TransactionTrait};
+use tests_integration::get_client;
+use tokio::runtime::Runtime;
+use tokio::time::{sleep, timeout, Duration};
+
+#[test]
+fn test_outbox() {
+    // the client migrates the database when it launches
+    let _client = get_client().lock();
+    let rt = Runtime::new().unwrap();
+    // generate a random name
+    let name: String = std::iter::repeat(())
+        .map(|()| thread_rng().sample(Alphanumeric))
+        .map(char::from)
+        .take(13)
+        .collect();
+    rt.block_on(async {
+        let mut options = ConnectOptions::new(CONFIG.databases.pgdb.url.to_owned());
+        options.max_connections(1);
+        let db = Database::connect(options).await.unwrap();
+        let queue = queue::connect(&CONFIG.databases.pulsar_mq.url)
+            .await
+            .unwrap();
+        // a topic of this test only
+        let topic = format!("outbox-test-{}", name);
+        let mut consumer = queue.subscribe(&[&topic], "test").await.unwrap();
+
+        // the event of a rolled back transaction is dropped with it
+        let txn = db.begin().await.unwrap();
+        outbox::enqueue_payload(&txn, &topic, "\"rolled back\"".to_string())
+            .await
+            .unwrap();
+        txn.rollback().await.unwrap();
+        // the event of a committed transaction is published
+        let txn = db.begin().await.unwrap();
+        outbox::enqueue_payload(&txn, &topic, "\"committed\"".to_string())
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        // relay like task-executor, which may be relaying at the same time
+        while !outbox::try_lock_relay(&db).await.unwrap() {
+            sleep(Duration::from_millis(100)).await;
+        }
+        while relay_outbox(&db, &*queue).await.unwrap() as u64 == CONFIG.outbox.batch_size {}
+        outbox::unlock_relay(&db).await.unwrap();
+
+        let delivery = timeout(Duration::from_secs(10), consumer.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(topic, delivery.topic);
+        assert_eq!(b"\"committed\"".to_vec(), delivery.payload);
+        consumer.ack(&delivery).await.unwrap();
+        // nothing else is published to the topic
+        assert!(timeout(Duration::from_secs(1), consumer.next())
+            .await
+            .is_err());
+    });
+}
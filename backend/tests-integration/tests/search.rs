@@ -7,7 +7,7 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use rocket::http::Status;
 use serde_json::json;
-use tests_integration::get_client;
+use tests_integration::{get_client, SEARCH_CAPACITY};
 use tokio::runtime::Runtime;
 
 #[test]
@@ -19,6 +19,7 @@ fn test_search() {
     let h2 = rt.spawn(pulsar_relation());
     let h3 = rt.spawn(pulsar_typesense());
     let h4 = rt.spawn(pulsar_email());
+    let h5 = rt.spawn(pulsar_outbox());
     std::thread::sleep(std::time::Duration::from_secs(1));
     // generate a random name
     let name: String = std::iter::repeat(())
@@ -454,6 +455,93 @@ fn test_search() {
     h2.abort();
     h3.abort();
     h4.abort();
+    h5.abort();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    // ---------- Clean up ----------
+}
+
+#[test]
+fn test_search_rate_limit() {
+    // ---------- Prepare ----------
+    // Init background task executor
+    let client = get_client().lock();
+    let rt = Runtime::new().unwrap();
+    let h4 = rt.spawn(pulsar_email());
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    // generate a random name
+    let name: String = std::iter::repeat(())
+        .map(|()| thread_rng().sample(Alphanumeric))
+        .map(char::from)
+        .take(14)
+        .collect();
+    // ---------- Prepare ----------
+
+    // set verification code
+    client
+        .post("/users/email")
+        .json(&json!({
+            "email": format!("{}@mails.tsinghua.edu.cn", name)
+        }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    // sign up a user
+    let response = client
+        .post("/users/sign-up")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword",
+            "email": format!("{}@mails.tsinghua.edu.cn", name),
+            "verification_code": "666666"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // user login
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // search until the bucket of the user is empty
+    for _ in 0..SEARCH_CAPACITY {
+        let response = client
+            .post("/search")
+            .json(&json!(SearchRequest::RetrieveBurrow { burrow_id: -1 }))
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .dispatch();
+        assert_ne!(response.status(), Status::TooManyRequests);
+    }
+    // the next search is rejected until a token is refilled
+    let response = client
+        .post("/search")
+        .json(&json!(SearchRequest::RetrieveBurrow { burrow_id: -1 }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+    let retry_after: i64 = response
+        .headers()
+        .get_one("Retry-After")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    let res = response.into_json::<ErrorResponse>().unwrap();
+    assert_eq!(res.error.code, ErrorCode::RateLimit);
+
+    // user log out
+    let response = client
+        .get("/users/logout")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // ---------- Clean up ----------
+    h4.abort();
     std::thread::sleep(std::time::Duration::from_secs(1));
     // ---------- Clean up ----------
 }
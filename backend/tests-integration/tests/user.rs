@@ -1,5 +1,9 @@
+use backend::config::user::REFRESH_REUSE_GRACE;
 use backend::models::error::*;
+use backend::models::session::SessionDisplay;
+use backend::models::user::{TotpEnrollResponse, TotpRecoveryCodes};
 use backend::utils::mq::*;
+use backend::utils::totp::{base32_decode, totp_code};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use rocket::http::Status;
@@ -649,7 +653,7 @@ fn test_user() {
         .dispatch();
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Success");
-    // user log in: find old token
+    // user log in again: a second session
     let response = client
         .post("/users/login")
         .json(&json!({
@@ -663,7 +667,7 @@ fn test_user() {
     let response = client
         .post("/users/login")
         .json(&json!({
-            "username": "usernotexist",
+            "username": format!("{}notexist", name),
             "password": "testpassword"}))
         .remote("127.0.0.1:8000".parse().unwrap())
         .dispatch();
@@ -685,6 +689,256 @@ fn test_user() {
         response.into_json::<ErrorResponse>().unwrap(),
         ErrorResponse::build(ErrorCode::CredentialInvalid, "Wrong username or password.",)
     );
+    // user log in: too many failures lock the username
+    let locked_name = format!("{}locked", name);
+    for _ in 1..5 {
+        let response = client
+            .post("/users/login")
+            .json(&json!({
+                "username": locked_name,
+                "password": "wrongpassword"}))
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": locked_name,
+            "password": "wrongpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("30"));
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap(),
+        ErrorResponse::build(ErrorCode::RateLimit, "Too many failed login attempts.")
+    );
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": locked_name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert!(response.headers().get_one("Retry-After").is_some());
+    // get sessions: both logins are kept
+    let response = client
+        .get("/users/sessions")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Vec<SessionDisplay>>().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.iter().filter(|s| s.current).count(), 1);
+    assert_eq!(sessions[0].ip, "127.0.0.1");
+    let other = sessions.iter().find(|s| !s.current).unwrap();
+    // revoke the other session
+    let response = client
+        .delete(format!("/users/sessions/{}", other.session_id))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    // revoke session: perform a wrong action (session not exist)
+    let response = client
+        .delete(format!("/users/sessions/{}", other.session_id))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap(),
+        ErrorResponse::build(ErrorCode::SessionNotExist, "Session not exist.")
+    );
+    let response = client
+        .get("/users/sessions")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Vec<SessionDisplay>>().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+    // refresh tokens: each refresh rotates the refresh token in cookie
+    for _ in 0..2 {
+        let response = client
+            .post("/users/refresh")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "Success");
+    }
+    let response = client
+        .get("/users/sessions")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Vec<SessionDisplay>>().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+    let session_a = sessions[0].session_id.to_owned();
+    let token_a = client.cookies().get_private("token").unwrap();
+    let refresh_a = client.cookies().get_private("refresh_token").unwrap();
+    // revoke session: the other sessions are kept
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let token_b = client.cookies().get_private("token").unwrap();
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .delete(format!("/users/sessions/{}", session_a))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .get("/users/sessions")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Vec<SessionDisplay>>().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions.iter().all(|s| s.session_id != session_a));
+    let response = client
+        .get("/users/sessions")
+        .private_cookie(token_b.clone())
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .get("/users/sessions")
+        .private_cookie(token_a)
+        .private_cookie(refresh_a)
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    // refresh tokens: replaying a rotated refresh token after the grace period
+    // revokes the session
+    let refresh_c = client.cookies().get_private("refresh_token").unwrap();
+    let response = client
+        .post("/users/refresh")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let token_c = client.cookies().get_private("token").unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(
+        REFRESH_REUSE_GRACE as u64 + 1,
+    ));
+    let response = client
+        .post("/users/refresh")
+        .private_cookie(refresh_c)
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = client
+        .get("/users/sessions")
+        .private_cookie(token_c)
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = client
+        .get("/users/sessions")
+        .private_cookie(token_b)
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let sessions = response.into_json::<Vec<SessionDisplay>>().unwrap();
+    assert_eq!(sessions.len(), 1);
+    // user log in again for the steps below
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    // enroll TOTP
+    let response = client
+        .post("/users/totp/enroll")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let res = response.into_json::<TotpEnrollResponse>().unwrap();
+    assert!(res.uri.starts_with("otpauth://totp/"));
+    let secret = base32_decode(&res.secret).unwrap();
+    // confirm TOTP: perform a wrong action (wrong code)
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let code = totp_code(&secret, now / 30, 6);
+    let response = client
+        .post("/users/totp/confirm")
+        .json(&json!({ "code": format!("{:06}", (code + 1) % 1000000) }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client
+        .post("/users/totp/confirm")
+        .json(&json!({ "code": format!("{:06}", code) }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let recovery_codes = response
+        .into_json::<TotpRecoveryCodes>()
+        .unwrap()
+        .recovery_codes;
+    // log in with TOTP: the password alone is not enough
+    let response = client
+        .post("/users/login")
+        .json(&json!({
+            "username": name,
+            "password": "testpassword"}))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(response.into_string().unwrap(), "TotpRequired");
+    // log in with TOTP: perform a wrong action (code already used to confirm)
+    let response = client
+        .post("/users/login/totp")
+        .json(&json!({ "code": format!("{:06}", code) }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap(),
+        ErrorResponse::build(ErrorCode::TotpInvalid, "Wrong TOTP code.")
+    );
+    let response = client
+        .post("/users/login/totp")
+        .json(&json!({ "code": recovery_codes[0] }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "Success");
+    // disable TOTP: perform a wrong action (recovery code already used)
+    let response = client
+        .post("/users/totp/disable")
+        .json(&json!({ "code": recovery_codes[0] }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.into_json::<ErrorResponse>().unwrap(),
+        ErrorResponse::build(ErrorCode::TotpInvalid, "Wrong TOTP code.")
+    );
+    let response = client
+        .post("/users/totp/disable")
+        .json(&json!({ "code": recovery_codes[1] }))
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
 
     // 3. test user_logout
     // user log out